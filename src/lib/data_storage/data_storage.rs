@@ -22,10 +22,23 @@ use chrono::{
 use crate::lib::zones::{
     Zone
 };
+use crate::lib::zones::flow::{default_pcu_factors, with_default_pcu_factors};
+use crate::lib::zones::discovery::LaneDiscovery;
+use crate::lib::masks::ExclusionMask;
+use crate::lib::spatial::SpatialConverter;
+use crate::lib::spatial::projection::ProjectionKind;
+use crate::lib::spatial::stabilization::StabilizationHealth;
+use crate::lib::draw::MetricGrid;
+use crate::lib::tamper::{TamperEvent, TamperKind, TamperStatus};
+
+use crate::lib::data_storage::ODMatrix;
+use crate::lib::data_storage::ViolationsStorage;
+use crate::lib::data_storage::{HistoryRecord, HistoryWriter};
 
 #[derive(Debug)]
 pub enum DataStorageError {
-    Poison
+    Poison,
+    History(rusqlite::Error)
 }
 
 impl<T> From<PoisonError<T>> for DataStorageError {
@@ -33,19 +46,91 @@ impl<T> From<PoisonError<T>> for DataStorageError {
         Self::Poison
     }
 }
+impl From<rusqlite::Error> for DataStorageError {
+    fn from(err: rusqlite::Error) -> Self {
+        Self::History(err)
+    }
+}
 impl std::fmt::Display for DataStorageError {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
         match self {
-            DataStorageError::Poison => write!(f, "PoisonError")
+            DataStorageError::Poison => write!(f, "PoisonError"),
+            DataStorageError::History(err) => write!(f, "HistoryError: {}", err)
+        }
+    }
+}
+
+// Last known position of the object on the camera-wide ground plane
+#[derive(Debug, Clone)]
+pub struct GroundTrack {
+    pub object_id: String,
+    pub classname: String,
+    pub lon: f64,
+    pub lat: f64,
+    // Value "-1" indicates no data
+    pub speed: f32,
+}
+
+// Period of the additional aggregation interval
+#[derive(Debug, Clone)]
+pub struct AggregationPeriod {
+    pub interval_milliseconds: i64,
+    pub period_start: DateTime<Utc>,
+    pub period_end: DateTime<Utc>,
+}
+
+impl AggregationPeriod {
+    pub fn new(interval_milliseconds: i64) -> Self {
+        AggregationPeriod {
+            interval_milliseconds: interval_milliseconds,
+            period_start: TimeZone::with_ymd_and_hms(&Utc, 1970, 1, 1, 0, 0, 0).unwrap(),
+            period_end: TimeZone::with_ymd_and_hms(&Utc, 1970, 1, 1, 0, 0, 0).unwrap(),
+        }
+    }
+    // Moves to the next period. The first one ends at the given time, next ones follow each other without gaps
+    pub fn advance(&mut self, now: DateTime<Utc>) {
+        if self.period_end == self.period_start {
+            self.period_end = now;
+        } else {
+            self.period_end = self.period_end + chrono::Duration::milliseconds(self.interval_milliseconds);
         }
+        self.period_start = self.period_end - chrono::Duration::milliseconds(self.interval_milliseconds);
     }
 }
 
 #[derive(Clone)]
 pub struct DataStorage {
     pub zones: Arc<RwLock<HashMap<String, Mutex<Zone>>>>,
+    // Origin-destination matrix for every aggregation interval. Key: interval (milliseconds)
+    pub od_matrices: Arc<RwLock<HashMap<i64, ODMatrix>>>,
+    pub violations: Arc<RwLock<ViolationsStorage>>,
+    pub exclusion_masks: Arc<RwLock<Vec<ExclusionMask>>>,
+    // Camera-wide spatial converter estimated by shared control points. It is not tied to any zone
+    pub ground_plane: Arc<Mutex<Option<SpatialConverter>>>,
+    // Objects on the ground plane for the last processed frame
+    pub ground_tracks: Arc<RwLock<Vec<GroundTrack>>>,
+    // Metric grid overlay for the MJPEG stream / output window
+    pub metric_grid: Arc<RwLock<MetricGrid>>,
+    // Learning mode which proposes lanes by recorded trajectories
+    pub lane_discovery: Arc<RwLock<LaneDiscovery>>,
+    // Last estimate of the camera shake. None means that stabilization is disabled
+    pub stabilization: Arc<RwLock<Option<StabilizationHealth>>>,
+    // Last state of the tamper monitor. None means that tamper detection is disabled
+    pub tamper: Arc<RwLock<Option<TamperStatus>>>,
+    // Tampering noticed during the current statistics period of every aggregation interval
+    pub period_tamper: Arc<RwLock<HashMap<i64, Vec<TamperKind>>>>,
+    // Writer of the finished statistics periods to the persistent storage. None means that history is disabled
+    pub history: Option<HistoryWriter>,
+    // Passenger car unit factors for every vehicle type
+    pub pcu_factors: HashMap<String, f32>,
+    // Metric plane for spatial converters of zones created at runtime
+    pub projection: ProjectionKind,
     pub period_start: DateTime<Utc>,
     pub period_end: DateTime<Utc>,
+    // Main aggregation interval (milliseconds). Its statistics is kept in `Zone.statistics`
+    pub interval_milliseconds: i64,
+    // Additional aggregation intervals. Their statistics is computed from the same observations
+    pub extra_periods: Vec<AggregationPeriod>,
     pub id: String,
     pub verbose: bool
 }
@@ -54,13 +139,164 @@ impl DataStorage {
     pub fn new_with_id(_id: String, _verbose: bool) -> Self {
         return DataStorage {
             zones: Arc::new(RwLock::new(HashMap::<String, Mutex<Zone>>::new())),
+            od_matrices: Arc::new(RwLock::new(HashMap::new())),
+            violations: Arc::new(RwLock::new(ViolationsStorage::default())),
+            exclusion_masks: Arc::new(RwLock::new(vec![])),
+            ground_plane: Arc::new(Mutex::new(None)),
+            ground_tracks: Arc::new(RwLock::new(vec![])),
+            metric_grid: Arc::new(RwLock::new(MetricGrid::default())),
+            lane_discovery: Arc::new(RwLock::new(LaneDiscovery::default())),
+            stabilization: Arc::new(RwLock::new(None)),
+            tamper: Arc::new(RwLock::new(None)),
+            period_tamper: Arc::new(RwLock::new(HashMap::new())),
+            history: None,
+            pcu_factors: default_pcu_factors(),
+            projection: ProjectionKind::default(),
             period_start: TimeZone::with_ymd_and_hms(&Utc, 1970, 1, 1, 0, 0, 0).unwrap(),
             period_end: TimeZone::with_ymd_and_hms(&Utc, 1970, 1, 1, 0, 0, 0).unwrap(),
+            interval_milliseconds: 0,
+            extra_periods: vec![],
             id: _id,
             verbose: _verbose
         };
     }
+    // Given factors override the default ones (see `with_default_pcu_factors`)
+    pub fn set_pcu_factors(&mut self, pcu_factors: HashMap<String, f32>) {
+        self.pcu_factors = with_default_pcu_factors(pcu_factors);
+    }
+    pub fn set_projection(&mut self, projection: ProjectionKind) {
+        self.projection = projection;
+    }
+    // Sets main aggregation interval and additional ones (milliseconds). Zones which are already inserted are updated too
+    pub fn set_aggregation_intervals(&mut self, main_interval: i64, extra_intervals: &[i64]) -> Result<(), DataStorageError> {
+        self.interval_milliseconds = main_interval;
+        let mut extra: Vec<i64> = extra_intervals.iter().cloned().filter(|interval| *interval > 0 && *interval != main_interval).collect();
+        extra.sort();
+        extra.dedup();
+        self.extra_periods = extra.iter().map(|interval| AggregationPeriod::new(*interval)).collect();
+        // Matrices of the kept intervals are preserved
+        let intervals = self.get_aggregation_intervals();
+        let mut od_matrices = self.od_matrices.write()?;
+        od_matrices.retain(|interval, _| intervals.contains(interval));
+        for interval in intervals.iter() {
+            od_matrices.entry(*interval).or_insert_with(ODMatrix::default);
+        }
+        drop(od_matrices);
+        let zones = self.zones.read()?;
+        for (_zone_id, zone) in zones.iter() {
+            let mut zone = zone.lock()?;
+            zone.set_aggregation_intervals(main_interval, &extra);
+        }
+        Ok(())
+    }
+    // Main interval goes first
+    pub fn get_aggregation_intervals(&self) -> Vec<i64> {
+        std::iter::once(self.interval_milliseconds).chain(self.extra_periods.iter().map(|period| period.interval_milliseconds)).collect()
+    }
+    fn get_extra_intervals(&self) -> Vec<i64> {
+        self.extra_periods.iter().map(|period| period.interval_milliseconds).collect()
+    }
+    // Takes tampering noticed during the finished period of the interval
+    fn take_invalid_reasons(&self, interval_milliseconds: i64) -> Result<Vec<String>, DataStorageError> {
+        let mut period_tamper = self.period_tamper.write()?;
+        Ok(period_tamper
+            .remove(&interval_milliseconds)
+            .unwrap_or(vec![])
+            .iter()
+            .map(|kind| kind.as_str().to_string())
+            .collect())
+    }
+    pub fn set_ground_plane(&self, converter: SpatialConverter) -> Result<(), DataStorageError> {
+        let mut ground_plane = self.ground_plane.lock()?;
+        *ground_plane = Some(converter);
+        Ok(())
+    }
+    pub fn set_ground_tracks(&self, tracks: Vec<GroundTrack>) -> Result<(), DataStorageError> {
+        let mut ground_tracks = self.ground_tracks.write()?;
+        *ground_tracks = tracks;
+        Ok(())
+    }
+    pub fn set_stabilization_health(&self, health: StabilizationHealth) -> Result<(), DataStorageError> {
+        let mut stabilization = self.stabilization.write()?;
+        *stabilization = Some(health);
+        Ok(())
+    }
+    // Stores state of the monitor and remembers active tampering for the current statistics period
+    pub fn set_tamper_status(&self, status: TamperStatus) -> Result<(), DataStorageError> {
+        let mut period_tamper = self.period_tamper.write()?;
+        for interval in self.get_aggregation_intervals() {
+            let kinds = period_tamper.entry(interval).or_insert(vec![]);
+            for kind in status.active.iter() {
+                if !kinds.contains(kind) {
+                    kinds.push(*kind);
+                }
+            }
+        }
+        drop(period_tamper);
+        let mut tamper = self.tamper.write()?;
+        *tamper = Some(status);
+        Ok(())
+    }
+    // Condition should last for a while to raise event, so it may have started during the finished periods.
+    // Their statistics (both current and stored in the history) are marked as invalid too
+    pub fn mark_tamper_events(&self, events: &[TamperEvent]) -> Result<(), DataStorageError> {
+        let intervals = self.get_aggregation_intervals();
+        let zones = self.zones.read()?;
+        for (_zone_id, zone) in zones.iter() {
+            let mut zone = zone.lock()?;
+            for interval in intervals.iter() {
+                let statistics = match zone.get_statistics_mut(*interval) {
+                    Some(statistics) => statistics,
+                    None => continue,
+                };
+                for event in events.iter() {
+                    let reason = event.kind.as_str().to_string();
+                    if statistics.period_end.timestamp() > event.started_at && !statistics.invalid_reasons.contains(&reason) {
+                        statistics.invalid_reasons.push(reason);
+                    }
+                }
+            }
+        }
+        drop(zones);
+        if let Some(ref history) = self.history {
+            for event in events.iter() {
+                history.mark_invalid(event.kind.as_str(), event.started_at);
+            }
+        }
+        Ok(())
+    }
+    pub fn set_history(&mut self, writer: HistoryWriter) {
+        self.history = Some(writer);
+    }
+    // Collects statistics of the finished period of the interval for every zone and queues it for the history.
+    // Records are written (and periods out of retention are removed hourly) by the writer thread
+    pub fn save_history(&self, interval_milliseconds: i64) -> Result<(), DataStorageError> {
+        let history = match self.history {
+            Some(ref history) => history,
+            None => {
+                return Ok(());
+            }
+        };
+        let zones = self.zones.read()?;
+        let mut records = vec![];
+        for (_zone_id, zone) in zones.iter() {
+            let zone = zone.lock()?;
+            if let Some(statistics) = zone.get_statistics(interval_milliseconds) {
+                records.push(HistoryRecord::new(&zone, statistics, interval_milliseconds));
+            }
+        }
+        drop(zones);
+        history.insert(records);
+        Ok(())
+    }
+    pub fn set_metric_grid(&self, metric_grid: MetricGrid) -> Result<(), DataStorageError> {
+        let mut current = self.metric_grid.write()?;
+        *current = metric_grid;
+        Ok(())
+    }
     pub fn insert_zone(&self, zone: Zone) -> Result<(), DataStorageError> {
+        let mut zone = zone;
+        zone.set_aggregation_intervals(self.interval_milliseconds, &self.get_extra_intervals());
         let zones = Arc::clone(&self.zones);
         match zones.write() {
             Ok(mut mutex) => {
@@ -84,21 +320,78 @@ impl DataStorage {
         };
         Ok(())
     }
+    pub fn insert_exclusion_mask(&self, mask: ExclusionMask) -> Result<(), DataStorageError> {
+        let mut masks = self.exclusion_masks.write()?;
+        masks.push(mask);
+        Ok(())
+    }
+    // Returns false if there is no mask with given identifier
+    pub fn delete_exclusion_mask(&self, mask_id: &String) -> Result<bool, DataStorageError> {
+        let mut masks = self.exclusion_masks.write()?;
+        let before = masks.len();
+        masks.retain(|mask| &mask.id != mask_id);
+        Ok(masks.len() != before)
+    }
     pub fn update_statistics(&mut self) -> Result<(), DataStorageError> {
+        // Statistics of the period is invalid if camera has been tampered at any moment of it
+        let invalid_reasons = self.take_invalid_reasons(self.interval_milliseconds)?;
         let zones = Arc::clone(&self.zones);
         match zones.read() {
             Ok(mutex) => {
                 for (_zone_id, zone) in mutex.iter() {
                     let mut zone = zone.lock()?;
-                    zone.update_statistics(self.period_start, self.period_end);
+                    zone.update_statistics(self.period_start, self.period_end, &self.pcu_factors);
+                    zone.statistics.invalid_reasons = invalid_reasons.clone();
                 }
             },
             Err(_) => {
                 return Err(DataStorageError::Poison);
             }
         };
+        let mut od_matrices = self.od_matrices.write()?;
+        if let Some(od_matrix) = od_matrices.get_mut(&self.interval_milliseconds) {
+            od_matrix.update_statistics(self.period_start, self.period_end);
+        }
+        Ok(())
+    }
+    // Finishes the current period of the additional aggregation interval and evaluates its statistics for every zone
+    pub fn update_interval_statistics(&mut self, interval_milliseconds: i64) -> Result<(), DataStorageError> {
+        let period = match self.extra_periods.iter_mut().find(|period| period.interval_milliseconds == interval_milliseconds) {
+            Some(period) => period,
+            None => {
+                return Ok(());
+            }
+        };
+        period.advance(Utc::now());
+        let (period_start, period_end) = (period.period_start, period.period_end);
+        let invalid_reasons = self.take_invalid_reasons(interval_milliseconds)?;
+        let zones = self.zones.read()?;
+        for (_zone_id, zone) in zones.iter() {
+            let mut zone = zone.lock()?;
+            if zone.update_interval_statistics(interval_milliseconds, period_start, period_end, &self.pcu_factors) {
+                if let Some(statistics) = zone.get_statistics_mut(interval_milliseconds) {
+                    statistics.invalid_reasons = invalid_reasons.clone();
+                }
+            }
+        }
+        drop(zones);
+        let mut od_matrices = self.od_matrices.write()?;
+        if let Some(od_matrix) = od_matrices.get_mut(&interval_milliseconds) {
+            od_matrix.update_statistics(period_start, period_end);
+        }
         Ok(())
     }
+    pub fn register_zones_path(&self, zones_path: &[String], classname: String) -> Result<(), DataStorageError> {
+        let mut od_matrices = self.od_matrices.write()?;
+        for (_, od_matrix) in od_matrices.iter_mut() {
+            od_matrix.register_path(zones_path, classname.clone());
+        }
+        Ok(())
+    }
+    pub fn register_violation(&self, zone_id: String, object_id: String, classname: String, snapshot: Arc<[u8]>) -> Result<String, DataStorageError> {
+        let mut violations = self.violations.write()?;
+        Ok(violations.register(zone_id, object_id, classname, snapshot))
+    }
 }
 
 pub type ThreadedDataStorage = Arc<RwLock<DataStorage>>;
@@ -131,6 +424,13 @@ pub fn start_analytics_thread(ds: ThreadedDataStorage, millis: u64, verbose: boo
                             println!("Can't update statistics due PoisonErr [1]");
                         }
                     }
+                    let interval_milliseconds = mutex.interval_milliseconds;
+                    match mutex.save_history(interval_milliseconds) {
+                        Ok(_) => {}
+                        Err(err) => {
+                            println!("Can't save statistics to the history due the error: {}", err);
+                        }
+                    }
                     last_tm = Utc::now();
                 },
                 Err(_) => {
@@ -0,0 +1,540 @@
+use rusqlite::{params, Connection, OpenFlags};
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+
+use chrono::Utc;
+
+use crate::lib::zones::{Statistics, Zone};
+
+// Maximum number of periods returned by single query
+pub const HISTORY_MAX_LIMIT: u32 = 1000;
+// Expired periods are removed not more often than this (seconds)
+const CLEANUP_PERIOD_SECONDS: i64 = 3600;
+// Readers wait for the writer this long before the database is reported as busy
+const READER_BUSY_TIMEOUT_MILLISECONDS: u64 = 5000;
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS zone_periods (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    zone_id TEXT NOT NULL,
+    interval_milliseconds INTEGER NOT NULL,
+    lane_number INTEGER NOT NULL,
+    lane_direction INTEGER NOT NULL,
+    group_name TEXT NOT NULL,
+    period_start INTEGER NOT NULL,
+    period_end INTEGER NOT NULL,
+    flow_rate REAL NOT NULL,
+    pcu_volume REAL NOT NULL,
+    pcu_flow_rate REAL NOT NULL,
+    density REAL NOT NULL,
+    occupancy_percent REAL NOT NULL,
+    max_queue_length REAL NOT NULL,
+    avg_queue_length REAL NOT NULL,
+    level_of_service TEXT NOT NULL,
+    invalid_reasons TEXT NOT NULL
+);
+CREATE INDEX IF NOT EXISTS zone_periods_start ON zone_periods (interval_milliseconds, period_start, zone_id);
+CREATE INDEX IF NOT EXISTS zone_periods_end ON zone_periods (period_end);
+CREATE TABLE IF NOT EXISTS vehicle_statistics (
+    period_id INTEGER NOT NULL REFERENCES zone_periods (id),
+    classname TEXT NOT NULL,
+    avg_speed REAL NOT NULL,
+    sum_intensity INTEGER NOT NULL,
+    flow_rate REAL NOT NULL,
+    pcu_volume REAL NOT NULL,
+    min_speed REAL NOT NULL,
+    max_speed REAL NOT NULL,
+    median_speed REAL NOT NULL,
+    p85_speed REAL NOT NULL,
+    std_speed REAL NOT NULL
+);
+CREATE INDEX IF NOT EXISTS vehicle_statistics_period ON vehicle_statistics (period_id, classname);
+";
+
+// Statistics of the single vehicle type for the finished period
+#[derive(Debug, Clone, PartialEq)]
+pub struct HistoryVehicleRecord {
+    pub classname: String,
+    pub avg_speed: f32,
+    pub sum_intensity: u32,
+    pub flow_rate: f32,
+    pub pcu_volume: f32,
+    pub min_speed: f32,
+    pub max_speed: f32,
+    pub median_speed: f32,
+    pub p85_speed: f32,
+    pub std_speed: f32,
+}
+
+// Statistics of the zone for the finished period
+#[derive(Debug, Clone, PartialEq)]
+pub struct HistoryRecord {
+    pub zone_id: String,
+    // Aggregation interval which the period belongs to
+    pub interval_milliseconds: i64,
+    pub lane_number: u16,
+    pub lane_direction: u8,
+    pub group_name: String,
+    // UTC Unix timestamps
+    pub period_start: i64,
+    pub period_end: i64,
+    pub flow_rate: f32,
+    pub pcu_volume: f32,
+    pub pcu_flow_rate: f32,
+    pub density: f32,
+    pub occupancy_percent: f32,
+    pub max_queue_length: f32,
+    pub avg_queue_length: f32,
+    pub level_of_service: String,
+    // Empty means that statistics is valid
+    pub invalid_reasons: Vec<String>,
+    pub vehicles: Vec<HistoryVehicleRecord>,
+}
+
+impl HistoryRecord {
+    pub fn new(zone: &Zone, statistics: &Statistics, interval_milliseconds: i64) -> Self {
+        let mut vehicles: Vec<HistoryVehicleRecord> = statistics
+            .vehicles_data
+            .iter()
+            .map(|(classname, parameters)| HistoryVehicleRecord {
+                classname: classname.clone(),
+                avg_speed: parameters.avg_speed,
+                sum_intensity: parameters.sum_intensity,
+                flow_rate: parameters.flow_rate,
+                pcu_volume: parameters.pcu_volume,
+                min_speed: parameters.speed_distribution.min_speed,
+                max_speed: parameters.speed_distribution.max_speed,
+                median_speed: parameters.speed_distribution.median_speed,
+                p85_speed: parameters.speed_distribution.p85_speed,
+                std_speed: parameters.speed_distribution.std_speed,
+            })
+            .collect();
+        vehicles.sort_by(|a, b| a.classname.cmp(&b.classname));
+        HistoryRecord {
+            zone_id: zone.get_id(),
+            interval_milliseconds: interval_milliseconds,
+            lane_number: zone.road_lane_num,
+            lane_direction: zone.road_lane_direction,
+            group_name: zone.group_name.clone(),
+            period_start: statistics.period_start.timestamp(),
+            period_end: statistics.period_end.timestamp(),
+            flow_rate: statistics.traffic_flow.flow_rate,
+            pcu_volume: statistics.traffic_flow.pcu_volume,
+            pcu_flow_rate: statistics.traffic_flow.pcu_flow_rate,
+            density: statistics.traffic_flow.density,
+            occupancy_percent: statistics.occupancy.occupancy_percent,
+            max_queue_length: statistics.queue.max_queue_length,
+            avg_queue_length: statistics.queue.avg_queue_length,
+            level_of_service: statistics.level_of_service.as_str().to_string(),
+            invalid_reasons: statistics.invalid_reasons.clone(),
+            vehicles: vehicles,
+        }
+    }
+}
+
+// Filter for the stored periods. Periods which start in [from; to) are matched
+#[derive(Debug, Clone)]
+pub struct HistoryQuery {
+    pub from: i64,
+    pub to: i64,
+    pub zone_id: Option<String>,
+    // Periods of every aggregation interval are matched if it is not set
+    pub interval_milliseconds: Option<i64>,
+    // Only periods with given vehicle type are matched, and only its statistics is returned
+    pub classname: Option<String>,
+    pub limit: u32,
+    pub offset: u32,
+}
+
+// Persistent storage of the finished statistics periods (SQLite)
+pub struct HistoryStorage {
+    connection: Connection,
+    // Periods older than this are removed. Value "0" indicates that periods are kept forever
+    retention_seconds: i64,
+    // Time of the last removal of expired periods (UTC Unix timestamp)
+    last_cleanup: i64,
+}
+
+impl HistoryStorage {
+    pub fn open(path: &str, retention_days: u32) -> Result<Self, rusqlite::Error> {
+        let connection = Connection::open(path)?;
+        // Write-ahead log lets readers work while the writer is in the middle of the transaction
+        connection.query_row("PRAGMA journal_mode = WAL", [], |_| Ok(()))?;
+        HistoryStorage::with_connection(connection, retention_days)
+    }
+    // Opens separate connection for queries. Schema is expected to be created by the writer
+    pub fn open_read_only(path: &str) -> Result<Self, rusqlite::Error> {
+        let connection = Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY | OpenFlags::SQLITE_OPEN_NO_MUTEX)?;
+        connection.busy_timeout(Duration::from_millis(READER_BUSY_TIMEOUT_MILLISECONDS))?;
+        Ok(HistoryStorage {
+            connection: connection,
+            retention_seconds: 0,
+            last_cleanup: 0,
+        })
+    }
+    fn with_connection(connection: Connection, retention_days: u32) -> Result<Self, rusqlite::Error> {
+        connection.execute_batch(SCHEMA)?;
+        Ok(HistoryStorage {
+            connection: connection,
+            retention_seconds: retention_days as i64 * 86400,
+            last_cleanup: 0,
+        })
+    }
+    pub fn insert(&mut self, records: &[HistoryRecord]) -> Result<(), rusqlite::Error> {
+        let tx = self.connection.transaction()?;
+        {
+            let mut insert_period = tx.prepare_cached(
+                "INSERT INTO zone_periods (zone_id, lane_number, lane_direction, group_name, period_start, period_end, flow_rate, pcu_volume, pcu_flow_rate, density, occupancy_percent, max_queue_length, avg_queue_length, level_of_service, invalid_reasons, interval_milliseconds)
+                VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16)",
+            )?;
+            let mut insert_vehicle = tx.prepare_cached(
+                "INSERT INTO vehicle_statistics (period_id, classname, avg_speed, sum_intensity, flow_rate, pcu_volume, min_speed, max_speed, median_speed, p85_speed, std_speed)
+                VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)",
+            )?;
+            for record in records.iter() {
+                let period_id = insert_period.insert(params![
+                    record.zone_id,
+                    record.lane_number,
+                    record.lane_direction,
+                    record.group_name,
+                    record.period_start,
+                    record.period_end,
+                    record.flow_rate,
+                    record.pcu_volume,
+                    record.pcu_flow_rate,
+                    record.density,
+                    record.occupancy_percent,
+                    record.max_queue_length,
+                    record.avg_queue_length,
+                    record.level_of_service,
+                    record.invalid_reasons.join(","),
+                    record.interval_milliseconds,
+                ])?;
+                for vehicle in record.vehicles.iter() {
+                    insert_vehicle.execute(params![
+                        period_id,
+                        vehicle.classname,
+                        vehicle.avg_speed,
+                        vehicle.sum_intensity,
+                        vehicle.flow_rate,
+                        vehicle.pcu_volume,
+                        vehicle.min_speed,
+                        vehicle.max_speed,
+                        vehicle.median_speed,
+                        vehicle.p85_speed,
+                        vehicle.std_speed,
+                    ])?;
+                }
+            }
+        }
+        tx.commit()
+    }
+    // Removes periods which are out of retention if the last removal was at least CLEANUP_PERIOD_SECONDS ago.
+    // Returns number of removed periods
+    pub fn cleanup(&mut self, now: i64) -> Result<usize, rusqlite::Error> {
+        if now - self.last_cleanup < CLEANUP_PERIOD_SECONDS {
+            return Ok(0);
+        }
+        let removed = self.remove_expired(now)?;
+        self.last_cleanup = now;
+        Ok(removed)
+    }
+    // Removes periods which are out of retention. Returns number of removed periods
+    pub fn remove_expired(&mut self, now: i64) -> Result<usize, rusqlite::Error> {
+        if self.retention_seconds <= 0 {
+            return Ok(0);
+        }
+        let threshold = now - self.retention_seconds;
+        let tx = self.connection.transaction()?;
+        tx.execute("DELETE FROM vehicle_statistics WHERE period_id IN (SELECT id FROM zone_periods WHERE period_end < ?1)", params![threshold])?;
+        let removed = tx.execute("DELETE FROM zone_periods WHERE period_end < ?1", params![threshold])?;
+        tx.commit()?;
+        Ok(removed)
+    }
+    // Adds reason to the stored periods which end after the given time (UTC Unix timestamp). Returns number of updated periods
+    pub fn mark_invalid(&mut self, reason: &str, since: i64) -> Result<usize, rusqlite::Error> {
+        self.connection.execute(
+            "UPDATE zone_periods SET invalid_reasons = CASE WHEN invalid_reasons = '' THEN ?1 ELSE invalid_reasons || ',' || ?1 END
+            WHERE period_end > ?2 AND instr(',' || invalid_reasons || ',', ',' || ?1 || ',') = 0",
+            params![reason, since],
+        )
+    }
+    // Returns total number of matched periods and the requested page of them ordered by period start and zone
+    pub fn query(&self, query: &HistoryQuery) -> Result<(u64, Vec<HistoryRecord>), rusqlite::Error> {
+        let filter = "period_start >= ?1 AND period_start < ?2 AND (?3 IS NULL OR zone_id = ?3) AND (?7 IS NULL OR interval_milliseconds = ?7)
+            AND (?4 IS NULL OR EXISTS (SELECT 1 FROM vehicle_statistics v WHERE v.period_id = zone_periods.id AND v.classname = ?4))";
+        let total: i64 = self.connection.query_row(
+            &format!("SELECT COUNT(*) FROM zone_periods WHERE {}", filter.replace("?7", "?5")),
+            params![query.from, query.to, query.zone_id, query.classname, query.interval_milliseconds],
+            |row| row.get(0),
+        )?;
+        let mut select_periods = self.connection.prepare(&format!(
+            "SELECT id, zone_id, lane_number, lane_direction, group_name, period_start, period_end, flow_rate, pcu_volume, pcu_flow_rate, density, occupancy_percent, max_queue_length, avg_queue_length, level_of_service, invalid_reasons, interval_milliseconds
+            FROM zone_periods WHERE {} ORDER BY period_start, interval_milliseconds, zone_id, id LIMIT ?5 OFFSET ?6",
+            filter
+        ))?;
+        let mut select_vehicles = self.connection.prepare_cached(
+            "SELECT classname, avg_speed, sum_intensity, flow_rate, pcu_volume, min_speed, max_speed, median_speed, p85_speed, std_speed
+            FROM vehicle_statistics WHERE period_id = ?1 AND (?2 IS NULL OR classname = ?2) ORDER BY classname",
+        )?;
+        let mut rows = select_periods.query(params![query.from, query.to, query.zone_id, query.classname, query.limit, query.offset, query.interval_milliseconds])?;
+        let mut records = vec![];
+        while let Some(row) = rows.next()? {
+            let period_id: i64 = row.get(0)?;
+            let invalid_reasons: String = row.get(15)?;
+            let vehicles = select_vehicles
+                .query_map(params![period_id, query.classname], |vehicle| {
+                    Ok(HistoryVehicleRecord {
+                        classname: vehicle.get(0)?,
+                        avg_speed: vehicle.get(1)?,
+                        sum_intensity: vehicle.get(2)?,
+                        flow_rate: vehicle.get(3)?,
+                        pcu_volume: vehicle.get(4)?,
+                        min_speed: vehicle.get(5)?,
+                        max_speed: vehicle.get(6)?,
+                        median_speed: vehicle.get(7)?,
+                        p85_speed: vehicle.get(8)?,
+                        std_speed: vehicle.get(9)?,
+                    })
+                })?
+                .collect::<Result<Vec<_>, _>>()?;
+            records.push(HistoryRecord {
+                zone_id: row.get(1)?,
+                interval_milliseconds: row.get(16)?,
+                lane_number: row.get(2)?,
+                lane_direction: row.get(3)?,
+                group_name: row.get(4)?,
+                period_start: row.get(5)?,
+                period_end: row.get(6)?,
+                flow_rate: row.get(7)?,
+                pcu_volume: row.get(8)?,
+                pcu_flow_rate: row.get(9)?,
+                density: row.get(10)?,
+                occupancy_percent: row.get(11)?,
+                max_queue_length: row.get(12)?,
+                avg_queue_length: row.get(13)?,
+                level_of_service: row.get(14)?,
+                invalid_reasons: invalid_reasons.split(',').filter(|reason| !reason.is_empty()).map(|reason| reason.to_string()).collect(),
+                vehicles: vehicles,
+            });
+        }
+        Ok((total as u64, records))
+    }
+}
+
+// Writes to the history which are executed by the dedicated thread
+enum HistoryCommand {
+    Insert(Vec<HistoryRecord>),
+    MarkInvalid(String, i64),
+}
+
+// Handle of the thread which owns the writing connection of the history.
+// Disk I/O happens on that thread only, so the video pipeline never waits for it. Queries use their own read-only connections
+#[derive(Clone)]
+pub struct HistoryWriter {
+    path: String,
+    sender: mpsc::Sender<HistoryCommand>,
+}
+
+impl HistoryWriter {
+    pub fn start(storage: HistoryStorage, path: &str) -> Self {
+        let (sender, receiver) = mpsc::channel::<HistoryCommand>();
+        let mut storage = storage;
+        thread::spawn(move || {
+            for command in receiver {
+                match command {
+                    HistoryCommand::Insert(records) => {
+                        match storage.insert(&records) {
+                            Ok(_) => {}
+                            Err(err) => {
+                                println!("Can't save statistics to the history due the error: {}", err);
+                            }
+                        }
+                        match storage.cleanup(Utc::now().timestamp()) {
+                            Ok(_) => {}
+                            Err(err) => {
+                                println!("Can't remove expired statistics from the history due the error: {}", err);
+                            }
+                        }
+                    }
+                    HistoryCommand::MarkInvalid(reason, since) => match storage.mark_invalid(&reason, since) {
+                        Ok(_) => {}
+                        Err(err) => {
+                            println!("Can't mark statistics in the history as invalid due the error: {}", err);
+                        }
+                    },
+                }
+            }
+        });
+        HistoryWriter {
+            path: path.to_string(),
+            sender: sender,
+        }
+    }
+    // Queues records for saving. Returns immediately
+    pub fn insert(&self, records: Vec<HistoryRecord>) {
+        if records.len() == 0 {
+            return;
+        }
+        if self.sender.send(HistoryCommand::Insert(records)).is_err() {
+            println!("Can't save statistics to the history: writer has been stopped");
+        }
+    }
+    // Queues marking of the stored periods as invalid (see HistoryStorage::mark_invalid). Returns immediately
+    pub fn mark_invalid(&self, reason: &str, since: i64) {
+        if self.sender.send(HistoryCommand::MarkInvalid(reason.to_string(), since)).is_err() {
+            println!("Can't mark statistics in the history as invalid: writer has been stopped");
+        }
+    }
+    pub fn open_reader(&self) -> Result<HistoryStorage, rusqlite::Error> {
+        HistoryStorage::open_read_only(&self.path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    fn record(zone_id: &str, period_start: i64, classnames: &[&str]) -> HistoryRecord {
+        HistoryRecord {
+            zone_id: zone_id.to_string(),
+            interval_milliseconds: 60000,
+            lane_number: 0,
+            lane_direction: 0,
+            group_name: "".to_string(),
+            period_start: period_start,
+            period_end: period_start + 60,
+            flow_rate: 120.0,
+            pcu_volume: 2.0,
+            pcu_flow_rate: 120.0,
+            density: -1.0,
+            occupancy_percent: 12.5,
+            max_queue_length: 0.0,
+            avg_queue_length: 0.0,
+            level_of_service: "B".to_string(),
+            invalid_reasons: if period_start == 60 { vec!["blurred".to_string(), "frozen".to_string()] } else { vec![] },
+            vehicles: classnames
+                .iter()
+                .map(|classname| HistoryVehicleRecord {
+                    classname: classname.to_string(),
+                    avg_speed: 40.0,
+                    sum_intensity: 1,
+                    flow_rate: 60.0,
+                    pcu_volume: 1.0,
+                    min_speed: 35.0,
+                    max_speed: 45.0,
+                    median_speed: 40.0,
+                    p85_speed: 44.0,
+                    std_speed: 3.0,
+                })
+                .collect(),
+        }
+    }
+    #[test]
+    fn test_history_storage() {
+        let mut storage = HistoryStorage::with_connection(Connection::open_in_memory().unwrap(), 1).unwrap();
+        let records = vec![
+            record("dir_0_lane_0", 0, &["car", "truck"]),
+            record("dir_0_lane_1", 0, &["car"]),
+            record("dir_0_lane_0", 60, &["bus", "car"]),
+            record("dir_0_lane_1", 60, &[]),
+            record("dir_0_lane_0", 120, &["car"]),
+        ];
+        storage.insert(&records).unwrap();
+        // Periods of the other aggregation interval
+        let mut hourly = record("dir_0_lane_0", 0, &["car"]);
+        hourly.interval_milliseconds = 3600000;
+        hourly.period_end = 3600;
+        storage.insert(&[hourly.clone()]).unwrap();
+        let mut query = HistoryQuery {
+            from: 0,
+            to: 120,
+            zone_id: None,
+            interval_milliseconds: Some(3600000),
+            classname: None,
+            limit: 100,
+            offset: 0,
+        };
+        let (total, found) = storage.query(&query).unwrap();
+        assert_eq!(total, 1);
+        assert_eq!(found, vec![hourly]);
+        query.interval_milliseconds = Some(60000);
+        let (total, found) = storage.query(&query).unwrap();
+        assert_eq!(total, 4);
+        assert_eq!(found, records[..4].to_vec());
+        // Pagination
+        query.limit = 2;
+        query.offset = 3;
+        let (total, found) = storage.query(&query).unwrap();
+        assert_eq!(total, 4);
+        assert_eq!(found, vec![records[3].clone()]);
+        // Filter by zone and vehicle type
+        query.limit = 100;
+        query.offset = 0;
+        query.zone_id = Some("dir_0_lane_0".to_string());
+        query.classname = Some("truck".to_string());
+        let (total, found) = storage.query(&query).unwrap();
+        assert_eq!(total, 1);
+        assert_eq!(found[0].vehicles.len(), 1);
+        assert_eq!(found[0].vehicles[0].classname, "truck");
+        // Tampering which started before the end of the stored period
+        assert_eq!(storage.mark_invalid("frozen", 110).unwrap(), 2);
+        assert_eq!(storage.mark_invalid("frozen", 110).unwrap(), 0);
+        query.zone_id = None;
+        query.classname = None;
+        query.from = 60;
+        query.to = 1000;
+        let (_, found) = storage.query(&query).unwrap();
+        assert_eq!(found[0].invalid_reasons, vec!["blurred".to_string(), "frozen".to_string()]);
+        assert_eq!(found[2].invalid_reasons, vec!["frozen".to_string()]);
+        query.from = 0;
+        // Retention is one day
+        assert_eq!(storage.cleanup(86400 + 150).unwrap(), 4);
+        // Expired periods are removed not more often than once an hour
+        assert_eq!(storage.cleanup(86400 + 150 + 1800).unwrap(), 0);
+        query.interval_milliseconds = None;
+        query.to = 1000;
+        query.zone_id = None;
+        query.classname = None;
+        let (total, found) = storage.query(&query).unwrap();
+        assert_eq!(total, 2);
+        // Hourly period starts earlier
+        assert_eq!(found[0].interval_milliseconds, 3600000);
+        assert_eq!(found[1].period_start, 120);
+        let vehicles_left: i64 = storage.connection.query_row("SELECT COUNT(*) FROM vehicle_statistics", [], |row| row.get(0)).unwrap();
+        assert_eq!(vehicles_left, 2);
+    }
+    #[test]
+    fn test_history_writer() {
+        let path = std::env::temp_dir().join(format!("history_{}.db", uuid::Uuid::new_v4()));
+        let path = path.to_str().unwrap().to_string();
+        let writer = HistoryWriter::start(HistoryStorage::open(&path, 0).unwrap(), &path);
+        writer.insert(vec![record("dir_0_lane_0", 0, &["car"]), record("dir_0_lane_1", 0, &[])]);
+        writer.mark_invalid("frozen", 30);
+        let query = HistoryQuery {
+            from: 0,
+            to: 1000,
+            zone_id: None,
+            interval_milliseconds: None,
+            classname: None,
+            limit: 100,
+            offset: 0,
+        };
+        // Writes are asynchronous, so reader waits for them
+        let mut found = vec![];
+        for _ in 0..100 {
+            let (_, records) = writer.open_reader().unwrap().query(&query).unwrap();
+            if records.len() == 2 && records.iter().all(|record| record.invalid_reasons.len() == 1) {
+                found = records;
+                break;
+            }
+            thread::sleep(Duration::from_millis(20));
+        }
+        assert_eq!(found.len(), 2);
+        assert_eq!(found[0].invalid_reasons, vec!["frozen".to_string()]);
+        drop(writer);
+        for suffix in ["", "-wal", "-shm"].iter() {
+            let _ = std::fs::remove_file(format!("{}{}", path, suffix));
+        }
+    }
+}
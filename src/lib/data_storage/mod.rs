@@ -1,3 +1,6 @@
 mod data_storage;
+mod od_matrix;
+mod violations;
+mod history;
 
-pub use self::{data_storage::*};
\ No newline at end of file
+pub use self::{data_storage::*, od_matrix::*, violations::*, history::*};
@@ -0,0 +1,84 @@
+use std::collections::HashMap;
+
+use chrono::{DateTime, TimeZone, Utc};
+
+// Pair of zones identifiers: where an object has been seen first and where it has been seen last
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ODPair {
+    pub origin: String,
+    pub destination: String,
+}
+
+type ODCounts = HashMap<ODPair, HashMap<String, u32>>;
+
+// Origin-destination (turning movements) matrix between zones
+#[derive(Debug)]
+pub struct ODMatrix {
+    pub period_start: DateTime<Utc>,
+    pub period_end: DateTime<Utc>,
+    // Aggregated data for the last finished period. Key: OD pair; Value: number of objects for each vehicle type
+    pub data: ODCounts,
+    // Data for the current period. It is moved to `data` when period is finished
+    accumulated: ODCounts,
+}
+
+impl ODMatrix {
+    pub fn default() -> Self {
+        ODMatrix {
+            period_start: TimeZone::with_ymd_and_hms(&Utc, 1970, 1, 1, 0, 0, 0).unwrap(),
+            period_end: TimeZone::with_ymd_and_hms(&Utc, 1970, 1, 1, 0, 0, 0).unwrap(),
+            data: HashMap::new(),
+            accumulated: HashMap::new(),
+        }
+    }
+    // Registers sequence of zones which has been passed by the object.
+    // Paths with less than two zones are ignored since destination is unknown for them
+    pub fn register_path(&mut self, zones_path: &[String], classname: String) {
+        if zones_path.len() < 2 {
+            return;
+        }
+        let od_pair = ODPair {
+            origin: zones_path[0].clone(),
+            destination: zones_path[zones_path.len() - 1].clone(),
+        };
+        let counts = self.accumulated.entry(od_pair).or_insert_with(HashMap::new);
+        *counts.entry(classname).or_insert(0) += 1;
+    }
+    pub fn update_statistics(&mut self, _period_start: DateTime<Utc>, _period_end: DateTime<Utc>) {
+        self.period_start = _period_start;
+        self.period_end = _period_end;
+        self.data = std::mem::take(&mut self.accumulated);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn test_register_path() {
+        let mut od_matrix = ODMatrix::default();
+        let path_ab = vec!["a".to_string(), "b".to_string()];
+        let path_acb = vec!["a".to_string(), "c".to_string(), "b".to_string()];
+        let path_single = vec!["a".to_string()];
+        od_matrix.register_path(&path_ab, "car".to_string());
+        od_matrix.register_path(&path_acb, "car".to_string());
+        od_matrix.register_path(&path_ab, "bus".to_string());
+        od_matrix.register_path(&path_single, "car".to_string());
+        assert_eq!(od_matrix.data.len(), 0);
+
+        od_matrix.update_statistics(Utc::now(), Utc::now());
+        assert_eq!(od_matrix.data.len(), 1);
+        let counts = od_matrix
+            .data
+            .get(&ODPair {
+                origin: "a".to_string(),
+                destination: "b".to_string(),
+            })
+            .unwrap();
+        assert_eq!(counts.get("car"), Some(&2));
+        assert_eq!(counts.get("bus"), Some(&1));
+
+        od_matrix.update_statistics(Utc::now(), Utc::now());
+        assert_eq!(od_matrix.data.len(), 0);
+    }
+}
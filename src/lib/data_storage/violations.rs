@@ -0,0 +1,84 @@
+use std::collections::VecDeque;
+use std::sync::Arc;
+
+use chrono::{DateTime, Utc};
+use uuid::Uuid;
+
+// Maximum number of violation events (with snapshots) kept in memory
+pub const VIOLATIONS_CAPACITY: usize = 100;
+
+// Appearance of the object of disallowed class in the zone
+#[derive(Debug, Clone)]
+pub struct ViolationEvent {
+    pub id: String,
+    pub zone_id: String,
+    pub object_id: String,
+    pub classname: String,
+    pub event_time: DateTime<Utc>,
+    // JPEG-encoded frame where violation has been registered. Shared between events of the same frame
+    pub snapshot: Arc<[u8]>,
+    published: bool,
+}
+
+// Bounded storage for the last violation events. The oldest events are dropped when capacity is exceeded
+#[derive(Debug)]
+pub struct ViolationsStorage {
+    events: VecDeque<ViolationEvent>,
+}
+
+impl ViolationsStorage {
+    pub fn default() -> Self {
+        ViolationsStorage {
+            events: VecDeque::with_capacity(VIOLATIONS_CAPACITY),
+        }
+    }
+    // Returns identifier of the new event
+    pub fn register(&mut self, zone_id: String, object_id: String, classname: String, snapshot: Arc<[u8]>) -> String {
+        if self.events.len() >= VIOLATIONS_CAPACITY {
+            self.events.pop_front();
+        }
+        let id = Uuid::new_v4().to_string();
+        self.events.push_back(ViolationEvent {
+            id: id.clone(),
+            zone_id: zone_id,
+            object_id: object_id,
+            classname: classname,
+            event_time: Utc::now(),
+            snapshot: snapshot,
+            published: false,
+        });
+        id
+    }
+    pub fn get_events(&self) -> Vec<&ViolationEvent> {
+        self.events.iter().collect()
+    }
+    pub fn get_event(&self, event_id: &str) -> Option<&ViolationEvent> {
+        self.events.iter().find(|event| event.id == event_id)
+    }
+    // Returns events which have not been published yet and marks them as published
+    pub fn take_unpublished(&mut self) -> Vec<ViolationEvent> {
+        let mut ans = vec![];
+        for event in self.events.iter_mut().filter(|event| !event.published) {
+            event.published = true;
+            ans.push(event.clone());
+        }
+        ans
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn test_violations_storage() {
+        let mut storage = ViolationsStorage::default();
+        let first_id = storage.register("dir_0_lane_0".to_string(), "a".to_string(), "car".to_string(), Arc::from(vec![]));
+        for _ in 0..VIOLATIONS_CAPACITY {
+            storage.register("dir_0_lane_0".to_string(), "b".to_string(), "truck".to_string(), Arc::from(vec![]));
+        }
+        assert_eq!(storage.get_events().len(), VIOLATIONS_CAPACITY);
+        assert!(storage.get_event(&first_id).is_none());
+        assert_eq!(storage.take_unpublished().len(), VIOLATIONS_CAPACITY);
+        assert_eq!(storage.take_unpublished().len(), 0);
+    }
+}
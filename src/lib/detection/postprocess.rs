@@ -5,6 +5,9 @@ use chrono::{DateTime, Utc};
 use mot_rs::mot::SimpleBlob;
 use mot_rs::utils::{Point, Rect};
 
+use crate::lib::masks::{is_masked, ExclusionMask};
+use crate::lib::spatial::mapping::FrameMapping;
+
 #[derive(Debug)]
 pub struct Detections {
     pub blobs: Vec<SimpleBlob>,
@@ -21,6 +24,8 @@ pub fn process_yolo_detections(
     max_points_in_track: usize,
     classes: &Vec<String>,
     filtered_classes: &'static [&'static str],
+    exclusion_masks: &[ExclusionMask],
+    mapping: &FrameMapping,
     dt: f32,
 ) -> Detections {
     let mut class_names = vec![];
@@ -56,7 +61,13 @@ pub fn process_yolo_detections(
                 }
                 if class_index > -1 && score > 0. {
                     let class_name = classes[class_index as usize].clone();
-                    if filtered_classes.contains(&&*class_name) {
+                    // Anchor point of the detection is the bottom center of the bounding box.
+                    // Masks are defined in the same space as road lanes
+                    if !filtered_classes.contains(&&*class_name) {
+                        continue;
+                    }
+                    let (anchor_x, anchor_y) = mapping.to_zones(center_x, center_y + height / 2.0);
+                    if !is_masked(exclusion_masks, anchor_x, anchor_y) {
                         let left = center_x - width / 2.0;
                         let top = center_y - height / 2.0;
                         let bbox = RectCV::new(
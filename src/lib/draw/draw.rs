@@ -2,17 +2,52 @@ use opencv::{
     core::Mat,
     core::Rect,
     core::Point,
+    core::Point2f,
     core::Scalar,
     imgproc::LINE_8,
     imgproc::LINE_4,
     imgproc::FONT_HERSHEY_SIMPLEX,
     imgproc::circle,
+    imgproc::line,
     imgproc::rectangle,
     imgproc::put_text,
 };
 
+use std::collections::HashMap;
+
+use crate::lib::spatial::mapping::FrameMapping;
+use crate::lib::spatial::SpatialConverter;
 use crate::lib::tracker::Tracker;
 
+// Default distance between lines of the metric grid (meters)
+pub const METRIC_GRID_STEP: f32 = 5.0;
+// Grid is not drawn further than this distance from the origin of the ground plane (meters),
+// since cells near the horizon collapse into few pixels anyway
+const METRIC_GRID_MAX_RADIUS: f64 = 150.0;
+// Number of pixels probed along each side of the region to find extent of the visible ground
+const METRIC_GRID_PROBES: i32 = 16;
+// Projected points further than this from the frame are considered as degenerate
+const METRIC_GRID_MAX_PIXELS: f32 = 100000.0;
+// Grid is coarsened if it would have more lines than this along any axis
+const METRIC_GRID_MAX_LINES: f64 = 50.0;
+
+// Metric grid overlay for visual verification of calibration
+#[derive(Debug, Clone, Copy)]
+pub struct MetricGrid {
+    pub enabled: bool,
+    // Distance between grid lines and between ticks along zones' skeletons (meters)
+    pub step_meters: f32,
+}
+
+impl MetricGrid {
+    pub fn default() -> Self {
+        MetricGrid {
+            enabled: false,
+            step_meters: METRIC_GRID_STEP,
+        }
+    }
+}
+
 pub fn draw_trajectories(img: &mut Mat, tracker: &Tracker, color: Scalar, inv_color: Scalar) {
     for (_, object) in tracker.engine.objects.iter() {
         let mut color_choose = color;
@@ -67,9 +102,11 @@ pub fn draw_identifiers(img: &mut Mat, tracker: &Tracker, color: Scalar, inv_col
 
 pub fn draw_speeds(img: &mut Mat, tracker: &Tracker, color: Scalar, inv_color: Scalar) {
     for (object_id, object_extra) in tracker.objects_extra.iter() {
-        let spatial_info = match object_extra.spatial_info {
-            Some(ref spatial_info) => spatial_info,
-            None => continue,
+        // Zone's speed is preferred. Camera-wide ground plane is used outside of zones
+        let speed = match (&object_extra.spatial_info, &object_extra.ground_info) {
+            (Some(spatial_info), _) => spatial_info.speed,
+            (None, Some(ground_info)) => ground_info.speed,
+            (None, None) => continue,
         };
         let object = tracker.engine.objects.get(&object_id).unwrap();
         let mut color_choose = color;
@@ -78,7 +115,7 @@ pub fn draw_speeds(img: &mut Mat, tracker: &Tracker, color: Scalar, inv_color: S
         }
         let bbox = object.get_bbox();
         let anchor = Point::new(bbox.x.floor() as i32 + 2, bbox.y.floor() as i32 + 20);
-        match put_text(img, &spatial_info.speed.to_string(), anchor, FONT_HERSHEY_SIMPLEX, 0.5, color_choose, 2, LINE_8, false) {
+        match put_text(img, &speed.to_string(), anchor, FONT_HERSHEY_SIMPLEX, 0.5, color_choose, 2, LINE_8, false) {
             Ok(_) => {},
             Err(err) => {
                 println!("Can't display velocity of object due the error {:?}", err);
@@ -87,13 +124,15 @@ pub fn draw_speeds(img: &mut Mat, tracker: &Tracker, color: Scalar, inv_color: S
     }
 }
 
-pub fn draw_projections(img: &mut Mat, tracker: &Tracker, color: Scalar, inv_color: Scalar) {
+// Projected points are evaluated in the space of road lanes, so they are mapped back to the displayed frame
+pub fn draw_projections(img: &mut Mat, tracker: &Tracker, mapping: &FrameMapping, color: Scalar, inv_color: Scalar) {
     for (object_id, object_extra) in tracker.objects_extra.iter() {
         let spatial_info = match object_extra.spatial_info {
             Some(ref spatial_info) => spatial_info,
             None => continue,
         };
-        let cv_pt = Point::new(spatial_info.last_x_projected.floor() as i32, spatial_info.last_y_projected.floor() as i32);
+        let pt = mapping.to_display(&[Point2f::new(spatial_info.last_x_projected, spatial_info.last_y_projected)])[0];
+        let cv_pt = Point::new(pt.x.floor() as i32, pt.y.floor() as i32);
         match circle(img, cv_pt, 5, Scalar::from((255.0, 255.0, 0.0)), 2, LINE_8, 0) {
             Ok(_) => {},
             Err(err) => {
@@ -108,7 +147,7 @@ pub fn draw_projections(img: &mut Mat, tracker: &Tracker, color: Scalar, inv_col
         // }
         // let bbox = object.get_bbox();
         // let anchor = Point::new(bbox.x.floor() as i32 + 2, bbox.y.floor() as i32 + 20);
-        // match put_text(img, &spatial_info.speed.to_string(), anchor, FONT_HERSHEY_SIMPLEX, 0.5, color_choose, 2, LINE_8, false) {
+        // match put_text(img, &speed.to_string(), anchor, FONT_HERSHEY_SIMPLEX, 0.5, color_choose, 2, LINE_8, false) {
         //     Ok(_) => {},
         //     Err(err) => {
         //         println!("Can't display velocity of object due the error {:?}", err);
@@ -117,6 +156,121 @@ pub fn draw_projections(img: &mut Mat, tracker: &Tracker, color: Scalar, inv_col
     }
 }
 
+// Segments of the metric grid projected into the video frame. Projection is costly, so segments are evaluated again
+// only when calibration, region or step of the grid changes
+pub struct MetricGridCache {
+    // Key: zone identifier (empty string for the camera-wide calibration)
+    entries: HashMap<String, MetricGridEntry>,
+}
+
+struct MetricGridEntry {
+    transform: Vec<f64>,
+    region: Rect,
+    step_meters: f32,
+    segments: Vec<(Point2f, Point2f)>,
+}
+
+impl MetricGridCache {
+    pub fn default() -> Self {
+        MetricGridCache {
+            entries: HashMap::new(),
+        }
+    }
+    // Draws grid of the ground plane with given step (meters) projected back into the region of the video frame.
+    // Cached segments are in the space of road lanes, so they are mapped to the displayed frame on each call
+    pub fn draw(&mut self, img: &mut Mat, key: &str, converter: &SpatialConverter, region: Rect, step_meters: f32, mapping: &FrameMapping, color: Scalar) {
+        let transform = converter.get_transform_values();
+        let outdated = match self.entries.get(key) {
+            Some(entry) => entry.transform != transform || entry.region != region || entry.step_meters != step_meters,
+            None => true,
+        };
+        if outdated {
+            self.entries.insert(key.to_string(), MetricGridEntry {
+                segments: project_metric_grid(converter, region, step_meters),
+                transform: transform,
+                region: region,
+                step_meters: step_meters,
+            });
+        }
+        let segments = &self.entries[key].segments;
+        let ends = mapping.to_display(&segments.iter().flat_map(|(a, b)| vec![*a, *b]).collect::<Vec<Point2f>>());
+        for segment in ends.chunks(2) {
+            let (a, b) = (Point::new(segment[0].x as i32, segment[0].y as i32), Point::new(segment[1].x as i32, segment[1].y as i32));
+            match line(img, a, b, color, 1, LINE_8, 0) {
+                Ok(_) => {},
+                Err(err) => {
+                    println!("Can't draw line of metric grid due the error {:?}", err);
+                    return;
+                }
+            };
+        }
+    }
+    // Forgets grids which keys are not in the given list (e.g. for removed zones)
+    pub fn retain(&mut self, keys: &[&str]) {
+        self.entries.retain(|key, _| keys.contains(&key.as_str()));
+    }
+}
+
+fn project_metric_grid(converter: &SpatialConverter, region: Rect, step_meters: f32) -> Vec<(Point2f, Point2f)> {
+    let mut segments = vec![];
+    if step_meters <= 0.0 || region.width <= 0 || region.height <= 0 {
+        return segments;
+    }
+    // Extent of the visible ground on the local tangent plane
+    let mut min_xy = (f64::MAX, f64::MAX);
+    let mut max_xy = (f64::MIN, f64::MIN);
+    for i in 0..=METRIC_GRID_PROBES {
+        for j in 0..=METRIC_GRID_PROBES {
+            let px = region.x as f32 + region.width as f32 * i as f32 / METRIC_GRID_PROBES as f32;
+            let py = region.y as f32 + region.height as f32 * j as f32 / METRIC_GRID_PROBES as f32;
+            if !converter.is_on_ground(px, py) {
+                continue;
+            }
+            let (x, y) = match converter.transform_to_local(px, py) {
+                Some(xy) => xy,
+                None => continue,
+            };
+            let x = x.max(-METRIC_GRID_MAX_RADIUS).min(METRIC_GRID_MAX_RADIUS);
+            let y = y.max(-METRIC_GRID_MAX_RADIUS).min(METRIC_GRID_MAX_RADIUS);
+            min_xy = (min_xy.0.min(x), min_xy.1.min(y));
+            max_xy = (max_xy.0.max(x), max_xy.1.max(y));
+        }
+    }
+    if min_xy.0 > max_xy.0 || min_xy.1 > max_xy.1 {
+        return segments;
+    }
+    // Step is multiplied by the whole number, so lines of the coarsened grid are the part of the requested one
+    let extent = f64::max(max_xy.0 - min_xy.0, max_xy.1 - min_xy.1);
+    let step = step_meters as f64 * f64::max((extent / step_meters as f64 / METRIC_GRID_MAX_LINES).ceil(), 1.0);
+    let (x_from, x_to) = ((min_xy.0 / step).floor() as i64, (max_xy.0 / step).ceil() as i64);
+    let (y_from, y_to) = ((min_xy.1 / step).floor() as i64, (max_xy.1 / step).ceil() as i64);
+    // Lines are split into cells, so the parts behind the horizon could be skipped
+    for k in x_from..=x_to {
+        for n in y_from..y_to {
+            let x = k as f64 * step;
+            segments.extend(project_ground_segment(converter, (x, n as f64 * step), (x, (n + 1) as f64 * step)));
+        }
+    }
+    for n in y_from..=y_to {
+        for k in x_from..x_to {
+            let y = n as f64 * step;
+            segments.extend(project_ground_segment(converter, (k as f64 * step, y), ((k + 1) as f64 * step, y)));
+        }
+    }
+    segments
+}
+
+fn project_ground_segment(converter: &SpatialConverter, a: (f64, f64), b: (f64, f64)) -> Option<(Point2f, Point2f)> {
+    let (a, b) = match (converter.transform_local_to_pixels(a.0, a.1), converter.transform_local_to_pixels(b.0, b.1)) {
+        (Some(a), Some(b)) => (a, b),
+        _ => return None,
+    };
+    if [a.0, a.1, b.0, b.1].iter().any(|v| !v.is_finite() || v.abs() > METRIC_GRID_MAX_PIXELS) {
+        return None;
+    }
+    Some((Point2f::new(a.0, a.1), Point2f::new(b.0, b.1)))
+}
+
 pub fn invert_color(color: &Scalar) -> Scalar {
     let b = color[0];
     let g = color[1];
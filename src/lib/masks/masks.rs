@@ -0,0 +1,65 @@
+use opencv::core::Point2f;
+use uuid::Uuid;
+
+use crate::lib::zones::polygon_contains_point;
+
+// Area where detections should be ignored (e.g. parking lots, billboards, sidewalks)
+#[derive(Debug, Clone)]
+pub struct ExclusionMask {
+    pub id: String,
+    pixel_coordinates: Vec<Point2f>,
+}
+
+impl ExclusionMask {
+    pub fn new(pixel_coordinates: Vec<Point2f>) -> Self {
+        ExclusionMask {
+            id: Uuid::new_v4().to_string(),
+            pixel_coordinates: pixel_coordinates,
+        }
+    }
+    pub fn get_id(&self) -> String {
+        self.id.clone()
+    }
+    pub fn get_pixel_coordinates(&self) -> Vec<Point2f> {
+        self.pixel_coordinates.clone()
+    }
+    pub fn contains_point(&self, x: f32, y: f32) -> bool {
+        polygon_contains_point(&self.pixel_coordinates, x, y)
+    }
+    pub fn scale_geom(&mut self, scale_factor_x: f32, scale_factor_y: f32) {
+        for pair in self.pixel_coordinates.iter_mut() {
+            pair.x = (pair.x * scale_factor_x).floor();
+            pair.y = (pair.y * scale_factor_y).floor();
+        }
+    }
+}
+
+// Checks if any of masks contains given point
+pub fn is_masked(masks: &[ExclusionMask], x: f32, y: f32) -> bool {
+    masks.iter().any(|mask| mask.contains_point(x, y))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn test_is_masked() {
+        let masks = vec![
+            ExclusionMask::new(vec![
+                Point2f::new(0.0, 0.0),
+                Point2f::new(10.0, 0.0),
+                Point2f::new(10.0, 10.0),
+                Point2f::new(0.0, 10.0),
+            ]),
+            ExclusionMask::new(vec![
+                Point2f::new(20.0, 20.0),
+                Point2f::new(30.0, 20.0),
+                Point2f::new(25.0, 30.0),
+            ]),
+        ];
+        assert_eq!(is_masked(&masks, 5.0, 5.0), true);
+        assert_eq!(is_masked(&masks, 25.0, 22.0), true);
+        assert_eq!(is_masked(&masks, 15.0, 15.0), false);
+        assert_eq!(is_masked(&[], 5.0, 5.0), false);
+    }
+}
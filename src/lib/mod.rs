@@ -1,9 +1,11 @@
 pub mod detection;
 pub mod zones;
+pub mod masks;
 pub mod spatial;
 pub mod tracker;
 pub mod draw;
 pub mod data_storage;
 pub mod mjpeg_streaming;
 pub mod rest_api;
-pub mod publisher;
\ No newline at end of file
+pub mod publisher;
+pub mod tamper;
\ No newline at end of file
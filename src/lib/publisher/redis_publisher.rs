@@ -4,9 +4,10 @@ extern crate redis;
 use redis::{ Client, Commands };
 use std::sync::{ Arc, RwLock };
 use std::error::Error;
-use std::collections::HashMap;
 use crate::lib::publisher::RedisMessage;
-use crate::lib::rest_api::zones_stats::{ AllZonesStats, ZoneStats, VehicleTypeParameters };
+use crate::lib::rest_api::zones_stats::AllZonesStats;
+use crate::lib::rest_api::zones_od_matrix::AllZonesODMatrix;
+use crate::lib::rest_api::zones_violations::{ AllViolations, ViolationInfo };
 use crate::lib::data_storage::ThreadedDataStorage;
 use std::thread;
 use std::time::Duration as STDDuration;
@@ -52,31 +53,11 @@ impl RedisConnection {
         println!("...Success");
         Ok(())
     }
-    pub fn push_statistics(&self) {
+    // Publishes statistics of the last finished period of the aggregation interval (milliseconds)
+    pub fn push_statistics(&self, interval_milliseconds: i64) {
         let ds_guard = self.data_storage.read().expect("DataStorage is poisoned [RWLock]");
         let zones = ds_guard.zones.read().expect("Spatial data is poisoned [RWLock]");
-        let mut prepared_message = AllZonesStats {
-            equipment_id: ds_guard.id.clone(),
-            data: vec![]
-        };
-        for (_, v) in zones.iter() {
-            let element = v.lock().expect("Mutex poisoned");
-            let mut stats = ZoneStats {
-                lane_number: element.road_lane_num,
-                lane_direction: element.road_lane_direction,
-                period_start: element.statistics.period_start,
-                period_end: element.statistics.period_end,
-                statistics: HashMap::new()
-            };
-            for (vehicle_type, statistics) in element.statistics.vehicles_data.iter() {
-                stats.statistics.insert(vehicle_type.to_string(), VehicleTypeParameters {
-                    estimated_avg_speed: statistics.avg_speed,
-                    estimated_sum_intensity: statistics.sum_intensity
-                });
-            }
-            drop(element);
-            prepared_message.data.push(stats);
-        }
+        let prepared_message = AllZonesStats::new(ds_guard.id.clone(), interval_milliseconds, &zones);
         drop(zones);
         drop(ds_guard);
         match self.publish(&prepared_message) {
@@ -86,6 +67,58 @@ impl RedisConnection {
             Ok(_) => {}
         };
     }
+    // Publishes origin-destination matrix of the last finished period of the aggregation interval (milliseconds)
+    pub fn push_od_matrix(&self, interval_milliseconds: i64) {
+        let ds_guard = self.data_storage.read().expect("DataStorage is poisoned [RWLock]");
+        let od_matrices = ds_guard.od_matrices.read().expect("OD matrix is poisoned [RWLock]");
+        let prepared_message = match od_matrices.get(&interval_milliseconds) {
+            Some(od_matrix) => AllZonesODMatrix::new(ds_guard.id.clone(), interval_milliseconds, od_matrix),
+            None => {
+                return;
+            }
+        };
+        drop(od_matrices);
+        drop(ds_guard);
+        match self.publish(&prepared_message) {
+            Err(_err) => {
+                println!("Errors while sending OD matrix to Redis: {}",_err);
+            }
+            Ok(_) => {}
+        };
+    }
+    pub fn push_violations(&self) {
+        let ds_guard = self.data_storage.read().expect("DataStorage is poisoned [RWLock]");
+        let mut violations = ds_guard.violations.write().expect("Violations storage is poisoned [RWLock]");
+        let prepared_message = AllViolations {
+            equipment_id: ds_guard.id.clone(),
+            data: violations.take_unpublished().iter().map(ViolationInfo::from).collect()
+        };
+        drop(violations);
+        drop(ds_guard);
+        if prepared_message.data.len() == 0 {
+            return;
+        }
+        match self.publish(&prepared_message) {
+            Err(_err) => {
+                println!("Errors while sending violations to Redis: {}",_err);
+            }
+            Ok(_) => {}
+        };
+    }
+}
+
+impl RedisMessage for AllZonesODMatrix {
+    fn prepare_string(&self) -> Result<String, Box<dyn Error>> {
+        let json = serde_json::to_string(self)?;
+        Ok(json)
+    }
+}
+
+impl RedisMessage for AllViolations {
+    fn prepare_string(&self) -> Result<String, Box<dyn Error>> {
+        let json = serde_json::to_string(self)?;
+        Ok(json)
+    }
 }
 
 impl RedisMessage for AllZonesStats {
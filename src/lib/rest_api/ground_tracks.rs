@@ -0,0 +1,58 @@
+use actix_web::{HttpResponse, web, Error};
+use serde::Serialize;
+use utoipa::ToSchema;
+
+use crate::lib::rest_api::APIStorage;
+
+/// Objects on the camera-wide ground plane for the last processed frame
+#[derive(Debug, Serialize, ToSchema)]
+pub struct AllGroundTracks {
+    /// Equipment identifier. Should match software configuration
+    #[schema(example = "1e23985f-1fa3-45d0-a365-2d8525a23ddd")]
+    pub equipment_id: String,
+    /// Set of tracked objects
+    pub data: Vec<GroundTrackInfo>
+}
+
+/// Position and speed of the tracked object
+#[derive(Debug, Serialize, ToSchema)]
+pub struct GroundTrackInfo {
+    /// Object identifier
+    #[schema(example = "8c0f4a3e-41f6-4f3a-9d6b-8f2e64c4ab7c")]
+    pub object_id: String,
+    /// Vehicle type
+    #[schema(example = "car")]
+    pub classname: String,
+    /// Position [longitude, latitude]
+    #[schema(example = json!([37.619001, 54.205674]))]
+    pub wgs84: [f64; 2],
+    /// Speed (km/h). Value "-1" indicates no data
+    #[schema(example = 42.5)]
+    pub speed: f32
+}
+
+#[utoipa::path(
+    get,
+    tag = "Statistics",
+    path = "/api/realtime/tracks",
+    responses(
+        (status = 200, description = "Positions and speeds of tracked objects on the camera-wide ground plane", body = AllGroundTracks)
+    )
+)]
+pub async fn all_ground_tracks(data: web::Data<APIStorage>) -> Result<HttpResponse, Error> {
+    let ds_guard = data.data_storage.read().expect("DataStorage is poisoned [RWLock]");
+    let tracks = ds_guard.ground_tracks.read().expect("Ground tracks are poisoned [RWLock]");
+    let mut ans = AllGroundTracks {
+        equipment_id: ds_guard.id.clone(),
+        data: tracks.iter().map(|track| GroundTrackInfo {
+            object_id: track.object_id.clone(),
+            classname: track.classname.clone(),
+            wgs84: [track.lon, track.lat],
+            speed: track.speed
+        }).collect()
+    };
+    drop(tracks);
+    drop(ds_guard);
+    ans.data.sort_by(|a, b| a.object_id.cmp(&b.object_id));
+    return Ok(HttpResponse::Ok().json(ans));
+}
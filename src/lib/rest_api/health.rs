@@ -0,0 +1,196 @@
+use actix_web::{HttpResponse, web, Error};
+use serde::Serialize;
+use utoipa::ToSchema;
+
+use crate::lib::rest_api::APIStorage;
+use crate::lib::spatial::stabilization::StabilizationHealth;
+use crate::lib::tamper::{TamperEvent, TamperStatus};
+
+/// Health metrics of the video input
+#[derive(Debug, Serialize, ToSchema)]
+pub struct HealthInfo {
+    /// Equipment identifier. Should match software configuration
+    #[schema(example = "1e23985f-1fa3-45d0-a365-2d8525a23ddd")]
+    pub equipment_id: String,
+    /// Camera shake compensation. Value "null" indicates that stabilization is disabled
+    pub stabilization: Option<StabilizationInfo>,
+    /// Camera drift and tamper detection. Value "null" indicates that tamper detection is disabled or no frame has been checked yet
+    pub tamper: Option<TamperInfo>
+}
+
+/// Last estimate of the camera shake relative to the reference frame
+#[derive(Debug, Serialize, ToSchema)]
+pub struct StabilizationInfo {
+    /// Whether the last frame has been registered to the reference one. Otherwise the previous estimate is used
+    #[schema(example = true)]
+    pub registered: bool,
+    /// Horizontal shift (pixels)
+    #[schema(example = 3.2)]
+    pub dx: f64,
+    /// Vertical shift (pixels)
+    #[schema(example = -1.7)]
+    pub dy: f64,
+    /// Rotation (degrees)
+    #[schema(example = 0.12)]
+    pub rotation_degrees: f64,
+    /// Uniform scale
+    #[schema(example = 1.001)]
+    pub scale: f64,
+    /// Largest shift of the frame corners (pixels)
+    #[schema(example = 4.1)]
+    pub displacement: f32,
+    /// Number of features detected on the last frame
+    #[schema(example = 950)]
+    pub features_num: usize,
+    /// Number of features matched with the reference frame
+    #[schema(example = 412)]
+    pub matches_num: usize,
+    /// Number of matches consistent with the estimate
+    #[schema(example = 287)]
+    pub inliers_num: usize,
+    /// UTC Unix timestamp of the last estimate
+    #[schema(example = 1693386819)]
+    pub updated_at: i64
+}
+
+impl From<StabilizationHealth> for StabilizationInfo {
+    fn from(health: StabilizationHealth) -> Self {
+        StabilizationInfo {
+            registered: health.registered,
+            dx: health.dx,
+            dy: health.dy,
+            rotation_degrees: health.rotation_degrees,
+            scale: health.scale,
+            displacement: health.displacement,
+            features_num: health.features_num,
+            matches_num: health.matches_num,
+            inliers_num: health.inliers_num,
+            updated_at: health.updated_at
+        }
+    }
+}
+
+/// Last measurements of the frame quality and active tamper conditions
+#[derive(Debug, Serialize, ToSchema)]
+pub struct TamperInfo {
+    /// Variance of Laplacian of the grayscale frame
+    #[schema(example = 312.5)]
+    pub sharpness: f32,
+    /// Mean intensity of the grayscale frame [0; 255]
+    #[schema(example = 118.3)]
+    pub brightness: f32,
+    /// Mean absolute difference with the previous checked frame. Value "-1" indicates no previous frame
+    #[schema(example = 6.4)]
+    pub difference: f32,
+    /// Largest shift of the frame corners relative to the reference scene (pixels). Value "-1" indicates no data
+    #[schema(example = 4.1)]
+    pub displacement: f32,
+    /// Whether frame has enough features, but they can't be matched with the reference scene
+    #[schema(example = false)]
+    pub scene_lost: bool,
+    /// Active tamper conditions: "displaced", "scene_changed", "blurred", "too_dark", "too_bright", "frozen"
+    #[schema(example = json!(["blurred"]))]
+    pub active: Vec<String>,
+    /// UTC Unix timestamp of the last check
+    #[schema(example = 1693386819)]
+    pub updated_at: i64
+}
+
+impl From<TamperStatus> for TamperInfo {
+    fn from(status: TamperStatus) -> Self {
+        TamperInfo {
+            sharpness: status.quality.sharpness,
+            brightness: status.quality.brightness,
+            difference: status.quality.difference,
+            displacement: status.quality.displacement,
+            scene_lost: status.quality.scene_lost,
+            active: status.active.iter().map(|kind| kind.as_str().to_string()).collect(),
+            updated_at: status.updated_at
+        }
+    }
+}
+
+/// Last tamper events
+#[derive(Debug, Serialize, ToSchema)]
+pub struct AllTamperEvents {
+    /// Equipment identifier. Should match software configuration
+    #[schema(example = "1e23985f-1fa3-45d0-a365-2d8525a23ddd")]
+    pub equipment_id: String,
+    /// Events from the oldest to the newest one
+    pub data: Vec<TamperEventInfo>
+}
+
+/// Tamper condition which lasted long enough
+#[derive(Debug, Serialize, ToSchema)]
+pub struct TamperEventInfo {
+    /// Event identifier
+    #[schema(example = "b7e0a1c2-4a57-4c5e-9d7e-3b1f0c6b2d11")]
+    pub id: String,
+    /// Kind of tampering: "displaced", "scene_changed", "blurred", "too_dark", "too_bright", "frozen"
+    #[schema(example = "displaced")]
+    pub kind: String,
+    /// UTC Unix timestamp when condition has been noticed for the first time
+    #[schema(example = 1693386819)]
+    pub started_at: i64,
+    /// UTC Unix timestamp when condition has gone. Value "-1" indicates that event is still active
+    #[schema(example = -1)]
+    pub finished_at: i64,
+    /// Last measured value: displacement (pixels), sharpness, brightness or frame difference depending on the kind
+    #[schema(example = 42.7)]
+    pub value: f32
+}
+
+impl From<&TamperEvent> for TamperEventInfo {
+    fn from(event: &TamperEvent) -> Self {
+        TamperEventInfo {
+            id: event.id.clone(),
+            kind: event.kind.as_str().to_string(),
+            started_at: event.started_at,
+            finished_at: event.finished_at,
+            value: event.value
+        }
+    }
+}
+
+#[utoipa::path(
+    get,
+    tag = "Health",
+    path = "/api/health",
+    responses(
+        (status = 200, description = "Health metrics of the video input", body = HealthInfo)
+    )
+)]
+pub async fn health_info(data: web::Data<APIStorage>) -> Result<HttpResponse, Error> {
+    let ds_guard = data.data_storage.read().expect("DataStorage is poisoned [RWLock]");
+    let stabilization = ds_guard.stabilization.read().expect("Stabilization is poisoned [RWLock]");
+    let tamper = ds_guard.tamper.read().expect("Tamper status is poisoned [RWLock]");
+    let ans = HealthInfo {
+        equipment_id: ds_guard.id.clone(),
+        stabilization: stabilization.clone().map(StabilizationInfo::from),
+        tamper: tamper.clone().map(TamperInfo::from)
+    };
+    drop(tamper);
+    drop(stabilization);
+    drop(ds_guard);
+    return Ok(HttpResponse::Ok().json(ans));
+}
+
+#[utoipa::path(
+    get,
+    tag = "Health",
+    path = "/api/health/tamper_events",
+    responses(
+        (status = 200, description = "Last tamper events", body = AllTamperEvents)
+    )
+)]
+pub async fn tamper_events(data: web::Data<APIStorage>) -> Result<HttpResponse, Error> {
+    let ds_guard = data.data_storage.read().expect("DataStorage is poisoned [RWLock]");
+    let tamper = ds_guard.tamper.read().expect("Tamper status is poisoned [RWLock]");
+    let ans = AllTamperEvents {
+        equipment_id: ds_guard.id.clone(),
+        data: tamper.as_ref().map_or(vec![], |status| status.events.iter().map(TamperEventInfo::from).collect())
+    };
+    drop(tamper);
+    drop(ds_guard);
+    return Ok(HttpResponse::Ok().json(ans));
+}
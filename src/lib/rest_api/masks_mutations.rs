@@ -0,0 +1,122 @@
+use actix_web::{HttpResponse, web, Error, http::StatusCode};
+use serde::{
+    Deserialize,
+    Serialize
+};
+use utoipa::ToSchema;
+use opencv::core::Point2f;
+use crate::lib::masks::ExclusionMask;
+use crate::lib::rest_api::APIStorage;
+
+#[derive(Debug, Serialize)]
+pub struct ErrorResponse {
+    pub error_text: String,
+}
+
+/// Areas where detections are ignored
+#[derive(Debug, Serialize, ToSchema)]
+pub struct AllExclusionMasks {
+    /// Set of exclusion masks
+    pub data: Vec<ExclusionMaskInfo>
+}
+
+/// Area where detections are ignored (e.g. parking lots, billboards, sidewalks)
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ExclusionMaskInfo {
+    /// Unique identifier of the mask
+    #[schema(example = "4f1c1a0e-2b5d-4c43-9a8e-8f3b9d2a6e11")]
+    pub mask_id: String,
+    /// Mask's coordinates for the video frames
+    #[schema(example = json!([[0,0],[120,0],[120,80],[0,80]]))]
+    pub pixel_points: Vec<[i32; 2]>
+}
+
+#[utoipa::path(
+    get,
+    tag = "Zones",
+    path = "/api/masks/list",
+    responses(
+        (status = 200, description = "List of exclusion masks", body = AllExclusionMasks)
+    )
+)]
+pub async fn all_masks_list(data: web::Data<APIStorage>) -> Result<HttpResponse, Error> {
+    let ds_guard = data.data_storage.read().expect("DataStorage is poisoned [RWLock]");
+    let masks = ds_guard.exclusion_masks.read().expect("Exclusion masks are poisoned [RWLock]");
+    let ans = AllExclusionMasks {
+        data: masks.iter().map(|mask| ExclusionMaskInfo {
+            mask_id: mask.get_id(),
+            pixel_points: mask.get_pixel_coordinates().iter().map(|pt| [pt.x as i32, pt.y as i32]).collect()
+        }).collect()
+    };
+    drop(masks);
+    drop(ds_guard);
+    return Ok(HttpResponse::Ok().json(ans));
+}
+
+#[derive(Debug, Deserialize)]
+pub struct MaskCreateRequest {
+    pub pixel_points: Vec<[u16; 2]>
+}
+
+#[derive(Debug, Serialize)]
+pub struct MaskCreateResponse {
+    pub mask_id: String
+}
+
+//
+// curl -XPOST 'http://localhost:42001/api/mutations/create_mask' -d '{"pixel_points": [[0, 0], [120, 0], [120, 80], [0, 80]]}' -H 'Content-Type: application/json'
+//
+pub async fn create_mask(data: web::Data<APIStorage>, _new_mask: web::Json<MaskCreateRequest>) -> Result<HttpResponse, Error> {
+    if _new_mask.pixel_points.len() < 3 {
+        return Ok(HttpResponse::build(StatusCode::BAD_REQUEST).json(ErrorResponse {
+            error_text: "Mask should have at least 3 points".to_string()
+        }));
+    }
+    let mask = ExclusionMask::new(_new_mask.pixel_points.iter().map(|pt| Point2f::new(pt[0] as f32, pt[1] as f32)).collect());
+    let new_id = mask.get_id();
+    let ds_guard = data.data_storage.read().expect("DataStorage is poisoned [RWLock]");
+    match ds_guard.insert_exclusion_mask(mask) {
+        Ok(_) => {},
+        Err(err) => {
+            return Ok(HttpResponse::build(StatusCode::INTERNAL_SERVER_ERROR).json(ErrorResponse {
+                error_text: format!("Can't insert mask ID: {}. Error: {}", new_id, err)
+            }));
+        }
+    }
+    return Ok(HttpResponse::Ok().json(MaskCreateResponse{
+        mask_id: new_id
+    }));
+}
+
+#[derive(Debug, Deserialize)]
+pub struct MaskDeleteRequest {
+    pub mask_id: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct MaskDeleteResponse <'a>{
+    pub message: &'a str,
+}
+
+//
+// curl -XPOST 'http://localhost:42001/api/mutations/delete_mask' -d '{"mask_id":"4f1c1a0e-2b5d-4c43-9a8e-8f3b9d2a6e11"}' -H 'Content-Type: application/json'
+//
+pub async fn delete_mask(data: web::Data<APIStorage>, _delete_mask: web::Json<MaskDeleteRequest>) -> Result<HttpResponse, Error> {
+    let ds_guard = data.data_storage.read().expect("DataStorage is poisoned [RWLock]");
+    match ds_guard.delete_exclusion_mask(&_delete_mask.mask_id) {
+        Ok(true) => {},
+        Ok(false) => {
+            return Ok(HttpResponse::build(StatusCode::FAILED_DEPENDENCY).json(ErrorResponse {
+                error_text: format!("No such mask. Requested ID: {}", _delete_mask.mask_id)
+            }));
+        },
+        Err(err) => {
+            return Ok(HttpResponse::build(StatusCode::INTERNAL_SERVER_ERROR).json(ErrorResponse {
+                error_text: format!("Can't delete mask ID: {}. Error: {}", _delete_mask.mask_id, err)
+            }));
+        }
+    }
+    return Ok(HttpResponse::Ok().json(MaskDeleteResponse{
+        message: "ok"
+    }));
+}
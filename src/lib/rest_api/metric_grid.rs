@@ -0,0 +1,81 @@
+use actix_web::{HttpResponse, web, Error, http::StatusCode};
+use serde::{
+    Deserialize,
+    Serialize
+};
+use utoipa::ToSchema;
+
+use crate::lib::rest_api::APIStorage;
+
+// Smaller steps produce too many lines to be readable
+const MIN_STEP_METERS: f32 = 0.5;
+
+#[derive(Debug, Serialize)]
+pub struct ErrorResponse {
+    pub error_text: String,
+}
+
+/// Metric grid overlay for visual verification of calibration
+#[derive(Debug, Serialize, ToSchema)]
+pub struct MetricGridInfo {
+    /// Whether grid (and distance ticks along zones' skeletons) is drawn on the MJPEG stream / output window
+    #[schema(example = true)]
+    pub enabled: bool,
+    /// Distance between grid lines and between ticks (meters). Grid is coarsened by the whole number of steps if there are more than 50 lines along any axis
+    #[schema(example = 5.0)]
+    pub step_meters: f32
+}
+
+#[utoipa::path(
+    get,
+    tag = "Zones",
+    path = "/api/overlay/metric_grid",
+    responses(
+        (status = 200, description = "Current state of the metric grid overlay", body = MetricGridInfo)
+    )
+)]
+pub async fn metric_grid_info(data: web::Data<APIStorage>) -> Result<HttpResponse, Error> {
+    let ds_guard = data.data_storage.read().expect("DataStorage is poisoned [RWLock]");
+    let metric_grid = *ds_guard.metric_grid.read().expect("Metric grid is poisoned [RWLock]");
+    drop(ds_guard);
+    return Ok(HttpResponse::Ok().json(MetricGridInfo {
+        enabled: metric_grid.enabled,
+        step_meters: metric_grid.step_meters
+    }));
+}
+
+#[derive(Debug, Deserialize)]
+pub struct MetricGridUpdateRequest {
+    pub enabled: bool,
+    pub step_meters: Option<f32>
+}
+
+//
+// curl -XPOST 'http://localhost:42001/api/mutations/metric_grid' -d '{"enabled": true, "step_meters": 5.0}' -H 'Content-Type: application/json'
+//
+pub async fn update_metric_grid(data: web::Data<APIStorage>, _update_grid: web::Json<MetricGridUpdateRequest>) -> Result<HttpResponse, Error> {
+    let ds_guard = data.data_storage.read().expect("DataStorage is poisoned [RWLock]");
+    let mut metric_grid = *ds_guard.metric_grid.read().expect("Metric grid is poisoned [RWLock]");
+    metric_grid.enabled = _update_grid.enabled;
+    if let Some(step_meters) = _update_grid.step_meters {
+        if !(step_meters >= MIN_STEP_METERS) {
+            return Ok(HttpResponse::build(StatusCode::BAD_REQUEST).json(ErrorResponse {
+                error_text: format!("Step of the grid should be at least {} meters, got {}", MIN_STEP_METERS, step_meters)
+            }));
+        }
+        metric_grid.step_meters = step_meters;
+    }
+    match ds_guard.set_metric_grid(metric_grid) {
+        Ok(_) => {},
+        Err(err) => {
+            return Ok(HttpResponse::build(StatusCode::INTERNAL_SERVER_ERROR).json(ErrorResponse {
+                error_text: format!("Can't update metric grid. Error: {}", err)
+            }));
+        }
+    }
+    drop(ds_guard);
+    return Ok(HttpResponse::Ok().json(MetricGridInfo {
+        enabled: metric_grid.enabled,
+        step_meters: metric_grid.step_meters
+    }));
+}
@@ -1,8 +1,18 @@
 mod mjpeg_page;
 mod mjpeg_client;
 mod zones_list;
+mod zones_calibration;
+mod spatial_projection;
+mod ground_tracks;
+mod health;
 pub mod zones_stats;
+pub mod zones_od_matrix;
+pub mod zones_violations;
+mod zones_history;
 mod zones_mutations;
+mod zones_discovery;
+mod masks_mutations;
+mod metric_grid;
 mod toml_mutations;
 mod rest_api;
 mod services;
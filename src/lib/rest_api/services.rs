@@ -3,7 +3,8 @@ use actix_web_static_files::ResourceFiles;
 include!(concat!(env!("OUT_DIR"), "/generated.rs"));
 
 use crate::lib::rest_api::{
-    mjpeg_client, mjpeg_page, toml_mutations, zones_list, zones_mutations, zones_stats,
+    ground_tracks, health, masks_mutations, metric_grid, mjpeg_client, mjpeg_page, spatial_projection, toml_mutations, zones_calibration, zones_discovery, zones_list, zones_mutations, zones_od_matrix,
+    zones_history, zones_stats, zones_violations,
 };
 
 async fn say_ping() -> impl Responder {
@@ -27,17 +28,59 @@ pub fn init_routes(enable_mjpeg: bool) -> impl Fn(&mut web::ServiceConfig) {
                 .service(RapiDoc::with_openapi("/docs.json", ApiDoc::openapi()))
                 .service(RapiDoc::new("/api/docs.json").path("/docs"))
                 .route("/ping", web::get().to(say_ping))
+                .route("/health", web::get().to(health::health_info))
+                .route("/health/tamper_events", web::get().to(health::tamper_events))
                 .service(
                     web::scope("/polygons")
-                        .route("/geojson", web::get().to(zones_list::all_zones_list)),
+                        .route("/geojson", web::get().to(zones_list::all_zones_list))
+                        .route(
+                            "/calibration",
+                            web::get().to(zones_calibration::all_zones_calibration),
+                        ),
+                )
+                .service(
+                    web::scope("/projection")
+                        .route("/to_wgs84", web::post().to(spatial_projection::pixels_to_wgs84))
+                        .route("/to_pixels", web::post().to(spatial_projection::wgs84_to_pixels)),
+                )
+                .service(
+                    web::scope("/discovery")
+                        .route("/status", web::get().to(zones_discovery::discovery_info))
+                        .route("/proposals", web::get().to(zones_discovery::discovery_proposals)),
                 )
                 .service(
-                    web::scope("/stats").route("/all", web::get().to(zones_stats::all_zones_stats)),
+                    web::scope("/overlay")
+                        .route("/metric_grid", web::get().to(metric_grid::metric_grid_info)),
+                )
+                .service(
+                    web::scope("/masks")
+                        .route("/list", web::get().to(masks_mutations::all_masks_list)),
+                )
+                .service(
+                    web::scope("/stats")
+                        .route("/all", web::get().to(zones_stats::all_zones_stats))
+                        .route(
+                            "/od_matrix",
+                            web::get().to(zones_od_matrix::all_zones_od_matrix),
+                        ),
+                )
+                .service(
+                    web::scope("/history")
+                        .route("/stats", web::get().to(zones_history::history_stats)),
+                )
+                .service(
+                    web::scope("/violations")
+                        .route("/list", web::get().to(zones_violations::all_violations))
+                        .route(
+                            "/snapshot/{event_id}",
+                            web::get().to(zones_violations::violation_snapshot),
+                        ),
+                )
+                .service(
+                    web::scope("/realtime")
+                        .route("/occupancy", web::get().to(zones_stats::all_zones_occupancy))
+                        .route("/tracks", web::get().to(ground_tracks::all_ground_tracks)),
                 )
-                .service(web::scope("/realtime").route(
-                    "/occupancy",
-                    web::get().to(zones_stats::all_zones_occupancy),
-                ))
                 .service(
                     web::scope("/mutations")
                         .route(
@@ -53,6 +96,15 @@ pub fn init_routes(enable_mjpeg: bool) -> impl Fn(&mut web::ServiceConfig) {
                             web::post().to(zones_mutations::delete_zone),
                         )
                         .route("/replace_all", web::post().to(zones_mutations::replace_all))
+                        .route(
+                            "/replace_all_by_centerline",
+                            web::post().to(zones_mutations::replace_all_by_centerline),
+                        )
+                        .route("/create_mask", web::post().to(masks_mutations::create_mask))
+                        .route("/delete_mask", web::post().to(masks_mutations::delete_mask))
+                        .route("/metric_grid", web::post().to(metric_grid::update_metric_grid))
+                        .route("/start_discovery", web::post().to(zones_discovery::start_discovery))
+                        .route("/accept_discovery", web::post().to(zones_discovery::accept_discovery))
                         .route("/save_toml", web::get().to(toml_mutations::save_toml)),
                 ),
         );
@@ -68,12 +120,28 @@ use utoipa_rapidoc::RapiDoc;
 #[openapi(
     paths(
         zones_list::all_zones_list,
+        zones_calibration::all_zones_calibration,
+        spatial_projection::pixels_to_wgs84,
+        spatial_projection::wgs84_to_pixels,
+        zones_discovery::discovery_info,
+        zones_discovery::discovery_proposals,
+        metric_grid::metric_grid_info,
+        masks_mutations::all_masks_list,
         zones_stats::all_zones_stats,
         zones_stats::all_zones_occupancy,
+        ground_tracks::all_ground_tracks,
+        zones_od_matrix::all_zones_od_matrix,
+        zones_history::history_stats,
+        zones_violations::all_violations,
+        zones_violations::violation_snapshot,
+        health::health_info,
+        health::tamper_events,
     ),
     tags(
         (name = "Zones", description = "Main information about detection zones"),
         (name = "Statistics", description = "Aggregated and real-time statistics in the detections zones"),
+        (name = "Violations", description = "Vehicles of disallowed types in the detection zones"),
+        (name = "Health", description = "Condition of the camera and the video input"),
     ),
     components(
         // We need to import all possible schemas since `utopia` can't discover recursive schemas (yet?)
@@ -82,11 +150,42 @@ use utoipa_rapidoc::RapiDoc;
             crate::lib::zones::geojson::ZoneFeature,
             crate::lib::zones::geojson::ZonePropertiesGeoJSON,
             crate::lib::zones::geojson::GeoPolygon,
+            crate::lib::zones::validation::GeometryError,
+            crate::lib::rest_api::zones_calibration::AllZonesCalibration,
+            crate::lib::rest_api::zones_calibration::ZoneCalibration,
+            crate::lib::rest_api::zones_calibration::ControlPointInfo,
+            crate::lib::rest_api::spatial_projection::PixelsToWGS84Request,
+            crate::lib::rest_api::spatial_projection::WGS84ToPixelsRequest,
+            crate::lib::rest_api::spatial_projection::WGS84Points,
+            crate::lib::rest_api::spatial_projection::PixelPoints,
+            crate::lib::rest_api::zones_discovery::DiscoveryStatus,
+            crate::lib::rest_api::metric_grid::MetricGridInfo,
+            crate::lib::rest_api::masks_mutations::AllExclusionMasks,
+            crate::lib::rest_api::masks_mutations::ExclusionMaskInfo,
             crate::lib::rest_api::zones_stats::AllZonesStats,
             crate::lib::rest_api::zones_stats::ZoneStats,
             crate::lib::rest_api::zones_stats::VehicleTypeParameters,
+            crate::lib::rest_api::zones_stats::HeadwayParameters,
+            crate::lib::rest_api::zones_stats::GroupStats,
+            crate::lib::rest_api::zones_stats::DirectionStats,
+            crate::lib::rest_api::zones_stats::AggregatedParameters,
+            crate::lib::rest_api::zones_stats::AggregatedVehicleTypeParameters,
             crate::lib::rest_api::zones_stats::AllZonesRealtimeStatistics,
             crate::lib::rest_api::zones_stats::ZoneRealtime,
+            crate::lib::rest_api::ground_tracks::AllGroundTracks,
+            crate::lib::rest_api::ground_tracks::GroundTrackInfo,
+            crate::lib::rest_api::zones_od_matrix::AllZonesODMatrix,
+            crate::lib::rest_api::zones_od_matrix::ODMatrixCell,
+            crate::lib::rest_api::zones_history::HistoryStats,
+            crate::lib::rest_api::zones_history::HistoryZoneStats,
+            crate::lib::rest_api::zones_history::HistoryVehicleStats,
+            crate::lib::rest_api::zones_violations::AllViolations,
+            crate::lib::rest_api::zones_violations::ViolationInfo,
+            crate::lib::rest_api::health::HealthInfo,
+            crate::lib::rest_api::health::StabilizationInfo,
+            crate::lib::rest_api::health::TamperInfo,
+            crate::lib::rest_api::health::AllTamperEvents,
+            crate::lib::rest_api::health::TamperEventInfo,
         ),
     )
 )]
@@ -0,0 +1,142 @@
+use actix_web::{HttpResponse, web, Error, http::StatusCode};
+use serde::{
+    Deserialize,
+    Serialize
+};
+use utoipa::ToSchema;
+
+use crate::lib::rest_api::APIStorage;
+use crate::lib::spatial::SpatialConverter;
+
+#[derive(Debug, Serialize)]
+pub struct ErrorResponse {
+    pub error_text: String,
+}
+
+/// Points on the video frame to be converted to WGS84
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct PixelsToWGS84Request {
+    /// Zone identifier which calibration should be used. Camera-wide calibration is used if it is not set
+    #[schema(example = "dir_0_lane_1")]
+    pub zone_id: Option<String>,
+    /// Points [x, y] in the same pixel space as zones' geometry
+    #[schema(example = json!([[51.0, 266.0], [281.0, 264.0]]))]
+    pub points: Vec<[f32; 2]>
+}
+
+/// Points in WGS84 to be converted to the video frame
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct WGS84ToPixelsRequest {
+    /// Zone identifier which calibration should be used. Camera-wide calibration is used if it is not set
+    #[schema(example = "dir_0_lane_1")]
+    pub zone_id: Option<String>,
+    /// Points [longitude, latitude]
+    #[schema(example = json!([[37.619001, 54.205674], [37.618964, 54.205678]]))]
+    pub points: Vec<[f64; 2]>
+}
+
+/// Converted points in WGS84
+#[derive(Debug, Serialize, ToSchema)]
+pub struct WGS84Points {
+    /// Points [longitude, latitude] in the same order as requested ones. Value "null" indicates that point is above the horizon (not on the ground)
+    #[schema(example = json!([[37.619001, 54.205674], null]))]
+    pub points: Vec<Option<[f64; 2]>>
+}
+
+/// Converted points on the video frame
+#[derive(Debug, Serialize, ToSchema)]
+pub struct PixelPoints {
+    /// Points [x, y] in the same order as requested ones. Value "null" indicates that point can't be seen by the camera
+    #[schema(example = json!([[51.0, 266.0], null]))]
+    pub points: Vec<Option<[f32; 2]>>
+}
+
+// Applies given conversion with the converter of the zone or with the camera-wide one
+fn with_converter<T>(data: &APIStorage, zone_id: &Option<String>, convert: impl Fn(&SpatialConverter) -> T) -> Result<T, ErrorResponse> {
+    let ds_guard = data.data_storage.read().expect("DataStorage is poisoned [RWLock]");
+    match zone_id {
+        Some(zone_id) => {
+            let zones = ds_guard.zones.read().expect("Spatial data is poisoned [RWLock]");
+            let zone_guarded = match zones.get(zone_id) {
+                Some(val) => val,
+                None => {
+                    return Err(ErrorResponse {
+                        error_text: format!("No such zone. Requested ID: {}", zone_id)
+                    });
+                }
+            };
+            let zone = zone_guarded.lock().expect("Zone is poisoned [Mutex]");
+            let converter = zone.get_spatial_converter();
+            if !converter.is_initialized() {
+                return Err(ErrorResponse {
+                    error_text: format!("Zone has no spatial calibration. Set both 'pixel_points' and 'spatial_points' or control points. Requested ID: {}", zone_id)
+                });
+            }
+            Ok(convert(converter))
+        },
+        None => {
+            let ground_plane = ds_guard.ground_plane.lock().expect("Ground plane is poisoned [Mutex]");
+            match ground_plane.as_ref() {
+                Some(converter) if converter.is_initialized() => Ok(convert(converter)),
+                _ => Err(ErrorResponse {
+                    error_text: "Camera-wide calibration is not configured. Provide 'zone_id' or set up [calibration] section".to_string()
+                })
+            }
+        }
+    }
+}
+
+// curl -XPOST 'http://localhost:42001/api/projection/to_wgs84' -d '{"zone_id": "dir_0_lane_1", "points": [[51.0, 266.0], [281.0, 264.0]]}' -H 'Content-Type: application/json'
+#[utoipa::path(
+    post,
+    tag = "Zones",
+    path = "/api/projection/to_wgs84",
+    request_body = PixelsToWGS84Request,
+    responses(
+        (status = 200, description = "Points converted from the video frame to WGS84", body = WGS84Points),
+        (status = 424, description = "There is no such zone, zone has no spatial calibration or camera-wide calibration is not configured")
+    )
+)]
+pub async fn pixels_to_wgs84(data: web::Data<APIStorage>, _request: web::Json<PixelsToWGS84Request>) -> Result<HttpResponse, Error> {
+    let answer = with_converter(&data, &_request.zone_id, |converter| {
+        _request
+            .points
+            .iter()
+            .map(|pt| {
+                if !converter.is_on_ground(pt[0], pt[1]) {
+                    return None;
+                }
+                converter.transform_to_wgs84(pt[0], pt[1]).map(|lonlat| [lonlat.0, lonlat.1])
+            })
+            .collect()
+    });
+    match answer {
+        Ok(points) => Ok(HttpResponse::Ok().json(WGS84Points { points: points })),
+        Err(err) => Ok(HttpResponse::build(StatusCode::FAILED_DEPENDENCY).json(err)),
+    }
+}
+
+// curl -XPOST 'http://localhost:42001/api/projection/to_pixels' -d '{"zone_id": "dir_0_lane_1", "points": [[37.619001, 54.205674], [37.618964, 54.205678]]}' -H 'Content-Type: application/json'
+#[utoipa::path(
+    post,
+    tag = "Zones",
+    path = "/api/projection/to_pixels",
+    request_body = WGS84ToPixelsRequest,
+    responses(
+        (status = 200, description = "Points converted from WGS84 to the video frame", body = PixelPoints),
+        (status = 424, description = "There is no such zone, zone has no spatial calibration or camera-wide calibration is not configured")
+    )
+)]
+pub async fn wgs84_to_pixels(data: web::Data<APIStorage>, _request: web::Json<WGS84ToPixelsRequest>) -> Result<HttpResponse, Error> {
+    let answer = with_converter(&data, &_request.zone_id, |converter| {
+        _request
+            .points
+            .iter()
+            .map(|pt| converter.transform_to_pixels(pt[0], pt[1]).map(|xy| [xy.0, xy.1]))
+            .collect()
+    });
+    match answer {
+        Ok(points) => Ok(HttpResponse::Ok().json(PixelPoints { points: points })),
+        Err(err) => Ok(HttpResponse::build(StatusCode::FAILED_DEPENDENCY).json(err)),
+    }
+}
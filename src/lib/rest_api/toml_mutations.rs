@@ -3,7 +3,7 @@ use serde::{
     Serialize
 };
 use crate::lib::rest_api::APIStorage;
-use crate::settings::RoadLanesSettings;
+use crate::settings::{RoadLanesSettings, ExclusionMaskSettings};
 
 #[derive(Debug, Serialize)]
 pub struct ErrorResponse {
@@ -26,11 +26,36 @@ pub async fn save_toml(data: web::Data<APIStorage>) -> Result<HttpResponse, Erro
             geometry: zone.get_pixel_coordinates().iter().map(|pt| [pt.x as i32, pt.y as i32]).collect(),
             geometry_wgs84: zone.get_spatial_coordinates_epsg4326().iter().map(|pt| [pt.x, pt.y]).collect(),
             lane_direction: zone.road_lane_direction,
-            lane_number: zone.road_lane_num
+            lane_number: zone.road_lane_num,
+            group_name: match zone.group_name.len() {
+                0 => None,
+                _ => Some(zone.group_name.clone())
+            },
+            counted_classes: Some(zone.get_target_classes()),
+            allowed_classes: match zone.get_allowed_classes() {
+                classes if classes.len() > 0 => Some(classes),
+                _ => None
+            },
+            level_of_service: Some(zone.get_los_thresholds()),
+            queue: Some(zone.get_queue_parameters()),
+            speed_trap: zone.get_speed_trap(),
+            // Control points shared by the whole camera are not duplicated for every road lane
+            control_points: match zone.get_control_points() {
+                points if points.len() == 0 => None,
+                points if setting_cloned.calibration.as_ref().map_or(false, |calibration| calibration.control_points == points) => None,
+                points => Some(points)
+            }
         });
         drop(zone);
     }
     drop(zones);
+    let masks = ds_guard.exclusion_masks.read().expect("Exclusion masks are poisoned [RWLock]");
+    if masks.len() > 0 {
+        setting_cloned.exclusion_masks = Some(masks.iter().map(|mask| ExclusionMaskSettings{
+            geometry: mask.get_pixel_coordinates().iter().map(|pt| [pt.x as i32, pt.y as i32]).collect()
+        }).collect());
+    }
+    drop(masks);
     drop(ds_guard);
     match setting_cloned.save(&data.settings_filename) {
         Ok(_) => {},
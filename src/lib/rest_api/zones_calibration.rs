@@ -0,0 +1,103 @@
+use actix_web::{HttpResponse, web, Error};
+use serde::Serialize;
+use utoipa::ToSchema;
+
+use crate::lib::rest_api::APIStorage;
+use crate::lib::spatial::calibration::CalibrationReport;
+
+/// Calibration quality for each detection zone
+#[derive(Debug, Serialize, ToSchema)]
+pub struct AllZonesCalibration {
+    /// Equipment identifier. Should match software configuration
+    #[schema(example = "1e23985f-1fa3-45d0-a365-2d8525a23ddd")]
+    pub equipment_id: String,
+    /// Set of detection zones and their calibration reports
+    pub data: Vec<ZoneCalibration>
+}
+
+/// Calibration quality for the specific detection zone
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ZoneCalibration {
+    /// Zone identifier
+    #[schema(example = "dir_0_lane_1")]
+    pub zone_id: String,
+    /// Whether spatial conversion is estimated by control points. Otherwise zone's corners are used and there is no report
+    #[schema(example = true)]
+    pub calibrated: bool,
+    /// Number of control points used for the final estimation
+    #[schema(example = 5)]
+    pub inliers_num: usize,
+    /// Root mean square of reprojection errors for inliers (meters). Value "-1" indicates no data
+    #[schema(example = 0.42)]
+    pub rms_error: f32,
+    /// Reprojection error for every control point
+    pub points: Vec<ControlPointInfo>
+}
+
+/// Reprojection error for the control point
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ControlPointInfo {
+    /// Point on the video frame [x, y]
+    #[schema(example = json!([51.0, 266.0]))]
+    pub pixel: [f32; 2],
+    /// Point on the ground [longitude, latitude]
+    #[schema(example = json!([37.619001, 54.205674]))]
+    pub wgs84: [f64; 2],
+    /// Distance between given WGS84 point and reprojected pixel point (meters)
+    #[schema(example = 0.31)]
+    pub error_meters: f32,
+    /// Whether point has been used for the final estimation. Outliers are rejected by RANSAC
+    #[schema(example = true)]
+    pub inlier: bool
+}
+
+impl ZoneCalibration {
+    fn new(zone_id: String, report: Option<CalibrationReport>) -> Self {
+        match report {
+            Some(report) => ZoneCalibration {
+                zone_id: zone_id,
+                calibrated: true,
+                inliers_num: report.inliers_num,
+                rms_error: report.rms_error,
+                points: report.points.iter().map(|point| ControlPointInfo {
+                    pixel: point.point.pixel,
+                    wgs84: point.point.wgs84,
+                    error_meters: point.error_meters,
+                    inlier: point.inlier
+                }).collect()
+            },
+            None => ZoneCalibration {
+                zone_id: zone_id,
+                calibrated: false,
+                inliers_num: 0,
+                rms_error: -1.0,
+                points: vec![]
+            }
+        }
+    }
+}
+
+#[utoipa::path(
+    get,
+    tag = "Zones",
+    path = "/api/polygons/calibration",
+    responses(
+        (status = 200, description = "Calibration reports for detection zones", body = AllZonesCalibration)
+    )
+)]
+pub async fn all_zones_calibration(data: web::Data<APIStorage>) -> Result<HttpResponse, Error> {
+    let ds_guard = data.data_storage.read().expect("DataStorage is poisoned [RWLock]");
+    let zones = ds_guard.zones.read().expect("Spatial data is poisoned [RWLock]");
+    let mut ans = AllZonesCalibration {
+        equipment_id: ds_guard.id.clone(),
+        data: vec![]
+    };
+    for (_, zone_guarded) in zones.iter() {
+        let zone = zone_guarded.lock().expect("Zone is poisoned [Mutex]");
+        ans.data.push(ZoneCalibration::new(zone.get_id(), zone.get_calibration_report()));
+    }
+    drop(zones);
+    drop(ds_guard);
+    ans.data.sort_by(|a, b| a.zone_id.cmp(&b.zone_id));
+    return Ok(HttpResponse::Ok().json(ans));
+}
@@ -0,0 +1,181 @@
+use actix_web::{HttpResponse, web, Error, http::StatusCode};
+use chrono::Utc;
+use serde::{
+    Deserialize,
+    Serialize
+};
+use utoipa::ToSchema;
+
+use crate::lib::rest_api::APIStorage;
+use crate::lib::rest_api::zones_mutations::{insert_zones, PolygonCreateRequest};
+use crate::lib::zones::ZonesFeatureCollection;
+use crate::lib::zones::discovery::MIN_CLUSTER_TRAJECTORIES;
+
+// Default duration of the learning mode (seconds)
+const DEFAULT_DISCOVERY_SECONDS: i64 = 300;
+// Trajectories are kept in memory until the end of the learning mode, so its duration is limited (seconds)
+const MAX_DISCOVERY_SECONDS: i64 = 3600;
+
+#[derive(Debug, Serialize)]
+pub struct ErrorResponse {
+    pub error_text: String,
+}
+
+/// State of the lane discovery (learning mode)
+#[derive(Debug, Serialize, ToSchema)]
+pub struct DiscoveryStatus {
+    /// Whether trajectories are being recorded
+    #[schema(example = true)]
+    pub running: bool,
+    /// Seconds left until recording stops
+    #[schema(example = 120)]
+    pub remaining_seconds: i64,
+    /// Whether recording has stopped and lanes are being proposed
+    #[schema(example = false)]
+    pub evaluating: bool,
+    /// Number of finished trajectories recorded so far
+    #[schema(example = 84)]
+    pub trajectories_num: usize,
+    /// Number of proposed lanes. Proposals are evaluated when recording stops
+    #[schema(example = 3)]
+    pub proposals_num: usize
+}
+
+fn discovery_status(data: &APIStorage) -> DiscoveryStatus {
+    let ds_guard = data.data_storage.read().expect("DataStorage is poisoned [RWLock]");
+    let discovery = ds_guard.lane_discovery.read().expect("Lane discovery is poisoned [RWLock]");
+    DiscoveryStatus {
+        running: discovery.is_running(),
+        remaining_seconds: discovery.get_remaining_seconds(Utc::now().timestamp()),
+        evaluating: discovery.is_evaluating(),
+        trajectories_num: discovery.get_trajectories_num(),
+        proposals_num: discovery.get_proposals().len()
+    }
+}
+
+#[utoipa::path(
+    get,
+    tag = "Zones",
+    path = "/api/discovery/status",
+    responses(
+        (status = 200, description = "State of the lane discovery", body = DiscoveryStatus)
+    )
+)]
+pub async fn discovery_info(data: web::Data<APIStorage>) -> Result<HttpResponse, Error> {
+    return Ok(HttpResponse::Ok().json(discovery_status(&data)));
+}
+
+#[utoipa::path(
+    get,
+    tag = "Zones",
+    path = "/api/discovery/proposals",
+    responses(
+        (status = 200, description = "Lanes proposed by the last lane discovery. Spatial geometry is filled only when camera-wide calibration is set", body = ZonesFeatureCollection)
+    )
+)]
+pub async fn discovery_proposals(data: web::Data<APIStorage>) -> Result<HttpResponse, Error> {
+    let ds_guard = data.data_storage.read().expect("DataStorage is poisoned [RWLock]");
+    let discovery = ds_guard.lane_discovery.read().expect("Lane discovery is poisoned [RWLock]");
+    let ground_plane = ds_guard.ground_plane.lock().expect("Ground plane is poisoned [Mutex]");
+    let mut ans = ZonesFeatureCollection::new();
+    for proposal in discovery.get_proposals().iter() {
+        ans.features.push(proposal.to_geojson(ground_plane.as_ref()));
+    }
+    drop(ground_plane);
+    drop(discovery);
+    drop(ds_guard);
+    return Ok(HttpResponse::Ok().json(ans));
+}
+
+#[derive(Debug, Deserialize)]
+pub struct DiscoveryStartRequest {
+    pub duration_seconds: Option<i64>,
+    // Minimum number of trajectories for the path to be proposed as a lane
+    pub min_trajectories: Option<usize>
+}
+
+//
+// curl -XPOST 'http://localhost:42001/api/mutations/start_discovery' -d '{"duration_seconds": 300, "min_trajectories": 5}' -H 'Content-Type: application/json'
+//
+pub async fn start_discovery(data: web::Data<APIStorage>, _start: web::Json<DiscoveryStartRequest>) -> Result<HttpResponse, Error> {
+    let duration_seconds = _start.duration_seconds.unwrap_or(DEFAULT_DISCOVERY_SECONDS);
+    if duration_seconds <= 0 || duration_seconds > MAX_DISCOVERY_SECONDS {
+        return Ok(HttpResponse::build(StatusCode::BAD_REQUEST).json(ErrorResponse {
+            error_text: format!("Duration should be in (0; {}] seconds, got {}", MAX_DISCOVERY_SECONDS, duration_seconds)
+        }));
+    }
+    let min_trajectories = usize::max(_start.min_trajectories.unwrap_or(MIN_CLUSTER_TRAJECTORIES), 1);
+    let ds_guard = data.data_storage.read().expect("DataStorage is poisoned [RWLock]");
+    let mut discovery = ds_guard.lane_discovery.write().expect("Lane discovery is poisoned [RWLock]");
+    discovery.start(Utc::now().timestamp(), duration_seconds, min_trajectories);
+    drop(discovery);
+    drop(ds_guard);
+    return Ok(HttpResponse::Ok().json(discovery_status(&data)));
+}
+
+#[derive(Debug, Deserialize)]
+pub struct DiscoveryAcceptRequest {
+    // Identifiers of accepted proposals. Every proposal is accepted if it is not set
+    pub proposal_ids: Option<Vec<String>>,
+    // Accepted proposals are added to existing zones by default. Every existing zone is removed if it is set to true
+    pub replace: Option<bool>
+}
+
+//
+// curl -XPOST 'http://localhost:42001/api/mutations/accept_discovery' -d '{"proposal_ids": ["a83c4c5c-7af0-4283-83f4-43ad4956269f"], "replace": false}' -H 'Content-Type: application/json'
+//
+pub async fn accept_discovery(data: web::Data<APIStorage>, _accept: web::Json<DiscoveryAcceptRequest>) -> Result<HttpResponse, Error> {
+    let ds_guard = data.data_storage.read().expect("DataStorage is poisoned [RWLock]");
+    let discovery = ds_guard.lane_discovery.read().expect("Lane discovery is poisoned [RWLock]");
+    let ground_plane = ds_guard.ground_plane.lock().expect("Ground plane is poisoned [Mutex]");
+    let mut new_zones = vec![];
+    for proposal in discovery.get_proposals().iter() {
+        if let Some(ref ids) = _accept.proposal_ids {
+            if !ids.contains(&proposal.id) {
+                continue;
+            }
+        }
+        let mut pixel_points = [[0_u16; 2]; 4];
+        for (i, pt) in proposal.pixel_points.iter().enumerate() {
+            pixel_points[i] = [pt.x.max(0.0).min(u16::MAX as f32).round() as u16, pt.y.max(0.0).min(u16::MAX as f32).round() as u16];
+        }
+        // Spatial geometry is known only if the whole zone lies on the ground plane
+        let spatial_points = match ground_plane.as_ref() {
+            Some(converter) if pixel_points.iter().all(|pt| converter.is_on_ground(pt[0] as f32, pt[1] as f32)) => {
+                let mut spatial_points = [[0.0_f64; 2]; 4];
+                let mut projected = true;
+                for (i, pt) in pixel_points.iter().enumerate() {
+                    match converter.transform_to_wgs84(pt[0] as f32, pt[1] as f32) {
+                        Some((lon, lat)) => spatial_points[i] = [lon, lat],
+                        None => projected = false,
+                    }
+                }
+                if projected { Some(spatial_points) } else { None }
+            },
+            _ => None
+        };
+        new_zones.push(PolygonCreateRequest {
+            pixel_points: Some(pixel_points),
+            spatial_points: spatial_points,
+            lane_number: Some(proposal.lane_number),
+            lane_direction: Some(proposal.lane_direction),
+            group_name: None,
+            color_rgb: None,
+            counted_classes: None,
+            allowed_classes: None,
+            level_of_service: None,
+            queue: None,
+            speed_trap: None,
+            control_points: None
+        });
+    }
+    drop(ground_plane);
+    drop(discovery);
+    drop(ds_guard);
+    if new_zones.len() == 0 {
+        return Ok(HttpResponse::build(StatusCode::FAILED_DEPENDENCY).json(ErrorResponse {
+            error_text: "No lane proposals to accept. Run lane discovery first".to_string()
+        }));
+    }
+    insert_zones(&data, &new_zones, _accept.replace.unwrap_or(false))
+}
@@ -0,0 +1,266 @@
+use actix_web::{HttpResponse, web, Error, http::StatusCode};
+use serde::{
+    Deserialize,
+    Serialize
+};
+use utoipa::{IntoParams, ToSchema};
+use chrono::{DateTime, TimeZone, Utc};
+
+use crate::lib::data_storage::{HistoryQuery, HistoryRecord, HistoryVehicleRecord, HISTORY_MAX_LIMIT};
+use crate::lib::rest_api::APIStorage;
+
+const DEFAULT_LIMIT: u32 = 100;
+// Default time range when 'from' is not set
+const DEFAULT_RANGE_SECONDS: i64 = 86400;
+
+#[derive(Debug, Serialize)]
+pub struct ErrorResponse {
+    pub error_text: String,
+}
+
+/// Filter for the stored statistics periods
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct HistoryStatsQuery {
+    /// UTC Unix timestamp. Periods which start at it or later are returned. Default is one day before 'to'
+    #[param(example = 1693300419)]
+    pub from: Option<i64>,
+    /// UTC Unix timestamp. Periods which start before it are returned. Default is current time
+    #[param(example = 1693386819)]
+    pub to: Option<i64>,
+    /// Zone identifier
+    #[param(example = "dir_0_lane_1")]
+    pub zone_id: Option<String>,
+    /// Aggregation interval (milliseconds). Periods of every interval are returned if it is not set
+    #[param(example = 300000)]
+    pub interval_milliseconds: Option<i64>,
+    /// Vehicle type. Only periods with such vehicles are returned, and only statistics of this type is included
+    #[param(example = "car")]
+    pub classname: Option<String>,
+    /// Maximum number of periods in the response. Default is 100, maximum is 1000
+    #[param(example = 100)]
+    pub limit: Option<u32>,
+    /// Number of periods to skip
+    #[param(example = 0)]
+    pub offset: Option<u32>
+}
+
+/// Page of the stored statistics periods
+#[derive(Debug, Serialize, ToSchema)]
+pub struct HistoryStats {
+    /// Equipment identifier. Should match software configuration
+    #[schema(example = "1e23985f-1fa3-45d0-a365-2d8525a23ddd")]
+    pub equipment_id: String,
+    /// Total number of periods matched by the filter
+    #[schema(example = 2880)]
+    pub total: u64,
+    #[schema(example = 100)]
+    pub limit: u32,
+    #[schema(example = 0)]
+    pub offset: u32,
+    /// Periods ordered by start time, aggregation interval and zone identifier
+    pub data: Vec<HistoryZoneStats>
+}
+
+/// Statistics of the zone for the finished period
+#[derive(Debug, Serialize, ToSchema)]
+pub struct HistoryZoneStats {
+    /// Zone identifier
+    #[schema(example = "dir_0_lane_1")]
+    pub zone_id: String,
+    /// Aggregation interval which the period belongs to (milliseconds)
+    #[schema(example = 300000)]
+    pub interval_milliseconds: i64,
+    /// Corresponding lane number
+    #[schema(example = 1)]
+    pub lane_number: u16,
+    /// Corresponding lane direction
+    #[schema(example = 0)]
+    pub lane_direction: u8,
+    /// Group of lanes. Empty string indicates that zone is not grouped
+    #[schema(example = "northbound")]
+    pub group_name: String,
+    /// Start time of the period
+    #[schema(value_type = String, example = "2023-01-02T15:00:42Z")]
+    pub period_start: DateTime<Utc>,
+    /// End time of the period
+    #[schema(value_type = String, example = "2023-01-02T15:01:12Z")]
+    pub period_end: DateTime<Utc>,
+    /// Flow rate (vehicles/hour). Value "-1" indicates unknown period
+    #[schema(example = 720.0)]
+    pub flow_rate: f32,
+    /// Summary road traffic flow in passenger car units
+    #[schema(example = 6.5)]
+    pub pcu_volume: f32,
+    /// Flow rate in passenger car units per hour. Value "-1" indicates unknown period
+    #[schema(example = 780.0)]
+    pub pcu_flow_rate: f32,
+    /// Density (vehicles/km). Value "-1" indicates no data
+    #[schema(example = 18.2)]
+    pub density: f32,
+    /// Share of time when at least one vehicle was inside the zone
+    #[schema(example = 37.5)]
+    pub occupancy_percent: f32,
+    /// Maximum queue length (meters)
+    #[schema(example = 24.0)]
+    pub max_queue_length: f32,
+    /// Average queue length (meters)
+    #[schema(example = 8.3)]
+    pub avg_queue_length: f32,
+    /// Level of service grade (A-F). Value "-" indicates no data
+    #[schema(example = "C")]
+    pub level_of_service: String,
+    /// Whether statistics of the period could be trusted
+    #[schema(example = true)]
+    pub valid: bool,
+    /// Reasons why statistics is invalid
+    #[schema(example = json!([]))]
+    pub invalid_reasons: Vec<String>,
+    /// Statistics for every vehicle type
+    pub vehicles: Vec<HistoryVehicleStats>
+}
+
+/// Statistics of the vehicle type for the finished period
+#[derive(Debug, Serialize, ToSchema)]
+pub struct HistoryVehicleStats {
+    /// Vehicle type
+    #[schema(example = "car")]
+    pub classname: String,
+    /// Average speed. Value "-1" indicates not vehicles detected at all
+    #[schema(example = 32.1)]
+    pub estimated_avg_speed: f32,
+    /// Summary road traffic flow
+    #[schema(example = 15)]
+    pub estimated_sum_intensity: u32,
+    /// Flow rate (vehicles/hour). Value "-1" indicates unknown period
+    #[schema(example = 180.0)]
+    pub estimated_flow_rate: f32,
+    /// Summary road traffic flow in passenger car units
+    #[schema(example = 15.0)]
+    pub estimated_pcu_volume: f32,
+    /// Minimum speed. Value "-1" indicates no vehicles with known speed
+    #[schema(example = 18.4)]
+    pub min_speed: f32,
+    /// Maximum speed. Value "-1" indicates no vehicles with known speed
+    #[schema(example = 51.7)]
+    pub max_speed: f32,
+    /// Median speed. Value "-1" indicates no vehicles with known speed
+    #[schema(example = 31.5)]
+    pub median_speed: f32,
+    /// 85th percentile of speed. Value "-1" indicates no vehicles with known speed
+    #[schema(example = 42.3)]
+    pub p85_speed: f32,
+    /// Standard deviation of speed. Value "-1" indicates no vehicles with known speed
+    #[schema(example = 8.9)]
+    pub std_speed: f32
+}
+
+impl From<&HistoryVehicleRecord> for HistoryVehicleStats {
+    fn from(record: &HistoryVehicleRecord) -> Self {
+        HistoryVehicleStats {
+            classname: record.classname.clone(),
+            estimated_avg_speed: record.avg_speed,
+            estimated_sum_intensity: record.sum_intensity,
+            estimated_flow_rate: record.flow_rate,
+            estimated_pcu_volume: record.pcu_volume,
+            min_speed: record.min_speed,
+            max_speed: record.max_speed,
+            median_speed: record.median_speed,
+            p85_speed: record.p85_speed,
+            std_speed: record.std_speed
+        }
+    }
+}
+
+impl From<&HistoryRecord> for HistoryZoneStats {
+    fn from(record: &HistoryRecord) -> Self {
+        HistoryZoneStats {
+            zone_id: record.zone_id.clone(),
+            interval_milliseconds: record.interval_milliseconds,
+            lane_number: record.lane_number,
+            lane_direction: record.lane_direction,
+            group_name: record.group_name.clone(),
+            period_start: Utc.timestamp_opt(record.period_start, 0).unwrap(),
+            period_end: Utc.timestamp_opt(record.period_end, 0).unwrap(),
+            flow_rate: record.flow_rate,
+            pcu_volume: record.pcu_volume,
+            pcu_flow_rate: record.pcu_flow_rate,
+            density: record.density,
+            occupancy_percent: record.occupancy_percent,
+            max_queue_length: record.max_queue_length,
+            avg_queue_length: record.avg_queue_length,
+            level_of_service: record.level_of_service.clone(),
+            valid: record.invalid_reasons.is_empty(),
+            invalid_reasons: record.invalid_reasons.clone(),
+            vehicles: record.vehicles.iter().map(HistoryVehicleStats::from).collect()
+        }
+    }
+}
+
+#[utoipa::path(
+    get,
+    tag = "Statistics",
+    path = "/api/history/stats",
+    params(HistoryStatsQuery),
+    responses(
+        (status = 200, description = "Page of the stored statistics periods", body = HistoryStats),
+        (status = 400, description = "Wrong time range or pagination"),
+        (status = 424, description = "Statistics history is not configured"),
+        (status = 500, description = "Can't read statistics history")
+    )
+)]
+pub async fn history_stats(data: web::Data<APIStorage>, _query: web::Query<HistoryStatsQuery>) -> Result<HttpResponse, Error> {
+    let to = _query.to.unwrap_or(Utc::now().timestamp());
+    let from = _query.from.unwrap_or(to - DEFAULT_RANGE_SECONDS);
+    if from > to {
+        return Ok(HttpResponse::build(StatusCode::BAD_REQUEST).json(ErrorResponse {
+            error_text: format!("'from' ({}) should not be greater than 'to' ({})", from, to)
+        }));
+    }
+    let limit = _query.limit.unwrap_or(DEFAULT_LIMIT);
+    if limit == 0 || limit > HISTORY_MAX_LIMIT {
+        return Ok(HttpResponse::build(StatusCode::BAD_REQUEST).json(ErrorResponse {
+            error_text: format!("'limit' should be in range [1; {}], got {}", HISTORY_MAX_LIMIT, limit)
+        }));
+    }
+    let query = HistoryQuery {
+        from: from,
+        to: to,
+        zone_id: _query.zone_id.clone(),
+        interval_milliseconds: _query.interval_milliseconds,
+        classname: _query.classname.clone(),
+        limit: limit,
+        offset: _query.offset.unwrap_or(0)
+    };
+    let ds_guard = data.data_storage.read().expect("DataStorage is poisoned [RWLock]");
+    let equipment_id = ds_guard.id.clone();
+    let history = ds_guard.history.clone();
+    drop(ds_guard);
+    let history = match history {
+        Some(history) => history,
+        None => {
+            return Ok(HttpResponse::build(StatusCode::FAILED_DEPENDENCY).json(ErrorResponse {
+                error_text: "Statistics history is not configured. Set up [history] section".to_string()
+            }));
+        }
+    };
+    let limit = query.limit;
+    let offset = query.offset;
+    // SQLite query is blocking, so it is executed on the thread pool with its own read-only connection
+    let answer = web::block(move || {
+        let storage = history.open_reader()?;
+        storage.query(&query)
+    }).await?;
+    match answer {
+        Ok((total, records)) => Ok(HttpResponse::Ok().json(HistoryStats {
+            equipment_id: equipment_id,
+            total: total,
+            limit: limit,
+            offset: offset,
+            data: records.iter().map(HistoryZoneStats::from).collect()
+        })),
+        Err(err) => Ok(HttpResponse::build(StatusCode::INTERNAL_SERVER_ERROR).json(ErrorResponse {
+            error_text: format!("Can't read statistics history due the error: {}", err)
+        }))
+    }
+}
@@ -4,21 +4,99 @@ use serde::{
     Serialize
 };
 use crate::lib::zones::Zone;
+use crate::lib::zones::los::LevelOfServiceThresholds;
+use crate::lib::zones::queue::QueueParameters;
+use crate::lib::zones::speed_trap::SpeedTrapLines;
+use crate::lib::zones::validation::{validate_pixel_points, validate_wgs84_points, validate_zone_geometry, validate_los_thresholds, GeometryError};
+use crate::lib::zones::centerline::lanes_from_centerline;
+use crate::lib::zones::geojson::GeoLineString;
+use crate::lib::spatial::SpatialConverter;
 use crate::lib::rest_api::APIStorage;
+use crate::lib::spatial::calibration::{calibrate, CalibrationReport, ControlPoint};
+use crate::lib::spatial::projection::ProjectionKind;
+use opencv::core::Point2d;
+use opencv::core::Point2f;
 
 #[derive(Debug, Serialize)]
 pub struct ErrorResponse {
     pub error_text: String,
 }
 
+#[derive(Debug, Serialize)]
+pub struct GeometryErrorResponse {
+    pub error_text: String,
+    pub errors: Vec<GeometryError>,
+}
+
+// Validates geometry provided by request. Correspondence of points is checked only when both sets are provided
+fn validate_geometry(prefix: &str, pixel: Option<Vec<Point2f>>, spatial: Option<Vec<Point2d>>) -> Vec<GeometryError> {
+    let pixel_field = format!("{}pixel_points", prefix);
+    let spatial_field = format!("{}spatial_points", prefix);
+    match (pixel, spatial) {
+        (Some(pixel), Some(spatial)) => {
+            match validate_zone_geometry(&pixel_field, &pixel, &spatial_field, &spatial) {
+                Ok(_) => vec![],
+                Err(errors) => errors
+            }
+        },
+        (Some(pixel), None) => validate_pixel_points(&pixel_field, &pixel),
+        (None, Some(spatial)) => validate_wgs84_points(&spatial_field, &spatial),
+        (None, None) => vec![]
+    }
+}
+
+// Pixel geometry of the new zone. Zone given by WGS84 geometry only is projected to pixels by the camera-wide calibration,
+// the same way as road lanes of the configuration
+fn project_request_geometry(ground_plane: Option<&SpatialConverter>, prefix: &str, pixel_points: Option<[[u16; 2]; 4]>, spatial_points: Option<[[f64; 2]; 4]>) -> Result<Option<Vec<Point2f>>, GeometryError> {
+    match (pixel_points, spatial_points, ground_plane) {
+        (Some(data), _, _) => Ok(Some(data.iter().map(|pt| Point2f::new(pt[0] as f32, pt[1] as f32)).collect())),
+        (None, Some(data), Some(converter)) => match converter.project_wgs84_points(&data) {
+            Ok(points) => Ok(Some(points)),
+            Err(err) => Err(GeometryError {
+                field: format!("{}spatial_points", prefix),
+                message: err
+            })
+        },
+        (None, Some(_), None) => Err(GeometryError {
+            field: format!("{}pixel_points", prefix),
+            message: "Pixel points are not set and there is no camera-wide calibration to project spatial points".to_string()
+        }),
+        (None, None, _) => Ok(None)
+    }
+}
+
+// Calibrates by control points provided by request, so the result is validated and applied at once.
+// Empty set is valid: it resets calibration, so there is nothing to apply
+fn calibrate_request(projection: ProjectionKind, prefix: &str, control_points: &Option<Vec<ControlPoint>>) -> Result<Option<(SpatialConverter, CalibrationReport)>, GeometryError> {
+    match control_points {
+        Some(points) if points.len() > 0 => {
+            match calibrate(projection, points) {
+                Ok(calibration) => Ok(Some(calibration)),
+                Err(err) => Err(GeometryError {
+                    field: format!("{}control_points", prefix),
+                    message: err.to_string()
+                })
+            }
+        },
+        _ => Ok(None)
+    }
+}
+
 #[derive(Debug, Deserialize)]
 pub struct PolygonUpdateRequest {
     pub polygon_id: String,
     pub pixel_points: Option<[[u16; 2]; 4]>,
-    pub spatial_points: Option<[[f32; 2]; 4]>,
+    pub spatial_points: Option<[[f64; 2]; 4]>,
     pub lane_number: Option<u16>,
     pub lane_direction: Option<u8>,
-    pub color_rgb: Option<[i16; 3]>
+    pub group_name: Option<String>,
+    pub color_rgb: Option<[i16; 3]>,
+    pub counted_classes: Option<Vec<String>>,
+    pub allowed_classes: Option<Vec<String>>,
+    pub level_of_service: Option<LevelOfServiceThresholds>,
+    pub queue: Option<QueueParameters>,
+    pub speed_trap: Option<SpeedTrapLines>,
+    pub control_points: Option<Vec<ControlPoint>>
 }
 
 #[derive(Debug, Serialize)]
@@ -27,7 +105,7 @@ pub struct PolygonUpdateResponse <'a>{
 }
 
 //
-// curl -XPOST 'http://localhost:42001/api/mutations/change_polygon' -d '{"polygon_id":"dir_0_lane_1", "lane_number": 939, "pixel_points": [[299, 222], [572, 265], [547, 66], [359, 69]], "color_rgb": [130, 0, 100]}' -H 'Content-Type: application/json'
+// curl -XPOST 'http://localhost:42001/api/mutations/change_polygon' -d '{"polygon_id":"dir_0_lane_1", "lane_number": 939, "group_name": "northbound approach", "pixel_points": [[299, 222], [572, 265], [547, 66], [359, 69]], "color_rgb": [130, 0, 100], "allowed_classes": ["bus"]}' -H 'Content-Type: application/json'
 //
 pub async fn update_zone(data: web::Data<APIStorage>, _update_zone: web::Json<PolygonUpdateRequest>) -> Result<HttpResponse, Error> {
 
@@ -44,9 +122,42 @@ pub async fn update_zone(data: web::Data<APIStorage>, _update_zone: web::Json<Po
         }
     };
 
+    // Geometry which is not updated is kept, so the resulting zone is validated as a whole when any part of it changes
+    let zone = zone_guarded.lock().expect("Zone is poisoned [Mutex]");
+    let pixel = match _update_zone.pixel_points {
+        Some(data) => Some(data.iter().map(|pt| Point2f::new(pt[0] as f32, pt[1] as f32)).collect()),
+        None => Some(zone.get_pixel_coordinates()).filter(|points| points.len() > 0),
+    };
+    let spatial = match _update_zone.spatial_points {
+        Some(data) => Some(data.iter().map(|pt| Point2d::new(pt[0], pt[1])).collect()),
+        None => Some(zone.get_spatial_coordinates_epsg4326()).filter(|points| points.len() > 0),
+    };
+    let projection = zone.get_projection();
+    drop(zone);
+    let mut errors = if _update_zone.pixel_points.is_some() || _update_zone.spatial_points.is_some() {
+        validate_geometry("", pixel, spatial)
+    } else {
+        vec![]
+    };
+    let calibration = match calibrate_request(projection, "", &_update_zone.control_points) {
+        Ok(calibration) => calibration,
+        Err(err) => {
+            errors.push(err);
+            None
+        }
+    };
+    if let Some(thresholds) = &_update_zone.level_of_service {
+        errors.extend(validate_los_thresholds("level_of_service", thresholds));
+    }
+    if errors.len() > 0 {
+        return Ok(HttpResponse::build(StatusCode::BAD_REQUEST).json(GeometryErrorResponse {
+            error_text: "Invalid geometry".to_string(),
+            errors: errors
+        }));
+    }
+
     // @todo need to deal with those (see main function):
     // polygon.scale_geom(scale_x, scale_y);    
-    // polygon.set_target_classes(COCO_FILTERED_CLASSNAMES);
 
     match _update_zone.pixel_points {
         Some(data) => {
@@ -80,6 +191,14 @@ pub async fn update_zone(data: web::Data<APIStorage>, _update_zone: web::Json<Po
         _ => {}
     }
 
+    match &_update_zone.group_name {
+        Some(val) => {
+            let mut zone = zone_guarded.lock().expect("Zone is poisoned [Mutex]");
+            zone.set_group_name(val.clone());
+        },
+        _ => {}
+    }
+
     match _update_zone.color_rgb {
         Some(val) => {
             let mut zone = zone_guarded.lock().expect("Zone is poisoned [Mutex]");
@@ -88,6 +207,58 @@ pub async fn update_zone(data: web::Data<APIStorage>, _update_zone: web::Json<Po
         _ => {}
     }
 
+    match &_update_zone.counted_classes {
+        Some(val) => {
+            let mut zone = zone_guarded.lock().expect("Zone is poisoned [Mutex]");
+            zone.set_target_classes(val.clone());
+        },
+        _ => {}
+    }
+
+    match &_update_zone.allowed_classes {
+        Some(val) => {
+            let mut zone = zone_guarded.lock().expect("Zone is poisoned [Mutex]");
+            zone.set_allowed_classes(val.clone());
+        },
+        _ => {}
+    }
+
+    match &_update_zone.level_of_service {
+        Some(val) => {
+            let mut zone = zone_guarded.lock().expect("Zone is poisoned [Mutex]");
+            zone.set_los_thresholds(val.clone());
+        },
+        _ => {}
+    }
+
+    match &_update_zone.queue {
+        Some(val) => {
+            let mut zone = zone_guarded.lock().expect("Zone is poisoned [Mutex]");
+            zone.set_queue_parameters(val.clone());
+        },
+        _ => {}
+    }
+
+    match &_update_zone.speed_trap {
+        Some(val) => {
+            let mut zone = zone_guarded.lock().expect("Zone is poisoned [Mutex]");
+            zone.set_speed_trap(val.clone());
+        },
+        _ => {}
+    }
+
+    match (&_update_zone.control_points, calibration) {
+        (Some(val), Some((converter, report))) => {
+            let mut zone = zone_guarded.lock().expect("Zone is poisoned [Mutex]");
+            zone.set_calibration(val.clone(), converter, report);
+        },
+        (Some(_), None) => {
+            let mut zone = zone_guarded.lock().expect("Zone is poisoned [Mutex]");
+            zone.clear_control_points();
+        },
+        _ => {}
+    }
+
     return Ok(HttpResponse::Ok().json(PolygonUpdateResponse{
         message: "ok"
     }));
@@ -125,10 +296,17 @@ pub async fn delete_zone(data: web::Data<APIStorage>, _delete_zone: web::Json<Po
 #[derive(Debug, Deserialize)]
 pub struct PolygonCreateRequest {
     pub pixel_points: Option<[[u16; 2]; 4]>,
-    pub spatial_points: Option<[[f32; 2]; 4]>,
+    pub spatial_points: Option<[[f64; 2]; 4]>,
     pub lane_number: Option<u16>,
     pub lane_direction: Option<u8>,
-    pub color_rgb: Option<[i16; 3]>
+    pub group_name: Option<String>,
+    pub color_rgb: Option<[i16; 3]>,
+    pub counted_classes: Option<Vec<String>>,
+    pub allowed_classes: Option<Vec<String>>,
+    pub level_of_service: Option<LevelOfServiceThresholds>,
+    pub queue: Option<QueueParameters>,
+    pub speed_trap: Option<SpeedTrapLines>,
+    pub control_points: Option<Vec<ControlPoint>>
 }
 
 #[derive(Debug, Serialize)]
@@ -141,14 +319,48 @@ pub struct PolygonCreateResponse {
 //
 pub async fn create_zone(data: web::Data<APIStorage>, _new_zone: web::Json<PolygonCreateRequest>) -> Result<HttpResponse, Error> {
 
+    let ds_guard = data.data_storage.read().expect("DataStorage is poisoned [RWLock]");
+    let ground_plane_guard = ds_guard.ground_plane.lock().expect("Ground plane is poisoned [Mutex]");
+    let ground_plane = ground_plane_guard.as_ref().filter(|converter| converter.is_initialized());
+    let pixel_points = match project_request_geometry(ground_plane, "", _new_zone.pixel_points, _new_zone.spatial_points) {
+        Ok(points) => points,
+        Err(err) => {
+            return Ok(HttpResponse::build(StatusCode::BAD_REQUEST).json(GeometryErrorResponse {
+                error_text: "Invalid geometry".to_string(),
+                errors: vec![err]
+            }));
+        }
+    };
+    let projection = ds_guard.projection;
+    drop(ground_plane_guard);
+    drop(ds_guard);
+    let spatial_points = _new_zone.spatial_points.map(|data| data.iter().map(|pt| Point2d::new(pt[0], pt[1])).collect());
+    let mut errors = validate_geometry("", pixel_points.clone(), spatial_points);
+    let calibration = match calibrate_request(projection, "", &_new_zone.control_points) {
+        Ok(calibration) => calibration,
+        Err(err) => {
+            errors.push(err);
+            None
+        }
+    };
+    if let Some(thresholds) = &_new_zone.level_of_service {
+        errors.extend(validate_los_thresholds("level_of_service", thresholds));
+    }
+    if errors.len() > 0 {
+        return Ok(HttpResponse::build(StatusCode::BAD_REQUEST).json(GeometryErrorResponse {
+            error_text: "Invalid geometry".to_string(),
+            errors: errors
+        }));
+    }
+
     // @todo need to deal with those (see main function):
     // polygon.scale_geom(scale_x, scale_y);    
-    // polygon.set_target_classes(COCO_FILTERED_CLASSNAMES);
 
     let mut zone = Zone::default();
-    match _new_zone.pixel_points {
-        Some(data) => {
-            zone.update_pixel_map(data);
+    zone.set_projection(projection);
+    match pixel_points {
+        Some(points) => {
+            zone.update_pixel_map_cv(points);
         },
         _ => {}
     }
@@ -174,6 +386,13 @@ pub async fn create_zone(data: web::Data<APIStorage>, _new_zone: web::Json<Polyg
         _ => {}
     }
 
+    match &_new_zone.group_name {
+        Some(val) => {
+            zone.set_group_name(val.clone());
+        },
+        _ => {}
+    }
+
     match _new_zone.color_rgb {
         Some(val) => {
             zone.set_color(val);
@@ -181,6 +400,48 @@ pub async fn create_zone(data: web::Data<APIStorage>, _new_zone: web::Json<Polyg
         _ => {}
     }
 
+    match &_new_zone.counted_classes {
+        Some(val) => {
+            zone.set_target_classes(val.clone());
+        },
+        _ => {}
+    }
+
+    match &_new_zone.allowed_classes {
+        Some(val) => {
+            zone.set_allowed_classes(val.clone());
+        },
+        _ => {}
+    }
+
+    match &_new_zone.level_of_service {
+        Some(val) => {
+            zone.set_los_thresholds(val.clone());
+        },
+        _ => {}
+    }
+
+    match &_new_zone.queue {
+        Some(val) => {
+            zone.set_queue_parameters(val.clone());
+        },
+        _ => {}
+    }
+
+    match &_new_zone.speed_trap {
+        Some(val) => {
+            zone.set_speed_trap(val.clone());
+        },
+        _ => {}
+    }
+
+    match (&_new_zone.control_points, calibration) {
+        (Some(val), Some((converter, report))) => {
+            zone.set_calibration(val.clone(), converter, report);
+        },
+        _ => {}
+    }
+
     let new_id = zone.get_id().clone();
 
     let ds_guard = data.data_storage.read().expect("DataStorage is poisoned [RWLock]");
@@ -214,27 +475,80 @@ pub struct PolygonReplaceAllResponse {
 // curl -XPOST 'http://localhost:42001/api/mutations/replace_all' -d '{"data":[{"lane_number":0,"lane_direction":0,"pixel_points":[[1,1],[50,1],[50,50],[1,50]],"spatial_points":[[37.618908137083054,54.20564619851147],[37.61891517788172,54.20564502193819],[37.618927247822285,54.205668749493036],[37.61892020702362,54.2056701221611]],"color_rgb":[130,130,0]},{"lane_number":1,"lane_direction":0,"pixel_points":[[55,55],[105,55],[105,105],[55,105]],"spatial_points":[[37.618908137083054,54.20564619851147],[37.61891517788172,54.20564502193819],[37.618927247822285,54.205668749493036],[37.61892020702362,54.2056701221611]],"color_rgb":[130,0,130]}]}' -H 'Content-Type: application/json'
 //
 pub async fn replace_all(data: web::Data<APIStorage>, _new_zones: web::Json<PolygonReplaceAllRequest>) -> Result<HttpResponse, Error> {
+    replace_all_zones(&data, &_new_zones.data)
+}
+
+// Validates new zones, inserts them and then removes every zone which existed before
+pub fn replace_all_zones(data: &APIStorage, new_zones: &[PolygonCreateRequest]) -> Result<HttpResponse, Error> {
+    insert_zones(data, new_zones, true)
+}
+
+// Validates new zones and inserts them. Every zone which existed before is removed if `replace` is set
+pub fn insert_zones(data: &APIStorage, new_zones: &[PolygonCreateRequest], replace: bool) -> Result<HttpResponse, Error> {
 
-    if _new_zones.data.len() == 0 {
+    if new_zones.len() == 0 {
         return Ok(HttpResponse::build(StatusCode::BAD_REQUEST).json(ErrorResponse {
             error_text: "No polygons".to_string()
         }));
     }
 
-    // Mark data for clean
     let ds_guard = data.data_storage.read().expect("DataStorage is poisoned [RWLock]");
-    let zones = ds_guard.zones.read().expect("Spatial data is poisoned [RWLock]");
-    let need_to_clean: Vec<String> = zones.iter().map(|poly| poly.0.clone()).collect();
-    drop(zones);
+    let ground_plane_guard = ds_guard.ground_plane.lock().expect("Ground plane is poisoned [Mutex]");
+    let ground_plane = ground_plane_guard.as_ref().filter(|converter| converter.is_initialized());
+    let projection = ds_guard.projection;
+    let mut errors = vec![];
+    let mut pixel_geometry = vec![];
+    let mut calibrations = vec![];
+    for (idx, new_zone) in new_zones.iter().enumerate() {
+        let prefix = format!("data[{}].", idx);
+        match project_request_geometry(ground_plane, &prefix, new_zone.pixel_points, new_zone.spatial_points) {
+            Ok(pixel_points) => {
+                let spatial_points = new_zone.spatial_points.map(|data| data.iter().map(|pt| Point2d::new(pt[0], pt[1])).collect());
+                errors.extend(validate_geometry(&prefix, pixel_points.clone(), spatial_points));
+                pixel_geometry.push(pixel_points);
+            },
+            Err(err) => {
+                errors.push(err);
+                pixel_geometry.push(None);
+            }
+        }
+        match calibrate_request(projection, &prefix, &new_zone.control_points) {
+            Ok(calibration) => calibrations.push(calibration),
+            Err(err) => {
+                errors.push(err);
+                calibrations.push(None);
+            }
+        }
+        if let Some(thresholds) = &new_zone.level_of_service {
+            errors.extend(validate_los_thresholds(&format!("{}level_of_service", prefix), thresholds));
+        }
+    }
+    drop(ground_plane_guard);
     drop(ds_guard);
+    if errors.len() > 0 {
+        return Ok(HttpResponse::build(StatusCode::BAD_REQUEST).json(GeometryErrorResponse {
+            error_text: "Invalid geometry".to_string(),
+            errors: errors
+        }));
+    }
+
+    // Mark data for clean
+    let need_to_clean: Vec<String> = if replace {
+        let ds_guard = data.data_storage.read().expect("DataStorage is poisoned [RWLock]");
+        let zones = ds_guard.zones.read().expect("Spatial data is poisoned [RWLock]");
+        zones.iter().map(|poly| poly.0.clone()).collect()
+    } else {
+        vec![]
+    };
 
     // Add new data
     let mut response = vec![];
-    for new_zone in _new_zones.data.iter() {
+    for ((new_zone, pixel_points), calibration) in new_zones.iter().zip(pixel_geometry.into_iter()).zip(calibrations.into_iter()) {
         let mut zone = Zone::default();
-        match new_zone.pixel_points {
-            Some(data) => {
-                zone.update_pixel_map(data);
+        zone.set_projection(projection);
+        match pixel_points {
+            Some(points) => {
+                zone.update_pixel_map_cv(points);
             },
             _ => {}
         }
@@ -260,6 +574,13 @@ pub async fn replace_all(data: web::Data<APIStorage>, _new_zones: web::Json<Poly
             _ => {}
         }
 
+        match &new_zone.group_name {
+            Some(val) => {
+                zone.set_group_name(val.clone());
+            },
+            _ => {}
+        }
+
         match new_zone.color_rgb {
             Some(val) => {
                 zone.set_color(val);
@@ -267,6 +588,48 @@ pub async fn replace_all(data: web::Data<APIStorage>, _new_zones: web::Json<Poly
             _ => {}
         }
 
+        match &new_zone.counted_classes {
+            Some(val) => {
+                zone.set_target_classes(val.clone());
+            },
+            _ => {}
+        }
+
+        match &new_zone.allowed_classes {
+            Some(val) => {
+                zone.set_allowed_classes(val.clone());
+            },
+            _ => {}
+        }
+
+        match &new_zone.level_of_service {
+            Some(val) => {
+                zone.set_los_thresholds(val.clone());
+            },
+            _ => {}
+        }
+
+        match &new_zone.queue {
+            Some(val) => {
+                zone.set_queue_parameters(val.clone());
+            },
+            _ => {}
+        }
+
+        match &new_zone.speed_trap {
+            Some(val) => {
+                zone.set_speed_trap(val.clone());
+            },
+            _ => {}
+        }
+
+        match (&new_zone.control_points, calibration) {
+            (Some(val), Some((converter, report))) => {
+                zone.set_calibration(val.clone(), converter, report);
+            },
+            _ => {}
+        }
+
         let new_id = zone.get_id().clone();
 
 
@@ -299,3 +662,126 @@ pub async fn replace_all(data: web::Data<APIStorage>, _new_zones: web::Json<Poly
         polygons_ids: response
     }));
 }
+
+#[derive(Debug, Deserialize)]
+pub struct CenterlineRoad {
+    // Centerline of the road ([longitude, latitude]) ordered along the traffic direction
+    pub centerline: GeoLineString,
+    pub lanes_num: u16,
+    // Width of each lane (meters)
+    pub lane_width: f64,
+    pub lane_direction: u8,
+    // Number of the leftmost lane (relative to the traffic direction). Lanes to the right get next numbers
+    pub first_lane_number: Option<u16>,
+    pub group_name: Option<String>,
+    pub counted_classes: Option<Vec<String>>,
+    pub allowed_classes: Option<Vec<String>>
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CenterlineReplaceAllRequest {
+    pub data: Vec<CenterlineRoad>
+}
+
+// Generates zones of road lanes around the centerline and projects them to the video frame
+fn centerline_to_zones(prefix: &str, road: &CenterlineRoad, ground_plane: &SpatialConverter) -> Result<Vec<PolygonCreateRequest>, GeometryError> {
+    let field = format!("{}centerline", prefix);
+    if road.centerline.geometry_type != "LineString" {
+        return Err(GeometryError {
+            field: field,
+            message: format!("Expected 'LineString' geometry, got '{}'", road.centerline.geometry_type)
+        });
+    }
+    let mut centerline = vec![];
+    for (i, pt) in road.centerline.coordinates.iter().enumerate() {
+        if pt.len() < 2 {
+            return Err(GeometryError {
+                field: field,
+                message: format!("Point #{} should have longitude and latitude", i)
+            });
+        }
+        centerline.push(Point2d::new(pt[0], pt[1]));
+    }
+    let lanes = match lanes_from_centerline(&centerline, road.lanes_num, road.lane_width) {
+        Ok(lanes) => lanes,
+        Err(err) => {
+            return Err(GeometryError {
+                field: field,
+                message: err
+            });
+        }
+    };
+    let first_lane_number = road.first_lane_number.unwrap_or(0);
+    let mut zones = vec![];
+    for (lane_idx, lane) in lanes.iter().enumerate() {
+        let mut pixel_points = [[0_u16; 2]; 4];
+        let mut spatial_points = [[0.0_f64; 2]; 4];
+        for (i, pt) in lane.iter().enumerate() {
+            let (x, y) = match ground_plane.transform_to_pixels(pt.x, pt.y) {
+                Some(xy) => xy,
+                None => {
+                    return Err(GeometryError {
+                        field: field,
+                        message: format!("Point #{} [{}, {}] of lane #{} can't be seen by the camera", i, pt.x, pt.y, lane_idx)
+                    });
+                }
+            };
+            if !(x >= 0.0 && y >= 0.0 && x <= u16::MAX as f32 && y <= u16::MAX as f32) {
+                return Err(GeometryError {
+                    field: field,
+                    message: format!("Point #{} [{}, {}] of lane #{} is projected outside of the video frame: [{}, {}]", i, pt.x, pt.y, lane_idx, x, y)
+                });
+            }
+            pixel_points[i] = [x.round() as u16, y.round() as u16];
+            spatial_points[i] = [pt.x, pt.y];
+        }
+        zones.push(PolygonCreateRequest {
+            pixel_points: Some(pixel_points),
+            spatial_points: Some(spatial_points),
+            lane_number: Some(first_lane_number + lane_idx as u16),
+            lane_direction: Some(road.lane_direction),
+            group_name: road.group_name.clone(),
+            color_rgb: None,
+            counted_classes: road.counted_classes.clone(),
+            allowed_classes: road.allowed_classes.clone(),
+            level_of_service: None,
+            queue: None,
+            speed_trap: None,
+            control_points: None
+        });
+    }
+    Ok(zones)
+}
+
+//
+// curl -XPOST 'http://localhost:42001/api/mutations/replace_all_by_centerline' -d '{"data":[{"centerline":{"type":"LineString","coordinates":[[37.618921,54.205633],[37.618951,54.205688]]},"lanes_num":2,"lane_width":3.5,"lane_direction":0,"group_name":"northbound approach"}]}' -H 'Content-Type: application/json'
+//
+pub async fn replace_all_by_centerline(data: web::Data<APIStorage>, _new_roads: web::Json<CenterlineReplaceAllRequest>) -> Result<HttpResponse, Error> {
+    let ds_guard = data.data_storage.read().expect("DataStorage is poisoned [RWLock]");
+    let ground_plane = ds_guard.ground_plane.lock().expect("Ground plane is poisoned [Mutex]");
+    let converter = match ground_plane.as_ref() {
+        Some(converter) => converter,
+        None => {
+            return Ok(HttpResponse::build(StatusCode::FAILED_DEPENDENCY).json(ErrorResponse {
+                error_text: "Camera-wide calibration is not configured. Set up [calibration] section".to_string()
+            }));
+        }
+    };
+    let mut new_zones = vec![];
+    let mut errors = vec![];
+    for (idx, road) in _new_roads.data.iter().enumerate() {
+        match centerline_to_zones(&format!("data[{}].", idx), road, converter) {
+            Ok(zones) => new_zones.extend(zones),
+            Err(err) => errors.push(err)
+        }
+    }
+    drop(ground_plane);
+    drop(ds_guard);
+    if errors.len() > 0 {
+        return Ok(HttpResponse::build(StatusCode::BAD_REQUEST).json(GeometryErrorResponse {
+            error_text: "Invalid geometry".to_string(),
+            errors: errors
+        }));
+    }
+    replace_all_zones(&data, &new_zones)
+}
@@ -0,0 +1,94 @@
+use actix_web::{HttpResponse, web, Error, http::StatusCode};
+use serde::Serialize;
+use utoipa::ToSchema;
+use chrono::{DateTime, Utc};
+
+use std::collections::HashMap;
+use crate::lib::data_storage::ODMatrix;
+use crate::lib::rest_api::APIStorage;
+use crate::lib::rest_api::zones_stats::StatsIntervalQuery;
+
+#[derive(Debug, Serialize)]
+pub struct ErrorResponse {
+    pub error_text: String,
+}
+
+/// Origin-destination (turning movements) matrix between detection zones for the equipment
+#[derive(Debug, Serialize, ToSchema)]
+pub struct AllZonesODMatrix {
+    /// Equipment identifier. Should match software configuration
+    #[schema(example = "1e23985f-1fa3-45d0-a365-2d8525a23ddd")]
+    pub equipment_id: String,
+    /// Aggregation interval (milliseconds) which the matrix belongs to
+    #[schema(example = 300000)]
+    pub interval_milliseconds: i64,
+    /// Start time for the statistics aggeration
+    #[schema(value_type = String, example = "2023-01-02T15:00:00Z")]
+    pub period_start: DateTime<Utc>,
+    /// End time for the statistics aggeration
+    #[schema(value_type = String, example = "2023-01-02T15:05:00Z")]
+    pub period_end: DateTime<Utc>,
+    /// Set of non-empty cells of the matrix
+    pub data: Vec<ODMatrixCell>
+}
+
+/// Number of vehicles moved from the origin zone to the destination zone
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ODMatrixCell {
+    /// Identifier of the zone where vehicles have been seen first
+    #[schema(example = "dir_0_lane_0")]
+    pub origin_zone_id: String,
+    /// Identifier of the zone where vehicles have been seen last
+    #[schema(example = "dir_1_lane_2")]
+    pub destination_zone_id: String,
+    /// Number of vehicles for every vehicle type. Key: vehicle type; Value - number of vehicles
+    #[schema(example = json!({"car": 12, "bus": 1}))]
+    pub statistics: HashMap<String, u32>
+}
+
+impl AllZonesODMatrix {
+    pub fn new(equipment_id: String, interval_milliseconds: i64, od_matrix: &ODMatrix) -> Self {
+        let mut ans = AllZonesODMatrix {
+            equipment_id: equipment_id,
+            interval_milliseconds: interval_milliseconds,
+            period_start: od_matrix.period_start,
+            period_end: od_matrix.period_end,
+            data: vec![]
+        };
+        for (od_pair, statistics) in od_matrix.data.iter() {
+            ans.data.push(ODMatrixCell {
+                origin_zone_id: od_pair.origin.clone(),
+                destination_zone_id: od_pair.destination.clone(),
+                statistics: statistics.clone()
+            });
+        }
+        ans
+    }
+}
+
+#[utoipa::path(
+    get,
+    tag = "Statistics",
+    path = "/api/stats/od_matrix",
+    params(StatsIntervalQuery),
+    responses(
+        (status = 200, description = "Origin-destination matrix between detection zones", body = AllZonesODMatrix),
+        (status = 400, description = "There is no such aggregation interval")
+    )
+)]
+pub async fn all_zones_od_matrix(data: web::Data<APIStorage>, _query: web::Query<StatsIntervalQuery>) -> Result<HttpResponse, Error> {
+    let ds_guard = data.data_storage.read().expect("DataStorage is poisoned [RWLock]");
+    let interval_milliseconds = _query.interval_milliseconds.unwrap_or(ds_guard.interval_milliseconds);
+    let od_matrices = ds_guard.od_matrices.read().expect("OD matrix is poisoned [RWLock]");
+    let ans = match od_matrices.get(&interval_milliseconds) {
+        Some(od_matrix) => AllZonesODMatrix::new(ds_guard.id.clone(), interval_milliseconds, od_matrix),
+        None => {
+            return Ok(HttpResponse::build(StatusCode::BAD_REQUEST).json(ErrorResponse {
+                error_text: format!("No such aggregation interval: {}. Available intervals: {:?}", interval_milliseconds, ds_guard.get_aggregation_intervals())
+            }));
+        }
+    };
+    drop(od_matrices);
+    drop(ds_guard);
+    return Ok(HttpResponse::Ok().json(ans));
+}
@@ -1,10 +1,16 @@
-use actix_web::{HttpResponse, web, Error};
-use serde::Serialize;
-use utoipa::ToSchema;
+use actix_web::{HttpResponse, web, Error, http::StatusCode};
+use serde::{
+    Deserialize,
+    Serialize
+};
+use utoipa::{IntoParams, ToSchema};
 use chrono::{DateTime, Utc};
 
 use std::collections::HashMap;
+use std::sync::Mutex;
 use crate::lib::rest_api::APIStorage;
+use crate::lib::zones::{Statistics, Zone};
+use crate::lib::zones::aggregation::{AggregatedStatistics, StatisticsAggregator};
 
 /// Information about aggregated road traffic flow parameters for the equipment
 #[derive(Debug, Serialize, ToSchema)]
@@ -12,8 +18,151 @@ pub struct AllZonesStats {
     /// Equipment identifier. Should match software configuration
     #[schema(example = "1e23985f-1fa3-45d0-a365-2d8525a23ddd")]
     pub equipment_id: String,
+    /// Aggregation interval which statistics is evaluated for (milliseconds)
+    #[schema(example = 30000)]
+    pub interval_milliseconds: i64,
     /// Set of data with summary information about road traffic parameters for each detection zone
-    pub data: Vec<ZoneStats>
+    pub data: Vec<ZoneStats>,
+    /// Road traffic parameters aggregated for each group of detection zones (e.g. approach)
+    pub groups: Vec<GroupStats>,
+    /// Road traffic parameters aggregated for each road lane direction
+    pub directions: Vec<DirectionStats>
+}
+
+impl AllZonesStats {
+    pub fn new(equipment_id: String, interval_milliseconds: i64, zones: &HashMap<String, Mutex<Zone>>) -> Self {
+        let mut ans = AllZonesStats {
+            equipment_id: equipment_id,
+            interval_milliseconds: interval_milliseconds,
+            data: vec![],
+            groups: vec![],
+            directions: vec![]
+        };
+        let mut groups: HashMap<String, StatisticsAggregator> = HashMap::new();
+        let mut directions: HashMap<u8, StatisticsAggregator> = HashMap::new();
+        for (_, zone_guarded) in zones.iter() {
+            let zone = zone_guarded.lock().expect("Zone is poisoned [Mutex]");
+            let statistics = match zone.get_statistics(interval_milliseconds) {
+                Some(statistics) => statistics,
+                None => {
+                    continue;
+                }
+            };
+            ans.data.push(ZoneStats::new(&zone, statistics));
+            if zone.group_name.len() > 0 {
+                groups.entry(zone.group_name.clone()).or_insert(StatisticsAggregator::default()).add_zone(zone.road_lane_num, statistics);
+            }
+            directions.entry(zone.road_lane_direction).or_insert(StatisticsAggregator::default()).add_zone(zone.road_lane_num, statistics);
+            drop(zone);
+        }
+        for (group_name, aggregator) in groups.iter() {
+            ans.groups.push(GroupStats {
+                group_name: group_name.clone(),
+                statistics: AggregatedParameters::from(&aggregator.get_statistics())
+            });
+        }
+        ans.groups.sort_by(|a, b| a.group_name.cmp(&b.group_name));
+        for (lane_direction, aggregator) in directions.iter() {
+            ans.directions.push(DirectionStats {
+                lane_direction: *lane_direction,
+                statistics: AggregatedParameters::from(&aggregator.get_statistics())
+            });
+        }
+        ans.directions.sort_by_key(|direction| direction.lane_direction);
+        ans
+    }
+}
+
+/// Road traffic parameters aggregated for the group of detection zones
+#[derive(Debug, Serialize, ToSchema)]
+pub struct GroupStats {
+    /// Name of the group
+    #[schema(example = "northbound approach")]
+    pub group_name: String,
+    /// Aggregated road traffic parameters
+    pub statistics: AggregatedParameters
+}
+
+/// Road traffic parameters aggregated for the road lane direction
+#[derive(Debug, Serialize, ToSchema)]
+pub struct DirectionStats {
+    /// Road lane direction
+    #[schema(example = 1)]
+    pub lane_direction: u8,
+    /// Aggregated road traffic parameters
+    pub statistics: AggregatedParameters
+}
+
+/// Road traffic parameters aggregated over several detection zones. Value "-1" indicates no data
+#[derive(Debug, Serialize, ToSchema)]
+pub struct AggregatedParameters {
+    /// Road lane numbers of aggregated detection zones
+    #[schema(example = json!([1, 2]))]
+    pub lanes: Vec<u16>,
+    /// Statistic for every vehicle type. Key: vehicle type; Value - aggregated road traffic flow parameters
+    #[schema(example = json!({"car":{"estimated_avg_speed":27.4,"estimated_sum_intensity":9,"estimated_flow_rate":108,"estimated_pcu_volume":9}}))]
+    pub vehicles_data: HashMap<String, AggregatedVehicleTypeParameters>,
+    /// Sum of flow rates (vehicles/hour)
+    #[schema(example = 1440.0)]
+    pub flow_rate: f32,
+    /// Sum of volumes in passenger car units
+    #[schema(example = 129.0)]
+    pub pcu_volume: f32,
+    /// Sum of flow rates in passenger car units (PCU/hour)
+    #[schema(example = 1548.0)]
+    pub pcu_flow_rate: f32,
+    /// Sum of densities (vehicles/km)
+    #[schema(example = 36.4)]
+    pub density: f32,
+    /// Average percentage of time when at least one vehicle was inside the zone
+    #[schema(example = 37.5)]
+    pub occupancy_percent: f32,
+    /// Maximum queue length from the stop-line among aggregated zones (meters)
+    #[schema(example = 24.5)]
+    pub max_queue_length: f32,
+    /// Number of vehicles of disallowed types for every vehicle type
+    #[schema(example = json!({"car": 2}))]
+    pub violations: HashMap<String, u32>
+}
+
+/// Road traffic parameters for specific vehicle type aggregated over several detection zones
+#[derive(Debug, Serialize, ToSchema)]
+pub struct AggregatedVehicleTypeParameters {
+    /// Average speed weighted by number of vehicles. Value "-1" indicates no vehicles with known speed
+    #[schema(example = 27.4)]
+    pub estimated_avg_speed: f32,
+    /// Summary road traffic flow
+    #[schema(example = 9)]
+    pub estimated_sum_intensity: u32,
+    /// Sum of flow rates (vehicles/hour). Value "-1" indicates unknown period
+    #[schema(example = 108.0)]
+    pub estimated_flow_rate: f32,
+    /// Summary road traffic flow in passenger car units
+    #[schema(example = 9.0)]
+    pub estimated_pcu_volume: f32
+}
+
+impl From<&AggregatedStatistics> for AggregatedParameters {
+    fn from(statistics: &AggregatedStatistics) -> Self {
+        AggregatedParameters {
+            lanes: statistics.lanes.clone(),
+            vehicles_data: statistics.vehicles_data.iter().map(|(vehicle_type, parameters)| {
+                (vehicle_type.clone(), AggregatedVehicleTypeParameters {
+                    estimated_avg_speed: parameters.avg_speed,
+                    estimated_sum_intensity: parameters.sum_intensity,
+                    estimated_flow_rate: parameters.flow_rate,
+                    estimated_pcu_volume: parameters.pcu_volume
+                })
+            }).collect(),
+            flow_rate: statistics.flow_rate,
+            pcu_volume: statistics.pcu_volume,
+            pcu_flow_rate: statistics.pcu_flow_rate,
+            density: statistics.density,
+            occupancy_percent: statistics.occupancy_percent,
+            max_queue_length: statistics.max_queue_length,
+            violations: statistics.violations.clone()
+        }
+    }
 }
 
 /// Summary information for each detection zone
@@ -25,6 +174,9 @@ pub struct ZoneStats {
     /// Corresponding road lane direction
     #[schema(example = 1)]
     pub lane_direction: u8,
+    /// Name of the group (e.g. approach) which zone belongs to. Empty string means that zone is not grouped
+    #[schema(example = "northbound approach")]
+    pub group_name: String,
     /// Start time for the statistics aggeration
     #[schema(value_type = String, example = "2023-01-02T15:00:00Z")]
     pub period_start: DateTime<Utc>,
@@ -32,8 +184,130 @@ pub struct ZoneStats {
     #[schema(value_type = String, example = "2023-01-02T15:05:00Z")]
     pub period_end: DateTime<Utc>,
     /// Statistic for every vehicle type. Key: vehicle type; Value - road traffic flow parameters
-    #[schema(example = json!({"train":{"estimated_avg_speed":-1,"estimated_sum_intensity":0},"bus":{"estimated_avg_speed":15.2,"estimated_sum_intensity":2},"truck":{"estimated_avg_speed":20.965343,"estimated_sum_intensity":3},"car":{"estimated_avg_speed":23.004976,"estimated_sum_intensity":4},"motorbike":{"estimated_avg_speed":-1,"estimated_sum_intensity":0}  }))]
-    pub statistics: HashMap<String, VehicleTypeParameters>
+    #[schema(example = json!({"bus":{"estimated_avg_speed":15.2,"estimated_sum_intensity":2,"estimated_flow_rate":240,"estimated_pcu_volume":6,"min_speed":12.1,"max_speed":18.3,"median_speed":15.2,"p85_speed":17.4,"std_speed":3.1,"speed_histogram":[0,1,1,0,0,0,0,0,0,0,0,0,0,0,0,0]},"car":{"estimated_avg_speed":23.004976,"estimated_sum_intensity":4,"estimated_flow_rate":480,"estimated_pcu_volume":4,"min_speed":17.5,"max_speed":29.8,"median_speed":22.4,"p85_speed":27.1,"std_speed":4.6,"speed_histogram":[0,1,3,0,0,0,0,0,0,0,0,0,0,0,0,0]},"motorbike":{"estimated_avg_speed":-1,"estimated_sum_intensity":0,"estimated_flow_rate":0,"estimated_pcu_volume":0,"min_speed":-1,"max_speed":-1,"median_speed":-1,"p85_speed":-1,"std_speed":-1,"speed_histogram":[0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0]}}))]
+    pub statistics: HashMap<String, VehicleTypeParameters>,
+    /// Headway, gap and spacing between consecutive vehicles passing the middle of the zone
+    pub headway: HeadwayParameters,
+    /// Maximum queue length from the stop-line (meters)
+    #[schema(example = 24.5)]
+    pub max_queue_length: f32,
+    /// Average queue length from the stop-line (meters)
+    #[schema(example = 8.2)]
+    pub avg_queue_length: f32,
+    /// Percentage of time when at least one vehicle was inside the zone. Value "-1" indicates no data
+    #[schema(example = 37.5)]
+    pub occupancy_percent: f32,
+    /// Percentage of time when at least one vehicle was over the virtual loop in the middle of the zone. Value "-1" indicates no data
+    #[schema(example = 12.1)]
+    pub loop_occupancy_percent: f32,
+    /// Number of vehicles of disallowed types for every vehicle type. Key: vehicle type; Value - number of violations
+    #[schema(example = json!({"car": 2}))]
+    pub violations: HashMap<String, u32>,
+    /// Flow rate for every counted vehicle type (vehicles/hour). Value "-1" indicates unknown period
+    #[schema(example = 720.0)]
+    pub flow_rate: f32,
+    /// Volume for every counted vehicle type in passenger car units
+    #[schema(example = 64.5)]
+    pub pcu_volume: f32,
+    /// Flow rate in passenger car units (PCU/hour). Value "-1" indicates unknown period
+    #[schema(example = 774.0)]
+    pub pcu_flow_rate: f32,
+    /// Density (vehicles/km) based on time-mean number of vehicles in the zone and its length. Value "-1" indicates no data
+    #[schema(example = 18.2)]
+    pub density: f32,
+    /// Level of service grade (A-F) based on density and ratio between average speed and free-flow speed. Value "-" indicates no data
+    #[schema(example = "C")]
+    pub level_of_service: String,
+    /// Whether statistics of the period could be trusted. It is false when camera has been tampered during the period
+    #[schema(example = true)]
+    pub valid: bool,
+    /// Reasons why statistics is invalid: "displaced", "scene_changed", "blurred", "too_dark", "too_bright", "frozen"
+    #[schema(example = json!([]))]
+    pub invalid_reasons: Vec<String>
+}
+
+impl ZoneStats {
+    pub fn new(zone: &Zone, statistics: &Statistics) -> Self {
+        let mut stats = ZoneStats{
+            lane_number: zone.road_lane_num,
+            lane_direction: zone.road_lane_direction,
+            group_name: zone.group_name.clone(),
+            period_start: statistics.period_start,
+            period_end: statistics.period_end,
+            statistics: HashMap::new(),
+            headway: HeadwayParameters{
+                vehicles_passed: statistics.headway.vehicles_passed,
+                avg_headway: statistics.headway.avg_headway,
+                min_headway: statistics.headway.min_headway,
+                max_headway: statistics.headway.max_headway,
+                avg_gap: statistics.headway.avg_gap,
+                min_gap: statistics.headway.min_gap,
+                avg_spacing: statistics.headway.avg_spacing,
+                min_spacing: statistics.headway.min_spacing,
+                max_spacing: statistics.headway.max_spacing
+            },
+            max_queue_length: statistics.queue.max_queue_length,
+            avg_queue_length: statistics.queue.avg_queue_length,
+            occupancy_percent: statistics.occupancy.occupancy_percent,
+            loop_occupancy_percent: statistics.occupancy.loop_occupancy_percent,
+            violations: statistics.violations.clone(),
+            flow_rate: statistics.traffic_flow.flow_rate,
+            pcu_volume: statistics.traffic_flow.pcu_volume,
+            pcu_flow_rate: statistics.traffic_flow.pcu_flow_rate,
+            density: statistics.traffic_flow.density,
+            level_of_service: statistics.level_of_service.as_str().to_string(),
+            valid: statistics.invalid_reasons.is_empty(),
+            invalid_reasons: statistics.invalid_reasons.clone()
+        };
+        for (vehicle_type, vehicle_statistics) in statistics.vehicles_data.iter() {
+            let distribution = &vehicle_statistics.speed_distribution;
+            stats.statistics.insert(vehicle_type.to_string(), VehicleTypeParameters{
+                estimated_avg_speed: vehicle_statistics.avg_speed,
+                estimated_sum_intensity: vehicle_statistics.sum_intensity,
+                estimated_flow_rate: vehicle_statistics.flow_rate,
+                estimated_pcu_volume: vehicle_statistics.pcu_volume,
+                min_speed: distribution.min_speed,
+                max_speed: distribution.max_speed,
+                median_speed: distribution.median_speed,
+                p85_speed: distribution.p85_speed,
+                std_speed: distribution.std_speed,
+                speed_histogram: distribution.histogram.clone()
+            });
+        }
+        stats
+    }
+}
+
+/// Headway, gap and spacing between consecutive vehicles. Value "-1" indicates not enough vehicles detected
+#[derive(Debug, Serialize, ToSchema)]
+pub struct HeadwayParameters {
+    /// Number of vehicles passed the reference point
+    #[schema(example = 12)]
+    pub vehicles_passed: u32,
+    /// Average time headway between fronts of consecutive vehicles (seconds)
+    #[schema(example = 2.4)]
+    pub avg_headway: f32,
+    /// Minimum time headway (seconds)
+    #[schema(example = 1.1)]
+    pub min_headway: f32,
+    /// Maximum time headway (seconds)
+    #[schema(example = 7.8)]
+    pub max_headway: f32,
+    /// Average time gap between rear of the leader and front of the follower (seconds)
+    #[schema(example = 1.9)]
+    pub avg_gap: f32,
+    /// Minimum time gap (seconds)
+    #[schema(example = 0.6)]
+    pub min_gap: f32,
+    /// Average spacing between fronts of consecutive vehicles (meters)
+    #[schema(example = 21.5)]
+    pub avg_spacing: f32,
+    /// Minimum spacing (meters)
+    #[schema(example = 9.3)]
+    pub min_spacing: f32,
+    /// Maximum spacing (meters)
+    #[schema(example = 64.0)]
+    pub max_spacing: f32
 }
 
 /// Road traffic parameters for specific vehicle type
@@ -44,41 +318,68 @@ pub struct VehicleTypeParameters {
     pub estimated_avg_speed: f32,
     /// Summary road traffic flow (if it is needed could be extrapolated to the intensity: vehicles/hour)
     #[schema(example = 15)]
-    pub estimated_sum_intensity: u32
+    pub estimated_sum_intensity: u32,
+    /// Flow rate extrapolated from the summary road traffic flow (vehicles/hour). Value "-1" indicates unknown period
+    #[schema(example = 180.0)]
+    pub estimated_flow_rate: f32,
+    /// Summary road traffic flow in passenger car units
+    #[schema(example = 15.0)]
+    pub estimated_pcu_volume: f32,
+    /// Minimum speed. Value "-1" indicates no vehicles with known speed
+    #[schema(example = 18.4)]
+    pub min_speed: f32,
+    /// Maximum speed. Value "-1" indicates no vehicles with known speed
+    #[schema(example = 51.7)]
+    pub max_speed: f32,
+    /// Median speed. Value "-1" indicates no vehicles with known speed
+    #[schema(example = 31.5)]
+    pub median_speed: f32,
+    /// 85th percentile of speed. Value "-1" indicates no vehicles with known speed
+    #[schema(example = 42.3)]
+    pub p85_speed: f32,
+    /// Standard deviation of speed. Value "-1" indicates no vehicles with known speed
+    #[schema(example = 8.9)]
+    pub std_speed: f32,
+    /// Number of vehicles in speed bins of 10 km/h: [0; 10), [10; 20), ... The last bin includes every speed above 150 km/h
+    #[schema(example = json!([0, 1, 3, 6, 4, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]))]
+    pub speed_histogram: Vec<u32>
+}
+
+#[derive(Debug, Serialize)]
+pub struct ErrorResponse {
+    pub error_text: String,
+}
+
+/// Aggregation interval of the statistics
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct StatsIntervalQuery {
+    /// Aggregation interval (milliseconds). Main interval ('reset_data_milliseconds') is used if it is not set
+    #[param(example = 300000)]
+    pub interval_milliseconds: Option<i64>
 }
 
 #[utoipa::path(
     get,
     tag = "Statistics",
     path = "/api/stats/all",
+    params(StatsIntervalQuery),
     responses(
-        (status = 200, description = "List of detections zones", body = AllZonesStats)
+        (status = 200, description = "List of detections zones", body = AllZonesStats),
+        (status = 400, description = "There is no such aggregation interval")
     )
 )]
-pub async fn all_zones_stats(data: web::Data<APIStorage>) -> Result<HttpResponse, Error> {
+pub async fn all_zones_stats(data: web::Data<APIStorage>, _query: web::Query<StatsIntervalQuery>) -> Result<HttpResponse, Error> {
     let ds_guard = data.data_storage.read().expect("DataStorage is poisoned [RWLock]");
-    let zones = ds_guard.zones.read().expect("Spatial data is poisoned [RWLock]");
-    let mut ans: AllZonesStats = AllZonesStats{
-        equipment_id: ds_guard.id.clone(),
-        data: vec![]
-    };
-    for (_, zone_guarded) in zones.iter() {
-        let zone = zone_guarded.lock().expect("Zone is poisoned [Mutex]");
-        let mut stats = ZoneStats{
-            lane_number: zone.road_lane_num,
-            lane_direction: zone.road_lane_direction,
-            period_start: zone.statistics.period_start,
-            period_end: zone.statistics.period_end,
-            statistics: HashMap::new()
-        };
-        for (vehicle_type, statistics) in zone.statistics.vehicles_data.iter() {
-            stats.statistics.insert(vehicle_type.to_string(), VehicleTypeParameters{
-                estimated_avg_speed: statistics.avg_speed,
-                estimated_sum_intensity: statistics.sum_intensity
-            });
-        }
-        ans.data.push(stats);
+    let intervals = ds_guard.get_aggregation_intervals();
+    let interval_milliseconds = _query.interval_milliseconds.unwrap_or(ds_guard.interval_milliseconds);
+    if !intervals.contains(&interval_milliseconds) {
+        return Ok(HttpResponse::build(StatusCode::BAD_REQUEST).json(ErrorResponse {
+            error_text: format!("No such aggregation interval: {}. Available intervals: {:?}", interval_milliseconds, intervals)
+        }));
     }
+    let zones = ds_guard.zones.read().expect("Spatial data is poisoned [RWLock]");
+    let ans = AllZonesStats::new(ds_guard.id.clone(), interval_milliseconds, &zones);
     drop(zones);
     drop(ds_guard);
     return Ok(HttpResponse::Ok().json(ans));
@@ -109,7 +410,10 @@ pub struct ZoneRealtime {
     pub last_time: u64,
     /// Occupancy
     #[schema(example = 3)]
-    pub occupancy: u16
+    pub occupancy: u16,
+    /// Queue length from the stop-line (meters)
+    #[schema(example = 12.3)]
+    pub queue_length: f32
 }
 
 #[utoipa::path(
@@ -133,7 +437,8 @@ pub async fn all_zones_occupancy(data: web::Data<APIStorage>) -> Result<HttpResp
             lane_number: zone.road_lane_num,
             lane_direction: zone.road_lane_direction,
             last_time: zone.current_statistics.last_time,
-            occupancy: zone.current_statistics.occupancy
+            occupancy: zone.current_statistics.occupancy,
+            queue_length: zone.current_statistics.queue_length
         };
         ans.data.push(stats);
     }
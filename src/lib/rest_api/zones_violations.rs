@@ -0,0 +1,106 @@
+use actix_web::{HttpResponse, web, Error, http::StatusCode};
+use serde::Serialize;
+use utoipa::ToSchema;
+use chrono::{DateTime, Utc};
+
+use crate::lib::data_storage::ViolationEvent;
+use crate::lib::rest_api::APIStorage;
+
+#[derive(Debug, Serialize)]
+pub struct ErrorResponse {
+    pub error_text: String,
+}
+
+/// Violation events (appearance of vehicles of disallowed types in the zones) for the equipment
+#[derive(Debug, Serialize, ToSchema)]
+pub struct AllViolations {
+    /// Equipment identifier. Should match software configuration
+    #[schema(example = "1e23985f-1fa3-45d0-a365-2d8525a23ddd")]
+    pub equipment_id: String,
+    /// Set of violation events
+    pub data: Vec<ViolationInfo>
+}
+
+/// Appearance of the vehicle of disallowed type in the zone (e.g. car in the bus lane)
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ViolationInfo {
+    /// Unique identifier of the event
+    #[schema(example = "9b3e2c0e-4a41-4d0f-8f5e-d0c1f7a8b7d2")]
+    pub id: String,
+    /// Zone identifier
+    #[schema(example = "dir_0_lane_1")]
+    pub zone_id: String,
+    /// Identifier of the tracked object
+    #[schema(example = "1f0b7a5e-3c5d-4b4e-9e43-6c2f6e1d0c1a")]
+    pub object_id: String,
+    /// Vehicle type
+    #[schema(example = "car")]
+    pub classname: String,
+    /// Time when violation has been registered
+    #[schema(value_type = String, example = "2023-01-02T15:01:12Z")]
+    pub event_time: DateTime<Utc>,
+    /// Relative URL of the JPEG snapshot for the event
+    #[schema(example = "/api/violations/snapshot/9b3e2c0e-4a41-4d0f-8f5e-d0c1f7a8b7d2")]
+    pub snapshot_url: String
+}
+
+impl From<&ViolationEvent> for ViolationInfo {
+    fn from(event: &ViolationEvent) -> Self {
+        ViolationInfo {
+            id: event.id.clone(),
+            zone_id: event.zone_id.clone(),
+            object_id: event.object_id.clone(),
+            classname: event.classname.clone(),
+            event_time: event.event_time,
+            snapshot_url: format!("/api/violations/snapshot/{}", event.id)
+        }
+    }
+}
+
+#[utoipa::path(
+    get,
+    tag = "Violations",
+    path = "/api/violations/list",
+    responses(
+        (status = 200, description = "Last violation events", body = AllViolations)
+    )
+)]
+pub async fn all_violations(data: web::Data<APIStorage>) -> Result<HttpResponse, Error> {
+    let ds_guard = data.data_storage.read().expect("DataStorage is poisoned [RWLock]");
+    let violations = ds_guard.violations.read().expect("Violations storage is poisoned [RWLock]");
+    let ans = AllViolations {
+        equipment_id: ds_guard.id.clone(),
+        data: violations.get_events().into_iter().map(ViolationInfo::from).collect()
+    };
+    drop(violations);
+    drop(ds_guard);
+    return Ok(HttpResponse::Ok().json(ans));
+}
+
+#[utoipa::path(
+    get,
+    tag = "Violations",
+    path = "/api/violations/snapshot/{event_id}",
+    params(
+        ("event_id" = String, Path, description = "Identifier of the violation event")
+    ),
+    responses(
+        (status = 200, description = "JPEG snapshot of the violation event", content_type = "image/jpeg"),
+        (status = 404, description = "No such event (or it has been dropped already)")
+    )
+)]
+pub async fn violation_snapshot(data: web::Data<APIStorage>, event_id: web::Path<String>) -> Result<HttpResponse, Error> {
+    let ds_guard = data.data_storage.read().expect("DataStorage is poisoned [RWLock]");
+    let violations = ds_guard.violations.read().expect("Violations storage is poisoned [RWLock]");
+    let snapshot = match violations.get_event(&event_id) {
+        Some(event) => event.snapshot.to_vec(),
+        None => {
+            return Ok(HttpResponse::build(StatusCode::NOT_FOUND).json(ErrorResponse {
+                error_text: format!("No such violation event. Requested ID: {}", event_id)
+            }));
+        }
+    };
+    drop(violations);
+    drop(ds_guard);
+    return Ok(HttpResponse::Ok().content_type("image/jpeg").body(snapshot));
+}
@@ -0,0 +1,136 @@
+use opencv::core::{Point2d, Point2f};
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::fmt;
+
+use crate::lib::spatial::haversine::haversine;
+use crate::lib::spatial::projection::ProjectionKind;
+use crate::lib::spatial::SpatialConverter;
+
+pub const MIN_CONTROL_POINTS: usize = 4;
+// Maximum reprojection error (meters on the ground) for the control point to be treated as inlier
+const RANSAC_THRESHOLD_METERS: f64 = 1.0;
+
+// Pair of corresponding points: on the video frame and on the ground
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ControlPoint {
+    // [x, y] in pixels
+    pub pixel: [f32; 2],
+    // [longitude, latitude] in WGS84
+    pub wgs84: [f64; 2],
+}
+
+#[derive(Debug, Clone)]
+pub struct ControlPointError {
+    pub point: ControlPoint,
+    // Distance (meters) between given WGS84 point and reprojected pixel point
+    pub error_meters: f32,
+    // Whether point has been used for the final estimation
+    pub inlier: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct CalibrationReport {
+    pub points: Vec<ControlPointError>,
+    pub inliers_num: usize,
+    // Root mean square of reprojection errors (meters) for inliers. Value "-1" indicates no inliers
+    pub rms_error: f32,
+}
+
+impl CalibrationReport {
+    pub fn new(points: &[ControlPoint], reprojected: &[(f64, f64)], inliers: &[bool]) -> Self {
+        let points: Vec<ControlPointError> = points
+            .iter()
+            .zip(reprojected.iter())
+            .zip(inliers.iter())
+            .map(|((point, projected), inlier)| ControlPointError {
+                point: point.clone(),
+                error_meters: (haversine(point.wgs84[0], point.wgs84[1], projected.0, projected.1) * 1000.0) as f32,
+                inlier: *inlier,
+            })
+            .collect();
+        let inliers_errors: Vec<f32> = points.iter().filter(|point| point.inlier).map(|point| point.error_meters).collect();
+        let rms_error = if inliers_errors.len() > 0 {
+            (inliers_errors.iter().map(|error| error * error).sum::<f32>() / inliers_errors.len() as f32).sqrt()
+        } else {
+            -1.0
+        };
+        CalibrationReport {
+            points: points,
+            inliers_num: inliers_errors.len(),
+            rms_error: rms_error,
+        }
+    }
+}
+
+impl fmt::Display for CalibrationReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "RMS error: {:.3} m, inliers: {}/{}", self.rms_error, self.inliers_num, self.points.len())?;
+        for point in self.points.iter() {
+            write!(
+                f,
+                "\n\tpixel [{}, {}] -> WGS84 [{}, {}]: error {:.3} m{}",
+                point.point.pixel[0],
+                point.point.pixel[1],
+                point.point.wgs84[0],
+                point.point.wgs84[1],
+                point.error_meters,
+                if point.inlier { "" } else { " (outlier)" }
+            )?;
+        }
+        Ok(())
+    }
+}
+
+// Estimates transformation from pixels to the given metric plane by control points. Outliers (e.g. bad GPS measurements) are rejected by RANSAC
+pub fn calibrate(kind: ProjectionKind, points: &[ControlPoint]) -> Result<(SpatialConverter, CalibrationReport), Box<dyn Error>> {
+    if points.len() < MIN_CONTROL_POINTS {
+        return Err(format!("Need at least {} control points, got {}", MIN_CONTROL_POINTS, points.len()).into());
+    }
+    let src: Vec<Point2f> = points.iter().map(|point| Point2f::new(point.pixel[0], point.pixel[1])).collect();
+    let dest: Vec<Point2d> = points.iter().map(|point| Point2d::new(point.wgs84[0], point.wgs84[1])).collect();
+    // Web Mercator stretches distances by 1/cos(latitude), so threshold should be stretched too
+    let threshold = match kind {
+        ProjectionKind::Mercator => {
+            let avg_lat = points.iter().map(|point| point.wgs84[1]).sum::<f64>() / points.len() as f64;
+            RANSAC_THRESHOLD_METERS / avg_lat.to_radians().cos()
+        }
+        ProjectionKind::Enu => RANSAC_THRESHOLD_METERS,
+    };
+    let (converter, inliers) = SpatialConverter::new_homography(kind, src, dest, threshold)?;
+    let reprojected: Vec<(f64, f64)> = points
+        .iter()
+        .map(|point| converter.transform_to_wgs84(point.pixel[0], point.pixel[1]))
+        .collect::<Option<Vec<(f64, f64)>>>()
+        .ok_or("Estimated homography can't be applied to control points")?;
+    let report = CalibrationReport::new(points, &reprojected, &inliers);
+    Ok((converter, report))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn test_calibration_report() {
+        let points = vec![
+            ControlPoint { pixel: [0.0, 0.0], wgs84: [37.6190, 54.2056] },
+            ControlPoint { pixel: [100.0, 0.0], wgs84: [37.6191, 54.2056] },
+            ControlPoint { pixel: [100.0, 100.0], wgs84: [37.6191, 54.2057] },
+            ControlPoint { pixel: [0.0, 100.0], wgs84: [37.6190, 54.2057] },
+        ];
+        // Third point is shifted by 0.0001 degree of latitude (about 11 meters)
+        let reprojected = vec![(37.6190, 54.2056), (37.6191, 54.2056), (37.6191, 54.2058), (37.6190, 54.2057)];
+        let report = CalibrationReport::new(&points, &reprojected, &[true, true, false, true]);
+        assert_eq!(report.inliers_num, 3);
+        assert!(report.rms_error < 0.001);
+        assert!((report.points[2].error_meters - 11.12).abs() < 0.01);
+        assert_eq!(report.points[2].inlier, false);
+
+        let report = CalibrationReport::new(&points, &reprojected, &[true, true, true, true]);
+        // sqrt(11.12^2 / 4)
+        assert!((report.rms_error - 5.56).abs() < 0.01);
+
+        let report = CalibrationReport::new(&points, &reprojected, &[false, false, false, false]);
+        assert_eq!(report.rms_error, -1.0);
+    }
+}
@@ -1,45 +1,45 @@
-use opencv::core::Point2f;
+use opencv::core::Point2d;
 
-use std::f32::consts::PI;
-use std::f32::consts::E;
-const EARTH_RADIUS_M: f32 = 6378137.0;
-const EARTH_RADIUS_KM: f32 = 6378.137;
-const f: f32 = 298.257223563;
+use std::f64::consts::PI;
+use std::f64::consts::E;
+const EARTH_RADIUS_M: f64 = 6378137.0;
+const EARTH_RADIUS_KM: f64 = 6378.137;
+const f: f64 = 298.257223563;
 
-pub fn lonlat_to_meters_cv(lon_lat: &Point2f) -> Point2f {
+pub fn lonlat_to_meters_cv(lon_lat: &Point2d) -> Point2d {
     let lon = lon_lat.x;
     let lat = lon_lat.y;
     let x = lon2x(lon);
     let y = lat2y(lat);
-    Point2f::new(x, y)
+    Point2d::new(x, y)
 }
 
-pub fn lon2x(lon: f32) -> f32 {
+pub fn lon2x(lon: f64) -> f64 {
     EARTH_RADIUS_KM * 1000. * lon.to_radians()
 }
 
-pub fn x2lon(x: f32) -> f32 {
+pub fn x2lon(x: f64) -> f64 {
     (x / (EARTH_RADIUS_KM * 1000.)).to_degrees()
 }
 
-pub fn lat2y(lat: f32) -> f32 {
+pub fn lat2y(lat: f64) -> f64 {
     ((lat.to_radians() / 2. + PI / 4.).tan()).log(E)
         * EARTH_RADIUS_KM
         * 1000.
 }
 
-pub fn y2lat(y: f32) -> f32 {
+pub fn y2lat(y: f64) -> f64 {
     (2. * ((y / (EARTH_RADIUS_KM * 1000.)).exp()).atan() - PI / 2.)
         .to_degrees()
 }
 
-pub fn lonlat_to_meters(lon: f32, lat: f32) -> (f32, f32) {
+pub fn lonlat_to_meters(lon: f64, lat: f64) -> (f64, f64) {
     let x = lon2x(lon);
     let y = lat2y(lat);
     (x, y)
 }
 
-pub fn meters_to_lonlat(x: f32, y: f32) -> (f32, f32) {
+pub fn meters_to_lonlat(x: f64, y: f64) -> (f64, f64) {
     let lon = x2lon(x);
     let lat = y2lat(y);
     (lon, lat)
@@ -50,13 +50,13 @@ mod tests {
     use super::*;
     #[test]
     fn test_epsg_conversion() {
-        let test_lon: f32 = 37.6202637616082;
-        let test_lat: f32 = 54.208100345367;
+        let test_lon: f64 = 37.6202637616082;
+        let test_lat: f64 = 54.208100345367;
 
-        let eps_xy: f32 = 3.0;
-        let eps_lonlat: f32 = 0.0001;
-        let correct_x: f32 = 4187868.6054508663;
-        let correct_y: f32 = 7209666.936059543;
+        let eps_xy: f64 = 0.000001;
+        let eps_lonlat: f64 = 0.000000001;
+        let correct_x: f64 = 4187868.6054508663;
+        let correct_y: f64 = 7209666.936059543;
         let (x, y) = lonlat_to_meters(test_lon, test_lat);
         println!("x: {}, y: {}", x, y);
         println!("correct_x: {}, correct_y: {}", correct_x, correct_y);
@@ -67,4 +67,13 @@ mod tests {
         assert!((lon - test_lon).abs() < eps_lonlat);
         assert!((lat - test_lat).abs() < eps_lonlat);
     }
+    #[test]
+    fn test_epsg_precision() {
+        // Shift by 1 cm along both axes should survive the round trip at EPSG:3857 magnitudes
+        let (x, y) = lonlat_to_meters(37.6202637616082, 54.208100345367);
+        let (lon, lat) = meters_to_lonlat(x + 0.01, y + 0.01);
+        let (x_shifted, y_shifted) = lonlat_to_meters(lon, lat);
+        assert!((x_shifted - x - 0.01).abs() < 0.000001);
+        assert!((y_shifted - y - 0.01).abs() < 0.000001);
+    }
 }
\ No newline at end of file
@@ -1,7 +1,7 @@
 use opencv::{
-    core::Point2f
+    core::Point2d
 };
-const EARTH_RADIUS_KM: f32 = 6371.0;
+const EARTH_RADIUS_KM: f64 = 6371.0;
 
 // haversine_cv
 // 
@@ -9,7 +9,7 @@ const EARTH_RADIUS_KM: f32 = 6371.0;
 // src - source point containing longitude/latitude [x;y]
 // dst - target point containing longitude/latitude [x;y]
 // 
-pub fn haversine_cv(src: Point2f, dst: Point2f) -> f32 {
+pub fn haversine_cv(src: Point2d, dst: Point2d) -> f64 {
     haversine(src.x, src.y, dst.x, dst.y)
 }
 
@@ -19,13 +19,13 @@ pub fn haversine_cv(src: Point2f, dst: Point2f) -> f32 {
 // src_lon/src_lat - source point containing longitude/latitude [x;y]
 // dst_lon/dst_lat - target point containing longitude/latitude [x;y]
 // 
-pub fn haversine(src_lon: f32, src_lat: f32, dst_lon: f32, dst_lat: f32) -> f32 {
+pub fn haversine(src_lon: f64, src_lat: f64, dst_lon: f64, dst_lat: f64) -> f64 {
     let lat1 = src_lat.to_radians();
 	let lat2 = dst_lat.to_radians();
     let diff_lat = (dst_lat - src_lat).to_radians();
 	let diff_lon = (dst_lon - src_lon).to_radians();
-    let a = f32::powi(f32::sin(diff_lat / 2.0), 2) + f32::cos(lat1)*f32::cos(lat2)*f32::powi(f32::sin(diff_lon/2.0), 2);
-    let c = 2.0 * f32::atan2(f32::sqrt(a), f32::sqrt(1.0 - a));
+    let a = f64::powi(f64::sin(diff_lat / 2.0), 2) + f64::cos(lat1)*f64::cos(lat2)*f64::powi(f64::sin(diff_lon/2.0), 2);
+    let c = 2.0 * f64::atan2(f64::sqrt(a), f64::sqrt(1.0 - a));
 	let km = c * EARTH_RADIUS_KM;
     return km;
 }
@@ -34,16 +34,16 @@ pub fn haversine(src_lon: f32, src_lat: f32, dst_lon: f32, dst_lat: f32) -> f32
 //
 // Computes center between two points on a sphere [OpenCV version]
 //
-pub fn compute_center_cv(a: Point2f, b: Point2f) -> Point2f {
+pub fn compute_center_cv(a: Point2d, b: Point2d) -> Point2d {
     let center = compute_center(a.x, a.y, b.x, b.y);
-    Point2f::new(center.0, center.1)
+    Point2d::new(center.0, center.1)
 }
 
 // compute_center
 //
 // Computes center between two points on a sphere
 //
-pub fn compute_center(lon1: f32, lat1: f32, lon2: f32, lat2: f32) -> (f32, f32) {
+pub fn compute_center(lon1: f64, lat1: f64, lon2: f64, lat2: f64) -> (f64, f64) {
     // Convert longitude and latitude to radians
     let lon1_rad = lon1.to_radians();
     let lat1_rad = lat1.to_radians();
@@ -80,30 +80,37 @@ mod tests {
     use super::*;
     #[test]
     fn test_haversine_cv() {
-        let src = Point2f::new(6.602018, 52.036769);
-        let dst = Point2f::new(6.603560, 52.036730);
+        let src = Point2d::new(6.602018, 52.036769);
+        let dst = Point2d::new(6.603560, 52.036730);
         let dist = haversine_cv(src, dst);
-        let correct_great_circle_distance = 0.10556793 as f32;
-        let eps = 0.000001;
+        let correct_great_circle_distance = 0.10556533 as f64;
+        let eps = 0.000000001;
         assert!((dist - correct_great_circle_distance).abs() < eps);
     }
     #[test]
     fn test_haversine() {
-        let src: (f32, f32) = (6.602018, 52.036769);
-        let dst: (f32, f32) = (6.603560, 52.036730);
+        let src: (f64, f64) = (6.602018, 52.036769);
+        let dst: (f64, f64) = (6.603560, 52.036730);
         let dist = haversine(src.0, src.1, dst.0, dst.1);
-        let correct_great_circle_distance = 0.10556793 as f32;
-        let eps = 0.000001;
+        let correct_great_circle_distance = 0.10556533 as f64;
+        let eps = 0.000000001;
         assert!((dist - correct_great_circle_distance).abs() < eps);
     }
     #[test]
     fn test_compute_center() {
-        let src: (f32, f32) = (37.6190602357743, 54.205634366333044);
-        let dst: (f32, f32) = (37.619014168449894, 54.205640353834866);
+        let src: (f64, f64) = (37.6190602357743, 54.205634366333044);
+        let dst: (f64, f64) = (37.619014168449894, 54.205640353834866);
         let center = compute_center(src.0, src.1, dst.0, dst.1);
-        let correct_center: (f32, f32) = (37.6190372021121, 54.205637360083955);
+        let correct_center: (f64, f64) = (37.6190372021121, 54.205637360083955);
         let eps = 0.00001;
         assert!((center.0 - correct_center.0).abs() < eps);
         assert!((center.1 - correct_center.1).abs() < eps);
     }
+    #[test]
+    fn test_haversine_precision() {
+        // 1 meter to the north is 1/R radians of latitude. Distance should be accurate to 1 millimeter
+        let lat_diff = (1.0 / (EARTH_RADIUS_KM * 1000.0)).to_degrees();
+        let dist = haversine(37.6202637616082, 54.208100345367, 37.6202637616082, 54.208100345367 + lat_diff) * 1000.0;
+        assert!((dist - 1.0).abs() < 0.001);
+    }
 }
\ No newline at end of file
@@ -0,0 +1,173 @@
+use opencv::{
+    calib3d::fisheye_init_undistort_rectify_map, calib3d::fisheye_undistort_points,
+    calib3d::init_undistort_rectify_map, calib3d::undistort_points, core::Mat, core::Point2f,
+    core::Scalar, core::Size, core::Vector, core::BORDER_CONSTANT, core::CV_32FC1,
+    imgproc::remap, imgproc::INTER_LINEAR, prelude::*,
+};
+use std::error::Error;
+use std::str::FromStr;
+
+// Refinement of distorted points stops when they move less than this (pixels)
+const DISTORT_EPS_PIXELS: f32 = 0.05;
+const DISTORT_MAX_ITERS: usize = 20;
+
+// Lens distortion model
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LensModel {
+    // Brown-Conrady model: k1, k2, p1, p2[, k3[, k4, k5, k6[, s1, s2, s3, s4[, tx, ty]]]]
+    Pinhole,
+    // Kannala-Brandt (equidistant) model: k1, k2, k3, k4
+    Fisheye,
+}
+
+impl FromStr for LensModel {
+    type Err = String;
+    fn from_str(model: &str) -> Result<Self, Self::Err> {
+        match model.to_lowercase().as_str() {
+            "pinhole" => Ok(LensModel::Pinhole),
+            "fisheye" => Ok(LensModel::Fisheye),
+            _ => Err(format!("Unknown lens model '{}'. Possible values: 'pinhole', 'fisheye'", model)),
+        }
+    }
+}
+
+impl LensModel {
+    // Checks if number of distortion coefficients is supported by the model
+    pub fn check_coefficients(&self, coefficients_num: usize) -> Result<(), String> {
+        let supported: &[usize] = match self {
+            LensModel::Pinhole => &[4, 5, 8, 12, 14],
+            LensModel::Fisheye => &[4],
+        };
+        if !supported.contains(&coefficients_num) {
+            return Err(format!("{:?} model supports {:?} distortion coefficients, got {}", self, supported, coefficients_num));
+        }
+        Ok(())
+    }
+}
+
+// Removes lens distortion from points of the video frame and (optionally) from frames themselves.
+// Undistorted points keep pixel units: camera matrix is used as the new projection matrix
+pub struct LensCorrection {
+    model: LensModel,
+    camera_matrix: Mat,
+    distortion: Mat,
+    dewarp_frames: bool,
+    // Maps for remapping of the whole frame. They are prepared once for known frame size
+    dewarp_maps: Option<(Mat, Mat)>,
+}
+
+impl LensCorrection {
+    // fx, fy - focal lengths (pixels), cx, cy - principal point (pixels)
+    pub fn new(model: LensModel, fx: f64, fy: f64, cx: f64, cy: f64, coefficients: &[f64], dewarp_frames: bool) -> Result<Self, Box<dyn Error>> {
+        model.check_coefficients(coefficients.len())?;
+        if fx <= 0.0 || fy <= 0.0 {
+            return Err(format!("Focal lengths should be positive, got fx = {}, fy = {}", fx, fy).into());
+        }
+        let camera_matrix = Mat::from_slice_2d(&vec![vec![fx, 0.0, cx], vec![0.0, fy, cy], vec![0.0, 0.0, 1.0]])?;
+        let distortion = Mat::from_slice_2d(&vec![coefficients.to_vec()])?;
+        let lens = LensCorrection {
+            model: model,
+            camera_matrix: camera_matrix,
+            distortion: distortion,
+            dewarp_frames: dewarp_frames,
+            dewarp_maps: None,
+        };
+        // Make sure that parameters are accepted by OpenCV, so points could be undistorted without errors later
+        lens.undistort_points(&[Point2f::new(cx as f32, cy as f32)])?;
+        Ok(lens)
+    }
+    pub fn is_dewarp_enabled(&self) -> bool {
+        self.dewarp_frames
+    }
+    pub fn undistort_points(&self, points: &[Point2f]) -> Result<Vec<Point2f>, Box<dyn Error>> {
+        let src = Vector::<Point2f>::from(points.to_vec());
+        let mut dst = Vector::<Point2f>::new();
+        let rectification = Mat::default();
+        match self.model {
+            LensModel::Pinhole => undistort_points(&src, &mut dst, &self.camera_matrix, &self.distortion, &rectification, &self.camera_matrix)?,
+            LensModel::Fisheye => fisheye_undistort_points(&src, &mut dst, &self.camera_matrix, &self.distortion, &rectification, &self.camera_matrix)?,
+        };
+        Ok(dst.to_vec())
+    }
+    // Returns undistorted point. Point is returned as is if it can't be undistorted
+    pub fn undistort_point(&self, x: f32, y: f32) -> (f32, f32) {
+        match self.undistort_points(&[Point2f::new(x, y)]) {
+            Ok(points) if points.len() == 1 => (points[0].x, points[0].y),
+            Ok(_) => (x, y),
+            Err(err) => {
+                println!("Can't undistort point [{}, {}] due the error: {}", x, y, err);
+                (x, y)
+            }
+        }
+    }
+    // Inverse of `undistort_points`: returns points of the original (distorted) frame.
+    // There is no closed form for the inverse, so points are refined iteratively
+    pub fn distort_points(&self, points: &[Point2f]) -> Result<Vec<Point2f>, Box<dyn Error>> {
+        let mut distorted = points.to_vec();
+        for _ in 0..DISTORT_MAX_ITERS {
+            let undistorted = self.undistort_points(&distorted)?;
+            if undistorted.len() != points.len() {
+                return Err(format!("Expected {} undistorted points, got {}", points.len(), undistorted.len()).into());
+            }
+            let mut max_residual: f32 = 0.0;
+            for ((pt, target), current) in distorted.iter_mut().zip(points.iter()).zip(undistorted.iter()) {
+                let (dx, dy) = (target.x - current.x, target.y - current.y);
+                pt.x += dx;
+                pt.y += dy;
+                max_residual = max_residual.max(dx.abs()).max(dy.abs());
+            }
+            if max_residual < DISTORT_EPS_PIXELS {
+                break;
+            }
+        }
+        Ok(distorted)
+    }
+    // Prepares maps for dewarping frames of given size. Should be called before `dewarp`
+    pub fn init_dewarp(&mut self, frame_size: Size) -> Result<(), Box<dyn Error>> {
+        let mut map_x = Mat::default();
+        let mut map_y = Mat::default();
+        let rectification = Mat::default();
+        match self.model {
+            LensModel::Pinhole => init_undistort_rectify_map(&self.camera_matrix, &self.distortion, &rectification, &self.camera_matrix, frame_size, CV_32FC1, &mut map_x, &mut map_y)?,
+            LensModel::Fisheye => fisheye_init_undistort_rectify_map(&self.camera_matrix, &self.distortion, &rectification, &self.camera_matrix, frame_size, CV_32FC1, &mut map_x, &mut map_y)?,
+        };
+        self.dewarp_maps = Some((map_x, map_y));
+        Ok(())
+    }
+    // Removes lens distortion from the frame
+    pub fn dewarp(&self, frame: &Mat) -> Result<Mat, Box<dyn Error>> {
+        let (map_x, map_y) = match &self.dewarp_maps {
+            Some(maps) => maps,
+            None => {
+                return Err("Maps for dewarping are not prepared".into());
+            }
+        };
+        let mut dewarped = Mat::default();
+        remap(frame, &mut dewarped, map_x, map_y, INTER_LINEAR, BORDER_CONSTANT, Scalar::default())?;
+        Ok(dewarped)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn test_lens_model() {
+        assert_eq!("Fisheye".parse::<LensModel>(), Ok(LensModel::Fisheye));
+        assert!("wide".parse::<LensModel>().is_err());
+        assert!(LensModel::Pinhole.check_coefficients(5).is_ok());
+        assert!(LensModel::Pinhole.check_coefficients(3).is_err());
+        assert!(LensModel::Fisheye.check_coefficients(4).is_ok());
+        assert!(LensModel::Fisheye.check_coefficients(5).is_err());
+    }
+    #[test]
+    fn test_distort_points() {
+        let lens = LensCorrection::new(LensModel::Pinhole, 1050.0, 1050.0, 960.0, 540.0, &[-0.32, 0.12, 0.0, 0.0, -0.02], false).unwrap();
+        let points = vec![Point2f::new(100.0, 80.0), Point2f::new(960.0, 540.0), Point2f::new(1700.0, 1000.0)];
+        let undistorted = lens.undistort_points(&points).unwrap();
+        let distorted = lens.distort_points(&undistorted).unwrap();
+        for (pt, restored) in points.iter().zip(distorted.iter()) {
+            assert!((pt.x - restored.x).abs() < 0.1 && (pt.y - restored.y).abs() < 0.1);
+        }
+    }
+}
@@ -0,0 +1,93 @@
+use opencv::core::Point2f;
+
+use crate::lib::spatial::lens::LensCorrection;
+use crate::lib::spatial::stabilization::Stabilizer;
+
+// Straight lines of overlays become curves on the distorted frame, so they are split into this number of parts
+const CURVE_PARTS: usize = 8;
+
+// Moves points between the video frame and the space where road lanes and exclusion masks are defined,
+// i.e. the reference frame of stabilization without lens distortion
+pub struct FrameMapping<'a> {
+    lens: Option<&'a LensCorrection>,
+    stabilizer: Option<&'a Stabilizer>,
+}
+
+impl<'a> FrameMapping<'a> {
+    pub fn new(lens: Option<&'a LensCorrection>, stabilizer: Option<&'a Stabilizer>) -> Self {
+        FrameMapping {
+            lens: lens,
+            stabilizer: stabilizer,
+        }
+    }
+    // Moves point of the video frame to the space of road lanes
+    pub fn to_zones(&self, x: f32, y: f32) -> (f32, f32) {
+        let (x, y) = match self.stabilizer {
+            Some(stabilizer) => stabilizer.stabilize_point(x, y),
+            None => (x, y),
+        };
+        match self.lens {
+            Some(lens) => lens.undistort_point(x, y),
+            None => (x, y),
+        }
+    }
+    // Whether overlays in the space of road lanes match the displayed frame as is
+    pub fn is_identity(&self) -> bool {
+        let dewarped = match self.lens {
+            Some(lens) => lens.is_dewarp_enabled(),
+            None => true,
+        };
+        dewarped && self.stabilizer.is_none()
+    }
+    // Moves points of the space of road lanes to the displayed frame. Frame is displayed without lens distortion if 'dewarp_frames' is set
+    pub fn to_display(&self, points: &[Point2f]) -> Vec<Point2f> {
+        if self.is_identity() {
+            return points.to_vec();
+        }
+        let mut points = points.to_vec();
+        if let Some(lens) = self.lens {
+            points = match lens.distort_points(&points) {
+                Ok(distorted) => distorted,
+                Err(err) => {
+                    println!("Can't map points to the displayed frame due the error: {}", err);
+                    return points;
+                }
+            };
+        }
+        if let Some(stabilizer) = self.stabilizer {
+            for pt in points.iter_mut() {
+                let (x, y) = stabilizer.destabilize_point(pt.x, pt.y);
+                *pt = Point2f::new(x, y);
+            }
+        }
+        match self.lens {
+            Some(lens) if lens.is_dewarp_enabled() => match lens.undistort_points(&points) {
+                Ok(undistorted) => undistorted,
+                Err(err) => {
+                    println!("Can't map points to the displayed frame due the error: {}", err);
+                    points
+                }
+            },
+            _ => points,
+        }
+    }
+    // Same as `to_display`, but segments between points are split, so they follow lens distortion
+    pub fn polyline_to_display(&self, points: &[Point2f], closed: bool) -> Vec<Point2f> {
+        if self.is_identity() || self.lens.is_none() || points.len() < 2 {
+            return self.to_display(points);
+        }
+        let segments_num = if closed { points.len() } else { points.len() - 1 };
+        let mut dense = Vec::with_capacity(segments_num * CURVE_PARTS + 1);
+        for i in 0..segments_num {
+            let (a, b) = (points[i], points[(i + 1) % points.len()]);
+            for k in 0..CURVE_PARTS {
+                let t = k as f32 / CURVE_PARTS as f32;
+                dense.push(Point2f::new(a.x + (b.x - a.x) * t, a.y + (b.y - a.y) * t));
+            }
+        }
+        if !closed {
+            dense.push(points[points.len() - 1]);
+        }
+        self.to_display(&dense)
+    }
+}
@@ -2,5 +2,10 @@ mod spatial;
 pub mod point;
 pub mod epsg;
 pub mod haversine;
+pub mod projection;
+pub mod calibration;
+pub mod lens;
+pub mod stabilization;
+pub mod mapping;
 
 pub use self::{spatial::*, point::*, epsg::*, haversine::*};
\ No newline at end of file
@@ -0,0 +1,195 @@
+use opencv::core::Point2d;
+use std::str::FromStr;
+
+use crate::lib::spatial::epsg::{lonlat_to_meters, meters_to_lonlat};
+
+// WGS84 ellipsoid parameters
+const WGS84_A: f64 = 6378137.0;
+const WGS84_F: f64 = 1.0 / 298.257223563;
+// Number of iterations for ECEF to geodetic conversion. Points are close to the ellipsoid, so few iterations are enough
+const GEODETIC_ITERATIONS: usize = 5;
+
+// Metric plane which spatial converters map pixels to
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ProjectionKind {
+    // Web Mercator (EPSG:3857). Distances are inflated by 1/cos(latitude), e.g. about 70% at 54 degrees
+    Mercator,
+    // Local tangent plane (East-North-Up). Distances are true meters
+    Enu,
+}
+
+impl ProjectionKind {
+    // Local tangent plane is used unless Web Mercator is selected explicitly
+    pub fn default() -> Self {
+        ProjectionKind::Enu
+    }
+}
+
+impl FromStr for ProjectionKind {
+    type Err = String;
+    fn from_str(kind: &str) -> Result<Self, Self::Err> {
+        match kind.to_lowercase().as_str() {
+            "mercator" => Ok(ProjectionKind::Mercator),
+            "enu" => Ok(ProjectionKind::Enu),
+            _ => Err(format!("Unknown projection '{}'. Possible values: 'mercator', 'enu'", kind)),
+        }
+    }
+}
+
+// Projection of WGS84 points to the metric plane around the origin.
+// Local tangent plane (East-North-Up) keeps true meters around the origin: scale error is below 1 ppm within few kilometers,
+// so it should be used for any distance or speed computation. Web Mercator is shifted to the origin to keep single precision of OpenCV
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LocalProjection {
+    kind: ProjectionKind,
+    origin_lon: f64,
+    origin_lat: f64,
+    origin_ecef: [f64; 3],
+    origin_mercator: (f64, f64),
+}
+
+// Converts geodetic coordinates (degrees, height is zero) to Earth-Centered Earth-Fixed coordinates (meters)
+fn geodetic_to_ecef(lon: f64, lat: f64) -> [f64; 3] {
+    let e2 = WGS84_F * (2.0 - WGS84_F);
+    let (sin_lat, cos_lat) = lat.to_radians().sin_cos();
+    let (sin_lon, cos_lon) = lon.to_radians().sin_cos();
+    let n = WGS84_A / (1.0 - e2 * sin_lat * sin_lat).sqrt();
+    [n * cos_lat * cos_lon, n * cos_lat * sin_lon, n * (1.0 - e2) * sin_lat]
+}
+
+// Converts Earth-Centered Earth-Fixed coordinates (meters) to geodetic longitude and latitude (degrees)
+fn ecef_to_geodetic(ecef: [f64; 3]) -> (f64, f64) {
+    let e2 = WGS84_F * (2.0 - WGS84_F);
+    let lon = ecef[1].atan2(ecef[0]);
+    let p = (ecef[0] * ecef[0] + ecef[1] * ecef[1]).sqrt();
+    let mut lat = ecef[2].atan2(p * (1.0 - e2));
+    for _ in 0..GEODETIC_ITERATIONS {
+        let sin_lat = lat.sin();
+        let n = WGS84_A / (1.0 - e2 * sin_lat * sin_lat).sqrt();
+        let h = p / lat.cos() - n;
+        lat = ecef[2].atan2(p * (1.0 - e2 * n / (n + h)));
+    }
+    (lon.to_degrees(), lat.to_degrees())
+}
+
+impl LocalProjection {
+    pub fn default() -> Self {
+        LocalProjection::new(0.0, 0.0)
+    }
+    // origin_lon, origin_lat - tangent point in WGS84
+    pub fn new(origin_lon: f64, origin_lat: f64) -> Self {
+        LocalProjection::new_with_kind(ProjectionKind::Enu, origin_lon, origin_lat)
+    }
+    pub fn new_with_kind(kind: ProjectionKind, origin_lon: f64, origin_lat: f64) -> Self {
+        LocalProjection {
+            kind: kind,
+            origin_lon: origin_lon,
+            origin_lat: origin_lat,
+            origin_ecef: geodetic_to_ecef(origin_lon, origin_lat),
+            origin_mercator: lonlat_to_meters(origin_lon, origin_lat),
+        }
+    }
+    // Creates tangent plane projection with tangent point in the center of given WGS84 points
+    pub fn new_centered(points: &[Point2d]) -> Self {
+        LocalProjection::new_centered_with_kind(ProjectionKind::Enu, points)
+    }
+    pub fn new_centered_with_kind(kind: ProjectionKind, points: &[Point2d]) -> Self {
+        if points.len() == 0 {
+            return LocalProjection::new_with_kind(kind, 0.0, 0.0);
+        }
+        let n = points.len() as f64;
+        LocalProjection::new_with_kind(
+            kind,
+            points.iter().map(|pt| pt.x).sum::<f64>() / n,
+            points.iter().map(|pt| pt.y).sum::<f64>() / n,
+        )
+    }
+    pub fn get_origin(&self) -> (f64, f64) {
+        (self.origin_lon, self.origin_lat)
+    }
+    // Returns east and north axes of the tangent plane in ECEF
+    fn axes(&self) -> ([f64; 3], [f64; 3]) {
+        let (sin_lat, cos_lat) = self.origin_lat.to_radians().sin_cos();
+        let (sin_lon, cos_lon) = self.origin_lon.to_radians().sin_cos();
+        (
+            [-sin_lon, cos_lon, 0.0],
+            [-sin_lat * cos_lon, -sin_lat * sin_lon, cos_lat],
+        )
+    }
+    // Converts WGS84 longitude/latitude to east/north meters relative to the origin (Mercator meters for Mercator projection)
+    pub fn forward(&self, lon: f64, lat: f64) -> (f64, f64) {
+        if self.kind == ProjectionKind::Mercator {
+            let xy = lonlat_to_meters(lon, lat);
+            return (xy.0 - self.origin_mercator.0, xy.1 - self.origin_mercator.1);
+        }
+        let ecef = geodetic_to_ecef(lon, lat);
+        let d = [ecef[0] - self.origin_ecef[0], ecef[1] - self.origin_ecef[1], ecef[2] - self.origin_ecef[2]];
+        let (east, north) = self.axes();
+        (
+            east[0] * d[0] + east[1] * d[1] + east[2] * d[2],
+            north[0] * d[0] + north[1] * d[1] + north[2] * d[2],
+        )
+    }
+    // Converts east/north meters relative to the origin to WGS84 longitude/latitude.
+    // Point on the tangent plane is projected to the ellipsoid along its normal
+    pub fn inverse(&self, x: f64, y: f64) -> (f64, f64) {
+        if self.kind == ProjectionKind::Mercator {
+            return meters_to_lonlat(x + self.origin_mercator.0, y + self.origin_mercator.1);
+        }
+        let (east, north) = self.axes();
+        let ecef = [
+            self.origin_ecef[0] + x * east[0] + y * north[0],
+            self.origin_ecef[1] + x * east[1] + y * north[1],
+            self.origin_ecef[2] + x * east[2] + y * north[2],
+        ];
+        ecef_to_geodetic(ecef)
+    }
+    pub fn forward_cv(&self, lon_lat: &Point2d) -> Point2d {
+        let (x, y) = self.forward(lon_lat.x, lon_lat.y);
+        Point2d::new(x, y)
+    }
+    pub fn inverse_cv(&self, xy: &Point2d) -> Point2d {
+        let (lon, lat) = self.inverse(xy.x, xy.y);
+        Point2d::new(lon, lat)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn test_local_projection() {
+        let projection = LocalProjection::new(37.6202637616082, 54.208100345367);
+        let (x, y) = projection.forward(37.6202637616082, 54.208100345367);
+        assert!(x.abs() < 0.000001 && y.abs() < 0.000001);
+
+        // Round trip for the point about 1 km away from the origin
+        let (x, y) = projection.forward(37.635, 54.215);
+        let (lon, lat) = projection.inverse(x, y);
+        assert!((lon - 37.635).abs() < 0.000000001);
+        assert!((lat - 54.215).abs() < 0.000000001);
+
+        // Length of 0.001 degree of longitude on the WGS84 ellipsoid: PI / 180 * a * cos(lat) / sqrt(1 - e^2 * sin^2(lat)) * 0.001
+        let (east, north) = projection.forward(37.6212637616082, 54.208100345367);
+        assert!((east - 65.248).abs() < 0.001);
+        assert!(north.abs() < 0.01);
+
+        // Web Mercator inflates the same distance by 1/cos(lat)
+        let mercator = LocalProjection::new_with_kind(ProjectionKind::Mercator, 37.6202637616082, 54.208100345367);
+        let (mercator_east, mercator_north) = mercator.forward(37.6212637616082, 54.208100345367);
+        assert!((mercator_east / east - 1.0 / 54.208100345367_f64.to_radians().cos()).abs() < 0.01);
+        assert!(mercator_north.abs() < 0.000001);
+        let expected_east = lonlat_to_meters(37.6212637616082, 54.208100345367).0 - lonlat_to_meters(37.6202637616082, 54.208100345367).0;
+        assert!((mercator_east - expected_east).abs() < 0.000001);
+        let (lon, lat) = mercator.inverse(mercator_east, mercator_north);
+        assert!((lon - 37.6212637616082).abs() < 0.000000001);
+        assert!((lat - 54.208100345367).abs() < 0.000000001);
+    }
+    #[test]
+    fn test_projection_kind() {
+        assert_eq!("Mercator".parse::<ProjectionKind>(), Ok(ProjectionKind::Mercator));
+        assert_eq!("enu".parse::<ProjectionKind>(), Ok(ProjectionKind::Enu));
+        assert!("utm".parse::<ProjectionKind>().is_err());
+        assert_eq!(ProjectionKind::default(), ProjectionKind::Enu);
+    }
+}
@@ -2,18 +2,63 @@ use opencv::{
     prelude::*,
     core::Mat,
     core::Point2f,
+    core::Point2d,
     core::Vector,
     core::DECOMP_LU,
     core::mul_mat_mat,
-    core::CV_32F,
-    imgproc::get_perspective_transform
+    core::invert,
+    imgproc::get_perspective_transform,
+    calib3d::find_homography_ext,
+    calib3d::RANSAC
 };
+use std::error::Error;
+
+use crate::lib::spatial::projection::{LocalProjection, ProjectionKind};
+
+// Maximum number of RANSAC iterations
+const RANSAC_MAX_ITERS: i32 = 2000;
+// Confidence level for RANSAC
+const RANSAC_CONFIDENCE: f64 = 0.995;
 
 // Spatial converter around transform matrix.
-// It helps to transform coordinates from Euclidean space to WGS84 projection
+// It helps to transform coordinates from Euclidean space to the local metric plane and WGS84.
+// Transform matrix maps pixels to meters of the metric plane given to constructor (see LocalProjection and ProjectionKind):
+// local tangent plane with true meters or Web Mercator
 #[derive(Debug)]
 pub struct SpatialConverter {
-    transform_mat: Mat
+    // Double precision (CV_64F) matrix
+    transform_mat: Mat,
+    // Inverse transform: from meters of the local plane to pixels
+    inverse_mat: Mat,
+    // Sign of the homogeneous scale for ground points which are visible on the video frame.
+    // Points with the opposite sign are behind the horizon
+    visible_sign: f64,
+    projection: LocalProjection
+}
+
+// Projects WGS84 points to the local metric plane.
+// OpenCV estimates transformations from single precision points only: local coordinates are small enough to keep precision
+fn to_local_points(projection: &LocalProjection, dest_points: &[Point2d]) -> Vec<Point2f> {
+    dest_points
+        .iter()
+        .map(|pt| {
+            let xy = projection.forward(pt.x, pt.y);
+            Point2f::new(xy.0 as f32, xy.1 as f32)
+        })
+        .collect()
+}
+
+// Multiplies 3x3 matrix by homogeneous point [x; y; 1]
+fn apply_mat(mat: &Mat, x: f64, y: f64) -> Result<(f64, f64, f64), Box<dyn Error>> {
+    let pmat_data = vec![
+        vec![x],
+        vec![y],
+        vec![1.0 as f64],
+    ];
+    let pmat = Mat::from_slice_2d(&pmat_data)?;
+    let answ = mul_mat_mat(mat, &pmat)?.to_mat()?;
+    let answ_ptr = answ.data_typed::<f64>()?;
+    Ok((answ_ptr[0], answ_ptr[1], answ_ptr[2]))
 }
 
 impl SpatialConverter {
@@ -21,77 +66,169 @@ impl SpatialConverter {
     pub fn default() -> Self {
         return SpatialConverter{
             transform_mat: Mat::default(),
+            inverse_mat: Mat::default(),
+            visible_sign: 1.0,
+            projection: LocalProjection::default()
         }
     }
+    // Prepares inverse transform for the estimated one
+    //
+    // visible_point - any source point, e.g. one of the points used for estimation
+    //
+    fn with_inverse(transform_mat: Mat, projection: LocalProjection, visible_point: Point2f) -> Result<Self, Box<dyn Error>> {
+        let mut inverse_mat = Mat::default();
+        if invert(&transform_mat, &mut inverse_mat, DECOMP_LU)? == 0.0 {
+            return Err("Transform matrix is singular".into());
+        }
+        let visible_sign = apply_mat(&transform_mat, visible_point.x as f64, visible_point.y as f64)?.2.signum();
+        Ok(SpatialConverter{
+            transform_mat: transform_mat,
+            inverse_mat: inverse_mat,
+            visible_sign: visible_sign,
+            projection: projection
+        })
+    }
     // Constructor for SpatialConverter
     //
+    // kind - metric plane which pixels are mapped to
     // src_points - OpenCV vector of source OpenCV points in Euclidean space
-    // dest_points - OpenCV vector of destination OpenCV points (for further transformation) in WGS84 projection
+    // dest_points - OpenCV vector of destination OpenCV points (for further transformation) in WGS84 (longitude, latitude)
     //
-    pub fn new(src_points: &Vector<Point2f>, dest_points: &Vector<Point2f>) -> Self {
-        let transform_mat_f64 = get_perspective_transform(&src_points, &dest_points, DECOMP_LU).unwrap();
-        let mut transform_mat_f32 = Mat::default();
-        match transform_mat_f64.convert_to(&mut transform_mat_f32, CV_32F, 1.0, 0.0) {
-            Ok(_) => {},
-            Err(err) => {
-                panic!("Can't cast tranform matrix into float32 due the error: {:?}", err);
-            }
-        };
-        return SpatialConverter{
-            transform_mat: transform_mat_f32
-        };
+    pub fn new(kind: ProjectionKind, src_points: &Vector<Point2f>, dest_points: &Vector<Point2d>) -> Self {
+        SpatialConverter::new_from(kind, src_points.to_vec(), dest_points.to_vec())
     }
     // Constructor for SpatialConverter
     //
+    // kind - metric plane which pixels are mapped to
     // src_points - built-in vector of source OpenCV points in Euclidean space
-    // dest_points - built-in vector of destination OpenCV points (for further transformation) in WGS84 projection
+    // dest_points - built-in vector of destination OpenCV points (for further transformation) in WGS84 (longitude, latitude)
     //
-    pub fn new_from(src_points: Vec<Point2f>, dest_points: Vec<Point2f>) -> Self {
-        let src = Vector::<Point2f>::from(src_points);
-        let trgt = Vector::<Point2f>::from(dest_points);
-        let transform_mat_f64 = get_perspective_transform(&src, &trgt, DECOMP_LU).unwrap();
-        let mut transform_mat_f32 = Mat::default();
-        match transform_mat_f64.convert_to(&mut transform_mat_f32, CV_32F, 1.0, 0.0) {
-            Ok(_) => {},
+    // Not initialized converter is returned if points are degenerate (see `is_initialized`)
+    //
+    pub fn new_from(kind: ProjectionKind, src_points: Vec<Point2f>, dest_points: Vec<Point2d>) -> Self {
+        match SpatialConverter::try_new_from(kind, src_points, dest_points) {
+            Ok(converter) => converter,
             Err(err) => {
-                panic!("Can't cast tranform matrix into float32 due the error: {:?}", err);
+                println!("Can't prepare spatial converter due the error: {}", err);
+                SpatialConverter::default()
             }
-        };
-        return SpatialConverter{
-            transform_mat: transform_mat_f32
-        };
+        }
+    }
+    fn try_new_from(kind: ProjectionKind, src_points: Vec<Point2f>, dest_points: Vec<Point2d>) -> Result<Self, Box<dyn Error>> {
+        let projection = LocalProjection::new_centered_with_kind(kind, &dest_points);
+        let src = Vector::<Point2f>::from(src_points);
+        let trgt = Vector::<Point2f>::from(to_local_points(&projection, &dest_points));
+        let transform_mat = get_perspective_transform(&src, &trgt, DECOMP_LU)?;
+        SpatialConverter::with_inverse(transform_mat, projection, src.get(0)?)
+    }
+    // Constructor for SpatialConverter from any number (at least 4) of point pairs
+    //
+    // kind - metric plane which pixels are mapped to
+    // src_points - built-in vector of source OpenCV points in Euclidean space
+    // dest_points - built-in vector of destination OpenCV points (for further transformation) in WGS84 (longitude, latitude)
+    // ransac_threshold - maximum reprojection error (meters of the metric plane) to treat pair as inlier
+    //
+    // Returns converter and inlier flag for every pair
+    //
+    pub fn new_homography(kind: ProjectionKind, src_points: Vec<Point2f>, dest_points: Vec<Point2d>, ransac_threshold: f64) -> Result<(Self, Vec<bool>), Box<dyn Error>> {
+        if src_points.len() < 4 || src_points.len() != dest_points.len() {
+            return Err(format!("Need at least 4 pairs of points, got {} source and {} destination points", src_points.len(), dest_points.len()).into());
+        }
+        let projection = LocalProjection::new_centered_with_kind(kind, &dest_points);
+        let src = Vector::<Point2f>::from(src_points);
+        let trgt = Vector::<Point2f>::from(to_local_points(&projection, &dest_points));
+        let mut mask = Mat::default();
+        let transform_mat = find_homography_ext(&src, &trgt, RANSAC, ransac_threshold, &mut mask, RANSAC_MAX_ITERS, RANSAC_CONFIDENCE)?;
+        if transform_mat.empty() {
+            return Err("Homography can't be estimated for given points".into());
+        }
+        let inliers = mask.data_typed::<u8>()?.iter().map(|flag| *flag != 0).collect();
+        let converter = SpatialConverter::with_inverse(transform_mat, projection, src.get(0)?)?;
+        Ok((converter, inliers))
+    }
+    // Elements of the transform matrix. They identify calibration, e.g. to invalidate cached projections
+    pub fn get_transform_values(&self) -> Vec<f64> {
+        match self.transform_mat.data_typed::<f64>() {
+            Ok(values) => values.to_vec(),
+            Err(_) => vec![],
+        }
+    }
+    // Whether transform has been estimated. Converter of the zone without geometry is not initialized
+    pub fn is_initialized(&self) -> bool {
+        !self.transform_mat.empty() && !self.inverse_mat.empty()
+    }
+    // Checks if given point of the video frame lies on the ground, i.e. below the horizon
+    pub fn is_on_ground(&self, src_x: f32, src_y: f32) -> bool {
+        match apply_mat(&self.transform_mat, src_x as f64, src_y as f64) {
+            Ok((_, _, scale)) => scale * self.visible_sign > 0.0,
+            Err(_) => false,
+        }
     }
     // Spatial conversion function
     //
     // src - point in Euclidean space
     //
-    pub fn transform_to_epsg_cv(&self, src: &Point2f) -> Point2f {
-        let pmat_data = vec![
-            vec![src.x as f32],
-            vec![src.y as f32],
-            vec![1.0 as f32],
-        ];
-        let pmat = Mat::from_slice_2d(&pmat_data).unwrap();
-        let answ = mul_mat_mat(&self.transform_mat, &pmat).unwrap().to_mat().unwrap();
-        let answ_ptr = answ.data_typed::<f32>().unwrap();
-        let scale = answ_ptr[2];
-        let xattr = answ_ptr[0];
-        let yattr = answ_ptr[1];
-        return Point2f::new(xattr / scale, yattr / scale);
-    }
-    pub fn transform_to_epsg(&self, src_x: f32, src_y: f32) -> (f32, f32) {
-        let pmat_data = vec![
-            vec![src_x],
-            vec![src_y],
-            vec![1.0 as f32],
-        ];
-        let pmat = Mat::from_slice_2d(&pmat_data).unwrap();
-        let answ = mul_mat_mat(&self.transform_mat, &pmat).unwrap().to_mat().unwrap();
-        let answ_ptr = answ.data_typed::<f32>().unwrap();
-        let scale = answ_ptr[2];
-        let xattr = answ_ptr[0];
-        let yattr = answ_ptr[1];
-        return (xattr / scale, yattr / scale);
+    // Returns point on the local metric plane (meters to the east and to the north of the projection origin).
+    // None is returned if converter is not initialized
+    //
+    pub fn transform_to_local_cv(&self, src: &Point2f) -> Option<Point2d> {
+        self.transform_to_local(src.x, src.y).map(|(x, y)| Point2d::new(x, y))
+    }
+    pub fn transform_to_local(&self, src_x: f32, src_y: f32) -> Option<(f64, f64)> {
+        let (xattr, yattr, scale) = apply_mat(&self.transform_mat, src_x as f64, src_y as f64).ok()?;
+        return Some((xattr / scale, yattr / scale));
+    }
+    // Spatial conversion function
+    //
+    // src - point in Euclidean space
+    //
+    // Returns point in WGS84 (longitude, latitude). None is returned if converter is not initialized
+    //
+    pub fn transform_to_wgs84_cv(&self, src: &Point2f) -> Option<Point2d> {
+        self.transform_to_wgs84(src.x, src.y).map(|(lon, lat)| Point2d::new(lon, lat))
+    }
+    pub fn transform_to_wgs84(&self, src_x: f32, src_y: f32) -> Option<(f64, f64)> {
+        let (x, y) = self.transform_to_local(src_x, src_y)?;
+        return Some(self.projection.inverse(x, y));
+    }
+    // Inverse spatial conversion function
+    //
+    // x, y - point on the local metric plane (meters)
+    //
+    // Returns point in Euclidean space. None is returned if the point is behind the horizon or converter is not initialized
+    //
+    pub fn transform_local_to_pixels(&self, x: f64, y: f64) -> Option<(f32, f32)> {
+        let (xattr, yattr, scale) = apply_mat(&self.inverse_mat, x, y).ok()?;
+        if scale * self.visible_sign <= 0.0 {
+            return None;
+        }
+        return Some(((xattr / scale) as f32, (yattr / scale) as f32));
+    }
+    // Inverse spatial conversion function
+    //
+    // lon, lat - point in WGS84
+    //
+    // Returns point in Euclidean space. None is returned if the point is behind the horizon
+    //
+    pub fn transform_to_pixels_cv(&self, lon_lat: &Point2d) -> Option<Point2f> {
+        self.transform_to_pixels(lon_lat.x, lon_lat.y).map(|(x, y)| Point2f::new(x, y))
+    }
+    pub fn transform_to_pixels(&self, lon: f64, lat: f64) -> Option<(f32, f32)> {
+        let (x, y) = self.projection.forward(lon, lat);
+        return self.transform_local_to_pixels(x, y);
+    }
+    // Projects WGS84 geometry to pixels. Fails if any point can't be seen by the camera
+    pub fn project_wgs84_points(&self, points: &[[f64; 2]]) -> Result<Vec<Point2f>, String> {
+        let mut ans = Vec::with_capacity(points.len());
+        for (i, pt) in points.iter().enumerate() {
+            match self.transform_to_pixels(pt[0], pt[1]) {
+                Some((x, y)) => ans.push(Point2f::new(x, y)),
+                None => {
+                    return Err(format!("Point #{} [{}, {}] can't be seen by the camera", i, pt[0], pt[1]));
+                }
+            }
+        }
+        Ok(ans)
     }
 }
 
@@ -100,7 +237,6 @@ mod tests {
     use super::*;
     use crate::lib::spatial::haversine::haversine;
     use crate::lib::spatial::epsg::lonlat_to_meters;
-    use crate::lib::spatial::epsg::meters_to_lonlat;
     #[test]
 
     fn test_spatial_converter() {
@@ -110,21 +246,18 @@ mod tests {
         src.push(Point2f::new(1098.0, 360.0));
         src.push(Point2f::new(998.0, 359.0));
 
-        let mut dst = Vector::<Point2f>::new();
-        dst.push(Point2f::new(37.353610, 55.853085));
-        dst.push(Point2f::new(37.353559, 55.853081));
-        dst.push(Point2f::new(37.353564, 55.852918));
-        dst.push(Point2f::new(37.353618, 55.852930));
-        dst = dst.into_iter().map(|pt| {
-            let pt = lonlat_to_meters(pt.x, pt.y);
-            Point2f::new(pt.0, pt.1)
-        }).collect();
+        let mut dst = Vector::<Point2d>::new();
+        dst.push(Point2d::new(37.353610, 55.853085));
+        dst.push(Point2d::new(37.353559, 55.853081));
+        dst.push(Point2d::new(37.353564, 55.852918));
+        dst.push(Point2d::new(37.353618, 55.852930));
 
-        let eps_transform = 10.0;
+        // Corners should be reproduced with sub-millimeter precision (0.00000001 degree is about 1 millimeter)
+        let eps_transform = 0.00000001;
 
-        let converter = SpatialConverter::new(&src, &dst);
+        let converter = SpatialConverter::new(ProjectionKind::Enu, &src, &dst);
         for (i, p) in src.iter().enumerate() {
-            let result = converter.transform_to_epsg_cv(&p);
+            let result = converter.transform_to_wgs84_cv(&p).unwrap();
             let result_x = result.x;
             let result_y = result.y;
             let correct_x = dst.get(i).unwrap().x;
@@ -137,27 +270,59 @@ mod tests {
             assert!(diff_y < eps_transform);
         }
 
-        let eps = 0.0001;
+        // 1 millimeter
+        let eps = 0.001;
 
         let a = Point2f::new(959.0, 664.0);
         let b = Point2f::new(1098.0, 360.0);
 
-        let a_epsg3857 = converter.transform_to_epsg_cv(&a);
-        let b_epsg3857 = converter.transform_to_epsg_cv(&b);
-
-        let a_wgs84 = meters_to_lonlat(a_epsg3857.x, a_epsg3857.y);
-        let b_wgs84 = meters_to_lonlat(b_epsg3857.x, b_epsg3857.y);
-
-        println!("a_epsg3857: {:?}", a_epsg3857);
-        println!("b_epsg3857: {:?}", b_epsg3857);
+        let a_wgs84 = converter.transform_to_wgs84_cv(&a).unwrap();
+        let b_wgs84 = converter.transform_to_wgs84_cv(&b).unwrap();
 
         println!("a_wgs84: {:?}", a_wgs84);
         println!("b_wgs84: {:?}", b_wgs84);
 
-        let distance = haversine(a_wgs84.0, a_wgs84.1, b_wgs84.0, b_wgs84.1) * 1000.0;
+        let distance = haversine(a_wgs84.x, a_wgs84.y, b_wgs84.x, b_wgs84.y) * 1000.0;
         println!("distance: {}", distance);
-        let coorrect_dist: f32 = 17.008713;
+        // Same as distance between corresponding WGS84 corners
+        let coorrect_dist: f64 = 18.127460;
         assert!((distance - coorrect_dist).abs() < eps);
+
+        // Local tangent plane keeps true (ellipsoidal) meters
+        let a_local = converter.transform_to_local_cv(&a).unwrap();
+        let b_local = converter.transform_to_local_cv(&b).unwrap();
+        let distance_local = ((a_local.x - b_local.x).powi(2) + (a_local.y - b_local.y).powi(2)).sqrt();
+        println!("distance_local: {}", distance_local);
+        let correct_dist_local: f64 = 18.150984;
+        assert!((distance_local - correct_dist_local).abs() < eps);
+
+        // Web Mercator plane keeps Web Mercator meters
+        let converter_mercator = SpatialConverter::new(ProjectionKind::Mercator, &src, &dst);
+        let a_local = converter_mercator.transform_to_local_cv(&a).unwrap();
+        let b_local = converter_mercator.transform_to_local_cv(&b).unwrap();
+        let distance_local = ((a_local.x - b_local.x).powi(2) + (a_local.y - b_local.y).powi(2)).sqrt();
+        println!("distance_local (Web Mercator): {}", distance_local);
+        let a_mercator = lonlat_to_meters(a_wgs84.x, a_wgs84.y);
+        let b_mercator = lonlat_to_meters(b_wgs84.x, b_wgs84.y);
+        let correct_dist_mercator = ((a_mercator.0 - b_mercator.0).powi(2) + (a_mercator.1 - b_mercator.1).powi(2)).sqrt();
+        assert!((distance_local - correct_dist_mercator).abs() < eps);
+
+        // Inverse transform should give initial pixels back
+        for (i, p) in dst.iter().enumerate() {
+            let result = converter.transform_to_pixels_cv(&p).unwrap();
+            let correct = src.get(i).unwrap();
+            assert!((result.x - correct.x).abs() < 0.01);
+            assert!((result.y - correct.y).abs() < 0.01);
+        }
+        // Point behind the camera (about 10 km to the north) is not visible on the video frame
+        assert!(converter.transform_to_pixels(37.353564, 55.942918).is_none());
+        assert!(converter.is_on_ground(998.0, 359.0));
+
+        // Degenerate geometry gives not initialized converter instead of panic
+        let empty = SpatialConverter::new_from(ProjectionKind::default(), vec![], vec![]);
+        assert!(!empty.is_initialized());
+        assert!(empty.transform_to_wgs84(10.0, 10.0).is_none());
+        assert!(empty.transform_to_pixels(37.353564, 55.852918).is_none());
     }
     #[test]
     fn test_skeleton() {
@@ -167,21 +332,18 @@ mod tests {
         src.push(Point2f::new(334.0, 80.0));
         src.push(Point2f::new(179.0, 68.0));
 
-        let mut dst = Vector::<Point2f>::new();
-        dst.push(Point2f::new(37.6190602357743, 54.205634366333044));
-        dst.push(Point2f::new(37.619014168449894, 54.205640353834866));
-        dst.push(Point2f::new(37.61899251287025, 54.205596598993196));
-        dst.push(Point2f::new(37.6190330678655, 54.205588538885735));
-        dst = dst.into_iter().map(|pt| {
-            let pt = lonlat_to_meters(pt.x, pt.y);
-            Point2f::new(pt.0, pt.1)
-        }).collect();
+        let mut dst = Vector::<Point2d>::new();
+        dst.push(Point2d::new(37.6190602357743, 54.205634366333044));
+        dst.push(Point2d::new(37.619014168449894, 54.205640353834866));
+        dst.push(Point2d::new(37.61899251287025, 54.205596598993196));
+        dst.push(Point2d::new(37.6190330678655, 54.205588538885735));
 
-        let eps_transform = 10.0;
+        // Corners should be reproduced with sub-millimeter precision (0.00000001 degree is about 1 millimeter)
+        let eps_transform = 0.00000001;
 
-        let converter = SpatialConverter::new(&src, &dst);
+        let converter = SpatialConverter::new(ProjectionKind::Enu, &src, &dst);
         for (i, p) in src.iter().enumerate() {
-            let result = converter.transform_to_epsg_cv(&p);
+            let result = converter.transform_to_wgs84_cv(&p).unwrap();
             let result_x = result.x;
             let result_y = result.y;
             let correct_x = dst.get(i).unwrap().x;
@@ -194,21 +356,13 @@ mod tests {
             assert!(diff_y < eps_transform);
         }
 
-        let eps = 0.0001;
-
         let a = Point2f::new(51.0, 266.0);
         let b = Point2f::new(281.0, 264.0);
 
-        let a_epsg3857 = converter.transform_to_epsg_cv(&a);
-        let b_epsg3857 = converter.transform_to_epsg_cv(&b);
-
-        let a_wgs84 = meters_to_lonlat(a_epsg3857.x, a_epsg3857.y);
-        let b_wgs84 = meters_to_lonlat(b_epsg3857.x, b_epsg3857.y);
-
-        println!("a_epsg3857: {:?}", a_epsg3857);
-        println!("b_epsg3857: {:?}", b_epsg3857);
+        let a_wgs84 = converter.transform_to_wgs84_cv(&a).unwrap();
+        let b_wgs84 = converter.transform_to_wgs84_cv(&b).unwrap();
 
         println!("a_wgs84: {:?}", a_wgs84);
         println!("b_wgs84: {:?}", b_wgs84);
     }
-}
\ No newline at end of file
+}
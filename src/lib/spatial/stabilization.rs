@@ -0,0 +1,285 @@
+use opencv::{
+    calib3d::estimate_affine_partial_2d, calib3d::RANSAC, core::no_array, core::DMatch, core::KeyPoint, core::Mat,
+    core::Point2f, core::Vector, core::NORM_HAMMING, features2d::BFMatcher, features2d::ORB, features2d::ORB_ScoreType,
+    features2d::Ptr, imgproc::cvt_color, imgproc::COLOR_BGR2GRAY, prelude::*,
+};
+use std::error::Error;
+
+// Minimum number of RANSAC inliers for the estimate to be trusted
+pub const MIN_INLIERS: usize = 15;
+// Maximum distance (pixels) between matched point and its reprojection to be considered as inlier
+const RANSAC_THRESHOLD_PIXELS: f64 = 3.0;
+const RANSAC_MAX_ITERS: usize = 2000;
+const RANSAC_CONFIDENCE: f64 = 0.99;
+const REFINE_ITERS: usize = 10;
+
+// Affine transform [a, b, tx, c, d, ty]: x' = a * x + b * y + tx, y' = c * x + d * y + ty
+pub type Affine = [f64; 6];
+
+pub const IDENTITY: Affine = [1.0, 0.0, 0.0, 0.0, 1.0, 0.0];
+
+pub fn apply_affine(transform: &Affine, x: f32, y: f32) -> (f32, f32) {
+    let (x, y) = (x as f64, y as f64);
+    (
+        (transform[0] * x + transform[1] * y + transform[2]) as f32,
+        (transform[3] * x + transform[4] * y + transform[5]) as f32,
+    )
+}
+
+// Inverse transform. Degenerate transform is replaced with identity
+pub fn invert_affine(transform: &Affine) -> Affine {
+    let det = transform[0] * transform[4] - transform[1] * transform[3];
+    if det.abs() < f64::EPSILON {
+        return IDENTITY;
+    }
+    let (a, b, c, d) = (transform[4] / det, -transform[1] / det, -transform[3] / det, transform[0] / det);
+    [a, b, -(a * transform[2] + b * transform[5]), c, d, -(c * transform[2] + d * transform[5])]
+}
+
+// Splits similarity transform into translation (pixels), rotation (degrees) and scale
+pub fn decompose_affine(transform: &Affine) -> (f64, f64, f64, f64) {
+    let scale = (transform[0] * transform[0] + transform[3] * transform[3]).sqrt();
+    let rotation = transform[3].atan2(transform[0]).to_degrees();
+    (transform[2], transform[5], rotation, scale)
+}
+
+// Largest shift (pixels) of the frame corners caused by the transform
+pub fn max_displacement(transform: &Affine, width: f32, height: f32) -> f32 {
+    [(0.0, 0.0), (width, 0.0), (0.0, height), (width, height)]
+        .iter()
+        .map(|(x, y)| {
+            let (tx, ty) = apply_affine(transform, *x, *y);
+            ((tx - x).powi(2) + (ty - y).powi(2)).sqrt()
+        })
+        .fold(0.0, f32::max)
+}
+
+// Last estimate of the camera shake
+#[derive(Debug, Clone)]
+pub struct StabilizationHealth {
+    // Whether the last estimate has been accepted. Otherwise previous transform is used
+    pub registered: bool,
+    // Shift of the current frame relative to the reference one (pixels). It is kept for rejected estimates too
+    pub dx: f64,
+    pub dy: f64,
+    pub rotation_degrees: f64,
+    pub scale: f64,
+    // Largest shift of the frame corners (pixels)
+    pub displacement: f32,
+    // Number of features detected on the last frame
+    pub features_num: usize,
+    pub matches_num: usize,
+    pub inliers_num: usize,
+    // Unix timestamp of the last estimate
+    pub updated_at: i64,
+}
+
+impl StabilizationHealth {
+    pub fn default() -> Self {
+        StabilizationHealth {
+            registered: false,
+            dx: 0.0,
+            dy: 0.0,
+            rotation_degrees: 0.0,
+            scale: 1.0,
+            displacement: 0.0,
+            features_num: 0,
+            matches_num: 0,
+            inliers_num: 0,
+            updated_at: -1,
+        }
+    }
+}
+
+// Registers frames to the reference one by ORB features and rigid (rotation, uniform scale, translation) transform,
+// so points detected on the shaking frame could be moved back to the reference frame where zones are defined
+pub struct Stabilizer {
+    orb: Ptr<ORB>,
+    matcher: Ptr<BFMatcher>,
+    reference_keypoints: Vec<Point2f>,
+    reference_descriptors: Mat,
+    // Estimates with larger displacement (pixels) are rejected as unreliable
+    max_displacement: f32,
+    // Transform from the current frame to the reference one
+    transform: Affine,
+    health: StabilizationHealth,
+}
+
+impl Stabilizer {
+    pub fn new(features_num: i32, max_displacement: f32) -> Result<Self, Box<dyn Error>> {
+        if features_num < MIN_INLIERS as i32 {
+            return Err(format!("Number of features should be at least {}, got {}", MIN_INLIERS, features_num).into());
+        }
+        Ok(Stabilizer {
+            orb: ORB::create(features_num, 1.2, 8, 31, 0, 2, ORB_ScoreType::HARRIS_SCORE, 31, 20)?,
+            matcher: BFMatcher::create(NORM_HAMMING, true)?,
+            reference_keypoints: vec![],
+            reference_descriptors: Mat::default(),
+            max_displacement: max_displacement,
+            transform: IDENTITY,
+            health: StabilizationHealth::default(),
+        })
+    }
+    fn detect(&mut self, frame: &Mat) -> Result<(Vec<Point2f>, Mat), Box<dyn Error>> {
+        let mut gray = Mat::default();
+        cvt_color(frame, &mut gray, COLOR_BGR2GRAY, 0)?;
+        let mut keypoints = Vector::<KeyPoint>::new();
+        let mut descriptors = Mat::default();
+        self.orb.detect_and_compute(&gray, &no_array(), &mut keypoints, &mut descriptors, false)?;
+        Ok((keypoints.iter().map(|keypoint| keypoint.pt()).collect(), descriptors))
+    }
+    pub fn has_reference(&self) -> bool {
+        self.reference_keypoints.len() > 0
+    }
+    // Frame where zones are defined. Previous estimate is reset
+    pub fn set_reference(&mut self, frame: &Mat) -> Result<(), Box<dyn Error>> {
+        let (keypoints, descriptors) = self.detect(frame)?;
+        if keypoints.len() < MIN_INLIERS {
+            return Err(format!("Reference frame has {} features only, need at least {}", keypoints.len(), MIN_INLIERS).into());
+        }
+        self.reference_keypoints = keypoints;
+        self.reference_descriptors = descriptors;
+        self.transform = IDENTITY;
+        self.health = StabilizationHealth::default();
+        Ok(())
+    }
+    // Estimates transform of the current frame to the reference one. Previous transform is kept if estimate is not reliable
+    pub fn update(&mut self, frame: &Mat, now: i64) -> Result<(), Box<dyn Error>> {
+        if !self.has_reference() {
+            return Err("Reference frame is not set".into());
+        }
+        self.health.registered = false;
+        self.health.updated_at = now;
+        self.health.features_num = 0;
+        let (keypoints, descriptors) = self.detect(frame)?;
+        self.health.features_num = keypoints.len();
+        if keypoints.len() < MIN_INLIERS {
+            self.health.matches_num = 0;
+            self.health.inliers_num = 0;
+            return Err(format!("Frame has {} features only, need at least {}", keypoints.len(), MIN_INLIERS).into());
+        }
+        let mut matches = Vector::<DMatch>::new();
+        self.matcher.train_match(&descriptors, &self.reference_descriptors, &mut matches, &no_array())?;
+        let mut current_points = Vector::<Point2f>::new();
+        let mut reference_points = Vector::<Point2f>::new();
+        for m in matches.iter() {
+            current_points.push(keypoints[m.query_idx as usize]);
+            reference_points.push(self.reference_keypoints[m.train_idx as usize]);
+        }
+        self.health.matches_num = matches.len();
+        self.health.inliers_num = 0;
+        if matches.len() < MIN_INLIERS {
+            return Err(format!("Only {} features are matched with the reference frame, need at least {}", matches.len(), MIN_INLIERS).into());
+        }
+        let mut inliers = Mat::default();
+        let estimate = estimate_affine_partial_2d(&current_points, &reference_points, &mut inliers, RANSAC, RANSAC_THRESHOLD_PIXELS, RANSAC_MAX_ITERS, RANSAC_CONFIDENCE, REFINE_ITERS)?;
+        if estimate.empty() {
+            return Err("Transform can't be estimated".into());
+        }
+        self.health.inliers_num = inliers.data_typed::<u8>()?.iter().filter(|flag| **flag != 0).count();
+        if self.health.inliers_num < MIN_INLIERS {
+            return Err(format!("Only {} matches are consistent with the transform, need at least {}", self.health.inliers_num, MIN_INLIERS).into());
+        }
+        let mut transform = IDENTITY;
+        for (i, value) in transform.iter_mut().enumerate() {
+            *value = *estimate.at_2d::<f64>(i as i32 / 3, i as i32 % 3)?;
+        }
+        // Measured displacement is reported even if it is rejected, so large camera movement could be noticed
+        let displacement = max_displacement(&transform, frame.cols() as f32, frame.rows() as f32);
+        let (dx, dy, rotation, scale) = decompose_affine(&transform);
+        self.health.dx = dx;
+        self.health.dy = dy;
+        self.health.rotation_degrees = rotation;
+        self.health.scale = scale;
+        self.health.displacement = displacement;
+        if displacement > self.max_displacement {
+            return Err(format!("Displacement {} px exceeds maximum {} px", displacement, self.max_displacement).into());
+        }
+        self.transform = transform;
+        self.health.registered = true;
+        Ok(())
+    }
+    // Moves point of the current frame to the reference frame
+    pub fn stabilize_point(&self, x: f32, y: f32) -> (f32, f32) {
+        apply_affine(&self.transform, x, y)
+    }
+    // Moves point of the reference frame to the current frame
+    pub fn destabilize_point(&self, x: f32, y: f32) -> (f32, f32) {
+        apply_affine(&invert_affine(&self.transform), x, y)
+    }
+    pub fn get_health(&self) -> StabilizationHealth {
+        self.health.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn test_affine() {
+        // Rotation by 2 degrees, scale 1.01 and shift
+        let (sin, cos) = 2.0_f64.to_radians().sin_cos();
+        let transform = [1.01 * cos, -1.01 * sin, 4.0, 1.01 * sin, 1.01 * cos, -3.0];
+        let (dx, dy, rotation, scale) = decompose_affine(&transform);
+        assert!((dx - 4.0).abs() < 1e-9 && (dy + 3.0).abs() < 1e-9);
+        assert!((rotation - 2.0).abs() < 1e-9);
+        assert!((scale - 1.01).abs() < 1e-9);
+
+        let (x, y) = apply_affine(&IDENTITY, 10.0, 20.0);
+        assert_eq!((x, y), (10.0, 20.0));
+        assert_eq!(max_displacement(&IDENTITY, 1920.0, 1080.0), 0.0);
+        let shift = [1.0, 0.0, 3.0, 0.0, 1.0, 4.0];
+        assert!((max_displacement(&shift, 1920.0, 1080.0) - 5.0).abs() < 1e-6);
+        // Rotation around the origin moves the farthest corner the most
+        let displacement = max_displacement(&[cos, -sin, 0.0, sin, cos, 0.0], 1920.0, 1080.0);
+        let radius = (1920.0_f64.powi(2) + 1080.0_f64.powi(2)).sqrt();
+        assert!((displacement as f64 - 2.0 * radius * (1.0_f64.to_radians()).sin()).abs() < 0.01);
+
+        let (x, y) = apply_affine(&transform, 10.0, 20.0);
+        let (x, y) = apply_affine(&invert_affine(&transform), x, y);
+        assert!((x - 10.0).abs() < 1e-4 && (y - 20.0).abs() < 1e-4);
+        assert_eq!(invert_affine(&[0.0; 6]), IDENTITY);
+    }
+    #[test]
+    fn test_update() {
+        use opencv::{
+            core::Rect, core::Scalar, core::Size, core::BORDER_CONSTANT, core::CV_8UC3, imgproc::rectangle,
+            imgproc::warp_affine, imgproc::INTER_LINEAR, imgproc::LINE_8,
+        };
+        // Textured scene of random rectangles (fixed seed, so test is reproducible)
+        let mut reference = Mat::new_rows_cols_with_default(480, 640, CV_8UC3, Scalar::all(0.0)).unwrap();
+        let mut seed: u32 = 42;
+        let mut next = |max: u32| {
+            seed = seed.wrapping_mul(1103515245).wrapping_add(12345);
+            (seed >> 16) % max
+        };
+        for _ in 0..300 {
+            let rect = Rect::new(next(620) as i32, next(460) as i32, 5 + next(40) as i32, 5 + next(40) as i32);
+            let color = Scalar::new(next(256) as f64, next(256) as f64, next(256) as f64, 0.0);
+            rectangle(&mut reference, rect, color, -1, LINE_8, 0).unwrap();
+        }
+        // Camera is shifted, so the scene moves by (7, -4) pixels
+        let shift = Mat::from_slice_2d(&[[1.0_f64, 0.0, 7.0], [0.0, 1.0, -4.0]]).unwrap();
+        let mut shifted = Mat::default();
+        warp_affine(&reference, &mut shifted, &shift, Size::new(640, 480), INTER_LINEAR, BORDER_CONSTANT, Scalar::all(0.0)).unwrap();
+
+        let mut stabilizer = Stabilizer::new(500, 50.0).unwrap();
+        assert!(stabilizer.update(&shifted, 0).is_err());
+        stabilizer.set_reference(&reference).unwrap();
+        stabilizer.update(&shifted, 1).unwrap();
+        let health = stabilizer.get_health();
+        assert!(health.registered);
+        assert!((health.dx + 7.0).abs() < 0.5 && (health.dy - 4.0).abs() < 0.5);
+        let (x, y) = stabilizer.stabilize_point(327.0, 196.0);
+        assert!((x - 320.0).abs() < 0.5 && (y - 200.0).abs() < 0.5);
+        let (x, y) = stabilizer.destabilize_point(320.0, 200.0);
+        assert!((x - 327.0).abs() < 0.5 && (y - 196.0).abs() < 0.5);
+
+        // Too small displacement limit rejects the estimate and keeps the previous transform
+        let mut strict = Stabilizer::new(500, 2.0).unwrap();
+        strict.set_reference(&reference).unwrap();
+        assert!(strict.update(&shifted, 1).is_err());
+        assert!(!strict.get_health().registered);
+        assert_eq!(strict.stabilize_point(327.0, 196.0), (327.0, 196.0));
+    }
+}
@@ -0,0 +1,3 @@
+mod tamper;
+
+pub use self::{tamper::*};
@@ -0,0 +1,370 @@
+use std::collections::{HashMap, VecDeque};
+use std::error::Error;
+
+use opencv::{
+    core::absdiff, core::mean, core::mean_std_dev, core::no_array, core::Mat, core::BORDER_DEFAULT, core::CV_64F,
+    imgproc::cvt_color, imgproc::laplacian, imgproc::COLOR_BGR2GRAY, prelude::*,
+};
+use uuid::Uuid;
+
+use crate::lib::spatial::stabilization::{Stabilizer, MIN_INLIERS};
+
+// Maximum number of tamper events kept in memory
+pub const TAMPER_EVENTS_CAPACITY: usize = 100;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TamperKind {
+    // Camera has been moved relative to the reference scene
+    Displaced,
+    // Frame can't be matched with the reference scene at all (covered lens, camera turned away)
+    SceneChanged,
+    // Frame is out of focus, lens is dirty or fogged
+    Blurred,
+    TooDark,
+    TooBright,
+    // Consecutive frames are the same (stuck encoder or video source)
+    Frozen,
+}
+
+impl TamperKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TamperKind::Displaced => "displaced",
+            TamperKind::SceneChanged => "scene_changed",
+            TamperKind::Blurred => "blurred",
+            TamperKind::TooDark => "too_dark",
+            TamperKind::TooBright => "too_bright",
+            TamperKind::Frozen => "frozen",
+        }
+    }
+}
+
+// Measurements of the single frame
+#[derive(Debug, Clone)]
+pub struct FrameQuality {
+    // Variance of Laplacian of the grayscale frame
+    pub sharpness: f32,
+    // Mean intensity of the grayscale frame [0; 255]
+    pub brightness: f32,
+    // Mean absolute difference with the previous measured frame. Value "-1" indicates no previous frame
+    pub difference: f32,
+    // Largest shift of the frame corners relative to the reference scene (pixels). Value "-1" indicates no data
+    pub displacement: f32,
+    // Whether frame has enough features, but they can't be matched with the reference scene
+    pub scene_lost: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct TamperThresholds {
+    pub min_sharpness: f32,
+    pub min_brightness: f32,
+    pub max_brightness: f32,
+    // Frames with smaller mean absolute difference are considered as the same ones
+    pub frozen_difference: f32,
+    pub max_displacement: f32,
+    // Condition should last this long (seconds) to raise event and should be absent this long to finish it
+    pub min_duration_seconds: i64,
+    // Frames should stay the same this long (seconds) to raise event
+    pub frozen_seconds: i64,
+}
+
+impl TamperThresholds {
+    pub fn default() -> Self {
+        TamperThresholds {
+            min_sharpness: 50.0,
+            min_brightness: 20.0,
+            max_brightness: 235.0,
+            frozen_difference: 0.5,
+            max_displacement: 30.0,
+            min_duration_seconds: 5,
+            frozen_seconds: 10,
+        }
+    }
+    fn required_duration(&self, kind: TamperKind) -> i64 {
+        match kind {
+            TamperKind::Frozen => self.frozen_seconds,
+            _ => self.min_duration_seconds,
+        }
+    }
+}
+
+// Conditions met by the frame along with the measured values
+pub fn detect_conditions(quality: &FrameQuality, thresholds: &TamperThresholds) -> Vec<(TamperKind, f32)> {
+    let mut ans = vec![];
+    if quality.scene_lost {
+        ans.push((TamperKind::SceneChanged, quality.displacement));
+    } else if quality.displacement > thresholds.max_displacement {
+        ans.push((TamperKind::Displaced, quality.displacement));
+    }
+    if quality.sharpness < thresholds.min_sharpness {
+        ans.push((TamperKind::Blurred, quality.sharpness));
+    }
+    if quality.brightness < thresholds.min_brightness {
+        ans.push((TamperKind::TooDark, quality.brightness));
+    } else if quality.brightness > thresholds.max_brightness {
+        ans.push((TamperKind::TooBright, quality.brightness));
+    }
+    if quality.difference >= 0.0 && quality.difference < thresholds.frozen_difference {
+        ans.push((TamperKind::Frozen, quality.difference));
+    }
+    ans
+}
+
+#[derive(Debug, Clone)]
+pub struct TamperEvent {
+    pub id: String,
+    pub kind: TamperKind,
+    // UTC Unix timestamp when condition has been noticed for the first time
+    pub started_at: i64,
+    // Value "-1" indicates that event is still active
+    pub finished_at: i64,
+    // Measured value which raised the event
+    pub value: f32,
+}
+
+impl TamperEvent {
+    pub fn is_active(&self) -> bool {
+        self.finished_at < 0
+    }
+}
+
+// Turns per-frame conditions into events. Condition should last for some time to raise event and should be absent for some time
+// to finish it, so single bad or good frames are ignored
+#[derive(Debug)]
+pub struct TamperDetector {
+    thresholds: TamperThresholds,
+    // Time when not yet raised condition has been noticed for the first time
+    pending: HashMap<TamperKind, i64>,
+    // Time when condition of the active event has not been met for the first time
+    recovering: HashMap<TamperKind, i64>,
+    events: VecDeque<TamperEvent>,
+}
+
+impl TamperDetector {
+    pub fn new(thresholds: TamperThresholds) -> Self {
+        TamperDetector {
+            thresholds: thresholds,
+            pending: HashMap::new(),
+            recovering: HashMap::new(),
+            events: VecDeque::with_capacity(TAMPER_EVENTS_CAPACITY),
+        }
+    }
+    // Returns events raised by the given conditions
+    pub fn update(&mut self, conditions: &[(TamperKind, f32)], now: i64) -> Vec<TamperEvent> {
+        // Finish events which conditions have been absent long enough and forget pending conditions which are not met anymore
+        for event in self.events.iter_mut().filter(|event| event.is_active()) {
+            if conditions.iter().any(|(kind, _)| *kind == event.kind) {
+                self.recovering.remove(&event.kind);
+                continue;
+            }
+            let recovered_at = *self.recovering.entry(event.kind).or_insert(now);
+            if now - recovered_at >= self.thresholds.min_duration_seconds {
+                event.finished_at = recovered_at;
+                self.recovering.remove(&event.kind);
+            }
+        }
+        self.pending.retain(|kind, _| conditions.iter().any(|(met, _)| met == kind));
+        let mut raised = vec![];
+        for (kind, value) in conditions.iter() {
+            if let Some(event) = self.events.iter_mut().find(|event| event.is_active() && event.kind == *kind) {
+                event.value = *value;
+                continue;
+            }
+            let started_at = *self.pending.entry(*kind).or_insert(now);
+            if now - started_at < self.thresholds.required_duration(*kind) {
+                continue;
+            }
+            self.pending.remove(kind);
+            // Only finished events are evicted, so active ones are never lost
+            if self.events.len() >= TAMPER_EVENTS_CAPACITY {
+                if let Some(idx) = self.events.iter().position(|event| !event.is_active()) {
+                    self.events.remove(idx);
+                }
+            }
+            let event = TamperEvent {
+                id: Uuid::new_v4().to_string(),
+                kind: *kind,
+                started_at: started_at,
+                finished_at: -1,
+                value: *value,
+            };
+            self.events.push_back(event.clone());
+            raised.push(event);
+        }
+        raised
+    }
+    pub fn get_active_kinds(&self) -> Vec<TamperKind> {
+        self.events.iter().filter(|event| event.is_active()).map(|event| event.kind).collect()
+    }
+    pub fn get_events(&self) -> Vec<TamperEvent> {
+        self.events.iter().cloned().collect()
+    }
+}
+
+// Snapshot of the monitor state for the REST API
+#[derive(Debug, Clone)]
+pub struct TamperStatus {
+    pub quality: FrameQuality,
+    pub active: Vec<TamperKind>,
+    pub events: Vec<TamperEvent>,
+    // UTC Unix timestamp of the last measurement
+    pub updated_at: i64,
+}
+
+// Compares frames with the reference scene and checks their quality
+pub struct TamperMonitor {
+    // Registration is used only to measure displacement, so no estimate is rejected
+    stabilizer: Stabilizer,
+    previous: Option<Mat>,
+    detector: TamperDetector,
+    status: Option<TamperStatus>,
+}
+
+impl TamperMonitor {
+    pub fn new(features_num: i32, thresholds: TamperThresholds) -> Result<Self, Box<dyn Error>> {
+        Ok(TamperMonitor {
+            stabilizer: Stabilizer::new(features_num, f32::MAX)?,
+            previous: None,
+            detector: TamperDetector::new(thresholds),
+            status: None,
+        })
+    }
+    pub fn has_reference(&self) -> bool {
+        self.stabilizer.has_reference()
+    }
+    // Scene which frames are compared with
+    pub fn set_reference(&mut self, frame: &Mat) -> Result<(), Box<dyn Error>> {
+        self.stabilizer.set_reference(frame)
+    }
+    fn measure(&mut self, frame: &Mat, now: i64) -> Result<FrameQuality, Box<dyn Error>> {
+        let mut gray = Mat::default();
+        cvt_color(frame, &mut gray, COLOR_BGR2GRAY, 0)?;
+        let mut edges = Mat::default();
+        laplacian(&gray, &mut edges, CV_64F, 1, 1.0, 0.0, BORDER_DEFAULT)?;
+        let mut edges_mean = Mat::default();
+        let mut edges_std = Mat::default();
+        mean_std_dev(&edges, &mut edges_mean, &mut edges_std, &no_array())?;
+        let sharpness = edges_std.at_2d::<f64>(0, 0)?.powi(2) as f32;
+        let brightness = mean(&gray, &no_array())?[0] as f32;
+        let difference = match &self.previous {
+            Some(previous) => {
+                let mut diff = Mat::default();
+                absdiff(&gray, previous, &mut diff)?;
+                mean(&diff, &no_array())?[0] as f32
+            }
+            None => -1.0,
+        };
+        self.previous = Some(gray);
+        let (displacement, scene_lost) = if !self.stabilizer.has_reference() {
+            (-1.0, false)
+        } else {
+            // Frame without enough features (covered lens, darkness, fog) is reported by blur and brightness checks,
+            // so only frame which can't be matched with the reference scene means that scene has changed
+            match self.stabilizer.update(frame, now) {
+                Ok(_) => (self.stabilizer.get_health().displacement, false),
+                Err(_) => (-1.0, self.stabilizer.get_health().features_num >= MIN_INLIERS),
+            }
+        };
+        Ok(FrameQuality {
+            sharpness: sharpness,
+            brightness: brightness,
+            difference: difference,
+            displacement: displacement,
+            scene_lost: scene_lost,
+        })
+    }
+    // Returns events raised by the frame
+    pub fn update(&mut self, frame: &Mat, now: i64) -> Result<Vec<TamperEvent>, Box<dyn Error>> {
+        let quality = self.measure(frame, now)?;
+        let conditions = detect_conditions(&quality, &self.detector.thresholds);
+        let raised = self.detector.update(&conditions, now);
+        self.status = Some(TamperStatus {
+            quality: quality,
+            active: self.detector.get_active_kinds(),
+            events: self.detector.get_events(),
+            updated_at: now,
+        });
+        Ok(raised)
+    }
+    pub fn get_status(&self) -> Option<TamperStatus> {
+        self.status.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn test_tamper_detector() {
+        let thresholds = TamperThresholds::default();
+        let good = FrameQuality {
+            sharpness: 300.0,
+            brightness: 120.0,
+            difference: 8.0,
+            displacement: 2.0,
+            scene_lost: false,
+        };
+        assert!(detect_conditions(&good, &thresholds).is_empty());
+        let bad = FrameQuality {
+            sharpness: 10.0,
+            brightness: 5.0,
+            difference: 0.1,
+            displacement: 45.0,
+            scene_lost: false,
+        };
+        let kinds: Vec<TamperKind> = detect_conditions(&bad, &thresholds).iter().map(|(kind, _)| *kind).collect();
+        assert_eq!(kinds, vec![TamperKind::Displaced, TamperKind::Blurred, TamperKind::TooDark, TamperKind::Frozen]);
+        let lost = FrameQuality { scene_lost: true, displacement: -1.0, ..good.clone() };
+        assert_eq!(detect_conditions(&lost, &thresholds)[0].0, TamperKind::SceneChanged);
+
+        let mut detector = TamperDetector::new(thresholds);
+        let dark = vec![(TamperKind::TooDark, 5.0)];
+        // Condition should last for 5 seconds
+        assert!(detector.update(&dark, 100).is_empty());
+        assert!(detector.update(&dark, 103).is_empty());
+        // Single good frame resets pending condition
+        assert!(detector.update(&[], 104).is_empty());
+        assert!(detector.update(&dark, 105).is_empty());
+        let raised = detector.update(&dark, 110);
+        assert_eq!(raised.len(), 1);
+        assert_eq!(raised[0].started_at, 105);
+        assert_eq!(detector.get_active_kinds(), vec![TamperKind::TooDark]);
+        // Active event is not raised twice
+        assert!(detector.update(&dark, 111).is_empty());
+        // Single good frame doesn't finish active event
+        detector.update(&[], 112);
+        detector.update(&dark, 113);
+        detector.update(&[], 114);
+        assert_eq!(detector.get_active_kinds(), vec![TamperKind::TooDark]);
+        // Condition should be absent for 5 seconds
+        detector.update(&[], 118);
+        assert_eq!(detector.get_active_kinds(), vec![TamperKind::TooDark]);
+        detector.update(&[], 119);
+        assert!(detector.get_active_kinds().is_empty());
+        let events = detector.get_events();
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].finished_at, 114);
+    }
+    #[test]
+    fn test_tamper_events_capacity() {
+        let mut detector = TamperDetector::new(TamperThresholds::default());
+        let blurred = vec![(TamperKind::Blurred, 10.0)];
+        detector.update(&blurred, 0);
+        assert_eq!(detector.update(&blurred, 5).len(), 1);
+        let dark = vec![(TamperKind::Blurred, 10.0), (TamperKind::TooDark, 5.0)];
+        let mut now = 10;
+        for _ in 0..TAMPER_EVENTS_CAPACITY {
+            detector.update(&dark, now);
+            detector.update(&dark, now + 5);
+            detector.update(&blurred, now + 6);
+            detector.update(&blurred, now + 11);
+            now += 12;
+        }
+        let events = detector.get_events();
+        assert_eq!(events.len(), TAMPER_EVENTS_CAPACITY);
+        // The oldest event is still active, so it is kept
+        assert_eq!(events[0].kind, TamperKind::Blurred);
+        assert!(events[0].is_active());
+        assert_eq!(detector.get_active_kinds(), vec![TamperKind::Blurred]);
+    }
+}
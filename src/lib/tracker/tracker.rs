@@ -4,36 +4,62 @@ use std::collections::HashMap;
 use std::error::Error;
 use uuid::Uuid;
 
+use mot_rs::utils::Point;
+
 use crate::lib::detection::Detections;
 use crate::lib::spatial::haversine;
 
 pub struct Tracker {
     pub engine: IoUTracker,
     pub objects_extra: HashMap<Uuid, ObjectExtra>,
+    // Objects which have been removed from the engine since last call of take_finished_objects()
+    finished_objects: Vec<ObjectExtra>,
 }
 
 pub struct ObjectExtra {
     class_name: String,
     confidence: f32,
-    // Timestamps along the whole track
-    pub times: Vec<f32>,
+    // Timestamps (seconds) along the whole track
+    pub times: Vec<f64>,
     pub estimated_velocity: f32,
     pub spatial_info: Option<SpatialInfo>,
+    // Position on the camera-wide ground plane. It is known anywhere on the ground, not only inside zones
+    pub ground_info: Option<GroundInfo>,
+    // Sequence of zones (identifiers) the object has passed through
+    pub zones_path: Vec<String>,
 }
 
 impl ObjectExtra {
     pub fn get_classname(&self) -> String {
         self.class_name.clone()
     }
+    // Appends zone to the path if it differs from the last registered one
+    pub fn register_zone(&mut self, zone_id: &str) {
+        match self.zones_path.last() {
+            Some(last_zone_id) if last_zone_id == zone_id => {}
+            _ => {
+                self.zones_path.push(zone_id.to_string());
+            }
+        }
+    }
+}
+
+// Position and speed of the object on the camera-wide ground plane
+#[derive(Debug, Clone)]
+pub struct GroundInfo {
+    pub lon: f64,
+    pub lat: f64,
+    // Speed (km/h) over the last part of the track. Value "-1" indicates no data
+    pub speed: f32,
 }
 
 pub struct SpatialInfo {
-    pub first_time: f32,
+    pub first_time: f64,
     pub first_x_projected: f32,
     pub first_y_projected: f32,
-    pub last_time: f32,
-    pub last_lon: f32,
-    pub last_lat: f32,
+    pub last_time: f64,
+    pub last_lon: f64,
+    pub last_lat: f64,
     pub last_x: f32,
     pub last_y: f32,
     pub last_x_projected: f32,
@@ -43,7 +69,7 @@ pub struct SpatialInfo {
 }
 
 impl SpatialInfo {
-    pub fn new(_time: f32, _x: f32, _y: f32, _x_projected: f32, _y_projected: f32) -> Self {
+    pub fn new(_time: f64, _x: f32, _y: f32, _x_projected: f32, _y_projected: f32) -> Self {
         Self {
             first_time: _time,
             first_x_projected: _x_projected,
@@ -59,7 +85,7 @@ impl SpatialInfo {
             speed: -1.0,
         }
     }
-    pub fn new_wgs84(_time: f32, _lon: f32, _lat: f32, _x: f32, _y: f32) -> Self {
+    pub fn new_wgs84(_time: f64, _lon: f64, _lat: f64, _x: f32, _y: f32) -> Self {
         Self {
             first_time: _time,
             first_x_projected: -1.0,
@@ -79,7 +105,7 @@ impl SpatialInfo {
     // This approach helps to avoid situation when distance between two points is approx. 0
     pub fn update_avg(
         &mut self,
-        _time: f32,
+        _time: f64,
         _x: f32,
         _y: f32,
         _x_projected: f32,
@@ -91,7 +117,7 @@ impl SpatialInfo {
             + (_y_projected - self.first_y_projected).powi(2))
         .sqrt();
         let distance_meters = distance_pixels / pixels_per_meter;
-        let time_diff = (_time - self.first_time).abs();
+        let time_diff = (_time - self.first_time).abs() as f32;
         let velocity = distance_meters / time_diff; // meters per second
         self.speed = velocity * 3.6; // convert m/s to km/h
         self.last_time = _time;
@@ -102,7 +128,7 @@ impl SpatialInfo {
     }
     pub fn update(
         &mut self,
-        _time: f32,
+        _time: f64,
         _x: f32,
         _y: f32,
         _x_projected: f32,
@@ -114,7 +140,7 @@ impl SpatialInfo {
             + (_y_projected - self.last_y_projected).powi(2))
         .sqrt();
         let distance_meters = distance_pixels / pixels_per_meter;
-        let time_diff = _time - self.last_time;
+        let time_diff = (_time - self.last_time) as f32;
         let velocity = distance_meters / time_diff; // meters per second
         self.speed = velocity * 3.6; // convert m/s to km/h
 
@@ -124,10 +150,10 @@ impl SpatialInfo {
         self.last_x_projected = _x_projected;
         self.last_y_projected = _y_projected;
     }
-    fn update_by_wgs84(&mut self, _time: f32, _lon: f32, _lat: f32, _x: f32, _y: f32) {
+    fn update_by_wgs84(&mut self, _time: f64, _lon: f64, _lat: f64, _x: f32, _y: f32) {
         // It is possible to calculate speed between two points (old and new)
-        let distance = haversine(self.last_lon, self.last_lat, _lon, _lat) * 1000.0;
-        let time_diff = _time - self.last_time;
+        let distance = (haversine(self.last_lon, self.last_lat, _lon, _lat) * 1000.0) as f32;
+        let time_diff = (_time - self.last_time) as f32;
         let velocity = distance / time_diff; // meters per second
         self.distance_traveled = distance;
         self.speed = velocity * 3.6; // convert m/s to km/h
@@ -139,17 +165,42 @@ impl SpatialInfo {
         self.last_y = _y;
     }
 }
+// Finds the latest track point which has been observed at least `seconds` before the last one.
+// Track points and timestamps are aligned by their ends.
+// Returns coordinates of found point and time difference between it and the last point
+pub fn find_point_before(track: &[Point], times: &[f64], seconds: f32) -> Option<(f32, f32, f32)> {
+    let n = usize::min(track.len(), times.len());
+    if n < 2 {
+        return None;
+    }
+    let last_time = times[times.len() - 1];
+    for k in 1..n {
+        let time = times[times.len() - 1 - k];
+        let seconds_between = (last_time - time) as f32;
+        if seconds_between >= seconds {
+            let pt = &track[track.len() - 1 - k];
+            return Some((pt.x, pt.y, seconds_between));
+        }
+    }
+    None
+}
+
 impl Tracker {
     pub fn new(_max_no_match: usize, _iou_threshold: f32) -> Self {
         Self {
             engine: IoUTracker::new(_max_no_match, _iou_threshold),
             objects_extra: HashMap::new(),
+            finished_objects: Vec::new(),
         }
     }
+    // Returns objects which have been lost since last call and clears internal buffer
+    pub fn take_finished_objects(&mut self) -> Vec<ObjectExtra> {
+        std::mem::take(&mut self.finished_objects)
+    }
     pub fn match_objects(
         &mut self,
         detections: &mut Detections,
-        current_second: f32,
+        current_second: f64,
     ) -> Result<(), Box<dyn Error>> {
         match self.engine.match_objects(&mut detections.blobs) {
             Ok(_) => {}
@@ -197,6 +248,8 @@ impl Tracker {
                         times: Vec::with_capacity(detection.get_max_track_len()),
                         estimated_velocity: -1.0,
                         spatial_info: None,
+                        ground_info: None,
+                        zones_path: vec![],
                     };
                     object_extra.times.push(current_second);
                     // print!("{}-initial_{}", object_id, detection.get_no_match_times());
@@ -216,10 +269,17 @@ impl Tracker {
 
         // Remove obsolete objects
         let ref_engine_objects = &self.engine.objects;
-        self.objects_extra.retain(|object_id, _| {
-            let save = ref_engine_objects.contains_key(object_id);
-            save
-        });
+        let obsolete: Vec<Uuid> = self
+            .objects_extra
+            .keys()
+            .filter(|object_id| !ref_engine_objects.contains_key(object_id))
+            .cloned()
+            .collect();
+        for object_id in obsolete.iter() {
+            if let Some(object_extra) = self.objects_extra.remove(object_id) {
+                self.finished_objects.push(object_extra);
+            }
+        }
         Ok(())
     }
 }
@@ -1,31 +1,74 @@
 // Export submodules
+pub(crate) mod aggregation;
+pub(crate) mod centerline;
+pub(crate) mod discovery;
+pub(crate) mod flow;
 pub(crate) mod geojson;
 pub(crate) mod geometry;
+pub(crate) mod headway;
+pub(crate) mod los;
+pub(crate) mod occupancy;
+pub(crate) mod queue;
+pub(crate) mod speed_distribution;
+pub(crate) mod speed_trap;
+pub(crate) mod validation;
+pub(crate) mod violations;
 
 use chrono::{DateTime, TimeZone, Utc};
 use std::collections::hash_map::Entry::{Occupied, Vacant};
 use std::collections::HashMap;
+use std::error::Error;
 use uuid::Uuid;
 
-use geometry::PointsOrientation;
-use geometry::{get_orientation, is_intersects, is_on_segment};
+use geometry::polygon_contains_point;
 
+use flow::{density, hourly_rate, pcu_factor, TrafficFlowStatistics};
 use geojson::{GeoPolygon, ZoneFeature, ZonePropertiesGeoJSON};
+use headway::{nominal_vehicle_length, DirectionEstimate, HeadwayAccumulator, HeadwayStatistics};
+use los::{LevelOfService, LevelOfServiceThresholds};
+use occupancy::{is_over_loop, OccupancyAccumulator, OccupancyStatistics};
+use queue::{QueueAccumulator, QueueParameters, QueueStatistics};
+use speed_distribution::SpeedDistribution;
+use speed_trap::{SpeedTrap, SpeedTrapLines};
+use violations::ViolationsAccumulator;
 
-use crate::lib::spatial::compute_center;
-use crate::lib::spatial::epsg::lonlat_to_meters;
-use crate::lib::spatial::haversine;
+use crate::lib::spatial::calibration::{calibrate, CalibrationReport, ControlPoint};
+use crate::lib::spatial::mapping::FrameMapping;
+use crate::lib::spatial::projection::{LocalProjection, ProjectionKind};
 use crate::lib::spatial::SpatialConverter;
 use opencv::{
-    core::Mat, core::Point2f, core::Point2i, core::Scalar, imgproc::line, imgproc::put_text,
-    imgproc::FONT_HERSHEY_SIMPLEX, imgproc::LINE_8,
+    core::add_weighted, core::Mat, core::Point2d, core::Point2f, core::Point2i, core::Rect, core::Scalar, core::Vector,
+    imgproc::fill_poly, imgproc::line, imgproc::put_text, imgproc::FONT_HERSHEY_SIMPLEX,
+    imgproc::LINE_8,
 };
 
+// Position of the reference point for headway evaluation (and center of the virtual loop) as fraction of the skeleton length
+const HEADWAY_REFERENCE_FRACTION: f32 = 0.5;
+// How long (seconds) to keep last positions of objects between periods
+const HEADWAY_KEEP_POSITIONS_SECONDS: f32 = 10.0;
+// How long (seconds) to keep objects in the speed trap between periods
+const SPEED_TRAP_KEEP_OBJECTS_SECONDS: f32 = 10.0;
+// Opacity of the level of service overlay
+const LOS_OVERLAY_ALPHA: f64 = 0.3;
+// Half-length of distance ticks along the skeleton (pixels)
+const SKELETON_TICK_PIXELS: f32 = 6.0;
+// How long (seconds) to remember violators between periods, so they are not counted twice
+const VIOLATIONS_KEEP_OBJECTS_SECONDS: f32 = 10.0;
+
 #[derive(Debug)]
 pub struct Statistics {
     pub period_start: DateTime<Utc>,
     pub period_end: DateTime<Utc>,
     pub vehicles_data: HashMap<String, VehicleTypeParameters>,
+    pub headway: HeadwayStatistics,
+    pub queue: QueueStatistics,
+    pub occupancy: OccupancyStatistics,
+    // Number of objects of disallowed classes for each vehicle type
+    pub violations: HashMap<String, u32>,
+    pub traffic_flow: TrafficFlowStatistics,
+    pub level_of_service: LevelOfService,
+    // Reasons (e.g. camera tampering) why statistics of the period can't be trusted. Empty means that statistics is valid
+    pub invalid_reasons: Vec<String>,
 }
 
 impl Statistics {
@@ -34,6 +77,13 @@ impl Statistics {
             period_start: TimeZone::with_ymd_and_hms(&Utc, 1970, 1, 1, 0, 0, 0).unwrap(),
             period_end: TimeZone::with_ymd_and_hms(&Utc, 1970, 1, 1, 0, 0, 0).unwrap(),
             vehicles_data: HashMap::new(),
+            headway: HeadwayStatistics::default(),
+            queue: QueueStatistics::default(),
+            occupancy: OccupancyStatistics::default(),
+            violations: HashMap::new(),
+            traffic_flow: TrafficFlowStatistics::default(),
+            level_of_service: LevelOfService::Undefined,
+            invalid_reasons: vec![],
         }
     }
 }
@@ -42,6 +92,11 @@ impl Statistics {
 pub struct VehicleTypeParameters {
     pub avg_speed: f32,
     pub sum_intensity: u32,
+    // Vehicles per hour. Value "-1" indicates unknown period
+    pub flow_rate: f32,
+    // Number of vehicles in passenger car units
+    pub pcu_volume: f32,
+    pub speed_distribution: SpeedDistribution,
 }
 
 impl VehicleTypeParameters {
@@ -49,6 +104,9 @@ impl VehicleTypeParameters {
         VehicleTypeParameters {
             avg_speed: -1.0,
             sum_intensity: 0,
+            flow_rate: -1.0,
+            pcu_volume: 0.0,
+            speed_distribution: SpeedDistribution::default(),
         }
     }
 }
@@ -61,6 +119,135 @@ struct ObjectInfo {
 
 type Registered = HashMap<Uuid, ObjectInfo>;
 
+// Observations collected during the single aggregation period
+#[derive(Debug)]
+struct PeriodAccumulators {
+    objects: Registered,
+    headway: HeadwayAccumulator,
+    queue: QueueAccumulator,
+    occupancy: OccupancyAccumulator,
+    violations: ViolationsAccumulator,
+}
+
+impl PeriodAccumulators {
+    fn default() -> Self {
+        PeriodAccumulators {
+            objects: HashMap::new(),
+            headway: HeadwayAccumulator::default(),
+            queue: QueueAccumulator::default(),
+            occupancy: OccupancyAccumulator::default(),
+            violations: ViolationsAccumulator::default(),
+        }
+    }
+    fn register_object(&mut self, object_id: Uuid, speed: f32, classname: String) {
+        match self.objects.entry(object_id) {
+            Occupied(mut entry) => {
+                entry.get_mut().classname = classname;
+                entry.get_mut().speed = speed;
+            }
+            Vacant(entry) => {
+                entry.insert(ObjectInfo {
+                    classname: classname,
+                    speed: speed,
+                });
+            }
+        }
+    }
+    // Evaluates statistics for the finished period and prepares accumulators for the next one
+    fn collect(&mut self, statistics: &mut Statistics, pcu_factors: &HashMap<String, f32>, length_meters: f32, los_thresholds: &LevelOfServiceThresholds) {
+        // Speed samples for every vehicle type
+        let mut speeds: HashMap<String, Vec<f32>> = HashMap::new();
+        for (_, object_info) in self.objects.iter() {
+            let classname = object_info.classname.to_owned();
+            let speed = object_info.speed;
+            speeds.entry(classname.clone()).or_insert(vec![]).push(speed);
+            let mut vehicle_type_parameters = match statistics.vehicles_data.entry(classname) {
+                Occupied(o) => o.into_mut(),
+                Vacant(v) => {
+                    v.insert(VehicleTypeParameters {
+                        sum_intensity: 1,
+                        avg_speed: speed,
+                        flow_rate: -1.0,
+                        pcu_volume: 0.0,
+                        speed_distribution: SpeedDistribution::default(),
+                    });
+                    continue;
+                }
+            };
+            vehicle_type_parameters.sum_intensity += 1;
+            // Iterative average calculation
+            // https://math.stackexchange.com/questions/106700/incremental-averageing
+            vehicle_type_parameters.avg_speed = vehicle_type_parameters.avg_speed
+                * ((vehicle_type_parameters.sum_intensity - 1) as f32
+                    / vehicle_type_parameters.sum_intensity as f32)
+                + speed / vehicle_type_parameters.sum_intensity as f32;
+        }
+        for (classname, samples) in speeds.iter() {
+            if let Some(vehicle_type_parameters) = statistics.vehicles_data.get_mut(classname) {
+                vehicle_type_parameters.speed_distribution = SpeedDistribution::from_samples(samples);
+            }
+        }
+        self.objects.clear();
+        statistics.headway = self.headway.get_statistics();
+        self.headway.reset(HEADWAY_KEEP_POSITIONS_SECONDS);
+        statistics.queue = self.queue.get_statistics();
+        self.queue.reset();
+        statistics.occupancy = self.occupancy.get_statistics();
+        self.occupancy.reset();
+        statistics.violations = self.violations.get_statistics();
+        self.violations.reset(VIOLATIONS_KEEP_OBJECTS_SECONDS);
+        update_traffic_flow(statistics, pcu_factors, length_meters);
+        let known_speeds: Vec<f32> = speeds.values().flatten().cloned().filter(|speed| *speed >= 0.0).collect();
+        let avg_speed = if known_speeds.len() > 0 {
+            known_speeds.iter().sum::<f32>() / known_speeds.len() as f32
+        } else {
+            -1.0
+        };
+        statistics.level_of_service = los_thresholds.classify(statistics.traffic_flow.density, avg_speed);
+    }
+}
+
+// Additional aggregation interval. It has its own accumulators which receive the same observations as the main ones
+#[derive(Debug)]
+struct ZoneInterval {
+    interval_milliseconds: i64,
+    accumulators: PeriodAccumulators,
+    statistics: Statistics,
+}
+
+// Prepares statistics for the new period. Known vehicle types are kept
+fn reset_statistics(statistics: &mut Statistics, _period_start: DateTime<Utc>, _period_end: DateTime<Utc>) {
+    statistics.period_start = _period_start;
+    statistics.period_end = _period_end;
+    for (_, class_stats) in statistics.vehicles_data.iter_mut() {
+        class_stats.sum_intensity = 0;
+        class_stats.avg_speed = -1.0;
+        class_stats.flow_rate = -1.0;
+        class_stats.pcu_volume = 0.0;
+        class_stats.speed_distribution = SpeedDistribution::default();
+    }
+}
+
+// Evaluates flow rates and PCU volumes for counted vehicles and density for the zone.
+// Should be called after counts and occupancy have been aggregated for the period
+fn update_traffic_flow(statistics: &mut Statistics, pcu_factors: &HashMap<String, f32>, length_meters: f32) {
+    let period_seconds = (statistics.period_end - statistics.period_start).num_milliseconds() as f32 / 1000.0;
+    let mut total_count = 0;
+    let mut total_pcu = 0.0;
+    for (classname, class_stats) in statistics.vehicles_data.iter_mut() {
+        class_stats.flow_rate = hourly_rate(class_stats.sum_intensity as f32, period_seconds);
+        class_stats.pcu_volume = class_stats.sum_intensity as f32 * pcu_factor(pcu_factors, classname);
+        total_count += class_stats.sum_intensity;
+        total_pcu += class_stats.pcu_volume;
+    }
+    statistics.traffic_flow = TrafficFlowStatistics {
+        flow_rate: hourly_rate(total_count as f32, period_seconds),
+        pcu_volume: total_pcu,
+        pcu_flow_rate: hourly_rate(total_pcu, period_seconds),
+        density: density(statistics.occupancy.avg_vehicles, length_meters),
+    };
+}
+
 #[derive(Debug)]
 struct Skeleton {
     line: [Point2f; 2],
@@ -127,28 +314,62 @@ impl Skeleton {
             (xP_prime, yP_prime)
         }
     }
+    // Signed distance (meters) along the skeleton from its first point to the projection of the given point.
+    // It is negative before the first point and greater than skeleton length after the last one
+    pub fn project_position(&self, x: f32, y: f32) -> f32 {
+        let a = self.line[0];
+        let b = self.line[1];
+        if self.length_pixels <= 0.0 {
+            return 0.0;
+        }
+        let scalar_projection = ((x - a.x) * (b.x - a.x) + (y - a.y) * (b.y - a.y)) / self.length_pixels;
+        scalar_projection / self.pixels_per_meter
+    }
 }
 
 #[derive(Debug)]
 pub struct Zone {
     pub id: String,
     pixel_coordinates: Vec<Point2f>,
-    spatial_coordinates_epsg4326: Vec<Point2f>,
-    spatial_coordinates_epsg3857: Vec<Point2f>,
+    spatial_coordinates_epsg4326: Vec<Point2d>,
     pub color: Scalar,
     pub road_lane_num: u16,
     pub road_lane_direction: u8,
+    // Name of the group (e.g. approach) which zone belongs to. Empty string means that zone is not grouped
+    pub group_name: String,
+    // Metric plane of the spatial converter
+    projection: ProjectionKind,
     spatial_converter: SpatialConverter,
+    // Pairs of points used for the spatial converter. Empty set means that zone's corners are used
+    control_points: Vec<ControlPoint>,
+    calibration_report: Option<CalibrationReport>,
+    // Statistics of the main aggregation interval
     pub statistics: Statistics,
-    objects: Registered,
+    accumulators: PeriodAccumulators,
+    // Main aggregation interval (milliseconds). Value "0" indicates that it is not set
+    interval_milliseconds: i64,
+    // Additional aggregation intervals
+    intervals: Vec<ZoneInterval>,
     pub current_statistics: RealTimeStatistics,
     skeleton: Skeleton,
+    // Vehicle types which are used for statistics. Empty set means that every type is counted
+    counted_classes: Vec<String>,
+    // Vehicle types which are allowed to be in the zone. Empty set means that every type is allowed
+    allowed_classes: Vec<String>,
+    los_thresholds: LevelOfServiceThresholds,
+    queue_parameters: QueueParameters,
+    // Prevailing direction of traffic along the skeleton. It is kept for the whole life of the zone
+    direction_estimate: DirectionEstimate,
+    // Alternative speed measurement. When it is set, speeds for statistics are taken from it instead of the skeleton projection
+    speed_trap: Option<SpeedTrap>,
 }
 
 #[derive(Debug)]
 pub struct RealTimeStatistics {
     pub last_time: u64,
     pub occupancy: u16,
+    // Queue length (meters) from the stop-line
+    pub queue_length: f32,
 }
 
 impl Zone {
@@ -157,39 +378,45 @@ impl Zone {
             id: Uuid::new_v4().to_string(),
             pixel_coordinates: vec![],
             spatial_coordinates_epsg4326: vec![],
-            spatial_coordinates_epsg3857: vec![],
             color: Scalar::from((255.0, 255.0, 255.0)),
             road_lane_num: 0,
             road_lane_direction: 0,
+            group_name: String::new(),
+            projection: ProjectionKind::default(),
             spatial_converter: SpatialConverter::default(),
+            control_points: vec![],
+            calibration_report: None,
             statistics: Statistics::default(),
-            objects: HashMap::new(),
+            accumulators: PeriodAccumulators::default(),
+            interval_milliseconds: 0,
+            intervals: vec![],
             current_statistics: RealTimeStatistics {
                 last_time: 0,
                 occupancy: 0,
+                queue_length: 0.0,
             },
             skeleton: Skeleton::default(),
+            counted_classes: vec![],
+            allowed_classes: vec![],
+            los_thresholds: LevelOfServiceThresholds::default(),
+            queue_parameters: QueueParameters::default(),
+            direction_estimate: DirectionEstimate::default(),
+            speed_trap: None,
         }
     }
     pub fn new(
         id: String,
         coordinates: Vec<Point2f>,
-        spatial_coordinates_epsg4326: Vec<Point2f>,
-        spatial_coordinates_epsg3857: Vec<Point2f>,
+        spatial_coordinates_epsg4326: Vec<Point2d>,
         color: Scalar,
         road_lane_num: u16,
         road_lane_direction: u8,
+        projection: ProjectionKind,
     ) -> Self {
         let converter =
-            SpatialConverter::new_from(coordinates.clone(), spatial_coordinates_epsg3857.clone());
+            SpatialConverter::new_from(projection, coordinates.clone(), spatial_coordinates_epsg4326.clone());
         /* Eval distance between sides */
-        let a = spatial_coordinates_epsg4326[0];
-        let b = spatial_coordinates_epsg4326[1];
-        let c = spatial_coordinates_epsg4326[2];
-        let d = spatial_coordinates_epsg4326[3];
-        let ab_center = compute_center(a.x, a.y, b.x, b.y);
-        let cd_center = compute_center(c.x, c.y, d.x, d.y);
-        let length_meters = haversine(ab_center.0, ab_center.1, cd_center.0, cd_center.1) * 1000.0;
+        let length_meters = sides_distance_meters(&spatial_coordinates_epsg4326);
 
         /* Init skeleton */
         let skeleton_line = find_skeleton_line(&coordinates, 0, 2); // 0-1 is first segment of polygon, 2-3 is second segment
@@ -200,18 +427,30 @@ impl Zone {
             id: id,
             pixel_coordinates: coordinates,
             spatial_coordinates_epsg4326: spatial_coordinates_epsg4326,
-            spatial_coordinates_epsg3857: spatial_coordinates_epsg3857,
             color: color,
             road_lane_num: road_lane_num,
             road_lane_direction: road_lane_direction,
+            group_name: String::new(),
+            projection: projection,
             spatial_converter: converter,
+            control_points: vec![],
+            calibration_report: None,
             statistics: Statistics::default(),
-            objects: HashMap::new(),
+            accumulators: PeriodAccumulators::default(),
+            interval_milliseconds: 0,
+            intervals: vec![],
             current_statistics: RealTimeStatistics {
                 last_time: 0,
                 occupancy: 0,
+                queue_length: 0.0,
             },
             skeleton: skeleton,
+            counted_classes: vec![],
+            allowed_classes: vec![],
+            los_thresholds: LevelOfServiceThresholds::default(),
+            queue_parameters: QueueParameters::default(),
+            direction_estimate: DirectionEstimate::default(),
+            speed_trap: None,
         }
     }
     pub fn new_from_cv_with_id(points: Vec<Point2f>, id: String) -> Self {
@@ -220,18 +459,30 @@ impl Zone {
             id: id,
             pixel_coordinates: points,
             spatial_coordinates_epsg4326: vec![],
-            spatial_coordinates_epsg3857: vec![],
             color: Scalar::from((255.0, 255.0, 255.0)),
             road_lane_num: 0,
             road_lane_direction: 0,
+            group_name: String::new(),
+            projection: ProjectionKind::default(),
             spatial_converter: SpatialConverter::default(),
+            control_points: vec![],
+            calibration_report: None,
             statistics: Statistics::default(),
-            objects: HashMap::new(),
+            accumulators: PeriodAccumulators::default(),
+            interval_milliseconds: 0,
+            intervals: vec![],
             current_statistics: RealTimeStatistics {
                 last_time: 0,
                 occupancy: 0,
+                queue_length: 0.0,
             },
             skeleton: Skeleton::new(skeleton_line[0], skeleton_line[1]),
+            counted_classes: vec![],
+            allowed_classes: vec![],
+            los_thresholds: LevelOfServiceThresholds::default(),
+            queue_parameters: QueueParameters::default(),
+            direction_estimate: DirectionEstimate::default(),
+            speed_trap: None,
         };
     }
     pub fn default_from_cv(points: Vec<Point2f>) -> Self {
@@ -249,10 +500,49 @@ impl Zone {
     pub fn set_road_lane_direction(&mut self, new_value: u8) {
         self.road_lane_direction = new_value;
     }
+    pub fn set_group_name(&mut self, new_value: String) {
+        self.group_name = new_value;
+    }
+    pub fn get_projection(&self) -> ProjectionKind {
+        self.projection
+    }
+    // Changes metric plane of the spatial converter. Converter is re-estimated if geometry or control points are set already
+    pub fn set_projection(&mut self, kind: ProjectionKind) {
+        if self.projection == kind {
+            return;
+        }
+        self.projection = kind;
+        if self.control_points.len() > 0 {
+            let points = self.control_points.clone();
+            match self.set_control_points(points) {
+                Ok(_) => {}
+                Err(err) => {
+                    println!("Can't calibrate zone '{}' for the new projection due the error: {}", self.id, err);
+                }
+            }
+        } else if self.pixel_coordinates.len() > 0 && self.spatial_coordinates_epsg4326.len() > 0 {
+            self.spatial_converter = SpatialConverter::new_from(
+                self.projection,
+                self.pixel_coordinates.clone(),
+                self.spatial_coordinates_epsg4326.clone(),
+            );
+        }
+    }
     pub fn get_pixel_coordinates(&self) -> Vec<Point2f> {
         self.pixel_coordinates.clone()
     }
-    pub fn get_spatial_coordinates_epsg4326(&self) -> Vec<Point2f> {
+    // Bounding box of the zone on the video frame
+    pub fn get_bounding_rect(&self) -> Rect {
+        let min_x = self.pixel_coordinates.iter().map(|pt| pt.x).fold(f32::MAX, f32::min);
+        let min_y = self.pixel_coordinates.iter().map(|pt| pt.y).fold(f32::MAX, f32::min);
+        let max_x = self.pixel_coordinates.iter().map(|pt| pt.x).fold(f32::MIN, f32::max);
+        let max_y = self.pixel_coordinates.iter().map(|pt| pt.y).fold(f32::MIN, f32::max);
+        if min_x > max_x || min_y > max_y {
+            return Rect::default();
+        }
+        Rect::new(min_x as i32, min_y as i32, (max_x - min_x) as i32, (max_y - min_y) as i32)
+    }
+    pub fn get_spatial_coordinates_epsg4326(&self) -> Vec<Point2d> {
         self.spatial_coordinates_epsg4326.clone()
     }
     pub fn set_color(&mut self, rgb: [i16; 3]) {
@@ -260,67 +550,101 @@ impl Zone {
     }
 
     pub fn update_skeleton(&mut self) {
-        /* Eval distance between sides */
-        let a = self.spatial_coordinates_epsg4326[0];
-        let b = self.spatial_coordinates_epsg4326[1];
-        let c = self.spatial_coordinates_epsg4326[2];
-        let d = self.spatial_coordinates_epsg4326[3];
-        let ab_center = compute_center(a.x, a.y, b.x, b.y);
-        let cd_center = compute_center(c.x, c.y, d.x, d.y);
-        let length_meters = haversine(ab_center.0, ab_center.1, cd_center.0, cd_center.1) * 1000.0;
+        if self.pixel_coordinates.len() < 4 || self.spatial_coordinates_epsg4326.len() < 4 {
+            return;
+        }
         /* Init skeleton */
         let skeleton_line = find_skeleton_line(&self.pixel_coordinates, 0, 2); // 0-1 is first segment of polygon, 2-3 is second segment
         let mut skeleton = Skeleton::new(skeleton_line[0], skeleton_line[1]);
-        skeleton.length_meters = length_meters;
+        /* Eval distance between sides. Calibration by control points is more precise than zone's corners */
+        let calibrated_length = if self.control_points.len() > 0 { self.calibrated_distance_meters(&skeleton_line) } else { None };
+        skeleton.length_meters = match calibrated_length {
+            Some(length_meters) => length_meters,
+            None => sides_distance_meters(&self.spatial_coordinates_epsg4326),
+        };
         skeleton.pixels_per_meter = skeleton.length_pixels / skeleton.length_meters;
         self.skeleton = skeleton;
     }
+    // Distance (meters) between pixel points on the metric plane of the spatial converter
+    fn calibrated_distance_meters(&self, line: &[Point2f; 2]) -> Option<f32> {
+        if !line.iter().all(|pt| self.spatial_converter.is_on_ground(pt.x, pt.y)) {
+            return None;
+        }
+        let (ax, ay) = self.spatial_converter.transform_to_local(line[0].x, line[0].y)?;
+        let (bx, by) = self.spatial_converter.transform_to_local(line[1].x, line[1].y)?;
+        Some(((ax - bx).powi(2) + (ay - by).powi(2)).sqrt() as f32)
+    }
     pub fn update_pixel_map_cv(&mut self, pixel_src_points: Vec<Point2f>) {
         self.pixel_coordinates = pixel_src_points;
+        // Skeleton could be reversed by the new geometry
+        self.direction_estimate = DirectionEstimate::default();
         if self.spatial_coordinates_epsg4326.len() == 0 {
             self.spatial_coordinates_epsg4326 = self
                 .pixel_coordinates
                 .iter()
-                .map(|pt| Point2f::new(pt.x as f32, pt.y as f32))
-                .collect();
-            self.spatial_coordinates_epsg3857 = self
-                .spatial_coordinates_epsg4326
-                .iter()
-                .map(|pt| {
-                    let lonlat = lonlat_to_meters(pt.x, pt.y);
-                    Point2f::new(lonlat.0, lonlat.1)
-                })
+                .map(|pt| Point2d::new(pt.x as f64, pt.y as f64))
                 .collect();
         }
-        self.spatial_converter = SpatialConverter::new_from(
-            self.pixel_coordinates.clone(),
-            self.spatial_coordinates_epsg3857.clone(),
-        );
+        if self.control_points.len() == 0 {
+            self.spatial_converter = SpatialConverter::new_from(
+                self.projection,
+                self.pixel_coordinates.clone(),
+                self.spatial_coordinates_epsg4326.clone(),
+            );
+        }
         self.update_skeleton();
     }
-    pub fn update_spatial_map_cv(&mut self, spatial_dest_points: Vec<Point2f>) {
+    pub fn update_spatial_map_cv(&mut self, spatial_dest_points: Vec<Point2d>) {
         self.spatial_coordinates_epsg4326 = spatial_dest_points;
-        self.spatial_coordinates_epsg3857 = self
-            .spatial_coordinates_epsg4326
-            .iter()
-            .map(|pt| {
-                let lonlat = lonlat_to_meters(pt.x, pt.y);
-                Point2f::new(lonlat.0, lonlat.1)
-            })
-            .collect();
         if self.pixel_coordinates.len() == 0 {
             self.pixel_coordinates = self
-                .spatial_coordinates_epsg3857
+                .spatial_coordinates_epsg4326
                 .iter()
                 .map(|pt| Point2f::new(pt.x as f32, pt.y as f32))
                 .collect();
         }
-        self.spatial_converter = SpatialConverter::new_from(
-            self.pixel_coordinates.clone(),
-            self.spatial_coordinates_epsg3857.clone(),
-        );
+        if self.control_points.len() == 0 {
+            self.spatial_converter = SpatialConverter::new_from(
+                self.projection,
+                self.pixel_coordinates.clone(),
+                self.spatial_coordinates_epsg4326.clone(),
+            );
+        }
+        self.update_skeleton();
+    }
+    // Replaces spatial converter with the one estimated by control points (at least 4 pairs).
+    // Converter is kept untouched if estimation fails
+    pub fn set_control_points(&mut self, points: Vec<ControlPoint>) -> Result<CalibrationReport, Box<dyn Error>> {
+        let (converter, report) = calibrate(self.projection, &points)?;
+        self.set_calibration(points, converter, report.clone());
+        Ok(report)
+    }
+    // Applies spatial converter which has been estimated by control points already (see `calibrate`)
+    pub fn set_calibration(&mut self, points: Vec<ControlPoint>, converter: SpatialConverter, report: CalibrationReport) {
+        self.spatial_converter = converter;
+        self.control_points = points;
+        self.calibration_report = Some(report);
+        self.update_skeleton();
+    }
+    // Returns spatial converter based on zone's corners back
+    pub fn clear_control_points(&mut self) {
+        self.control_points = vec![];
+        self.calibration_report = None;
+        if self.pixel_coordinates.len() > 0 && self.spatial_coordinates_epsg4326.len() > 0 {
+            self.spatial_converter = SpatialConverter::new_from(
+                self.projection,
+                self.pixel_coordinates.clone(),
+                self.spatial_coordinates_epsg4326.clone(),
+            );
+        }
         self.update_skeleton();
     }
+    pub fn get_control_points(&self) -> Vec<ControlPoint> {
+        self.control_points.clone()
+    }
+    pub fn get_calibration_report(&self) -> Option<CalibrationReport> {
+        self.calibration_report.clone()
+    }
     pub fn update_pixel_map(&mut self, pixel_src_points: [[u16; 2]; 4]) {
         let val = pixel_src_points
             .iter()
@@ -328,134 +652,253 @@ impl Zone {
             .collect();
         self.update_pixel_map_cv(val);
     }
-    pub fn update_spatial_map(&mut self, spatial_dest_points: [[f32; 2]; 4]) {
+    pub fn update_spatial_map(&mut self, spatial_dest_points: [[f64; 2]; 4]) {
         let val = spatial_dest_points
             .iter()
-            .map(|pt| Point2f::new(pt[0], pt[1]))
+            .map(|pt| Point2d::new(pt[0], pt[1]))
             .collect();
         self.update_spatial_map_cv(val);
     }
-    pub fn set_target_classes(&mut self, vehicle_types: &'static [&'static str]) {
-        for class in vehicle_types.iter() {
-            self.statistics
-                .vehicles_data
-                .insert(class.to_string(), VehicleTypeParameters::default());
+    pub fn set_target_classes(&mut self, vehicle_types: Vec<String>) {
+        let statistics = std::iter::once(&mut self.statistics).chain(self.intervals.iter_mut().map(|interval| &mut interval.statistics));
+        for statistics in statistics {
+            statistics.vehicles_data.clear();
+            for class in vehicle_types.iter() {
+                statistics
+                    .vehicles_data
+                    .insert(class.to_string(), VehicleTypeParameters::default());
+            }
         }
+        self.counted_classes = vehicle_types;
     }
-    pub fn register_or_update_object(&mut self, object_id: Uuid, _speed: f32, _classname: String) {
-        match self.objects.entry(object_id) {
-            Occupied(mut entry) => {
-                entry.get_mut().classname = _classname;
-                entry.get_mut().speed = _speed;
+    // Sets main aggregation interval and additional ones (milliseconds). Accumulators of the kept intervals are preserved
+    pub fn set_aggregation_intervals(&mut self, main_interval: i64, extra_intervals: &[i64]) {
+        self.interval_milliseconds = main_interval;
+        let mut intervals = vec![];
+        for interval_milliseconds in extra_intervals.iter().filter(|interval| **interval != main_interval) {
+            if intervals.iter().any(|interval: &ZoneInterval| interval.interval_milliseconds == *interval_milliseconds) {
+                continue;
             }
-            Vacant(entry) => {
-                entry.insert(ObjectInfo {
-                    classname: _classname,
-                    speed: _speed,
-                });
+            let interval = match self.intervals.iter().position(|interval| interval.interval_milliseconds == *interval_milliseconds) {
+                Some(idx) => self.intervals.remove(idx),
+                None => {
+                    let mut statistics = Statistics::default();
+                    for class in self.counted_classes.iter() {
+                        statistics.vehicles_data.insert(class.to_string(), VehicleTypeParameters::default());
+                    }
+                    ZoneInterval {
+                        interval_milliseconds: *interval_milliseconds,
+                        accumulators: PeriodAccumulators::default(),
+                        statistics: statistics,
+                    }
+                }
+            };
+            intervals.push(interval);
+        }
+        self.intervals = intervals;
+    }
+    // Statistics of the last finished period for the given aggregation interval (milliseconds)
+    pub fn get_statistics(&self, interval_milliseconds: i64) -> Option<&Statistics> {
+        if interval_milliseconds == self.interval_milliseconds {
+            return Some(&self.statistics);
+        }
+        self.intervals.iter().find(|interval| interval.interval_milliseconds == interval_milliseconds).map(|interval| &interval.statistics)
+    }
+    pub fn get_statistics_mut(&mut self, interval_milliseconds: i64) -> Option<&mut Statistics> {
+        if interval_milliseconds == self.interval_milliseconds {
+            return Some(&mut self.statistics);
+        }
+        self.intervals.iter_mut().find(|interval| interval.interval_milliseconds == interval_milliseconds).map(|interval| &mut interval.statistics)
+    }
+    // Accumulators of every aggregation interval. Main ones go first
+    fn all_accumulators(&mut self) -> impl Iterator<Item = &mut PeriodAccumulators> {
+        std::iter::once(&mut self.accumulators).chain(self.intervals.iter_mut().map(|interval| &mut interval.accumulators))
+    }
+    pub fn get_target_classes(&self) -> Vec<String> {
+        self.counted_classes.clone()
+    }
+    pub fn set_allowed_classes(&mut self, vehicle_types: Vec<String>) {
+        self.allowed_classes = vehicle_types;
+    }
+    pub fn get_allowed_classes(&self) -> Vec<String> {
+        self.allowed_classes.clone()
+    }
+    pub fn set_los_thresholds(&mut self, thresholds: LevelOfServiceThresholds) {
+        self.los_thresholds = thresholds;
+    }
+    pub fn get_los_thresholds(&self) -> LevelOfServiceThresholds {
+        self.los_thresholds.clone()
+    }
+    pub fn set_queue_parameters(&mut self, parameters: QueueParameters) {
+        self.queue_parameters = parameters;
+    }
+    pub fn get_queue_parameters(&self) -> QueueParameters {
+        self.queue_parameters.clone()
+    }
+    // Enables speed trap mode. Lines should lie inside of the zone. Non-positive distance disables speed trap
+    pub fn set_speed_trap(&mut self, lines: SpeedTrapLines) {
+        if lines.distance_meters <= 0.0 {
+            self.speed_trap = None;
+            return;
+        }
+        self.speed_trap = Some(SpeedTrap::new(&lines));
+    }
+    pub fn get_speed_trap(&self) -> Option<SpeedTrapLines> {
+        self.speed_trap.as_ref().map(|trap| trap.get_lines())
+    }
+    // Registers position of the object in the speed trap (if it is enabled)
+    // Returns measured speed (km/h) if object has just passed the speed trap
+    pub fn update_speed_trap(&mut self, object_id: Uuid, time: f64, x: f32, y: f32) -> Option<f32> {
+        match self.speed_trap {
+            Some(ref mut trap) => trap.update(object_id, time, x, y),
+            None => None,
+        }
+    }
+    pub fn is_class_counted(&self, classname: &str) -> bool {
+        self.counted_classes.len() == 0 || self.counted_classes.iter().any(|class| class == classname)
+    }
+    pub fn is_class_allowed(&self, classname: &str) -> bool {
+        self.allowed_classes.len() == 0 || self.allowed_classes.iter().any(|class| class == classname)
+    }
+    // Registers object of disallowed class as the violator
+    // Returns true if object is new violator (so the violation event should be generated)
+    pub fn register_violation(&mut self, object_id: Uuid, time: f64, classname: &str) -> bool {
+        if self.is_class_allowed(classname) {
+            return false;
+        }
+        for interval in self.intervals.iter_mut() {
+            interval.accumulators.violations.register(object_id, time, classname);
+        }
+        self.accumulators.violations.register(object_id, time, classname)
+    }
+    pub fn register_or_update_object(&mut self, object_id: Uuid, _speed: f32, _classname: String) {
+        if !self.is_class_counted(&_classname) {
+            return;
+        }
+        let _speed = match self.speed_trap {
+            Some(ref trap) => trap.get_speed(&object_id),
+            None => _speed,
+        };
+        for accumulators in self.all_accumulators() {
+            accumulators.register_object(object_id, _speed, _classname.clone());
+        }
+    }
+    // Registers position of the object along the skeleton for headway / gap / spacing evaluation
+    // Returns true if object has passed the reference point (middle of the skeleton)
+    pub fn register_crossing(&mut self, object_id: Uuid, time: f64, x: f32, y: f32, speed: f32, classname: &str) -> bool {
+        if self.skeleton.pixels_per_meter <= 0.0 {
+            return false;
+        }
+        let position = self.skeleton.project_position(x, y);
+        let reference = self.skeleton.length_meters * HEADWAY_REFERENCE_FRACTION;
+        for interval in self.intervals.iter_mut() {
+            interval.accumulators.headway.update(object_id, time, position, reference, speed, classname);
+        }
+        let direction = self.accumulators.headway.update(object_id, time, position, reference, speed, classname);
+        self.direction_estimate.register(direction);
+        direction != 0
+    }
+    // Registers object as the queue member if it is stationary or slow.
+    // Speed is estimated between two given positions of the object (in pixels) separated by `seconds_between`
+    pub fn register_queue_candidate(&mut self, x: f32, y: f32, x_before: f32, y_before: f32, seconds_between: f32) {
+        if self.skeleton.pixels_per_meter <= 0.0 || seconds_between <= 0.0 {
+            return;
+        }
+        let position = self.skeleton.project_position(x, y);
+        let position_before = self.skeleton.project_position(x_before, y_before);
+        let speed = (position - position_before).abs() / seconds_between * 3.6;
+        if speed >= self.queue_parameters.speed_threshold {
+            return;
+        }
+        // Stop-line is the end of the skeleton in the direction of traffic flow
+        let flow_direction = match self.queue_parameters.flow_direction {
+            0 => self.direction_estimate.prevailing(),
+            direction => direction,
+        };
+        // Vehicles which have passed the stop-line are treated as standing on it
+        let distance_from_stop_line = match flow_direction {
+            1 => self.skeleton.length_meters - position,
+            -1 => position,
+            // Stop-line is unknown until vehicles have passed the zone
+            _ => return,
+        }.max(0.0);
+        for accumulators in self.all_accumulators() {
+            accumulators.queue.register(distance_from_stop_line);
+        }
+    }
+    // Evaluates queue length for registered candidates. Should be called once per frame
+    pub fn update_queue(&mut self) {
+        let max_spacing = self.queue_parameters.max_spacing;
+        for interval in self.intervals.iter_mut() {
+            interval.accumulators.queue.commit_frame(max_spacing);
+        }
+        self.current_statistics.queue_length = self.accumulators.queue.commit_frame(max_spacing);
+    }
+    // Marks the zone as occupied by the object in the current frame.
+    // Virtual loop is marked as occupied too if the object (with nominal length of its class) overlaps it
+    pub fn register_occupancy(&mut self, x: f32, y: f32, classname: &str) {
+        let over_loop = self.skeleton.pixels_per_meter > 0.0 && {
+            let position = self.skeleton.project_position(x, y);
+            let loop_center = self.skeleton.length_meters * HEADWAY_REFERENCE_FRACTION;
+            is_over_loop(position, nominal_vehicle_length(classname), loop_center)
+        };
+        for accumulators in self.all_accumulators() {
+            accumulators.occupancy.register_zone_occupied();
+            if over_loop {
+                accumulators.occupancy.register_loop_occupied();
             }
         }
     }
-    pub fn reset_objects(&mut self) {
-        self.objects.clear();
+    // Accumulates occupied time up to the given frame timestamp (seconds). Should be called once per frame
+    pub fn update_occupancy(&mut self, time: f64) {
+        let loop_available = self.skeleton.pixels_per_meter > 0.0;
+        for accumulators in self.all_accumulators() {
+            accumulators.occupancy.commit_frame(time, loop_available);
+        }
     }
     pub fn reset_statistics(&mut self, _period_start: DateTime<Utc>, _period_end: DateTime<Utc>) {
-        self.statistics.period_start = _period_start;
-        self.statistics.period_end = _period_end;
-        for (_, class_stats) in self.statistics.vehicles_data.iter_mut() {
-            class_stats.sum_intensity = 0;
-            class_stats.avg_speed = -1.0;
-        }
+        reset_statistics(&mut self.statistics, _period_start, _period_end);
     }
-    pub fn update_statistics(&mut self, _period_start: DateTime<Utc>, _period_end: DateTime<Utc>) {
+    pub fn update_statistics(&mut self, _period_start: DateTime<Utc>, _period_end: DateTime<Utc>, pcu_factors: &HashMap<String, f32>) {
         self.reset_statistics(_period_start, _period_end);
-        for (_, object_info) in self.objects.iter() {
-            let classname = object_info.classname.to_owned();
-            let speed = object_info.speed;
-            let mut vehicle_type_parameters = match self.statistics.vehicles_data.entry(classname) {
-                Occupied(o) => o.into_mut(),
-                Vacant(v) => {
-                    v.insert(VehicleTypeParameters {
-                        sum_intensity: 1,
-                        avg_speed: speed,
-                    });
-                    continue;
-                }
-            };
-            vehicle_type_parameters.sum_intensity += 1;
-            // Iterative average calculation
-            // https://math.stackexchange.com/questions/106700/incremental-averageing
-            vehicle_type_parameters.avg_speed = vehicle_type_parameters.avg_speed
-                * ((vehicle_type_parameters.sum_intensity - 1) as f32
-                    / vehicle_type_parameters.sum_intensity as f32)
-                + speed / vehicle_type_parameters.sum_intensity as f32;
+        self.accumulators.collect(&mut self.statistics, pcu_factors, self.skeleton.length_meters, &self.los_thresholds);
+        if let Some(ref mut trap) = self.speed_trap {
+            trap.reset(SPEED_TRAP_KEEP_OBJECTS_SECONDS);
         }
-        self.reset_objects();
+    }
+    // Same as `update_statistics` but for the additional aggregation interval. Returns false if there is no such interval
+    pub fn update_interval_statistics(&mut self, interval_milliseconds: i64, _period_start: DateTime<Utc>, _period_end: DateTime<Utc>, pcu_factors: &HashMap<String, f32>) -> bool {
+        let interval = match self.intervals.iter_mut().find(|interval| interval.interval_milliseconds == interval_milliseconds) {
+            Some(interval) => interval,
+            None => {
+                return false;
+            }
+        };
+        reset_statistics(&mut interval.statistics, _period_start, _period_end);
+        interval.accumulators.collect(&mut interval.statistics, pcu_factors, self.skeleton.length_meters, &self.los_thresholds);
+        true
     }
     // Checks if given polygon contains a point
-    // Code has been taken from: https://github.com/LdDl/odam/blob/master/virtual_polygons.go#L180
     pub fn contains_point(&self, x: f32, y: f32) -> bool {
-        let n = self.pixel_coordinates.len();
-        // @todo: math.maxInt could lead to overflow obviously. Need good workaround. PRs are welcome
-        let extreme_point = vec![99999.0, y as f32];
-        let mut intersections_cnt = 0;
-        let mut previous = 0;
-        loop {
-            let current = (previous + 1) % n;
-            // Check if the segment from given point P to extreme point intersects with the segment from polygon point on previous interation to  polygon point on current interation
-            if is_intersects(
-                self.pixel_coordinates[previous].x as f32,
-                self.pixel_coordinates[previous].y as f32,
-                self.pixel_coordinates[current].x as f32,
-                self.pixel_coordinates[current].y as f32,
-                x,
-                y,
-                extreme_point[0],
-                extreme_point[1],
-            ) {
-                let orientation = get_orientation(
-                    self.pixel_coordinates[previous].x as f32,
-                    self.pixel_coordinates[previous].y as f32,
-                    x,
-                    y,
-                    self.pixel_coordinates[current].x as f32,
-                    self.pixel_coordinates[current].y as f32,
-                );
-                // If given point P is collinear with segment from polygon point on previous interation to  polygon point on current interation
-                if orientation == PointsOrientation::Collinear {
-                    // then check if it is on segment
-                    // 'True' will be returns if it lies on segment. Otherwise 'False' will be returned
-                    return is_on_segment(
-                        self.pixel_coordinates[previous].x as f32,
-                        self.pixel_coordinates[previous].y as f32,
-                        x,
-                        y,
-                        self.pixel_coordinates[current].x as f32,
-                        self.pixel_coordinates[current].y as f32,
-                    );
-                }
-                intersections_cnt += 1;
-            }
-            previous = current;
-            if previous == 0 {
-                break;
-            }
-        }
-        // If ray intersects even number of times then return true
-        // Otherwise return false
-        if intersections_cnt % 2 == 1 {
-            return true;
-        }
-        false
+        polygon_contains_point(&self.pixel_coordinates, x, y)
     }
     pub fn contains_point_cv(&self, pt: &Point2f) -> bool {
         self.contains_point(pt.x, pt.y)
     }
-    pub fn transform_to_epsg_cv(&self, pt: &Point2f) -> Point2f {
-        self.spatial_converter.transform_to_epsg_cv(pt)
+    pub fn get_spatial_converter(&self) -> &SpatialConverter {
+        &self.spatial_converter
+    }
+    pub fn transform_to_local_cv(&self, pt: &Point2f) -> Option<Point2d> {
+        self.spatial_converter.transform_to_local_cv(pt)
+    }
+    pub fn transform_to_local(&self, x: f32, y: f32) -> Option<(f64, f64)> {
+        self.spatial_converter.transform_to_local(x, y)
     }
-    pub fn transform_to_epsg(&self, x: f32, y: f32) -> (f32, f32) {
-        self.spatial_converter.transform_to_epsg(x, y)
+    pub fn transform_to_wgs84_cv(&self, pt: &Point2f) -> Option<Point2d> {
+        self.spatial_converter.transform_to_wgs84_cv(pt)
+    }
+    pub fn transform_to_wgs84(&self, x: f32, y: f32) -> Option<(f64, f64)> {
+        self.spatial_converter.transform_to_wgs84(x, y)
     }
     // Checks if an object has entered the polygon
     // Let's clarify for future questions: we are assuming the object is represented by a center, not a bounding box
@@ -482,6 +925,25 @@ impl Zone {
             pair.x = (pair.x * scale_factor_x).floor();
             pair.y = (pair.y * scale_factor_y).floor();
         }
+        if let Some(ref mut trap) = self.speed_trap {
+            trap.scale_geom(scale_factor_x, scale_factor_y);
+        }
+        if self.control_points.len() > 0 {
+            let points = self
+                .control_points
+                .iter()
+                .map(|point| ControlPoint {
+                    pixel: [point.pixel[0] * scale_factor_x, point.pixel[1] * scale_factor_y],
+                    wgs84: point.wgs84,
+                })
+                .collect();
+            match self.set_control_points(points) {
+                Ok(_) => {}
+                Err(err) => {
+                    println!("Can't scale control points for zone '{}' due the error: {}", self.id, err);
+                }
+            }
+        }
     }
     pub fn project_to_skeleton(&self, x: f32, y: f32) -> (f32, f32) {
         self.skeleton.project(x, y)
@@ -493,60 +955,105 @@ impl Zone {
         let pt = self.project_to_skeleton(pt.x, pt.y);
         Point2f::new(pt.0, pt.1)
     }
-    pub fn draw_geom(&self, img: &mut Mat) {
-        // @todo: proper error handling
-        for i in 1..self.pixel_coordinates.len() {
-            let prev_pt = Point2i::new(
-                self.pixel_coordinates[i - 1].x as i32,
-                self.pixel_coordinates[i - 1].y as i32,
-            );
-            let current_pt = Point2i::new(
-                self.pixel_coordinates[i].x as i32,
-                self.pixel_coordinates[i].y as i32,
-            );
-            match line(img, prev_pt, current_pt, self.color, 2, LINE_8, 0) {
-                Ok(_) => {}
-                Err(err) => {
-                    panic!("Can't draw line for polygon due the error: {:?}", err)
-                }
-            };
-        }
-        let last_pt = Point2i::new(
-            self.pixel_coordinates[self.pixel_coordinates.len() - 1].x as i32,
-            self.pixel_coordinates[self.pixel_coordinates.len() - 1].y as i32,
-        );
-        let first_pt = Point2i::new(
-            self.pixel_coordinates[0].x as i32,
-            self.pixel_coordinates[0].y as i32,
-        );
-        match line(img, last_pt, first_pt, self.color, 2, LINE_8, 0) {
+    pub fn draw_geom(&self, img: &mut Mat, mapping: &FrameMapping) {
+        match draw_polyline(img, &mapping.polyline_to_display(&self.pixel_coordinates, true), true, self.color) {
             Ok(_) => {}
             Err(err) => {
                 panic!("Can't draw line for polygon due the error: {:?}", err)
             }
         };
     }
-    pub fn draw_skeleton(&self, img: &mut Mat) {
-        let a = Point2i::new(
-            self.skeleton.line[0].x as i32,
-            self.skeleton.line[0].y as i32,
-        );
-        let b = Point2i::new(
-            self.skeleton.line[1].x as i32,
-            self.skeleton.line[1].y as i32,
-        );
-        match line(img, a, b, self.skeleton.color, 2, LINE_8, 0) {
+    pub fn draw_skeleton(&self, img: &mut Mat, mapping: &FrameMapping) {
+        match draw_polyline(img, &mapping.polyline_to_display(&self.skeleton.line, false), false, self.skeleton.color) {
             Ok(_) => {}
             Err(err) => {
                 panic!("Can't draw line for polygon due the error: {:?}", err)
             }
         };
+        if let Some(ref trap) = self.speed_trap {
+            for trap_line in trap.get_pixel_lines().iter() {
+                match draw_polyline(img, &mapping.polyline_to_display(trap_line, false), false, self.color) {
+                    Ok(_) => {}
+                    Err(err) => {
+                        panic!("Can't draw line for speed trap due the error: {:?}", err)
+                    }
+                };
+            }
+        }
+    }
+    // Draws ticks every 'step_meters' along the skeleton. Ticks are placed by the spatial converter,
+    // so uneven spacing of them shows perspective, while mismatch with road markings shows calibration errors
+    pub fn draw_skeleton_ticks(&self, img: &mut Mat, step_meters: f32, mapping: &FrameMapping) {
+        if step_meters <= 0.0 {
+            return;
+        }
+        let (a, b) = (self.skeleton.line[0], self.skeleton.line[1]);
+        if !self.spatial_converter.is_on_ground(a.x, a.y) || !self.spatial_converter.is_on_ground(b.x, b.y) {
+            return;
+        }
+        let (a_local, b_local) = match (self.spatial_converter.transform_to_local(a.x, a.y), self.spatial_converter.transform_to_local(b.x, b.y)) {
+            (Some(a_local), Some(b_local)) => (a_local, b_local),
+            _ => return,
+        };
+        let length = ((b_local.0 - a_local.0).powi(2) + (b_local.1 - a_local.1).powi(2)).sqrt();
+        let length_pixels = ((b.x - a.x).powi(2) + (b.y - a.y).powi(2)).sqrt();
+        if length <= 0.0 || length_pixels <= 0.0 {
+            return;
+        }
+        // Ticks are perpendicular to the skeleton on the video frame
+        let normal = ((a.y - b.y) / length_pixels * SKELETON_TICK_PIXELS, (b.x - a.x) / length_pixels * SKELETON_TICK_PIXELS);
+        let ticks_num = (length / step_meters as f64).floor() as usize;
+        let mut ends = Vec::with_capacity(2 * (ticks_num + 1));
+        let mut distances = Vec::with_capacity(ticks_num + 1);
+        for i in 0..=ticks_num {
+            let distance = i as f64 * step_meters as f64;
+            let t = distance / length;
+            let (x, y) = match self.spatial_converter.transform_local_to_pixels(
+                a_local.0 + (b_local.0 - a_local.0) * t,
+                a_local.1 + (b_local.1 - a_local.1) * t,
+            ) {
+                Some(pt) => pt,
+                None => continue,
+            };
+            ends.push(Point2f::new(x - normal.0, y - normal.1));
+            ends.push(Point2f::new(x + normal.0, y + normal.1));
+            distances.push(distance);
+        }
+        let ends = mapping.to_display(&ends);
+        for (tick, distance) in ends.chunks(2).zip(distances.iter()) {
+            let from = Point2i::new(tick[0].x as i32, tick[0].y as i32);
+            let to = Point2i::new(tick[1].x as i32, tick[1].y as i32);
+            match line(img, from, to, self.skeleton.color, 2, LINE_8, 0) {
+                Ok(_) => {}
+                Err(err) => {
+                    println!("Can't draw tick for skeleton due the error {:?}", err);
+                    continue;
+                }
+            };
+            match put_text(
+                img,
+                &format!("{}", distance),
+                Point2i::new(to.x + 2, to.y),
+                FONT_HERSHEY_SIMPLEX,
+                0.35,
+                self.skeleton.color,
+                1,
+                LINE_8,
+                false,
+            ) {
+                Ok(_) => {}
+                Err(err) => {
+                    println!("Can't display distance of tick due the error {:?}", err);
+                }
+            };
+        }
     }
-    pub fn draw_current_intensity(&self, img: &mut Mat) {
-        let current_intensity = self.objects.len();
+    pub fn draw_current_intensity(&self, img: &mut Mat, mapping: &FrameMapping) {
+        let current_intensity = self.accumulators.objects.len();
+        let first_pt = mapping.to_display(&self.pixel_coordinates[0..1])[0];
         let anchor = Point2i::new(
-            self.pixel_coordinates[0].x as i32 + 20,
-            self.pixel_coordinates[0].y as i32 - 10,
+            first_pt.x as i32 + 20,
+            first_pt.y as i32 - 10,
         );
         match put_text(
             img,
@@ -565,6 +1072,28 @@ impl Zone {
             }
         };
     }
+    // Fills the zone with color of the level of service for the last period. Returns false if level of service is undefined.
+    // Overlay is shared by every zone and blended with the frame once (see `blend_level_of_service`)
+    pub fn draw_level_of_service(&self, overlay: &mut Mat, mapping: &FrameMapping) -> bool {
+        let color = match self.statistics.level_of_service.color() {
+            Some(color) => Scalar::from(color),
+            None => {
+                return false;
+            }
+        };
+        let polygon: Vector<Vector<Point2i>> = Vector::from(vec![mapping
+            .polyline_to_display(&self.pixel_coordinates, true)
+            .iter()
+            .map(|pt| Point2i::new(pt.x as i32, pt.y as i32))
+            .collect::<Vector<Point2i>>()]);
+        match fill_poly(overlay, &polygon, color, LINE_8, 0, Point2i::default()) {
+            Ok(_) => true,
+            Err(err) => {
+                println!("Can't fill zone with level of service color due the error {:?}", err);
+                false
+            }
+        }
+    }
     pub fn to_geojson(&self) -> ZoneFeature {
         let mut euclidean: Vec<Vec<i32>> = Vec::new();
         for pt in self.pixel_coordinates.iter() {
@@ -586,7 +1115,10 @@ impl Zone {
             properties: ZonePropertiesGeoJSON {
                 road_lane_num: self.road_lane_num,
                 road_lane_direction: self.road_lane_direction,
+                group_name: self.group_name.clone(),
                 coordinates: euclidean,
+                counted_classes: self.counted_classes.clone(),
+                allowed_classes: self.allowed_classes.clone(),
                 color_rgb: [
                     self.color[2] as i16,
                     self.color[1] as i16,
@@ -601,6 +1133,16 @@ impl Zone {
     }
 }
 
+// Distance (meters) between centers of the first (0-1) and the third (2-3) sides of the zone on the ground.
+// Distance is measured on the local tangent plane, so it is not inflated by Web Mercator scale
+fn sides_distance_meters(spatial_coordinates: &[Point2d]) -> f32 {
+    let projection = LocalProjection::new_centered(spatial_coordinates);
+    let local: Vec<Point2d> = spatial_coordinates.iter().map(|pt| projection.forward_cv(pt)).collect();
+    let ab_center = Point2d::new((local[0].x + local[1].x) / 2.0, (local[0].y + local[1].y) / 2.0);
+    let cd_center = Point2d::new((local[2].x + local[3].x) / 2.0, (local[2].y + local[3].y) / 2.0);
+    ((ab_center.x - cd_center.x).powi(2) + (ab_center.y - cd_center.y).powi(2)).sqrt() as f32
+}
+
 fn find_skeleton_line(
     coordinates: &Vec<Point2f>,
     first_line_idx: usize,
@@ -617,6 +1159,32 @@ fn find_skeleton_line(
     [a_b_center, c_d_center]
 }
 
+// Blends overlay with colors of the level of service into the frame, so zones become semi-transparent
+pub fn blend_level_of_service(img: &mut Mat, overlay: &Mat) {
+    let mut blended = Mat::default();
+    match add_weighted(overlay, LOS_OVERLAY_ALPHA, img, 1.0 - LOS_OVERLAY_ALPHA, 0.0, &mut blended, -1) {
+        Ok(_) => {
+            *img = blended;
+        }
+        Err(err) => {
+            println!("Can't blend level of service overlay due the error {:?}", err);
+        }
+    };
+}
+
+// Draws lines between consecutive points
+fn draw_polyline(img: &mut Mat, points: &[Point2f], closed: bool, color: Scalar) -> opencv::Result<()> {
+    if points.len() < 2 {
+        return Ok(());
+    }
+    let segments_num = if closed { points.len() } else { points.len() - 1 };
+    for i in 0..segments_num {
+        let (a, b) = (points[i], points[(i + 1) % points.len()]);
+        line(img, Point2i::new(a.x as i32, a.y as i32), Point2i::new(b.x as i32, b.y as i32), color, 2, LINE_8, 0)?;
+    }
+    Ok(())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -724,4 +1292,16 @@ mod tests {
         let left = polygon.object_left_cv(d_track_must_not_enter[0], d_track_must_not_enter[1]);
         assert_eq!(left, false);
     }
+    #[test]
+    fn test_project_position() {
+        // 100 pixels long skeleton along Y axis, 10 pixels per meter
+        let mut skeleton = Skeleton::new(Point2f::new(50.0, 0.0), Point2f::new(50.0, 100.0));
+        skeleton.length_meters = 10.0;
+        skeleton.pixels_per_meter = skeleton.length_pixels / skeleton.length_meters;
+        let eps = 0.0001;
+        assert!((skeleton.project_position(70.0, 30.0) - 3.0).abs() < eps);
+        // Points outside of the skeleton are not folded back
+        assert!((skeleton.project_position(50.0, -20.0) + 2.0).abs() < eps);
+        assert!((skeleton.project_position(40.0, 120.0) - 12.0).abs() < eps);
+    }
 }
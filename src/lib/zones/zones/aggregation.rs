@@ -0,0 +1,214 @@
+use std::collections::HashMap;
+
+use super::Statistics;
+
+// Aggregated parameters for specific vehicle type over several zones. Values "-1" indicate no data
+#[derive(Debug, Clone)]
+pub struct AggregatedVehicleTypeParameters {
+    pub avg_speed: f32,
+    pub sum_intensity: u32,
+    pub flow_rate: f32,
+    pub pcu_volume: f32,
+}
+
+// Aggregated traffic flow parameters over several zones (e.g. lanes of the same approach). Values "-1" indicate no data
+#[derive(Debug, Clone)]
+pub struct AggregatedStatistics {
+    // Road lane numbers of aggregated zones
+    pub lanes: Vec<u16>,
+    pub vehicles_data: HashMap<String, AggregatedVehicleTypeParameters>,
+    // Sum of flow rates (vehicles/hour)
+    pub flow_rate: f32,
+    // Sum of volumes in passenger car units
+    pub pcu_volume: f32,
+    // Sum of flow rates in passenger car units (PCU/hour)
+    pub pcu_flow_rate: f32,
+    // Sum of densities (vehicles/km)
+    pub density: f32,
+    // Average occupancy across lanes (percents)
+    pub occupancy_percent: f32,
+    // Maximum queue length across lanes (meters)
+    pub max_queue_length: f32,
+    // Number of violations for every vehicle type
+    pub violations: HashMap<String, u32>,
+}
+
+#[derive(Debug)]
+struct VehicleTypeAccumulator {
+    sum_intensity: u32,
+    // Sum of average speeds weighted by number of vehicles
+    weighted_speed: f32,
+    speed_weight: u32,
+    flow_rate: f32,
+    pcu_volume: f32,
+}
+
+// Sums statistics of zones which belong to the same group or direction
+#[derive(Debug)]
+pub struct StatisticsAggregator {
+    lanes: Vec<u16>,
+    vehicles: HashMap<String, VehicleTypeAccumulator>,
+    flow_rate: f32,
+    pcu_volume: f32,
+    pcu_flow_rate: f32,
+    density: f32,
+    occupancy_sum: f32,
+    occupancy_lanes: u32,
+    max_queue_length: f32,
+    violations: HashMap<String, u32>,
+}
+
+// Adds value to the accumulated one. Value "-1" indicates no data and it is skipped
+fn add_known(accumulated: f32, value: f32) -> f32 {
+    if value < 0.0 {
+        return accumulated;
+    }
+    if accumulated < 0.0 {
+        return value;
+    }
+    accumulated + value
+}
+
+impl StatisticsAggregator {
+    pub fn default() -> Self {
+        StatisticsAggregator {
+            lanes: vec![],
+            vehicles: HashMap::new(),
+            flow_rate: -1.0,
+            pcu_volume: -1.0,
+            pcu_flow_rate: -1.0,
+            density: -1.0,
+            occupancy_sum: 0.0,
+            occupancy_lanes: 0,
+            max_queue_length: -1.0,
+            violations: HashMap::new(),
+        }
+    }
+    pub fn add_zone(&mut self, lane_number: u16, statistics: &Statistics) {
+        self.lanes.push(lane_number);
+        for (vehicle_type, parameters) in statistics.vehicles_data.iter() {
+            let accumulator = self.vehicles.entry(vehicle_type.clone()).or_insert(VehicleTypeAccumulator {
+                sum_intensity: 0,
+                weighted_speed: 0.0,
+                speed_weight: 0,
+                flow_rate: -1.0,
+                pcu_volume: -1.0,
+            });
+            accumulator.sum_intensity += parameters.sum_intensity;
+            if parameters.avg_speed >= 0.0 && parameters.sum_intensity > 0 {
+                accumulator.weighted_speed += parameters.avg_speed * parameters.sum_intensity as f32;
+                accumulator.speed_weight += parameters.sum_intensity;
+            }
+            accumulator.flow_rate = add_known(accumulator.flow_rate, parameters.flow_rate);
+            accumulator.pcu_volume = add_known(accumulator.pcu_volume, parameters.pcu_volume);
+        }
+        let flow = &statistics.traffic_flow;
+        self.flow_rate = add_known(self.flow_rate, flow.flow_rate);
+        self.pcu_volume = add_known(self.pcu_volume, flow.pcu_volume);
+        self.pcu_flow_rate = add_known(self.pcu_flow_rate, flow.pcu_flow_rate);
+        self.density = add_known(self.density, flow.density);
+        if statistics.occupancy.occupancy_percent >= 0.0 {
+            self.occupancy_sum += statistics.occupancy.occupancy_percent;
+            self.occupancy_lanes += 1;
+        }
+        if statistics.queue.max_queue_length > self.max_queue_length {
+            self.max_queue_length = statistics.queue.max_queue_length;
+        }
+        for (vehicle_type, count) in statistics.violations.iter() {
+            *self.violations.entry(vehicle_type.clone()).or_insert(0) += count;
+        }
+    }
+    pub fn get_statistics(&self) -> AggregatedStatistics {
+        let mut lanes = self.lanes.clone();
+        lanes.sort();
+        AggregatedStatistics {
+            lanes: lanes,
+            vehicles_data: self
+                .vehicles
+                .iter()
+                .map(|(vehicle_type, accumulator)| {
+                    let avg_speed = if accumulator.speed_weight > 0 {
+                        accumulator.weighted_speed / accumulator.speed_weight as f32
+                    } else {
+                        -1.0
+                    };
+                    (
+                        vehicle_type.clone(),
+                        AggregatedVehicleTypeParameters {
+                            avg_speed: avg_speed,
+                            sum_intensity: accumulator.sum_intensity,
+                            flow_rate: accumulator.flow_rate,
+                            pcu_volume: accumulator.pcu_volume,
+                        },
+                    )
+                })
+                .collect(),
+            flow_rate: self.flow_rate,
+            pcu_volume: self.pcu_volume,
+            pcu_flow_rate: self.pcu_flow_rate,
+            density: self.density,
+            occupancy_percent: if self.occupancy_lanes > 0 {
+                self.occupancy_sum / self.occupancy_lanes as f32
+            } else {
+                -1.0
+            },
+            max_queue_length: self.max_queue_length,
+            violations: self.violations.clone(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lib::zones::VehicleTypeParameters;
+    #[test]
+    fn test_aggregation() {
+        let mut first = Statistics::default();
+        let mut car = VehicleTypeParameters::default();
+        car.avg_speed = 40.0;
+        car.sum_intensity = 3;
+        car.flow_rate = 36.0;
+        car.pcu_volume = 3.0;
+        first.vehicles_data.insert("car".to_string(), car);
+        first.traffic_flow.flow_rate = 36.0;
+        first.traffic_flow.density = 10.0;
+        first.occupancy.occupancy_percent = 20.0;
+        first.queue.max_queue_length = 12.0;
+        first.violations.insert("truck".to_string(), 1);
+
+        let mut second = Statistics::default();
+        let mut car = VehicleTypeParameters::default();
+        car.avg_speed = 60.0;
+        car.sum_intensity = 1;
+        car.flow_rate = 12.0;
+        car.pcu_volume = 1.0;
+        second.vehicles_data.insert("car".to_string(), car);
+        let mut bus = VehicleTypeParameters::default();
+        bus.pcu_volume = -1.0;
+        second.vehicles_data.insert("bus".to_string(), bus);
+        second.traffic_flow.flow_rate = 12.0;
+        second.queue.max_queue_length = 4.0;
+        second.violations.insert("truck".to_string(), 2);
+
+        let mut aggregator = StatisticsAggregator::default();
+        aggregator.add_zone(2, &first);
+        aggregator.add_zone(1, &second);
+        let stats = aggregator.get_statistics();
+        assert_eq!(stats.lanes, vec![1, 2]);
+        let car = stats.vehicles_data.get("car").unwrap();
+        assert_eq!(car.sum_intensity, 4);
+        assert_eq!(car.avg_speed, 45.0);
+        assert_eq!(car.flow_rate, 48.0);
+        assert_eq!(car.pcu_volume, 4.0);
+        // Unknown PCU volume is not summed up
+        assert_eq!(stats.vehicles_data.get("bus").unwrap().pcu_volume, -1.0);
+        assert_eq!(stats.flow_rate, 48.0);
+        // No data for the second lane
+        assert_eq!(stats.density, 10.0);
+        assert_eq!(stats.occupancy_percent, 20.0);
+        assert_eq!(stats.pcu_flow_rate, -1.0);
+        assert_eq!(stats.max_queue_length, 12.0);
+        assert_eq!(stats.violations.get("truck"), Some(&3));
+    }
+}
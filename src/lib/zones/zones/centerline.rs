@@ -0,0 +1,97 @@
+use opencv::core::Point2d;
+
+use crate::lib::spatial::projection::LocalProjection;
+
+// Minimum length of the centerline (meters). Shorter lanes can't be used for speed estimation anyway
+const MIN_CENTERLINE_LENGTH: f64 = 1.0;
+
+// Unit vector of the segment on the local tangent plane
+fn direction(a: &Point2d, b: &Point2d) -> Option<(f64, f64)> {
+    let (dx, dy) = (b.x - a.x, b.y - a.y);
+    let length = (dx * dx + dy * dy).sqrt();
+    if length == 0.0 {
+        return None;
+    }
+    Some((dx / length, dy / length))
+}
+
+// Generates quadrilaterals of road lanes around the centerline
+//
+// centerline - points in WGS84 ([longitude, latitude]) ordered along the traffic direction
+// lanes_num - number of lanes. Lanes are placed symmetrically around the centerline
+// lane_width - width of each lane (meters)
+//
+// Returns WGS84 points for every lane from the left one to the right one (relative to the traffic direction).
+// Points are ordered as zones expect them: left and right points of the entry, then right and left points of the exit.
+// Entry and exit are perpendicular to the first and the last segments of the centerline, so curved roads produce trapezoids
+//
+pub fn lanes_from_centerline(centerline: &[Point2d], lanes_num: u16, lane_width: f64) -> Result<Vec<[Point2d; 4]>, String> {
+    if centerline.len() < 2 {
+        return Err(format!("Centerline should have at least 2 points, got {}", centerline.len()));
+    }
+    if lanes_num == 0 {
+        return Err("Number of lanes should be positive".to_string());
+    }
+    if !(lane_width > 0.0) {
+        return Err(format!("Lane width should be positive, got {}", lane_width));
+    }
+    let projection = LocalProjection::new_centered(centerline);
+    // Consecutive duplicates are skipped, since they have no direction
+    let mut local: Vec<Point2d> = vec![];
+    for pt in centerline.iter() {
+        let pt = projection.forward_cv(pt);
+        if local.last().map_or(true, |last| *last != pt) {
+            local.push(pt);
+        }
+    }
+    let length: f64 = local.windows(2).map(|w| ((w[1].x - w[0].x).powi(2) + (w[1].y - w[0].y).powi(2)).sqrt()).sum();
+    if local.len() < 2 || length < MIN_CENTERLINE_LENGTH {
+        return Err(format!("Centerline length {} m is less than minimum {} m", length, MIN_CENTERLINE_LENGTH));
+    }
+    let n = local.len();
+    let entry = (local[0], direction(&local[0], &local[1]).unwrap());
+    let exit = (local[n - 1], direction(&local[n - 2], &local[n - 1]).unwrap());
+    // Point shifted to the left (positive offset) or to the right (negative offset) of the traffic direction
+    let shift = |(pt, dir): (Point2d, (f64, f64)), offset: f64| {
+        let (x, y) = (pt.x - dir.1 * offset, pt.y + dir.0 * offset);
+        projection.inverse_cv(&Point2d::new(x, y))
+    };
+    let half_width = lanes_num as f64 * lane_width / 2.0;
+    let lanes = (0..lanes_num)
+        .map(|i| {
+            let left = half_width - i as f64 * lane_width;
+            let right = left - lane_width;
+            [shift(entry, left), shift(entry, right), shift(exit, right), shift(exit, left)]
+        })
+        .collect();
+    Ok(lanes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn test_lanes_from_centerline() {
+        // About 50 meters to the north
+        let centerline = vec![Point2d::new(37.6202637616082, 54.208100345367), Point2d::new(37.6202637616082, 54.2085496)];
+        let lanes = lanes_from_centerline(&centerline, 3, 3.5).unwrap();
+        assert_eq!(lanes.len(), 3);
+        let projection = LocalProjection::new(37.6202637616082, 54.208100345367);
+        let local: Vec<Vec<Point2d>> = lanes.iter().map(|lane| lane.iter().map(|pt| projection.forward_cv(pt)).collect()).collect();
+        // Traffic goes to the north, so the first lane is the western one
+        assert!((local[0][0].x + 5.25).abs() < 0.001);
+        assert!((local[0][1].x + 1.75).abs() < 0.001);
+        assert!((local[2][1].x - 5.25).abs() < 0.001);
+        for lane in local.iter() {
+            // Width of the entry and the exit
+            assert!(((lane[1].x - lane[0].x) - 3.5).abs() < 0.001);
+            assert!(((lane[2].x - lane[3].x) - 3.5).abs() < 0.001);
+            assert!(lane[0].y.abs() < 0.001);
+            assert!((lane[3].y - 50.0).abs() < 0.1);
+        }
+        assert!(lanes_from_centerline(&centerline[..1], 3, 3.5).is_err());
+        assert!(lanes_from_centerline(&centerline, 0, 3.5).is_err());
+        assert!(lanes_from_centerline(&centerline, 2, -1.0).is_err());
+        assert!(lanes_from_centerline(&vec![centerline[0], centerline[0]], 2, 3.5).is_err());
+    }
+}
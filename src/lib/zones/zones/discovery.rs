@@ -0,0 +1,439 @@
+use opencv::core::Point2f;
+use std::collections::HashMap;
+use uuid::Uuid;
+
+use super::geojson::{GeoPolygon, ZoneFeature, ZonePropertiesGeoJSON};
+use crate::lib::spatial::SpatialConverter;
+
+// Number of points each trajectory is resampled to before comparison
+const RESAMPLE_POINTS: usize = 16;
+// Trajectories with fewer points or shorter path (pixels) are treated as noise
+const MIN_TRAJECTORY_POINTS: usize = 5;
+const MIN_TRAJECTORY_PIXELS: f32 = 50.0;
+// Trajectories closer than this (mean distance between resampled points, pixels) belong to the same path
+pub const CLUSTER_THRESHOLD_PIXELS: f32 = 25.0;
+// Minimum number of trajectories for the path to be proposed as a lane
+pub const MIN_CLUSTER_TRAJECTORIES: usize = 5;
+// Lower bound for the half of the proposed lane width (pixels)
+const MIN_HALF_WIDTH_PIXELS: f32 = 10.0;
+// Paths with angle less than acos(value) between them are considered as the same direction
+const SAME_DIRECTION_COS: f32 = 0.7;
+
+// Lane proposed by the path of similar trajectories
+#[derive(Debug, Clone)]
+pub struct LaneProposal {
+    pub id: String,
+    pub lane_number: u16,
+    pub lane_direction: u8,
+    // Points are ordered as zones expect them: entry (0-1) and exit (2-3), so skeleton follows the traffic direction
+    pub pixel_points: [Point2f; 4],
+    pub trajectories_num: usize,
+}
+
+impl LaneProposal {
+    // Spatial geometry is available only when the camera-wide calibration is set, otherwise it is empty
+    pub fn to_geojson(&self, ground_plane: Option<&SpatialConverter>) -> ZoneFeature {
+        let mut geojson_poly = vec![];
+        // Spatial geometry is known only if the whole lane lies on the ground plane
+        if let Some(converter) = ground_plane.filter(|converter| self.pixel_points.iter().all(|pt| converter.is_on_ground(pt.x, pt.y))) {
+            let poly_element: Option<Vec<Vec<f64>>> = self
+                .pixel_points
+                .iter()
+                .map(|pt| converter.transform_to_wgs84(pt.x, pt.y).map(|(lon, lat)| vec![lon, lat]))
+                .collect();
+            if let Some(mut poly_element) = poly_element {
+                poly_element.push(poly_element[0].clone());
+                geojson_poly.push(poly_element);
+            }
+        }
+        ZoneFeature {
+            typ: "Feature".to_string(),
+            id: self.id.clone(),
+            properties: ZonePropertiesGeoJSON {
+                road_lane_num: self.lane_number,
+                road_lane_direction: self.lane_direction,
+                group_name: "".to_string(),
+                coordinates: self.pixel_points.iter().map(|pt| vec![pt.x as i32, pt.y as i32]).collect(),
+                counted_classes: vec![],
+                allowed_classes: vec![],
+                color_rgb: [255, 255, 255],
+            },
+            geometry: GeoPolygon {
+                geometry_type: "Polygon".to_string(),
+                coordinates: geojson_poly,
+            },
+        }
+    }
+}
+
+// Recorded trajectories which lanes should be proposed for. Clustering is heavy, so it is evaluated outside of the video thread
+#[derive(Debug)]
+pub struct DiscoveryJob {
+    session: u64,
+    min_trajectories: usize,
+    trajectories: Vec<Vec<Point2f>>,
+}
+
+impl DiscoveryJob {
+    pub fn evaluate(&self) -> Vec<LaneProposal> {
+        propose_lanes(&self.trajectories, CLUSTER_THRESHOLD_PIXELS, self.min_trajectories)
+    }
+}
+
+// Learning mode: records trajectories for a while and proposes lanes by clustering them
+#[derive(Debug)]
+pub struct LaneDiscovery {
+    // Unix timestamp (seconds) when recording stops. Value "-1" indicates that recording is not running
+    deadline: i64,
+    min_trajectories: usize,
+    // Incremented on every start, so results of the outdated evaluation are dropped
+    session: u64,
+    // Number of trajectories which are being evaluated. None means that evaluation is not running
+    evaluating_num: Option<usize>,
+    // Trajectories of objects which are still tracked
+    active: HashMap<Uuid, Vec<Point2f>>,
+    trajectories: Vec<Vec<Point2f>>,
+    proposals: Vec<LaneProposal>,
+}
+
+impl LaneDiscovery {
+    pub fn default() -> Self {
+        LaneDiscovery {
+            deadline: -1,
+            min_trajectories: MIN_CLUSTER_TRAJECTORIES,
+            session: 0,
+            evaluating_num: None,
+            active: HashMap::new(),
+            trajectories: vec![],
+            proposals: vec![],
+        }
+    }
+    // Starts recording from scratch. Previous proposals are discarded
+    pub fn start(&mut self, now: i64, duration_seconds: i64, min_trajectories: usize) {
+        self.deadline = now + duration_seconds;
+        self.min_trajectories = min_trajectories;
+        self.session += 1;
+        self.evaluating_num = None;
+        self.active.clear();
+        self.trajectories.clear();
+        self.proposals.clear();
+    }
+    pub fn is_running(&self) -> bool {
+        self.deadline >= 0
+    }
+    // Recording has stopped, but proposals are not evaluated yet
+    pub fn is_evaluating(&self) -> bool {
+        self.evaluating_num.is_some()
+    }
+    pub fn get_remaining_seconds(&self, now: i64) -> i64 {
+        if !self.is_running() {
+            return 0;
+        }
+        i64::max(self.deadline - now, 0)
+    }
+    // Number of finished trajectories recorded so far
+    pub fn get_trajectories_num(&self) -> usize {
+        self.trajectories.len() + self.evaluating_num.unwrap_or(0)
+    }
+    pub fn get_proposals(&self) -> &[LaneProposal] {
+        &self.proposals
+    }
+    pub fn register_point(&mut self, object_id: Uuid, x: f32, y: f32) {
+        if !self.is_running() {
+            return;
+        }
+        self.active.entry(object_id).or_insert_with(Vec::new).push(Point2f::new(x, y));
+    }
+    // Finishes trajectories of objects which are not tracked anymore
+    pub fn finish_lost(&mut self, is_alive: impl Fn(&Uuid) -> bool) {
+        let lost: Vec<Uuid> = self.active.keys().filter(|object_id| !is_alive(object_id)).cloned().collect();
+        for object_id in lost.iter() {
+            if let Some(trajectory) = self.active.remove(object_id) {
+                self.trajectories.push(trajectory);
+            }
+        }
+    }
+    // Stops recording when the time is up. Returns recorded trajectories which proposals should be evaluated for (see `finish`)
+    pub fn update(&mut self, now: i64) -> Option<DiscoveryJob> {
+        if !self.is_running() || now < self.deadline {
+            return None;
+        }
+        self.finish_lost(|_| false);
+        self.deadline = -1;
+        let trajectories = std::mem::take(&mut self.trajectories);
+        self.evaluating_num = Some(trajectories.len());
+        Some(DiscoveryJob {
+            session: self.session,
+            min_trajectories: self.min_trajectories,
+            trajectories: trajectories,
+        })
+    }
+    // Stores evaluated proposals. Returns false if discovery has been restarted during evaluation, so proposals are outdated
+    pub fn finish(&mut self, job: DiscoveryJob, proposals: Vec<LaneProposal>) -> bool {
+        if job.session != self.session || !self.is_evaluating() {
+            return false;
+        }
+        self.evaluating_num = None;
+        self.trajectories = job.trajectories;
+        self.proposals = proposals;
+        true
+    }
+}
+
+fn distance(a: &Point2f, b: &Point2f) -> f32 {
+    ((a.x - b.x).powi(2) + (a.y - b.y).powi(2)).sqrt()
+}
+
+// Resamples trajectory to the given number of points equally spaced along the path
+fn resample(trajectory: &[Point2f], points_num: usize) -> Vec<Point2f> {
+    let mut cumulative = vec![0.0];
+    for i in 1..trajectory.len() {
+        cumulative.push(cumulative[i - 1] + distance(&trajectory[i - 1], &trajectory[i]));
+    }
+    let length = cumulative[cumulative.len() - 1];
+    let mut resampled = Vec::with_capacity(points_num);
+    let mut segment = 1;
+    for k in 0..points_num {
+        let target = length * k as f32 / (points_num - 1) as f32;
+        while segment < trajectory.len() - 1 && cumulative[segment] < target {
+            segment += 1;
+        }
+        let (a, b) = (trajectory[segment - 1], trajectory[segment]);
+        let segment_length = cumulative[segment] - cumulative[segment - 1];
+        let t = if segment_length > 0.0 { ((target - cumulative[segment - 1]) / segment_length).max(0.0).min(1.0) } else { 0.0 };
+        resampled.push(Point2f::new(a.x + (b.x - a.x) * t, a.y + (b.y - a.y) * t));
+    }
+    resampled
+}
+
+// Mean distance between corresponding points. Trajectories in opposite directions are far from each other
+fn paths_distance(a: &[Point2f], b: &[Point2f]) -> f32 {
+    a.iter().zip(b.iter()).map(|(pa, pb)| distance(pa, pb)).sum::<f32>() / a.len() as f32
+}
+
+fn mean_path(paths: &[&Vec<Point2f>]) -> Vec<Point2f> {
+    let n = paths.len() as f32;
+    (0..RESAMPLE_POINTS)
+        .map(|k| Point2f::new(paths.iter().map(|path| path[k].x).sum::<f32>() / n, paths.iter().map(|path| path[k].y).sum::<f32>() / n))
+        .collect()
+}
+
+// Groups trajectories by their paths. Returns indices of resampled trajectories for each cluster
+//
+// Clusters are built greedily (trajectory joins the closest cluster or starts a new one) and then refined once:
+// every trajectory is reassigned to the closest final centroid, so result does not depend on order of trajectories much
+//
+pub fn cluster_trajectories(paths: &[Vec<Point2f>], threshold_pixels: f32) -> Vec<Vec<usize>> {
+    let mut centroids: Vec<(Vec<Point2f>, usize)> = vec![];
+    for path in paths.iter() {
+        let closest = centroids
+            .iter()
+            .enumerate()
+            .map(|(idx, centroid)| (idx, paths_distance(&centroid.0, path)))
+            .filter(|(_, dist)| *dist < threshold_pixels)
+            .min_by(|a, b| a.1.total_cmp(&b.1));
+        match closest {
+            Some((idx, _)) => {
+                // Running mean of the cluster
+                let (ref mut centroid, ref mut members) = centroids[idx];
+                *members += 1;
+                for (c, pt) in centroid.iter_mut().zip(path.iter()) {
+                    c.x += (pt.x - c.x) / *members as f32;
+                    c.y += (pt.y - c.y) / *members as f32;
+                }
+            }
+            None => centroids.push((path.clone(), 1)),
+        }
+    }
+    let mut clusters: Vec<Vec<usize>> = vec![vec![]; centroids.len()];
+    for (path_idx, path) in paths.iter().enumerate() {
+        let closest = centroids
+            .iter()
+            .enumerate()
+            .map(|(idx, centroid)| (idx, paths_distance(&centroid.0, path)))
+            .min_by(|a, b| a.1.total_cmp(&b.1));
+        if let Some((idx, dist)) = closest {
+            if dist < threshold_pixels {
+                clusters[idx].push(path_idx);
+            }
+        }
+    }
+    clusters.retain(|members| members.len() > 0);
+    clusters
+}
+
+// Quadrilateral around the mean path. Its width is based on the lateral spread of trajectories at the entry and at the exit
+fn lane_quad(mean: &[Point2f], members: &[&Vec<Point2f>]) -> Option<[Point2f; 4]> {
+    let (entry, exit) = (mean[0], mean[mean.len() - 1]);
+    let length = distance(&entry, &exit);
+    if length <= 0.0 {
+        return None;
+    }
+    let normal = ((entry.y - exit.y) / length, (exit.x - entry.x) / length);
+    let half_width = |center: &Point2f, idx: usize| {
+        let n = members.len() as f32;
+        let variance = members
+            .iter()
+            .map(|path| ((path[idx].x - center.x) * normal.0 + (path[idx].y - center.y) * normal.1).powi(2))
+            .sum::<f32>()
+            / n;
+        f32::max(2.0 * variance.sqrt(), MIN_HALF_WIDTH_PIXELS)
+    };
+    let (entry_half, exit_half) = (half_width(&entry, 0), half_width(&exit, mean.len() - 1));
+    Some([
+        Point2f::new(entry.x - normal.0 * entry_half, entry.y - normal.1 * entry_half),
+        Point2f::new(entry.x + normal.0 * entry_half, entry.y + normal.1 * entry_half),
+        Point2f::new(exit.x + normal.0 * exit_half, exit.y + normal.1 * exit_half),
+        Point2f::new(exit.x - normal.0 * exit_half, exit.y - normal.1 * exit_half),
+    ])
+}
+
+// Proposes lanes for recorded trajectories.
+// Lanes of similar directions share the same direction number and are numbered across the traffic
+pub fn propose_lanes(trajectories: &[Vec<Point2f>], threshold_pixels: f32, min_trajectories: usize) -> Vec<LaneProposal> {
+    let paths: Vec<Vec<Point2f>> = trajectories
+        .iter()
+        .filter(|trajectory| trajectory.len() >= MIN_TRAJECTORY_POINTS)
+        .filter(|trajectory| trajectory.windows(2).map(|w| distance(&w[0], &w[1])).sum::<f32>() >= MIN_TRAJECTORY_PIXELS)
+        .map(|trajectory| resample(trajectory, RESAMPLE_POINTS))
+        .collect();
+    let mut lanes: Vec<([Point2f; 4], usize, (f32, f32))> = vec![];
+    for members in cluster_trajectories(&paths, threshold_pixels).iter() {
+        if members.len() < min_trajectories {
+            continue;
+        }
+        let member_paths: Vec<&Vec<Point2f>> = members.iter().map(|idx| &paths[*idx]).collect();
+        let mean = mean_path(&member_paths);
+        if let Some(quad) = lane_quad(&mean, &member_paths) {
+            let (entry, exit) = (mean[0], mean[mean.len() - 1]);
+            let length = distance(&entry, &exit);
+            lanes.push((quad, members.len(), ((exit.x - entry.x) / length, (exit.y - entry.y) / length)));
+        }
+    }
+    // Group lanes by direction
+    let mut directions: Vec<(f32, f32)> = vec![];
+    let mut lane_directions = vec![];
+    for (_, _, dir) in lanes.iter() {
+        let found = directions.iter().position(|d| d.0 * dir.0 + d.1 * dir.1 >= SAME_DIRECTION_COS);
+        let idx = match found {
+            Some(idx) => idx,
+            None => {
+                directions.push(*dir);
+                directions.len() - 1
+            }
+        };
+        lane_directions.push(idx);
+    }
+    let mut proposals = vec![];
+    for (direction_idx, dir) in directions.iter().enumerate() {
+        // Lanes are numbered from left to right relative to the traffic direction
+        let mut group: Vec<(f32, &([Point2f; 4], usize, (f32, f32)))> = lanes
+            .iter()
+            .zip(lane_directions.iter())
+            .filter(|(_, idx)| **idx == direction_idx)
+            .map(|(lane, _)| {
+                let cx = lane.0.iter().map(|pt| pt.x).sum::<f32>() / 4.0;
+                let cy = lane.0.iter().map(|pt| pt.y).sum::<f32>() / 4.0;
+                (cx * dir.1 - cy * dir.0, lane)
+            })
+            .collect();
+        group.sort_by(|a, b| b.0.total_cmp(&a.0));
+        for (lane_number, (_, lane)) in group.iter().enumerate() {
+            proposals.push(LaneProposal {
+                id: Uuid::new_v4().to_string(),
+                lane_number: lane_number as u16,
+                lane_direction: direction_idx as u8,
+                pixel_points: lane.0,
+                trajectories_num: lane.1,
+            });
+        }
+    }
+    proposals
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    // Straight trajectory from the top to the bottom of the frame with small lateral noise
+    fn vertical_trajectory(x: f32, noise: f32, reverse: bool) -> Vec<Point2f> {
+        let mut trajectory: Vec<Point2f> = (0..20).map(|i| Point2f::new(x + noise * (i % 2) as f32, 100.0 + 15.0 * i as f32)).collect();
+        if reverse {
+            trajectory.reverse();
+        }
+        trajectory
+    }
+    #[test]
+    fn test_resample() {
+        let trajectory = vec![Point2f::new(0.0, 0.0), Point2f::new(10.0, 0.0), Point2f::new(10.0, 30.0)];
+        let resampled = resample(&trajectory, 5);
+        assert_eq!(resampled.len(), 5);
+        assert!(distance(&resampled[0], &Point2f::new(0.0, 0.0)) < 0.001);
+        assert!(distance(&resampled[1], &Point2f::new(10.0, 0.0)) < 0.001);
+        assert!(distance(&resampled[2], &Point2f::new(10.0, 10.0)) < 0.001);
+        assert!(distance(&resampled[4], &Point2f::new(10.0, 30.0)) < 0.001);
+    }
+    #[test]
+    fn test_propose_lanes() {
+        let mut trajectories = vec![];
+        for i in 0..6 {
+            // Two lanes to the bottom of the frame and one lane to the top
+            trajectories.push(vertical_trajectory(100.0, i as f32, false));
+            trajectories.push(vertical_trajectory(200.0, i as f32, false));
+            trajectories.push(vertical_trajectory(400.0, i as f32, true));
+        }
+        // Noise: too short trajectory and a lonely path
+        trajectories.push(vec![Point2f::new(0.0, 0.0), Point2f::new(1.0, 1.0)]);
+        trajectories.push(vertical_trajectory(700.0, 0.0, false));
+
+        let proposals = propose_lanes(&trajectories, CLUSTER_THRESHOLD_PIXELS, MIN_CLUSTER_TRAJECTORIES);
+        assert_eq!(proposals.len(), 3);
+        let down: Vec<&LaneProposal> = proposals.iter().filter(|p| p.pixel_points[0].y < p.pixel_points[3].y).collect();
+        assert_eq!(down.len(), 2);
+        assert_eq!(down[0].lane_direction, down[1].lane_direction);
+        assert!(down.iter().all(|p| p.trajectories_num == 6));
+        // Traffic goes down, so the left lane is the one with greater x
+        let left = down.iter().find(|p| p.lane_number == 0).unwrap();
+        assert!((left.pixel_points[0].x + left.pixel_points[1].x) / 2.0 > 150.0);
+        let up: Vec<&LaneProposal> = proposals.iter().filter(|p| p.pixel_points[0].y > p.pixel_points[3].y).collect();
+        assert_eq!(up.len(), 1);
+        assert_ne!(up[0].lane_direction, down[0].lane_direction);
+        // Quad covers the path
+        let width = distance(&up[0].pixel_points[0], &up[0].pixel_points[1]);
+        assert!(width >= 2.0 * MIN_HALF_WIDTH_PIXELS - 0.001);
+    }
+    #[test]
+    fn test_lane_discovery() {
+        let mut discovery = LaneDiscovery::default();
+        let object_id = Uuid::new_v4();
+        discovery.register_point(object_id, 1.0, 1.0);
+        assert_eq!(discovery.get_trajectories_num(), 0);
+        discovery.start(1000, 60, 1);
+        assert!(discovery.is_running());
+        assert_eq!(discovery.get_remaining_seconds(1030), 30);
+        for pt in vertical_trajectory(100.0, 0.0, false).iter() {
+            discovery.register_point(object_id, pt.x, pt.y);
+        }
+        discovery.finish_lost(|id| *id == object_id);
+        assert_eq!(discovery.get_trajectories_num(), 0);
+        assert!(discovery.update(1059).is_none());
+        let job = discovery.update(1060).unwrap();
+        assert!(!discovery.is_running());
+        assert!(discovery.is_evaluating());
+        assert_eq!(discovery.get_trajectories_num(), 1);
+        let proposals = job.evaluate();
+        assert!(discovery.finish(job, proposals));
+        assert!(!discovery.is_evaluating());
+        assert_eq!(discovery.get_trajectories_num(), 1);
+        assert_eq!(discovery.get_proposals().len(), 1);
+
+        // Proposals of the previous recording are dropped after restart
+        discovery.start(2000, 60, 1);
+        discovery.finish_lost(|_| false);
+        let job = discovery.update(2060).unwrap();
+        discovery.start(2070, 60, 1);
+        let proposals = job.evaluate();
+        assert!(!discovery.finish(job, proposals));
+        assert!(discovery.is_running());
+        assert_eq!(discovery.get_proposals().len(), 0);
+    }
+}
@@ -0,0 +1,91 @@
+use std::collections::HashMap;
+
+// Passenger car unit factors which are used when no factors are provided by configuration
+pub fn default_pcu_factors() -> HashMap<String, f32> {
+    let mut factors = HashMap::new();
+    factors.insert("motorbike".to_string(), 0.5);
+    factors.insert("car".to_string(), 1.0);
+    factors.insert("truck".to_string(), 2.5);
+    factors.insert("bus".to_string(), 3.0);
+    factors.insert("train".to_string(), 5.0);
+    factors
+}
+
+// Completes configured factors with the default ones, so vehicle types which are not configured keep default factors
+pub fn with_default_pcu_factors(factors: HashMap<String, f32>) -> HashMap<String, f32> {
+    let mut merged = default_pcu_factors();
+    merged.extend(factors);
+    merged
+}
+
+// Returns PCU factor for given vehicle type. Unknown vehicle types are treated as passenger cars
+pub fn pcu_factor(factors: &HashMap<String, f32>, classname: &str) -> f32 {
+    match factors.get(classname) {
+        Some(factor) => *factor,
+        None => 1.0,
+    }
+}
+
+// Extrapolates number of vehicles observed during the period to the hourly rate. Value "-1" indicates unknown period
+pub fn hourly_rate(count: f32, period_seconds: f32) -> f32 {
+    if period_seconds <= 0.0 {
+        return -1.0;
+    }
+    count * 3600.0 / period_seconds
+}
+
+// Density (vehicles/km) for given time-mean number of vehicles in the zone. Value "-1" indicates no data
+pub fn density(avg_vehicles: f32, length_meters: f32) -> f32 {
+    if avg_vehicles < 0.0 || length_meters <= 0.0 {
+        return -1.0;
+    }
+    avg_vehicles / (length_meters / 1000.0)
+}
+
+// Aggregated traffic flow parameters for every vehicle type in the zone. Values "-1" indicate no data, except volume
+#[derive(Debug, Clone)]
+pub struct TrafficFlowStatistics {
+    // Vehicles per hour
+    pub flow_rate: f32,
+    // Number of vehicles in passenger car units. It is zero until anything is counted
+    pub pcu_volume: f32,
+    // Passenger car units per hour
+    pub pcu_flow_rate: f32,
+    // Vehicles per kilometer
+    pub density: f32,
+}
+
+impl TrafficFlowStatistics {
+    pub fn default() -> Self {
+        TrafficFlowStatistics {
+            flow_rate: -1.0,
+            pcu_volume: 0.0,
+            pcu_flow_rate: -1.0,
+            density: -1.0,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn test_flow() {
+        let factors = default_pcu_factors();
+        assert_eq!(pcu_factor(&factors, "bus"), 3.0);
+        assert_eq!(pcu_factor(&factors, "bicycle"), 1.0);
+        let mut configured = HashMap::new();
+        configured.insert("bus".to_string(), 2.0);
+        configured.insert("tractor".to_string(), 1.5);
+        let factors = with_default_pcu_factors(configured);
+        assert_eq!(pcu_factor(&factors, "bus"), 2.0);
+        assert_eq!(pcu_factor(&factors, "truck"), 2.5);
+        assert_eq!(pcu_factor(&factors, "tractor"), 1.5);
+        assert_eq!(TrafficFlowStatistics::default().pcu_volume, 0.0);
+        assert_eq!(hourly_rate(15.0, 300.0), 180.0);
+        assert_eq!(hourly_rate(15.0, 0.0), -1.0);
+        assert_eq!(density(1.5, 50.0), 30.0);
+        assert_eq!(density(-1.0, 50.0), -1.0);
+        assert_eq!(density(1.5, -1.0), -1.0);
+    }
+}
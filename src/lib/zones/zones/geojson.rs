@@ -46,9 +46,21 @@ pub struct ZonePropertiesGeoJSON {
     /// Corresponding road lane direction
     #[schema(example = 1)]
     pub road_lane_direction: u8,
+    /// Name of the group (e.g. approach) which zone belongs to. Empty string means that zone is not grouped
+    #[serde(default)]
+    #[schema(example = "northbound approach")]
+    pub group_name: String,
     /// Corresponding zone's coordinates for the video frames
     #[schema(example = json!([[51,266],[281,264],[334,80],[179,68]]))]
     pub coordinates: Vec<Vec<i32>>,
+    /// Vehicle types which are used for statistics. Empty set means that every type is counted
+    #[serde(default)]
+    #[schema(example = json!(["car", "bus", "truck"]))]
+    pub counted_classes: Vec<String>,
+    /// Vehicle types which are allowed to be in the zone. Empty set means that every type is allowed
+    #[serde(default)]
+    #[schema(example = json!(["bus"]))]
+    pub allowed_classes: Vec<String>,
     /// Color to visually distinct zones
     #[schema(example = json!([255, 0, 0]))]
     pub color_rgb: [i16; 3],
@@ -64,5 +76,17 @@ pub struct GeoPolygon {
     /// Coordinates for the given geometry (WGS84, EPSG 4326, [longitude, latitude])
     #[serde(rename(serialize = "coordinates", deserialize = "coordinates"))]
     #[schema(example = json!([[[37.61896,54.20568],[37.618927,54.205685],[37.618908,54.205647],[37.618946,54.20564],[37.61896,54.20568]]]))]
-    pub coordinates: Vec<Vec<Vec<f32>>>,
+    pub coordinates: Vec<Vec<Vec<f64>>>,
+}
+
+/// LineString in GeoJSON specification
+#[derive(Serialize, Deserialize, Debug, Default, Clone, ToSchema)]
+pub struct GeoLineString {
+    /// Constant value for specific geometry type
+    #[serde(rename(serialize = "type", deserialize = "type"))]
+    #[schema(example = "LineString")]
+    pub geometry_type: String,
+    /// Coordinates for the given geometry (WGS84, EPSG 4326, [longitude, latitude])
+    #[schema(example = json!([[37.618921, 54.205633], [37.618951, 54.205688]]))]
+    pub coordinates: Vec<Vec<f64>>,
 }
@@ -1,3 +1,5 @@
+use opencv::core::Point2f;
+
 #[derive(Copy, Clone, PartialEq)]
 pub enum PointsOrientation {
     Collinear,
@@ -100,3 +102,63 @@ pub fn is_intersects(
     // Segments do not intersect
     return false;
 }
+
+// Checks if given polygon contains a point
+// Code has been taken from: https://github.com/LdDl/odam/blob/master/virtual_polygons.go#L180
+pub fn polygon_contains_point(polygon: &[Point2f], x: f32, y: f32) -> bool {
+    let n = polygon.len();
+    if n < 3 {
+        return false;
+    }
+    // @todo: math.maxInt could lead to overflow obviously. Need good workaround. PRs are welcome
+    let extreme_point = vec![99999.0, y as f32];
+    let mut intersections_cnt = 0;
+    let mut previous = 0;
+    loop {
+        let current = (previous + 1) % n;
+        // Check if the segment from given point P to extreme point intersects with the segment from polygon point on previous interation to  polygon point on current interation
+        if is_intersects(
+            polygon[previous].x as f32,
+            polygon[previous].y as f32,
+            polygon[current].x as f32,
+            polygon[current].y as f32,
+            x,
+            y,
+            extreme_point[0],
+            extreme_point[1],
+        ) {
+            let orientation = get_orientation(
+                polygon[previous].x as f32,
+                polygon[previous].y as f32,
+                x,
+                y,
+                polygon[current].x as f32,
+                polygon[current].y as f32,
+            );
+            // If given point P is collinear with segment from polygon point on previous interation to  polygon point on current interation
+            if orientation == PointsOrientation::Collinear {
+                // then check if it is on segment
+                // 'True' will be returns if it lies on segment. Otherwise 'False' will be returned
+                return is_on_segment(
+                    polygon[previous].x as f32,
+                    polygon[previous].y as f32,
+                    x,
+                    y,
+                    polygon[current].x as f32,
+                    polygon[current].y as f32,
+                );
+            }
+            intersections_cnt += 1;
+        }
+        previous = current;
+        if previous == 0 {
+            break;
+        }
+    }
+    // If ray intersects even number of times then return true
+    // Otherwise return false
+    if intersections_cnt % 2 == 1 {
+        return true;
+    }
+    false
+}
@@ -0,0 +1,312 @@
+use std::collections::HashMap;
+use uuid::Uuid;
+
+// Nominal vehicle lengths (meters) which are used to convert headway into the gap
+pub fn nominal_vehicle_length(classname: &str) -> f32 {
+    match classname {
+        "motorbike" => 2.0,
+        "car" => 4.5,
+        "truck" => 10.0,
+        "bus" => 12.0,
+        "train" => 25.0,
+        _ => 4.5,
+    }
+}
+
+#[derive(Debug)]
+struct Crossing {
+    time: f64,
+    // Speed in meters per second. Value "-1" indicates unknown speed
+    speed: f32,
+    length: f32,
+}
+
+// Aggregated headway / gap / spacing values. Value "-1" indicates no data
+#[derive(Debug, Clone)]
+pub struct HeadwayStatistics {
+    pub vehicles_passed: u32,
+    pub avg_headway: f32,
+    pub min_headway: f32,
+    pub max_headway: f32,
+    pub avg_gap: f32,
+    pub min_gap: f32,
+    pub avg_spacing: f32,
+    pub min_spacing: f32,
+    pub max_spacing: f32,
+}
+
+impl HeadwayStatistics {
+    pub fn default() -> Self {
+        HeadwayStatistics {
+            vehicles_passed: 0,
+            avg_headway: -1.0,
+            min_headway: -1.0,
+            max_headway: -1.0,
+            avg_gap: -1.0,
+            min_gap: -1.0,
+            avg_spacing: -1.0,
+            min_spacing: -1.0,
+            max_spacing: -1.0,
+        }
+    }
+}
+
+// Collects moments when vehicles pass the reference point on the skeleton
+// and evaluates time headway, time gap and spatial spacing between consecutive vehicles
+#[derive(Debug)]
+pub struct HeadwayAccumulator {
+    // Last known time and position (meters along the skeleton) for each object
+    last_positions: HashMap<Uuid, (f64, f32)>,
+    // Previous vehicles which have passed the reference point along and against the skeleton.
+    // Headways are evaluated within the same travel direction only. They are kept between periods
+    last_crossing_along: Option<Crossing>,
+    last_crossing_against: Option<Crossing>,
+    vehicles_passed: u32,
+    headways: Vec<f32>,
+    gaps: Vec<f32>,
+    spacings: Vec<f32>,
+}
+
+impl HeadwayAccumulator {
+    pub fn default() -> Self {
+        HeadwayAccumulator {
+            last_positions: HashMap::new(),
+            last_crossing_along: None,
+            last_crossing_against: None,
+            vehicles_passed: 0,
+            headways: vec![],
+            gaps: vec![],
+            spacings: vec![],
+        }
+    }
+    // Registers new position of the object.
+    //
+    // time - monotonic timestamp (seconds). It should not be reset between periods
+    // position - distance along the skeleton (meters)
+    // reference - position of the reference point along the skeleton (meters)
+    // speed - speed of the object (km/h). Value "-1" indicates unknown speed
+    //
+    // Returns direction of the crossing if object has passed the reference point: "1" - along the skeleton, "-1" - against it.
+    // Value "0" means that reference point has not been passed
+    pub fn update(&mut self, object_id: Uuid, time: f64, position: f32, reference: f32, speed: f32, classname: &str) -> i8 {
+        let previous = self.last_positions.insert(object_id, (time, position));
+        let (prev_time, prev_position) = match previous {
+            Some(v) => v,
+            None => return 0,
+        };
+        // Crossing can't be interpolated if time goes backwards
+        if time <= prev_time {
+            return 0;
+        }
+        let direction: i8 = if prev_position < reference && position >= reference {
+            1
+        } else if prev_position > reference && position <= reference {
+            -1
+        } else {
+            return 0;
+        };
+        // Interpolate moment of crossing between two observations
+        let crossing_time = if (position - prev_position).abs() > f32::EPSILON {
+            prev_time + ((reference - prev_position) / (position - prev_position)) as f64 * (time - prev_time)
+        } else {
+            time
+        };
+        let speed_ms = if speed > 0.0 { speed / 3.6 } else { -1.0 };
+        let last_crossing = if direction > 0 { &mut self.last_crossing_along } else { &mut self.last_crossing_against };
+        if let Some(ref leader) = last_crossing {
+            let headway = (crossing_time - leader.time) as f32;
+            if headway > 0.0 {
+                self.headways.push(headway);
+                if leader.speed > 0.0 {
+                    self.gaps.push(f32::max(headway - leader.length / leader.speed, 0.0));
+                }
+                if speed_ms > 0.0 {
+                    self.spacings.push(headway * speed_ms);
+                }
+            }
+        }
+        *last_crossing = Some(Crossing {
+            time: crossing_time,
+            speed: speed_ms,
+            length: nominal_vehicle_length(classname),
+        });
+        self.vehicles_passed += 1;
+        direction
+    }
+    pub fn get_statistics(&self) -> HeadwayStatistics {
+        let (avg_headway, min_headway, max_headway) = summarize(&self.headways);
+        let (avg_gap, min_gap, _) = summarize(&self.gaps);
+        let (avg_spacing, min_spacing, max_spacing) = summarize(&self.spacings);
+        HeadwayStatistics {
+            vehicles_passed: self.vehicles_passed,
+            avg_headway,
+            min_headway,
+            max_headway,
+            avg_gap,
+            min_gap,
+            avg_spacing,
+            min_spacing,
+            max_spacing,
+        }
+    }
+    // Clears collected samples. Positions of objects observed during `keep_seconds` before the last observation are kept
+    pub fn reset(&mut self, keep_seconds: f32) {
+        self.vehicles_passed = 0;
+        self.headways.clear();
+        self.gaps.clear();
+        self.spacings.clear();
+        let last_time = self
+            .last_positions
+            .values()
+            .map(|v| v.0)
+            .fold(f64::MIN, f64::max);
+        self.last_positions.retain(|_, v| last_time - v.0 <= keep_seconds as f64);
+    }
+}
+
+// Prevailing travel direction by crossings of the reference point. It is not tied to statistics periods, so it is never reset with them
+#[derive(Debug)]
+pub struct DirectionEstimate {
+    crossings_along: u32,
+    crossings_against: u32,
+}
+
+impl DirectionEstimate {
+    pub fn default() -> Self {
+        DirectionEstimate {
+            crossings_along: 0,
+            crossings_against: 0,
+        }
+    }
+    // direction - direction of the crossing (see `HeadwayAccumulator::update`). Value "0" is ignored
+    pub fn register(&mut self, direction: i8) {
+        match direction {
+            1 => self.crossings_along = self.crossings_along.saturating_add(1),
+            -1 => self.crossings_against = self.crossings_against.saturating_add(1),
+            _ => {}
+        }
+    }
+    // Prevailing direction of crossings: "1" - along the skeleton, "-1" - against the skeleton, "0" - unknown.
+    // Single wrong-way vehicles don't change it
+    pub fn prevailing(&self) -> i8 {
+        if self.crossings_along > self.crossings_against {
+            1
+        } else if self.crossings_against > self.crossings_along {
+            -1
+        } else {
+            0
+        }
+    }
+}
+
+// Returns average, minimum and maximum values. Values "-1" indicate empty data
+fn summarize(values: &[f32]) -> (f32, f32, f32) {
+    if values.len() == 0 {
+        return (-1.0, -1.0, -1.0);
+    }
+    let sum: f32 = values.iter().sum();
+    let min = values.iter().cloned().fold(f32::MAX, f32::min);
+    let max = values.iter().cloned().fold(f32::MIN, f32::max);
+    (sum / values.len() as f32, min, max)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn test_headway() {
+        let mut accumulator = HeadwayAccumulator::default();
+        let leader = Uuid::new_v4();
+        let follower = Uuid::new_v4();
+        // Leader passes reference point (10m) at 1.5s with speed 36 km/h (10 m/s)
+        assert_eq!(accumulator.update(leader, 1.0, 5.0, 10.0, 36.0, "car"), 0);
+        assert_eq!(accumulator.update(leader, 2.0, 15.0, 10.0, 36.0, "car"), 1);
+        // Follower passes reference point at 3.5s with the same speed
+        assert_eq!(accumulator.update(follower, 3.0, 5.0, 10.0, 36.0, "car"), 0);
+        assert_eq!(accumulator.update(follower, 4.0, 15.0, 10.0, 36.0, "car"), 1);
+
+        let eps = 0.0001;
+        let stats = accumulator.get_statistics();
+        assert_eq!(stats.vehicles_passed, 2);
+        assert!((stats.avg_headway - 2.0).abs() < eps);
+        assert!((stats.min_gap - 1.55).abs() < eps);
+        assert!((stats.avg_spacing - 20.0).abs() < eps);
+
+        accumulator.reset(5.0);
+        let stats = accumulator.get_statistics();
+        assert_eq!(stats.vehicles_passed, 0);
+        assert_eq!(stats.avg_headway, -1.0);
+        // Wrong-way vehicle
+        let wrong_way = Uuid::new_v4();
+        accumulator.update(wrong_way, 4.5, 15.0, 10.0, 36.0, "car");
+        assert_eq!(accumulator.update(wrong_way, 5.0, 5.0, 10.0, 36.0, "car"), -1);
+        // Headway is not evaluated between vehicles of opposite directions
+        let stats = accumulator.get_statistics();
+        assert_eq!(stats.vehicles_passed, 1);
+        assert_eq!(stats.avg_headway, -1.0);
+        // Next vehicle along the skeleton follows the follower (passed at 3.5s), not the wrong-way vehicle
+        let next = Uuid::new_v4();
+        accumulator.update(next, 5.5, 5.0, 10.0, 36.0, "car");
+        assert_eq!(accumulator.update(next, 6.5, 15.0, 10.0, 36.0, "car"), 1);
+        let stats = accumulator.get_statistics();
+        assert!((stats.avg_headway - 2.5).abs() < eps);
+    }
+    #[test]
+    fn test_direction_estimate() {
+        let mut estimate = DirectionEstimate::default();
+        assert_eq!(estimate.prevailing(), 0);
+        estimate.register(0);
+        assert_eq!(estimate.prevailing(), 0);
+        estimate.register(-1);
+        estimate.register(-1);
+        // Single wrong-way vehicle doesn't flip the direction of the flow
+        estimate.register(1);
+        assert_eq!(estimate.prevailing(), -1);
+    }
+    #[test]
+    fn test_headway_across_reset() {
+        let mut accumulator = HeadwayAccumulator::default();
+        let leader = Uuid::new_v4();
+        let follower = Uuid::new_v4();
+        // Period ends at 30s while leader is approaching the reference point
+        accumulator.update(leader, 29.5, 5.0, 10.0, 36.0, "car");
+        accumulator.update(follower, 29.5, -5.0, 10.0, 36.0, "car");
+        accumulator.reset(5.0);
+        // Leader crosses in the next period, interpolated between observations from both periods
+        assert_eq!(accumulator.update(leader, 30.5, 15.0, 10.0, 36.0, "car"), 1);
+        assert_eq!(accumulator.update(follower, 31.5, 15.0, 10.0, 36.0, "car"), 1);
+        let eps = 0.0001;
+        let stats = accumulator.get_statistics();
+        assert_eq!(stats.vehicles_passed, 2);
+        // Leader passes at 30.0s, follower passes at 31.0s
+        assert!((stats.avg_headway - 1.0).abs() < eps);
+
+        // Leader of the previous period is kept, so the first headway of the next period is valid as well
+        accumulator.reset(5.0);
+        let next = Uuid::new_v4();
+        accumulator.update(next, 32.0, 5.0, 10.0, 36.0, "car");
+        assert_eq!(accumulator.update(next, 33.0, 15.0, 10.0, 36.0, "car"), 1);
+        let stats = accumulator.get_statistics();
+        assert_eq!(stats.vehicles_passed, 1);
+        assert!((stats.avg_headway - 1.5).abs() < eps);
+
+        // Observation with timestamp from the past is ignored
+        let late = Uuid::new_v4();
+        accumulator.update(late, 40.0, 5.0, 10.0, 36.0, "car");
+        assert_eq!(accumulator.update(late, 0.5, 15.0, 10.0, 36.0, "car"), 0);
+    }
+    #[test]
+    fn test_headway_long_uptime() {
+        let mut accumulator = HeadwayAccumulator::default();
+        let leader = Uuid::new_v4();
+        let follower = Uuid::new_v4();
+        // About 231 days since the start of the capture: single precision would give 2 seconds resolution here
+        let start = 20000000.0;
+        accumulator.update(leader, start + 0.04, 5.0, 10.0, 36.0, "car");
+        assert_eq!(accumulator.update(leader, start + 0.08, 15.0, 10.0, 36.0, "car"), 1);
+        accumulator.update(follower, start + 0.12, 5.0, 10.0, 36.0, "car");
+        assert_eq!(accumulator.update(follower, start + 0.16, 15.0, 10.0, 36.0, "car"), 1);
+        let stats = accumulator.get_statistics();
+        assert!((stats.avg_headway - 0.08).abs() < 0.0001);
+    }
+}
@@ -0,0 +1,118 @@
+use serde::{Deserialize, Serialize};
+
+// Level of service grade (HCM-style)
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd)]
+pub enum LevelOfService {
+    A,
+    B,
+    C,
+    D,
+    E,
+    F,
+    // Not enough data to classify
+    Undefined,
+}
+
+impl LevelOfService {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            LevelOfService::A => "A",
+            LevelOfService::B => "B",
+            LevelOfService::C => "C",
+            LevelOfService::D => "D",
+            LevelOfService::E => "E",
+            LevelOfService::F => "F",
+            LevelOfService::Undefined => "-",
+        }
+    }
+    // Color (BGR) for visual representation. Undefined grade has no color
+    pub fn color(&self) -> Option<(f64, f64, f64)> {
+        match self {
+            LevelOfService::A => Some((0.0, 176.0, 0.0)),
+            LevelOfService::B => Some((0.0, 220.0, 140.0)),
+            LevelOfService::C => Some((0.0, 230.0, 230.0)),
+            LevelOfService::D => Some((0.0, 165.0, 255.0)),
+            LevelOfService::E => Some((0.0, 80.0, 255.0)),
+            LevelOfService::F => Some((0.0, 0.0, 200.0)),
+            LevelOfService::Undefined => None,
+        }
+    }
+    fn from_index(idx: usize) -> Self {
+        match idx {
+            0 => LevelOfService::A,
+            1 => LevelOfService::B,
+            2 => LevelOfService::C,
+            3 => LevelOfService::D,
+            4 => LevelOfService::E,
+            _ => LevelOfService::F,
+        }
+    }
+}
+
+// Thresholds for the level of service classification
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LevelOfServiceThresholds {
+    // Free-flow speed (km/h). Non-positive value disables speed criterion
+    pub free_flow_speed: f32,
+    // Upper bounds of density (vehicles/km) for grades A..E. Higher density gives grade F
+    pub density_thresholds: [f32; 5],
+    // Lower bounds of ratio between average speed and free-flow speed for grades A..E. Lower ratio gives grade F
+    pub speed_ratio_thresholds: [f32; 5],
+}
+
+impl LevelOfServiceThresholds {
+    pub fn default() -> Self {
+        LevelOfServiceThresholds {
+            free_flow_speed: 60.0,
+            // HCM basic segment: 11, 18, 26, 35, 45 pc/mi/ln
+            density_thresholds: [7.0, 11.0, 16.0, 22.0, 28.0],
+            // HCM urban street segment
+            speed_ratio_thresholds: [0.85, 0.67, 0.5, 0.4, 0.3],
+        }
+    }
+    // Classifies traffic by density (vehicles/km) and average speed (km/h). Values "-1" indicate no data.
+    // When both criteria are available the worse grade is used
+    pub fn classify(&self, density: f32, avg_speed: f32) -> LevelOfService {
+        let by_density = if density >= 0.0 {
+            let idx = self.density_thresholds.iter().position(|threshold| density <= *threshold).unwrap_or(5);
+            Some(LevelOfService::from_index(idx))
+        } else {
+            None
+        };
+        let by_speed = if avg_speed >= 0.0 && self.free_flow_speed > 0.0 {
+            let ratio = avg_speed / self.free_flow_speed;
+            let idx = self.speed_ratio_thresholds.iter().position(|threshold| ratio > *threshold).unwrap_or(5);
+            Some(LevelOfService::from_index(idx))
+        } else {
+            None
+        };
+        match (by_density, by_speed) {
+            (Some(a), Some(b)) => {
+                if a > b {
+                    a
+                } else {
+                    b
+                }
+            }
+            (Some(a), None) => a,
+            (None, Some(b)) => b,
+            (None, None) => LevelOfService::Undefined,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn test_classify() {
+        let thresholds = LevelOfServiceThresholds::default();
+        assert_eq!(thresholds.classify(5.0, 58.0), LevelOfService::A);
+        assert_eq!(thresholds.classify(5.0, 45.0), LevelOfService::B);
+        assert_eq!(thresholds.classify(20.0, 58.0), LevelOfService::D);
+        assert_eq!(thresholds.classify(40.0, -1.0), LevelOfService::F);
+        assert_eq!(thresholds.classify(-1.0, 10.0), LevelOfService::F);
+        assert_eq!(thresholds.classify(-1.0, -1.0), LevelOfService::Undefined);
+        assert_eq!(thresholds.classify(-1.0, -1.0).as_str(), "-");
+    }
+}
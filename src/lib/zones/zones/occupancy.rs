@@ -0,0 +1,151 @@
+// Length (meters) of the virtual loop placed across the skeleton. Typical inductive loop is about 2 meters long
+pub const VIRTUAL_LOOP_LENGTH: f32 = 2.0;
+// Frames separated by larger interval (seconds) are not used for time accumulation (e.g. after the stream has been stalled)
+pub const OCCUPANCY_MAX_FRAME_INTERVAL: f32 = 5.0;
+
+// Aggregated time-occupancy values (percents). Value "-1" indicates no data
+#[derive(Debug, Clone)]
+pub struct OccupancyStatistics {
+    // Share of time when at least one vehicle was inside the zone
+    pub occupancy_percent: f32,
+    // Share of time when at least one vehicle was over the virtual loop
+    pub loop_occupancy_percent: f32,
+    // Time-mean number of vehicles inside the zone
+    pub avg_vehicles: f32,
+}
+
+impl OccupancyStatistics {
+    pub fn default() -> Self {
+        OccupancyStatistics {
+            occupancy_percent: -1.0,
+            loop_occupancy_percent: -1.0,
+            avg_vehicles: -1.0,
+        }
+    }
+}
+
+// Accumulates occupied time of the zone and of the virtual loop inside it using frame timestamps
+#[derive(Debug)]
+pub struct OccupancyAccumulator {
+    // Timestamp (seconds) of the last committed frame. It is kept between periods
+    last_time: Option<f64>,
+    // Number of vehicles inside the zone in the current frame
+    zone_vehicles: u32,
+    loop_occupied: bool,
+    // Whether virtual loop could be evaluated (skeleton has known scale) in the current period
+    loop_available: bool,
+    observed_seconds: f32,
+    occupied_seconds: f32,
+    loop_occupied_seconds: f32,
+    // Integral of number of vehicles over time
+    vehicles_seconds: f32,
+}
+
+impl OccupancyAccumulator {
+    pub fn default() -> Self {
+        OccupancyAccumulator {
+            last_time: None,
+            zone_vehicles: 0,
+            loop_occupied: false,
+            loop_available: false,
+            observed_seconds: 0.0,
+            occupied_seconds: 0.0,
+            loop_occupied_seconds: 0.0,
+            vehicles_seconds: 0.0,
+        }
+    }
+    // Marks the zone as occupied by one more vehicle in the current frame
+    pub fn register_zone_occupied(&mut self) {
+        self.zone_vehicles += 1;
+    }
+    // Marks the virtual loop as occupied in the current frame
+    pub fn register_loop_occupied(&mut self) {
+        self.loop_occupied = true;
+    }
+    // Interval between the previous frame and the current one is attributed to the state of the current frame.
+    // Should be called once per frame
+    pub fn commit_frame(&mut self, time: f64, loop_available: bool) {
+        if let Some(last_time) = self.last_time {
+            let dt = (time - last_time) as f32;
+            if dt > 0.0 && dt <= OCCUPANCY_MAX_FRAME_INTERVAL {
+                self.observed_seconds += dt;
+                if self.zone_vehicles > 0 {
+                    self.occupied_seconds += dt;
+                }
+                self.vehicles_seconds += self.zone_vehicles as f32 * dt;
+                if self.loop_occupied {
+                    self.loop_occupied_seconds += dt;
+                }
+            }
+        }
+        self.last_time = Some(time);
+        self.loop_available = loop_available;
+        self.zone_vehicles = 0;
+        self.loop_occupied = false;
+    }
+    pub fn get_statistics(&self) -> OccupancyStatistics {
+        if self.observed_seconds <= 0.0 {
+            return OccupancyStatistics::default();
+        }
+        OccupancyStatistics {
+            occupancy_percent: self.occupied_seconds / self.observed_seconds * 100.0,
+            loop_occupancy_percent: if self.loop_available {
+                self.loop_occupied_seconds / self.observed_seconds * 100.0
+            } else {
+                -1.0
+            },
+            avg_vehicles: self.vehicles_seconds / self.observed_seconds,
+        }
+    }
+    pub fn reset(&mut self) {
+        self.observed_seconds = 0.0;
+        self.occupied_seconds = 0.0;
+        self.loop_occupied_seconds = 0.0;
+        self.vehicles_seconds = 0.0;
+    }
+}
+
+// Checks if vehicle of given length (meters) centered at given position overlaps the loop
+pub fn is_over_loop(position: f32, vehicle_length: f32, loop_center: f32) -> bool {
+    let half_vehicle = vehicle_length / 2.0;
+    let half_loop = VIRTUAL_LOOP_LENGTH / 2.0;
+    position + half_vehicle >= loop_center - half_loop && position - half_vehicle <= loop_center + half_loop
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn test_is_over_loop() {
+        assert_eq!(is_over_loop(10.0, 4.5, 10.0), true);
+        assert_eq!(is_over_loop(7.0, 4.5, 10.0), true);
+        assert_eq!(is_over_loop(6.0, 4.5, 10.0), false);
+        assert_eq!(is_over_loop(14.0, 4.5, 10.0), false);
+    }
+    #[test]
+    fn test_occupancy_accumulator() {
+        let mut accumulator = OccupancyAccumulator::default();
+        // First frame only sets the reference time
+        accumulator.commit_frame(0.0, true);
+        accumulator.register_zone_occupied();
+        accumulator.register_zone_occupied();
+        accumulator.register_loop_occupied();
+        accumulator.commit_frame(1.0, true);
+        accumulator.register_zone_occupied();
+        accumulator.commit_frame(2.0, true);
+        accumulator.commit_frame(4.0, true);
+        let eps = 0.0001;
+        let stats = accumulator.get_statistics();
+        assert!((stats.occupancy_percent - 50.0).abs() < eps);
+        assert!((stats.loop_occupancy_percent - 25.0).abs() < eps);
+        assert!((stats.avg_vehicles - 0.75).abs() < eps);
+        accumulator.reset();
+        assert_eq!(accumulator.get_statistics().occupancy_percent, -1.0);
+        // Time reference is kept between periods
+        accumulator.register_zone_occupied();
+        accumulator.commit_frame(5.0, false);
+        let stats = accumulator.get_statistics();
+        assert!((stats.occupancy_percent - 100.0).abs() < eps);
+        assert_eq!(stats.loop_occupancy_percent, -1.0);
+    }
+}
@@ -0,0 +1,137 @@
+use serde::{Deserialize, Serialize};
+
+// Default speed (km/h) below which vehicle is considered as stationary or slow
+pub const QUEUE_SPEED_THRESHOLD: f32 = 5.0;
+// Default maximum distance (meters) between consecutive queued vehicles (and between stop-line and the first one)
+pub const QUEUE_MAX_SPACING: f32 = 15.0;
+
+// Queue detection parameters of the zone
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct QueueParameters {
+    // Speed (km/h) below which vehicle is considered as stationary or slow
+    pub speed_threshold: f32,
+    // Maximum distance (meters) between consecutive queued vehicles (and between stop-line and the first one)
+    pub max_spacing: f32,
+    // Direction of traffic flow relative to the skeleton: "1" - along it, "-1" - against it. Stop-line is at the end of the skeleton in this direction.
+    // Value "0" means that direction is derived from the prevailing direction of vehicles passing the zone, so queue is not evaluated until vehicles pass it
+    #[serde(default)]
+    pub flow_direction: i8,
+}
+
+impl QueueParameters {
+    pub fn default() -> Self {
+        QueueParameters {
+            speed_threshold: QUEUE_SPEED_THRESHOLD,
+            max_spacing: QUEUE_MAX_SPACING,
+            flow_direction: 0,
+        }
+    }
+}
+
+// Aggregated queue length values (meters)
+#[derive(Debug, Clone)]
+pub struct QueueStatistics {
+    pub max_queue_length: f32,
+    pub avg_queue_length: f32,
+}
+
+impl QueueStatistics {
+    pub fn default() -> Self {
+        QueueStatistics {
+            max_queue_length: 0.0,
+            avg_queue_length: 0.0,
+        }
+    }
+}
+
+// Collects positions of slow vehicles for the current frame and queue length samples for the current period
+#[derive(Debug)]
+pub struct QueueAccumulator {
+    // Distances (meters) from the stop-line to queued vehicles in the current frame
+    queued_distances: Vec<f32>,
+    samples_sum: f32,
+    samples_count: u32,
+    max_queue_length: f32,
+}
+
+impl QueueAccumulator {
+    pub fn default() -> Self {
+        QueueAccumulator {
+            queued_distances: vec![],
+            samples_sum: 0.0,
+            samples_count: 0,
+            max_queue_length: 0.0,
+        }
+    }
+    pub fn register(&mut self, distance_from_stop_line: f32) {
+        self.queued_distances.push(distance_from_stop_line);
+    }
+    // Evaluates queue length for the current frame, stores it as the sample and prepares accumulator for the next frame
+    pub fn commit_frame(&mut self, max_spacing: f32) -> f32 {
+        let queue_length = estimate_queue_length(&mut self.queued_distances, max_spacing);
+        self.queued_distances.clear();
+        self.samples_sum += queue_length;
+        self.samples_count += 1;
+        self.max_queue_length = f32::max(self.max_queue_length, queue_length);
+        queue_length
+    }
+    pub fn get_statistics(&self) -> QueueStatistics {
+        QueueStatistics {
+            max_queue_length: self.max_queue_length,
+            avg_queue_length: if self.samples_count > 0 {
+                self.samples_sum / self.samples_count as f32
+            } else {
+                0.0
+            },
+        }
+    }
+    pub fn reset(&mut self) {
+        self.samples_sum = 0.0;
+        self.samples_count = 0;
+        self.max_queue_length = 0.0;
+    }
+}
+
+// Queue is a chain of slow vehicles starting at the stop-line where consecutive vehicles are not too far from each other.
+// Returns distance (meters) from the stop-line to the last vehicle in such chain
+pub fn estimate_queue_length(distances: &mut Vec<f32>, max_spacing: f32) -> f32 {
+    distances.sort_by(|a, b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
+    let mut queue_length = 0.0;
+    for distance in distances.iter() {
+        if distance - queue_length > max_spacing {
+            break;
+        }
+        queue_length = *distance;
+    }
+    queue_length
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn test_estimate_queue_length() {
+        let mut distances = vec![14.0, 2.0, 60.0, 25.0];
+        assert_eq!(estimate_queue_length(&mut distances, QUEUE_MAX_SPACING), 25.0);
+        let mut distances = vec![20.0, 30.0];
+        assert_eq!(estimate_queue_length(&mut distances, QUEUE_MAX_SPACING), 0.0);
+        // Larger spacing joins distant vehicles into the queue
+        let mut distances = vec![20.0, 30.0];
+        assert_eq!(estimate_queue_length(&mut distances, 25.0), 30.0);
+        let mut distances = vec![];
+        assert_eq!(estimate_queue_length(&mut distances, QUEUE_MAX_SPACING), 0.0);
+    }
+    #[test]
+    fn test_queue_accumulator() {
+        let mut accumulator = QueueAccumulator::default();
+        accumulator.register(5.0);
+        accumulator.register(12.0);
+        assert_eq!(accumulator.commit_frame(QUEUE_MAX_SPACING), 12.0);
+        assert_eq!(accumulator.commit_frame(QUEUE_MAX_SPACING), 0.0);
+        let stats = accumulator.get_statistics();
+        assert_eq!(stats.max_queue_length, 12.0);
+        assert_eq!(stats.avg_queue_length, 6.0);
+        accumulator.reset();
+        assert_eq!(accumulator.get_statistics().max_queue_length, 0.0);
+    }
+}
@@ -0,0 +1,95 @@
+// Width (km/h) of the speed histogram bin
+pub const SPEED_HISTOGRAM_BIN_WIDTH: f32 = 10.0;
+// Number of the speed histogram bins. The last bin includes every speed above (SPEED_HISTOGRAM_BINS - 1) * SPEED_HISTOGRAM_BIN_WIDTH
+pub const SPEED_HISTOGRAM_BINS: usize = 16;
+
+// Distribution of speeds (km/h) for the period. Values "-1" indicate no data
+#[derive(Debug, Clone)]
+pub struct SpeedDistribution {
+    pub samples: u32,
+    pub min_speed: f32,
+    pub max_speed: f32,
+    pub median_speed: f32,
+    pub p85_speed: f32,
+    pub std_speed: f32,
+    // Number of samples in every bin: [0; w), [w; 2w), ..., [(n-1)w; +inf)
+    pub histogram: Vec<u32>,
+}
+
+impl SpeedDistribution {
+    pub fn default() -> Self {
+        SpeedDistribution {
+            samples: 0,
+            min_speed: -1.0,
+            max_speed: -1.0,
+            median_speed: -1.0,
+            p85_speed: -1.0,
+            std_speed: -1.0,
+            histogram: vec![0; SPEED_HISTOGRAM_BINS],
+        }
+    }
+    // Builds distribution for given speed samples. Negative values (unknown speed) are ignored
+    pub fn from_samples(samples: &[f32]) -> Self {
+        let mut speeds: Vec<f32> = samples.iter().cloned().filter(|speed| *speed >= 0.0).collect();
+        let mut ans = SpeedDistribution::default();
+        if speeds.len() == 0 {
+            return ans;
+        }
+        speeds.sort_by(|a, b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
+        let n = speeds.len() as f32;
+        let mean = speeds.iter().sum::<f32>() / n;
+        let variance = speeds.iter().map(|speed| (speed - mean).powi(2)).sum::<f32>() / n;
+        for speed in speeds.iter() {
+            let bin = usize::min((speed / SPEED_HISTOGRAM_BIN_WIDTH) as usize, SPEED_HISTOGRAM_BINS - 1);
+            ans.histogram[bin] += 1;
+        }
+        ans.samples = speeds.len() as u32;
+        ans.min_speed = speeds[0];
+        ans.max_speed = speeds[speeds.len() - 1];
+        ans.median_speed = percentile(&speeds, 50.0);
+        ans.p85_speed = percentile(&speeds, 85.0);
+        ans.std_speed = variance.sqrt();
+        ans
+    }
+}
+
+// Percentile of sorted values with linear interpolation between closest ranks
+pub fn percentile(sorted: &[f32], p: f32) -> f32 {
+    if sorted.len() == 0 {
+        return -1.0;
+    }
+    let rank = p / 100.0 * (sorted.len() - 1) as f32;
+    let lower = rank.floor() as usize;
+    let upper = rank.ceil() as usize;
+    sorted[lower] + (sorted[upper] - sorted[lower]) * (rank - lower as f32)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn test_percentile() {
+        let values = vec![10.0, 20.0, 30.0, 40.0, 50.0];
+        assert_eq!(percentile(&values, 50.0), 30.0);
+        assert_eq!(percentile(&values, 0.0), 10.0);
+        assert_eq!(percentile(&values, 100.0), 50.0);
+        assert!((percentile(&values, 85.0) - 44.0).abs() < 0.0001);
+        assert_eq!(percentile(&[], 85.0), -1.0);
+    }
+    #[test]
+    fn test_speed_distribution() {
+        let distribution = SpeedDistribution::from_samples(&[40.0, -1.0, 60.0, 50.0, 200.0]);
+        assert_eq!(distribution.samples, 4);
+        assert_eq!(distribution.min_speed, 40.0);
+        assert_eq!(distribution.max_speed, 200.0);
+        assert_eq!(distribution.median_speed, 55.0);
+        assert!((distribution.std_speed - 65.336).abs() < 0.01);
+        assert_eq!(distribution.histogram[4], 1);
+        assert_eq!(distribution.histogram[5], 1);
+        assert_eq!(distribution.histogram[6], 1);
+        assert_eq!(distribution.histogram[SPEED_HISTOGRAM_BINS - 1], 1);
+        let empty = SpeedDistribution::from_samples(&[-1.0]);
+        assert_eq!(empty.p85_speed, -1.0);
+        assert_eq!(empty.histogram.iter().sum::<u32>(), 0);
+    }
+}
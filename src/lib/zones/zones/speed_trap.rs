@@ -0,0 +1,205 @@
+use opencv::core::Point2f;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use uuid::Uuid;
+
+// Configuration of the speed trap: two lines (in pixels) and known distance between them
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SpeedTrapLines {
+    pub entry_line: [[f32; 2]; 2],
+    pub exit_line: [[f32; 2]; 2],
+    // Distance (meters) between lines along the road. Non-positive value disables the speed trap
+    pub distance_meters: f32,
+}
+
+#[derive(Debug)]
+struct TrackState {
+    // Last observed time (seconds) and position (pixels)
+    time: f64,
+    x: f32,
+    y: f32,
+    // Index of the line which has been crossed first and interpolated moment of crossing
+    first_crossing: Option<(usize, f64)>,
+}
+
+// Measures speed of objects by timing them between two lines which are known distance apart.
+// Moments of crossing are interpolated between consecutive observations (sub-frame precision)
+#[derive(Debug)]
+pub struct SpeedTrap {
+    lines: [[Point2f; 2]; 2],
+    distance_meters: f32,
+    states: HashMap<Uuid, TrackState>,
+    // Measured speeds (km/h) for objects which have crossed both lines
+    speeds: HashMap<Uuid, f32>,
+}
+
+impl SpeedTrap {
+    pub fn new(settings: &SpeedTrapLines) -> Self {
+        let to_line = |line: &[[f32; 2]; 2]| [Point2f::new(line[0][0], line[0][1]), Point2f::new(line[1][0], line[1][1])];
+        SpeedTrap {
+            lines: [to_line(&settings.entry_line), to_line(&settings.exit_line)],
+            distance_meters: settings.distance_meters,
+            states: HashMap::new(),
+            speeds: HashMap::new(),
+        }
+    }
+    pub fn get_lines(&self) -> SpeedTrapLines {
+        let from_line = |line: &[Point2f; 2]| [[line[0].x, line[0].y], [line[1].x, line[1].y]];
+        SpeedTrapLines {
+            entry_line: from_line(&self.lines[0]),
+            exit_line: from_line(&self.lines[1]),
+            distance_meters: self.distance_meters,
+        }
+    }
+    pub fn get_pixel_lines(&self) -> [[Point2f; 2]; 2] {
+        self.lines
+    }
+    pub fn scale_geom(&mut self, scale_factor_x: f32, scale_factor_y: f32) {
+        for line in self.lines.iter_mut() {
+            for pt in line.iter_mut() {
+                pt.x = (pt.x * scale_factor_x).floor();
+                pt.y = (pt.y * scale_factor_y).floor();
+            }
+        }
+    }
+    // Registers new position of the object. Lines could be crossed in any order, so both traffic directions are supported.
+    // Time (seconds) should be monotonic, i.e. it should not be reset between periods.
+    // Returns measured speed (km/h) if object has crossed the second line
+    pub fn update(&mut self, object_id: Uuid, time: f64, x: f32, y: f32) -> Option<f32> {
+        let state = match self.states.get_mut(&object_id) {
+            Some(state) => state,
+            None => {
+                self.states.insert(object_id, TrackState { time, x, y, first_crossing: None });
+                return None;
+            }
+        };
+        // Crossings can't be timed if time goes backwards: start timing from scratch
+        if time <= state.time {
+            state.time = time;
+            state.x = x;
+            state.y = y;
+            state.first_crossing = None;
+            return None;
+        }
+        // Both lines could be crossed between two observations, so crossings are processed in order of their appearance
+        let mut crossings: Vec<(usize, f32)> = vec![];
+        for (idx, line) in self.lines.iter().enumerate() {
+            if let Some(fraction) = segments_intersection(state.x, state.y, x, y, line[0].x, line[0].y, line[1].x, line[1].y) {
+                crossings.push((idx, fraction));
+            }
+        }
+        crossings.sort_by(|a, b| a.1.partial_cmp(&b.1).unwrap_or(std::cmp::Ordering::Equal));
+        let mut measured = None;
+        for (idx, fraction) in crossings {
+            let crossing_time = state.time + fraction as f64 * (time - state.time);
+            match state.first_crossing {
+                None => {
+                    state.first_crossing = Some((idx, crossing_time));
+                }
+                Some((first_idx, first_time)) if first_idx != idx => {
+                    let dt = (crossing_time - first_time) as f32;
+                    state.first_crossing = None;
+                    // Non-positive travel time gives no speed
+                    if dt <= 0.0 {
+                        continue;
+                    }
+                    let speed = self.distance_meters / dt * 3.6;
+                    self.speeds.insert(object_id, speed);
+                    measured = Some(speed);
+                }
+                // The same line has been crossed again (e.g. jitter of the track): restart timing
+                Some(_) => {
+                    state.first_crossing = Some((idx, crossing_time));
+                }
+            }
+        }
+        state.time = time;
+        state.x = x;
+        state.y = y;
+        measured
+    }
+    // Returns measured speed (km/h) for the object. Value "-1" indicates that object has not passed the speed trap yet
+    pub fn get_speed(&self, object_id: &Uuid) -> f32 {
+        match self.speeds.get(object_id) {
+            Some(speed) => *speed,
+            None => -1.0,
+        }
+    }
+    // Forgets objects observed earlier than `keep_seconds` before the last observation
+    pub fn reset(&mut self, keep_seconds: f32) {
+        let last_time = self.states.values().map(|state| state.time).fold(f64::MIN, f64::max);
+        self.states.retain(|_, state| last_time - state.time <= keep_seconds as f64);
+        let states = &self.states;
+        self.speeds.retain(|object_id, _| states.contains_key(object_id));
+    }
+}
+
+// Finds intersection of segments P1-P2 and Q1-Q2.
+// Returns position of intersection point along P1-P2 as fraction of its length
+fn segments_intersection(p1x: f32, p1y: f32, p2x: f32, p2y: f32, q1x: f32, q1y: f32, q2x: f32, q2y: f32) -> Option<f32> {
+    let (rx, ry) = (p2x - p1x, p2y - p1y);
+    let (sx, sy) = (q2x - q1x, q2y - q1y);
+    let denominator = rx * sy - ry * sx;
+    if denominator.abs() < f32::EPSILON {
+        // Parallel or collinear segments
+        return None;
+    }
+    let (qpx, qpy) = (q1x - p1x, q1y - p1y);
+    let t = (qpx * sy - qpy * sx) / denominator;
+    let u = (qpx * ry - qpy * rx) / denominator;
+    if t < 0.0 || t > 1.0 || u < 0.0 || u > 1.0 {
+        return None;
+    }
+    Some(t)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn test_segments_intersection() {
+        assert_eq!(segments_intersection(0.0, 0.0, 10.0, 0.0, 2.5, -1.0, 2.5, 1.0), Some(0.25));
+        assert_eq!(segments_intersection(0.0, 0.0, 10.0, 0.0, 12.0, -1.0, 12.0, 1.0), None);
+        assert_eq!(segments_intersection(0.0, 0.0, 10.0, 0.0, 0.0, 1.0, 10.0, 1.0), None);
+    }
+    #[test]
+    fn test_speed_trap() {
+        // Horizontal lines at y = 100 and y = 200, 20 meters apart
+        let mut trap = SpeedTrap::new(&SpeedTrapLines {
+            entry_line: [[0.0, 100.0], [500.0, 100.0]],
+            exit_line: [[0.0, 200.0], [500.0, 200.0]],
+            distance_meters: 20.0,
+        });
+        let object_id = Uuid::new_v4();
+        assert_eq!(trap.update(object_id, 0.0, 250.0, 50.0), None);
+        // Entry line is crossed at 0.5s
+        assert_eq!(trap.update(object_id, 1.0, 250.0, 150.0), None);
+        assert_eq!(trap.get_speed(&object_id), -1.0);
+        // Exit line is crossed at 1.5s: 20 meters per 1 second
+        let speed = trap.update(object_id, 2.0, 250.0, 250.0).unwrap();
+        assert!((speed - 72.0).abs() < 0.001);
+        assert!((trap.get_speed(&object_id) - 72.0).abs() < 0.001);
+
+        // Opposite direction
+        let other_id = Uuid::new_v4();
+        assert_eq!(trap.update(other_id, 10.0, 250.0, 250.0), None);
+        // Both lines are crossed between two observations: 20 meters per 0.25 second
+        let speed = trap.update(other_id, 10.5, 250.0, 50.0).unwrap();
+        assert!((speed - 288.0).abs() < 0.001);
+
+        trap.reset(5.0);
+        assert_eq!(trap.get_speed(&object_id), -1.0);
+        assert!(trap.get_speed(&other_id) > 0.0);
+
+        // Time going backwards between crossings gives no speed
+        let late_id = Uuid::new_v4();
+        assert_eq!(trap.update(late_id, 29.5, 250.0, 50.0), None);
+        assert_eq!(trap.update(late_id, 30.0, 250.0, 150.0), None);
+        assert_eq!(trap.update(late_id, 0.5, 250.0, 250.0), None);
+        assert_eq!(trap.get_speed(&late_id), -1.0);
+        // Timing restarts from that observation: exit line is crossed at 0.75s and entry line at 1.5s
+        assert_eq!(trap.update(late_id, 1.0, 250.0, 150.0), None);
+        let speed = trap.update(late_id, 2.0, 250.0, 50.0).unwrap();
+        assert!((speed - 96.0).abs() < 0.001);
+    }
+}
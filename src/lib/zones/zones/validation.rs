@@ -0,0 +1,262 @@
+use opencv::core::{Point2d, Point2f};
+use serde::Serialize;
+use std::fmt;
+use utoipa::ToSchema;
+
+use super::geometry::is_intersects;
+use super::los::LevelOfServiceThresholds;
+use crate::lib::spatial::projection::LocalProjection;
+
+// Zone is a quadrilateral: first and third sides are used for the skeleton and perspective transform needs exactly four points
+pub const ZONE_POINTS_NUM: usize = 4;
+// Minimum area of the zone (square pixels) on the video frame
+const MIN_PIXEL_AREA: f64 = 100.0;
+// Minimum area of the zone (square meters) on the ground
+const MIN_SPATIAL_AREA: f64 = 1.0;
+// Latitude limits of the Web Mercator projection (EPSG:3857)
+const MAX_LATITUDE: f64 = 85.05112878;
+
+/// Problem found in the geometry of the detection zone
+#[derive(Debug, Clone, PartialEq, Serialize, ToSchema)]
+pub struct GeometryError {
+    /// Name of the field with invalid geometry
+    #[schema(example = "pixel_points")]
+    pub field: String,
+    /// Human-readable description of the problem
+    #[schema(example = "Polygon is self-intersecting")]
+    pub message: String,
+}
+
+impl GeometryError {
+    fn new(field: &str, message: String) -> Self {
+        GeometryError {
+            field: field.to_string(),
+            message: message,
+        }
+    }
+}
+
+impl fmt::Display for GeometryError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {}", self.field, self.message)
+    }
+}
+
+// Signed area of the polygon (shoelace formula). Sign depends on order of points
+fn signed_area(points: &[Point2d]) -> f64 {
+    let n = points.len();
+    let mut sum = 0.0;
+    for i in 0..n {
+        let a = points[i];
+        let b = points[(i + 1) % n];
+        sum += a.x * b.y - b.x * a.y;
+    }
+    sum / 2.0
+}
+
+// Converts WGS84 points to meters on the local tangent plane at the first point
+fn to_local_meters(points: &[Point2d]) -> Vec<Point2d> {
+    if points.len() == 0 {
+        return vec![];
+    }
+    let projection = LocalProjection::new(points[0].x, points[0].y);
+    points.iter().map(|pt| projection.forward_cv(pt)).collect()
+}
+
+// Pixel points are checked with the same (double precision) routines as spatial ones
+fn to_double(points: &[Point2f]) -> Vec<Point2d> {
+    points.iter().map(|pt| Point2d::new(pt.x as f64, pt.y as f64)).collect()
+}
+
+// Checks number of points, degeneracy, self-intersection and convexity of the quadrilateral
+fn validate_quad(field: &str, points: &[Point2d]) -> Vec<GeometryError> {
+    let mut errors = vec![];
+    if points.len() != ZONE_POINTS_NUM {
+        errors.push(GeometryError::new(field, format!("Expected {} points, got {}", ZONE_POINTS_NUM, points.len())));
+        return errors;
+    }
+    for i in 0..ZONE_POINTS_NUM {
+        for j in (i + 1)..ZONE_POINTS_NUM {
+            if points[i] == points[j] {
+                errors.push(GeometryError::new(field, format!("Points #{} and #{} are the same", i, j)));
+            }
+        }
+    }
+    if errors.len() > 0 {
+        return errors;
+    }
+    // Opposite sides should not intersect each other
+    let side_intersects = |a: usize, b: usize, c: usize, d: usize| {
+        is_intersects(
+            points[a].x as f32, points[a].y as f32, points[b].x as f32, points[b].y as f32,
+            points[c].x as f32, points[c].y as f32, points[d].x as f32, points[d].y as f32,
+        )
+    };
+    if side_intersects(0, 1, 2, 3) || side_intersects(1, 2, 3, 0) {
+        errors.push(GeometryError::new(field, "Polygon is self-intersecting".to_string()));
+        return errors;
+    }
+    // Every turn should have the same direction. Zero turn means that three consecutive points are collinear
+    let mut positive = 0;
+    let mut negative = 0;
+    for i in 0..ZONE_POINTS_NUM {
+        let a = points[i];
+        let b = points[(i + 1) % ZONE_POINTS_NUM];
+        let c = points[(i + 2) % ZONE_POINTS_NUM];
+        let cross = (b.x - a.x) * (c.y - b.y) - (b.y - a.y) * (c.x - b.x);
+        if cross == 0.0 {
+            errors.push(GeometryError::new(field, format!("Points #{}, #{} and #{} are collinear", i, (i + 1) % ZONE_POINTS_NUM, (i + 2) % ZONE_POINTS_NUM)));
+        } else if cross > 0.0 {
+            positive += 1;
+        } else {
+            negative += 1;
+        }
+    }
+    if errors.len() == 0 && positive > 0 && negative > 0 {
+        errors.push(GeometryError::new(field, "Polygon is not convex".to_string()));
+    }
+    errors
+}
+
+// Validates zone's coordinates for the video frames
+pub fn validate_pixel_points(field: &str, points: &[Point2f]) -> Vec<GeometryError> {
+    let mut errors = vec![];
+    for (i, pt) in points.iter().enumerate() {
+        if pt.x < 0.0 || pt.y < 0.0 {
+            errors.push(GeometryError::new(field, format!("Point #{} has negative coordinates [{}, {}]", i, pt.x, pt.y)));
+        }
+    }
+    let points = to_double(points);
+    errors.extend(validate_quad(field, &points));
+    if errors.len() > 0 {
+        return errors;
+    }
+    let area = signed_area(&points).abs();
+    if area < MIN_PIXEL_AREA {
+        errors.push(GeometryError::new(field, format!("Area {} px² is less than minimum {} px²", area, MIN_PIXEL_AREA)));
+    }
+    errors
+}
+
+// Validates zone's coordinates in WGS84 (EPSG:4326, [longitude, latitude])
+pub fn validate_wgs84_points(field: &str, points: &[Point2d]) -> Vec<GeometryError> {
+    let mut errors = vec![];
+    for (i, pt) in points.iter().enumerate() {
+        if !pt.x.is_finite() || pt.x < -180.0 || pt.x > 180.0 {
+            errors.push(GeometryError::new(field, format!("Point #{} has longitude {} out of range [-180; 180]", i, pt.x)));
+        }
+        if !pt.y.is_finite() || pt.y < -MAX_LATITUDE || pt.y > MAX_LATITUDE {
+            errors.push(GeometryError::new(field, format!("Point #{} has latitude {} out of range [-{}; {}]", i, pt.y, MAX_LATITUDE, MAX_LATITUDE)));
+        }
+    }
+    if errors.len() > 0 {
+        return errors;
+    }
+    let local_points = to_local_meters(points);
+    errors.extend(validate_quad(field, &local_points));
+    if errors.len() > 0 {
+        return errors;
+    }
+    let area = signed_area(&local_points).abs();
+    if area < MIN_SPATIAL_AREA {
+        errors.push(GeometryError::new(field, format!("Area {:.3} m² is less than minimum {} m²", area, MIN_SPATIAL_AREA)));
+    }
+    errors
+}
+
+// Validates both sets of zone's coordinates and their correspondence.
+// Returns every found problem, so the whole report could be shown at once
+pub fn validate_zone_geometry(pixel_field: &str, pixel_points: &[Point2f], wgs84_field: &str, wgs84_points: &[Point2d]) -> Result<(), Vec<GeometryError>> {
+    let mut errors = validate_pixel_points(pixel_field, pixel_points);
+    errors.extend(validate_wgs84_points(wgs84_field, wgs84_points));
+    if pixel_points.len() != wgs84_points.len() {
+        errors.push(GeometryError::new(wgs84_field, format!("Number of points {} does not match number of points in '{}' {}", wgs84_points.len(), pixel_field, pixel_points.len())));
+    }
+    if errors.len() > 0 {
+        return Err(errors);
+    }
+    // Y-axis of the video frame points down while latitude grows up, so the same order of points gives opposite signs of areas
+    if signed_area(&to_double(pixel_points)).signum() == signed_area(&to_local_meters(wgs84_points)).signum() {
+        errors.push(GeometryError::new(wgs84_field, format!("Order of points is mirrored relative to '{}'", pixel_field)));
+        return Err(errors);
+    }
+    Ok(())
+}
+
+// Validates thresholds for the level of service classification: grades A..E should be ordered from the best to the worst
+pub fn validate_los_thresholds(field: &str, thresholds: &LevelOfServiceThresholds) -> Vec<GeometryError> {
+    let mut errors = vec![];
+    if !thresholds.free_flow_speed.is_finite() {
+        errors.push(GeometryError::new(field, format!("Free-flow speed {} is not a number", thresholds.free_flow_speed)));
+    }
+    if thresholds.density_thresholds.iter().any(|value| !value.is_finite() || *value <= 0.0) {
+        errors.push(GeometryError::new(field, format!("Density thresholds {:?} should be positive", thresholds.density_thresholds)));
+    } else if thresholds.density_thresholds.windows(2).any(|pair| pair[0] >= pair[1]) {
+        errors.push(GeometryError::new(field, format!("Density thresholds {:?} should increase strictly", thresholds.density_thresholds)));
+    }
+    if thresholds.speed_ratio_thresholds.iter().any(|value| !value.is_finite() || *value <= 0.0 || *value > 1.0) {
+        errors.push(GeometryError::new(field, format!("Speed ratio thresholds {:?} should be in (0; 1]", thresholds.speed_ratio_thresholds)));
+    } else if thresholds.speed_ratio_thresholds.windows(2).any(|pair| pair[0] <= pair[1]) {
+        errors.push(GeometryError::new(field, format!("Speed ratio thresholds {:?} should decrease strictly", thresholds.speed_ratio_thresholds)));
+    }
+    errors
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    fn to_points(points: &[[f32; 2]]) -> Vec<Point2f> {
+        points.iter().map(|pt| Point2f::new(pt[0], pt[1])).collect()
+    }
+    fn to_points_wgs84(points: &[[f64; 2]]) -> Vec<Point2d> {
+        points.iter().map(|pt| Point2d::new(pt[0], pt[1])).collect()
+    }
+    #[test]
+    fn test_validate_zone_geometry() {
+        let pixel = to_points(&[[51.0, 266.0], [281.0, 264.0], [334.0, 80.0], [179.0, 68.0]]);
+        let wgs84 = to_points_wgs84(&[[37.619001, 54.205674], [37.618964, 54.205678], [37.618947, 54.205639], [37.618983, 54.205632]]);
+        assert_eq!(validate_zone_geometry("geometry", &pixel, "geometry_wgs84", &wgs84), Ok(()));
+
+        let mirrored: Vec<Point2d> = wgs84.iter().rev().cloned().collect();
+        let errors = validate_zone_geometry("geometry", &pixel, "geometry_wgs84", &mirrored).unwrap_err();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].field, "geometry_wgs84");
+
+        let errors = validate_zone_geometry("geometry", &pixel[..3], "geometry_wgs84", &wgs84).unwrap_err();
+        assert_eq!(errors.len(), 2);
+        assert_eq!(errors[0].field, "geometry");
+    }
+    #[test]
+    fn test_validate_pixel_points() {
+        let self_intersecting = to_points(&[[0.0, 0.0], [100.0, 100.0], [100.0, 0.0], [0.0, 100.0]]);
+        assert_eq!(validate_pixel_points("pixel_points", &self_intersecting)[0].message, "Polygon is self-intersecting");
+        let concave = to_points(&[[0.0, 0.0], [100.0, 0.0], [20.0, 20.0], [0.0, 100.0]]);
+        assert_eq!(validate_pixel_points("pixel_points", &concave)[0].message, "Polygon is not convex");
+        let degenerate = to_points(&[[0.0, 0.0], [50.0, 0.0], [100.0, 0.0], [0.0, 100.0]]);
+        assert_eq!(validate_pixel_points("pixel_points", &degenerate).len(), 1);
+        let duplicated = to_points(&[[0.0, 0.0], [100.0, 0.0], [100.0, 0.0], [0.0, 100.0]]);
+        assert_eq!(validate_pixel_points("pixel_points", &duplicated)[0].message, "Points #1 and #2 are the same");
+        let tiny = to_points(&[[0.0, 0.0], [5.0, 0.0], [5.0, 5.0], [0.0, 5.0]]);
+        assert_eq!(validate_pixel_points("pixel_points", &tiny).len(), 1);
+    }
+    #[test]
+    fn test_validate_wgs84_points() {
+        let out_of_range = to_points_wgs84(&[[37.619001, 54.205674], [37.618964, 54.205678], [37.618947, 54.205639], [37.618983, 154.205632]]);
+        assert_eq!(validate_wgs84_points("spatial_points", &out_of_range).len(), 1);
+        // About 0.6 x 0.5 meters
+        let tiny = to_points_wgs84(&[[37.619, 54.2056], [37.61901, 54.2056], [37.61901, 54.205605], [37.619, 54.205605]]);
+        assert_eq!(validate_wgs84_points("spatial_points", &tiny).len(), 1);
+    }
+    #[test]
+    fn test_validate_los_thresholds() {
+        let mut thresholds = LevelOfServiceThresholds::default();
+        assert_eq!(validate_los_thresholds("level_of_service", &thresholds).len(), 0);
+        thresholds.density_thresholds = [7.0, 16.0, 11.0, 22.0, 28.0];
+        assert_eq!(validate_los_thresholds("level_of_service", &thresholds).len(), 1);
+        thresholds.speed_ratio_thresholds = [0.3, 0.4, 0.5, 0.67, 0.85];
+        assert_eq!(validate_los_thresholds("level_of_service", &thresholds).len(), 2);
+        thresholds = LevelOfServiceThresholds::default();
+        thresholds.speed_ratio_thresholds = [1.5, 0.67, 0.5, 0.4, 0.3];
+        assert_eq!(validate_los_thresholds("level_of_service", &thresholds)[0].field, "level_of_service");
+    }
+}
@@ -0,0 +1,59 @@
+use std::collections::HashMap;
+use uuid::Uuid;
+
+// Counts objects of disallowed classes in the zone. Every object is counted only once while it stays in the zone
+#[derive(Debug)]
+pub struct ViolationsAccumulator {
+    // Last time (seconds) when violator has been seen. It is kept between periods
+    last_seen: HashMap<Uuid, f64>,
+    // Number of violations for each vehicle type in the current period
+    counts: HashMap<String, u32>,
+}
+
+impl ViolationsAccumulator {
+    pub fn default() -> Self {
+        ViolationsAccumulator {
+            last_seen: HashMap::new(),
+            counts: HashMap::new(),
+        }
+    }
+    // Returns true if object has not been registered as violator before
+    pub fn register(&mut self, object_id: Uuid, time: f64, classname: &str) -> bool {
+        if self.last_seen.insert(object_id, time).is_some() {
+            return false;
+        }
+        *self.counts.entry(classname.to_string()).or_insert(0) += 1;
+        true
+    }
+    pub fn get_statistics(&self) -> HashMap<String, u32> {
+        self.counts.clone()
+    }
+    // Clears counters. Violators observed during `keep_seconds` before the last observation are kept
+    pub fn reset(&mut self, keep_seconds: f32) {
+        self.counts.clear();
+        let last_time = self.last_seen.values().cloned().fold(f64::MIN, f64::max);
+        self.last_seen.retain(|_, time| last_time - *time <= keep_seconds as f64);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn test_violations_accumulator() {
+        let mut accumulator = ViolationsAccumulator::default();
+        let car = Uuid::new_v4();
+        let truck = Uuid::new_v4();
+        assert_eq!(accumulator.register(car, 1.0, "car"), true);
+        assert_eq!(accumulator.register(car, 2.0, "car"), false);
+        assert_eq!(accumulator.register(truck, 20.0, "truck"), true);
+        let stats = accumulator.get_statistics();
+        assert_eq!(stats.get("car"), Some(&1));
+        assert_eq!(stats.get("truck"), Some(&1));
+        accumulator.reset(10.0);
+        assert_eq!(accumulator.get_statistics().len(), 0);
+        // Truck is still known as violator, but car has been forgotten
+        assert_eq!(accumulator.register(truck, 21.0, "truck"), false);
+        assert_eq!(accumulator.register(car, 21.0, "car"), true);
+    }
+}
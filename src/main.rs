@@ -1,18 +1,21 @@
 use chrono::Utc;
 use opencv::dnn::read_net_from_onnx;
 use opencv::{
-    core::get_cuda_enabled_device_count, core::Mat, core::Scalar, core::Size, core::Vector,
+    core::get_cuda_enabled_device_count, core::Mat, core::Rect, core::Scalar, core::Size, core::Vector,
     core::CV_32F, dnn::blob_from_image, dnn::read_net, dnn::Net, dnn::DNN_BACKEND_CUDA,
     dnn::DNN_TARGET_CUDA, highgui::imshow, highgui::named_window, highgui::resize_window,
-    highgui::wait_key, imgcodecs::imencode, imgproc::resize, prelude::*, videoio::get_backends,
+    highgui::wait_key, imgcodecs::imencode, imgcodecs::imread, imgcodecs::IMREAD_COLOR, imgproc::resize, prelude::*, videoio::get_backends,
     videoio::VideoCapture,
 };
 
 mod lib;
-use lib::data_storage::new_datastorage;
+use lib::data_storage::{new_datastorage, GroundTrack, HistoryWriter};
 use lib::detection::process_yolo_detections;
 use lib::draw;
-use lib::tracker::{SpatialInfo, Tracker};
+use lib::masks::ExclusionMask;
+use lib::spatial::mapping::FrameMapping;
+use lib::spatial::projection::ProjectionKind;
+use lib::tracker::{find_point_before, GroundInfo, SpatialInfo, Tracker};
 use lib::zones::Zone;
 
 mod settings;
@@ -32,7 +35,8 @@ use std::error::Error;
 use std::fmt;
 use std::io::Write;
 use std::process;
-use std::sync::{mpsc, Arc, RwLock};
+use std::collections::HashMap;
+use std::sync::{mpsc, Arc, Mutex, RwLock};
 use std::thread;
 use std::time::Duration as STDDuration;
 use std::time::Instant;
@@ -48,6 +52,8 @@ const COCO_FILTERED_CLASSNAMES: &'static [&'static str] =
 const BLOB_SCALE: f64 = 1.0 / 255.0;
 const BLOB_NAME: &'static str = "";
 const EMPTY_FRAMES_LIMIT: u16 = 60;
+// Time window (seconds) for estimating whether vehicle is stationary
+const SLOW_SPEED_WINDOW: f32 = 1.0;
 
 fn get_sys_time_in_secs() -> u64 {
     match SystemTime::now().duration_since(SystemTime::UNIX_EPOCH) {
@@ -157,6 +163,61 @@ fn prepare_neural_net(model: &str) -> Result<(Net, Vector<String>), AppError> {
     Ok((neural_net, out_layers_names))
 }
 
+// Draws metric grid and zones mapped to the displayed frame
+fn draw_zones(
+    frame: &mut Mat,
+    ds_guard: &data_storage::DataStorage,
+    zones: &HashMap<String, Mutex<Zone>>,
+    mapping: &FrameMapping,
+    metric_grid_cache: &mut draw::MetricGridCache,
+    grid_scalar: Scalar,
+) {
+    let metric_grid = *ds_guard.metric_grid.read().expect("Metric grid is poisoned [RWLock]");
+    if metric_grid.enabled {
+        let ground_plane = ds_guard.ground_plane.lock().expect("Ground plane is poisoned [Mutex]");
+        match ground_plane.as_ref() {
+            Some(converter) => {
+                let region = Rect::new(0, 0, frame.cols(), frame.rows());
+                metric_grid_cache.draw(frame, "", converter, region, metric_grid.step_meters, mapping, grid_scalar);
+                metric_grid_cache.retain(&[""]);
+            }
+            None => {
+                // There is no camera-wide calibration, so each zone shows grid of its own calibration
+                for (zone_id, v) in zones.iter() {
+                    let polygon = v.lock().expect("Mutex poisoned");
+                    metric_grid_cache.draw(frame, zone_id, polygon.get_spatial_converter(), polygon.get_bounding_rect(), metric_grid.step_meters, mapping, grid_scalar);
+                }
+                metric_grid_cache.retain(&zones.keys().map(|zone_id| zone_id.as_str()).collect::<Vec<&str>>());
+            }
+        }
+    }
+    // Zones are filled with colors of the level of service on the single overlay, so the frame is blended once
+    match frame.try_clone() {
+        Ok(mut los_overlay) => {
+            let mut filled = false;
+            for (_, v) in zones.iter() {
+                let polygon = v.lock().expect("Mutex poisoned");
+                filled |= polygon.draw_level_of_service(&mut los_overlay, mapping);
+            }
+            if filled {
+                zones::blend_level_of_service(frame, &los_overlay);
+            }
+        }
+        Err(err) => {
+            println!("Can't copy frame for level of service overlay due the error: {}", err);
+        }
+    };
+    for (_, v) in zones.iter() {
+        let polygon = v.lock().expect("Mutex poisoned");
+        polygon.draw_geom(frame, mapping);
+        polygon.draw_skeleton(frame, mapping);
+        if metric_grid.enabled {
+            polygon.draw_skeleton_ticks(frame, metric_grid.step_meters, mapping);
+        }
+        polygon.draw_current_intensity(frame, mapping);
+    }
+}
+
 fn run(
     settings: &AppSettings,
     path_to_config: &str,
@@ -178,6 +239,46 @@ fn run(
 
     /* Preprocess spatial data */
     let data_storage = new_datastorage(settings.equipment_info.id.clone(), verbose);
+    // Metric plane for every spatial converter: configured zones, zones created via REST API and camera-wide ground plane
+    let projection = match &settings.projection {
+        Some(projection_settings) => match projection_settings.to_kind() {
+            Ok(kind) => kind,
+            Err(err) => {
+                println!("Can't apply projection settings due the error: {}. Local tangent plane is used", err);
+                ProjectionKind::default()
+            }
+        },
+        None => ProjectionKind::default(),
+    };
+    println!("Projection is '{:?}'", projection);
+    data_storage.write().unwrap().set_projection(projection);
+    match &settings.worker.pcu_factors {
+        Some(pcu_factors) => {
+            data_storage.write().unwrap().set_pcu_factors(pcu_factors.clone());
+        }
+        None => {}
+    }
+
+    let aggregation_intervals = settings.worker.aggregation_intervals_milliseconds.clone().unwrap_or(vec![]);
+    match data_storage.write().unwrap().set_aggregation_intervals(settings.worker.reset_data_milliseconds, &aggregation_intervals) {
+        Ok(_) => {}
+        Err(err) => {
+            panic!("Can't set aggregation intervals due the error {:?}", err);
+        }
+    }
+
+    match &settings.history {
+        Some(history_settings) => match history_settings.to_storage() {
+            Ok(storage) => {
+                data_storage.write().unwrap().set_history(HistoryWriter::start(storage, &history_settings.path));
+                println!("Statistics history is saved to '{}'", history_settings.path);
+            }
+            Err(err) => {
+                println!("Can't open statistics history '{}' due the error: {}. History is disabled", history_settings.path, err);
+            }
+        },
+        None => {}
+    }
 
     let scale_x = match settings.input.scale_x {
         Some(x) => x,
@@ -187,10 +288,82 @@ fn run(
         Some(y) => y,
         None => 1.0,
     };
+    // Camera-wide ground plane. It is estimated in pixels of configuration to project road lanes given in WGS84 only
+    let ground_plane = match &settings.calibration {
+        Some(calibration) => match calibration.to_ground_plane(projection, 1.0, 1.0) {
+            Ok((converter, report)) => {
+                println!("Camera-wide calibration. {}", report);
+                Some(converter)
+            }
+            Err(err) => {
+                println!("Can't apply camera-wide calibration due the error: {}", err);
+                None
+            }
+        },
+        None => None,
+    };
     for road_lane in settings.road_lanes.iter() {
-        let mut polygon = Zone::from(road_lane);
+        let mut road_lane = road_lane.clone();
+        if road_lane.geometry.len() == 0 {
+            let projected = match &ground_plane {
+                Some(converter) => road_lane.project_geometry(converter),
+                None => Err("Pixel geometry is not set and there is no camera-wide calibration to project WGS84 geometry".to_string()),
+            };
+            match projected {
+                Ok(_) => {
+                    println!("Road lane (direction: {}, lane: {}) is projected to pixels: {:?}", road_lane.lane_direction, road_lane.lane_number, road_lane.geometry);
+                }
+                Err(err) => {
+                    println!("Road lane (direction: {}, lane: {}) is skipped: {}", road_lane.lane_direction, road_lane.lane_number, err);
+                    continue;
+                }
+            }
+        }
+        match road_lane.validate_geometry() {
+            Ok(_) => {}
+            Err(errors) => {
+                println!("Road lane (direction: {}, lane: {}) is skipped due invalid geometry:", road_lane.lane_direction, road_lane.lane_number);
+                for err in errors.iter() {
+                    println!("\t{}", err);
+                }
+                continue;
+            }
+        }
+        match road_lane.validate_level_of_service() {
+            Ok(_) => {}
+            Err(errors) => {
+                println!("Road lane (direction: {}, lane: {}) uses default level of service thresholds due invalid custom ones:", road_lane.lane_direction, road_lane.lane_number);
+                for err in errors.iter() {
+                    println!("\t{}", err);
+                }
+                road_lane.level_of_service = None;
+            }
+        }
+        let mut polygon = road_lane.to_zone(projection);
+        let control_points = match &road_lane.control_points {
+            Some(points) => Some(points),
+            None => settings.calibration.as_ref().map(|calibration| &calibration.control_points),
+        };
+        match control_points {
+            Some(points) => match polygon.set_control_points(points.clone()) {
+                Ok(report) => {
+                    println!("Calibration for zone '{}'. {}", polygon.get_id(), report);
+                }
+                Err(err) => {
+                    println!("Can't calibrate zone '{}' by control points due the error: {}. Zone's corners are used", polygon.get_id(), err);
+                }
+            },
+            None => {}
+        }
         polygon.scale_geom(scale_x, scale_y);
-        polygon.set_target_classes(COCO_FILTERED_CLASSNAMES);
+        if road_lane.counted_classes.is_none() {
+            polygon.set_target_classes(
+                COCO_FILTERED_CLASSNAMES
+                    .iter()
+                    .map(|class| class.to_string())
+                    .collect(),
+            );
+        }
         match data_storage.write().unwrap().insert_zone(polygon) {
             Ok(_) => {}
             Err(err) => {
@@ -199,6 +372,38 @@ fn run(
         };
     }
 
+    // Ground plane for the runtime is estimated in scaled pixels
+    match &settings.calibration {
+        Some(calibration) if ground_plane.is_some() => match calibration.to_ground_plane(projection, scale_x, scale_y) {
+            Ok((converter, _)) => match data_storage.read().unwrap().set_ground_plane(converter) {
+                Ok(_) => {}
+                Err(err) => {
+                    panic!("Can't set camera-wide calibration due the error {:?}", err);
+                }
+            },
+            Err(err) => {
+                println!("Can't apply camera-wide calibration due the error: {}", err);
+            }
+        },
+        _ => {}
+    }
+
+    match &settings.exclusion_masks {
+        Some(masks) => {
+            for mask_settings in masks.iter() {
+                let mut mask = ExclusionMask::from(mask_settings);
+                mask.scale_geom(scale_x, scale_y);
+                match data_storage.write().unwrap().insert_exclusion_mask(mask) {
+                    Ok(_) => {}
+                    Err(err) => {
+                        panic!("Can't insert exclusion mask due the error {:?}", err);
+                    }
+                };
+            }
+        }
+        None => {}
+    }
+
     // let data_storage_threaded = data_storage.clone();
 
     println!("Press `Ctrl-C` to stop main programm");
@@ -275,6 +480,75 @@ fn run(
     }
     let (width, height, fps) = probe_video(&mut video_capture)?;
     println!("Video probe: {{Width: {width}px | Height: {height}px | FPS: {fps}}}");
+    // Lens correction. When it is enabled, geometry of zones is expected in pixels of the undistorted frame
+    let mut lens_correction = match &settings.lens {
+        Some(lens_settings) => match lens_settings.to_lens_correction(scale_x, scale_y) {
+            Ok(lens) => Some(lens),
+            Err(err) => {
+                println!("Can't prepare lens correction due the error: {}. Lens distortion is ignored", err);
+                None
+            }
+        },
+        None => None,
+    };
+    if let Some(ref mut lens) = lens_correction {
+        if lens.is_dewarp_enabled() {
+            match lens.init_dewarp(Size::new(width as i32, height as i32)) {
+                Ok(_) => {}
+                Err(err) => {
+                    println!("Can't prepare dewarping of frames due the error: {}", err);
+                }
+            }
+        }
+    }
+    // Camera shake compensation. Points of tracks are moved back to the reference frame before they are matched with zones
+    let mut stabilizer = match &settings.stabilization {
+        Some(stabilization_settings) => match stabilization_settings.to_stabilizer() {
+            Ok(mut stabilizer) => {
+                if let Some(ref reference_image) = stabilization_settings.reference_image {
+                    let reference = imread(reference_image, IMREAD_COLOR).map_err(|err| err.into()).and_then(|image| stabilizer.set_reference(&image));
+                    match reference {
+                        Ok(_) => {}
+                        Err(err) => {
+                            println!("Can't use '{}' as reference frame for stabilization due the error: {}. The first frame is used", reference_image, err);
+                        }
+                    }
+                }
+                Some(stabilizer)
+            }
+            Err(err) => {
+                println!("Can't prepare stabilization due the error: {}. Camera shake is ignored", err);
+                None
+            }
+        },
+        None => None,
+    };
+    let stabilization_every_n_frames = settings.stabilization.as_ref().map_or(1, |stabilization| stabilization.get_every_n_frames());
+    let mut stabilization_frames: u32 = 0;
+    // Camera drift and tamper detection. Statistics of the periods with tampering are marked as invalid
+    let mut tamper_monitor = match &settings.tamper {
+        Some(tamper_settings) => match tamper_settings.to_monitor() {
+            Ok(mut monitor) => {
+                if let Some(ref reference_image) = tamper_settings.reference_image {
+                    let reference = imread(reference_image, IMREAD_COLOR).map_err(|err| err.into()).and_then(|image| monitor.set_reference(&image));
+                    match reference {
+                        Ok(_) => {}
+                        Err(err) => {
+                            println!("Can't use '{}' as reference scene for tamper detection due the error: {}. The first frame is used", reference_image, err);
+                        }
+                    }
+                }
+                Some(monitor)
+            }
+            Err(err) => {
+                println!("Can't prepare tamper detection due the error: {}. Tampering is ignored", err);
+                None
+            }
+        },
+        None => None,
+    };
+    let tamper_every_n_frames = settings.tamper.as_ref().map_or(1, |tamper| tamper.get_every_n_frames());
+    let mut tamper_frames: u32 = 0;
     // Create imshow() if needed
     let window = &settings.output.window_name;
     let output_width: i32 = settings.output.width;
@@ -304,7 +578,10 @@ fn run(
     ) = mpsc::sync_channel(0);
     thread::spawn(move || {
         let mut frames_counter: f32 = 0.0;
+        let mut frames_total: u64 = 0;
         let mut total_seconds: f32 = 0.0;
+        // Elapsed seconds for every additional aggregation interval
+        let mut extra_seconds: Vec<(i64, f32)> = ds_worker.read().expect("Bad DS").get_aggregation_intervals().into_iter().skip(1).map(|interval| (interval, 0.0)).collect();
         let mut empty_frames_countrer: u16 = 0;
         // @experimental
         let skip_every_n_frame = 2;
@@ -331,9 +608,13 @@ fn run(
                 continue;
             }
             frames_counter += 1.0;
+            frames_total += 1;
             let second_fraction = total_seconds + (frames_counter / fps);
             if frames_counter >= fps {
                 total_seconds += 1.0;
+                for (_, seconds) in extra_seconds.iter_mut() {
+                    *seconds += 1.0;
+                }
                 frames_counter = 0.0;
             }
             if frames_counter as i32 % skip_every_n_frame != 0 {
@@ -344,7 +625,7 @@ fn run(
             /* Send frame and capture info */
             let frame = ThreadedFrame {
                 frame: read_frame,
-                current_second: second_fraction,
+                timestamp: frames_total as f64 / fps as f64,
             };
 
             match tx_capture.send(frame) {
@@ -377,6 +658,12 @@ fn run(
 
                 match ds_writer.update_statistics() {
                     Ok(_) => {
+                        match ds_writer.save_history(reset_time) {
+                            Ok(_) => {}
+                            Err(err) => {
+                                println!("Can't save statistics to the history due the error: {}", err);
+                            }
+                        }
                         // Do not forget to drop mutex explicitly since we possible need to work with DS in REST API and Redis
                         drop(ds_writer)
                     }
@@ -385,7 +672,35 @@ fn run(
                     }
                 }
                 if redis_enabled {
-                    redis_conn.as_ref().unwrap().push_statistics();
+                    redis_conn.as_ref().unwrap().push_statistics(reset_time);
+                    redis_conn.as_ref().unwrap().push_od_matrix(reset_time);
+                    redis_conn.as_ref().unwrap().push_violations();
+                }
+            }
+            for (interval, seconds) in extra_seconds.iter_mut() {
+                if *seconds < *interval as f32 / 1000.0 {
+                    continue;
+                }
+                *seconds = 0.0;
+                let mut ds_writer = ds_worker.write().expect("Bad DS");
+                match ds_writer.update_interval_statistics(*interval) {
+                    Ok(_) => {
+                        match ds_writer.save_history(*interval) {
+                            Ok(_) => {}
+                            Err(err) => {
+                                println!("Can't save statistics to the history due the error: {}", err);
+                            }
+                        }
+                        drop(ds_writer)
+                    }
+                    Err(err) => {
+                        println!("Can't update statistics for interval {} due the error: {}", interval, err);
+                        continue;
+                    }
+                }
+                if redis_enabled {
+                    redis_conn.as_ref().unwrap().push_statistics(*interval);
+                    redis_conn.as_ref().unwrap().push_od_matrix(*interval);
                 }
             }
         }
@@ -420,9 +735,82 @@ fn run(
     let bbox_scalar_inverse: Scalar = draw::invert_color(&bbox_scalar);
     let id_scalar: Scalar = Scalar::from((0.0, 255.0, 0.0));
     let id_scalar_inverse: Scalar = draw::invert_color(&id_scalar);
+    let grid_scalar: Scalar = Scalar::from((255.0, 255.0, 255.0));
+    let mut metric_grid_cache = draw::MetricGridCache::default();
     for received in rx_capture {
-        // println!("Received frame from capture thread: {}", received.current_second);
+        // println!("Received frame from capture thread: {}", received.timestamp);
         let mut frame = received.frame.clone();
+        if let Some(ref mut stabilizer) = stabilizer {
+            if !stabilizer.has_reference() {
+                match stabilizer.set_reference(&frame) {
+                    Ok(_) => {
+                        println!("Reference frame for stabilization is set");
+                    }
+                    Err(err) => {
+                        println!("Can't set reference frame for stabilization due the error: {}", err);
+                    }
+                }
+            } else if stabilization_frames % stabilization_every_n_frames == 0 {
+                match stabilizer.update(&frame, Utc::now().timestamp()) {
+                    Ok(_) => {}
+                    Err(err) => {
+                        if verbose {
+                            println!("Can't register frame to the reference one due the error: {}", err);
+                        }
+                    }
+                }
+                match ds_tracker.read().expect("DataStorage is poisoned [RWLock]").set_stabilization_health(stabilizer.get_health()) {
+                    Ok(_) => {}
+                    Err(err) => {
+                        println!("Can't update stabilization health due the error: {}", err);
+                    }
+                };
+            }
+            stabilization_frames = stabilization_frames.wrapping_add(1);
+        }
+        if let Some(ref mut monitor) = tamper_monitor {
+            if !monitor.has_reference() {
+                match monitor.set_reference(&frame) {
+                    Ok(_) => {
+                        println!("Reference scene for tamper detection is set");
+                    }
+                    Err(err) => {
+                        println!("Can't set reference scene for tamper detection due the error: {}", err);
+                    }
+                }
+            }
+            if tamper_frames % tamper_every_n_frames == 0 {
+                let raised = match monitor.update(&frame, Utc::now().timestamp()) {
+                    Ok(raised) => {
+                        for event in raised.iter() {
+                            println!("Camera tampering '{}' since {} (measured value: {})", event.kind.as_str(), event.started_at, event.value);
+                        }
+                        raised
+                    }
+                    Err(err) => {
+                        println!("Can't check frame for tampering due the error: {}", err);
+                        vec![]
+                    }
+                };
+                if let Some(status) = monitor.get_status() {
+                    match ds_tracker.read().expect("DataStorage is poisoned [RWLock]").set_tamper_status(status) {
+                        Ok(_) => {}
+                        Err(err) => {
+                            println!("Can't update tamper status due the error: {}", err);
+                        }
+                    };
+                }
+                if !raised.is_empty() {
+                    match ds_tracker.read().expect("DataStorage is poisoned [RWLock]").mark_tamper_events(&raised) {
+                        Ok(_) => {}
+                        Err(err) => {
+                            println!("Can't mark statistics of tampered periods due the error: {}", err);
+                        }
+                    };
+                }
+            }
+            tamper_frames = tamper_frames.wrapping_add(1);
+        }
         let blobimg =
             blob_from_image(&frame, BLOB_SCALE, net_size, blob_mean, true, false, CV_32F)?;
         match neural_net.set_input(&blobimg, BLOB_NAME, 1.0, blob_mean) {
@@ -445,6 +833,12 @@ fn run(
         }
 
         /* Process detected objects and match them to existing ones */
+        let frame_mapping = FrameMapping::new(lens_correction.as_ref(), stabilizer.as_ref());
+        let ds_masks = ds_tracker.read().expect("DataStorage is poisoned [RWLock]");
+        let exclusion_masks = ds_masks
+            .exclusion_masks
+            .read()
+            .expect("Exclusion masks are poisoned [RWLock]");
         let mut tmp_detections = process_yolo_detections(
             &detections,
             conf_threshold,
@@ -454,10 +848,14 @@ fn run(
             max_points_in_track,
             &coco_classnames,
             COCO_FILTERED_CLASSNAMES,
+            &exclusion_masks,
+            &frame_mapping,
             tracker_dt,
         );
+        drop(exclusion_masks);
+        drop(ds_masks);
 
-        match tracker.match_objects(&mut tmp_detections, received.current_second) {
+        match tracker.match_objects(&mut tmp_detections, received.timestamp) {
             Ok(_) => {}
             Err(err) => {
                 println!("Can't match objects due the error: {:?}", err);
@@ -466,6 +864,17 @@ fn run(
         };
 
         let ds_guard = ds_tracker.read().expect("DataStorage is poisoned [RWLock]");
+
+        // Lost objects have finished their paths through zones
+        for finished_object in tracker.take_finished_objects().iter() {
+            match ds_guard.register_zones_path(&finished_object.zones_path, finished_object.get_classname()) {
+                Ok(_) => {}
+                Err(err) => {
+                    println!("Can't register path of the object due the error: {}", err);
+                }
+            };
+        }
+
         let zones = ds_guard
             .zones
             .read()
@@ -480,6 +889,17 @@ fn run(
             drop(zone);
         }
 
+        let ground_plane = ds_guard
+            .ground_plane
+            .lock()
+            .expect("Ground plane is poisoned [Mutex]");
+        let mut ground_tracks = vec![];
+        let mut violations_found = vec![];
+        let mut lane_discovery = ds_guard
+            .lane_discovery
+            .write()
+            .expect("Lane discovery is poisoned [RWLock]");
+
         for (object_id, object_extra) in tracker.objects_extra.iter_mut() {
             let object = tracker.engine.objects.get(object_id).unwrap();
             if object.get_no_match_times() > 1 {
@@ -493,22 +913,70 @@ fn run(
 
             let track: &Vec<mot_rs::utils::Point> = object.get_track();
             let last_point = &track[track.len() - 1];
+            let point_before = find_point_before(track, times, SLOW_SPEED_WINDOW);
+            // Zones work with stabilized and undistorted points. The latest estimate of the shake is used for the previous point too,
+            // since camera sways slowly compared to the time between them
+            let (last_x, last_y) = frame_mapping.to_zones(last_point.x, last_point.y);
+            let point_before = point_before.map(|(x_before, y_before, seconds_between)| {
+                let (x_before, y_before) = frame_mapping.to_zones(x_before, y_before);
+                (x_before, y_before, seconds_between)
+            });
+
+            // Position and speed on the camera-wide ground plane
+            object_extra.ground_info = match ground_plane.as_ref() {
+                Some(converter) if converter.is_on_ground(last_x, last_y) => match converter.transform_to_wgs84(last_x, last_y) {
+                    Some((lon, lat)) => {
+                        let speed = match point_before {
+                            Some((x_before, y_before, seconds_between)) if seconds_between > 0.0 && converter.is_on_ground(x_before, y_before) => {
+                                match (converter.transform_to_local(last_x, last_y), converter.transform_to_local(x_before, y_before)) {
+                                    (Some((east, north)), Some((east_before, north_before))) => {
+                                        let meters = ((east - east_before).powi(2) + (north - north_before).powi(2)).sqrt();
+                                        (meters / seconds_between as f64 * 3.6) as f32
+                                    }
+                                    _ => -1.0,
+                                }
+                            }
+                            _ => -1.0,
+                        };
+                        Some(GroundInfo {
+                            lon: lon,
+                            lat: lat,
+                            speed: speed,
+                        })
+                    }
+                    None => None,
+                },
+                _ => None,
+            };
+            lane_discovery.register_point(*object_id, last_x, last_y);
+            if let Some(ref ground_info) = object_extra.ground_info {
+                ground_tracks.push(GroundTrack {
+                    object_id: object_id.to_string(),
+                    classname: object_extra.get_classname(),
+                    lon: ground_info.lon,
+                    lat: ground_info.lat,
+                    speed: ground_info.speed,
+                });
+            }
 
             // Check if object is inside of any polygon
             for (_, zone_guarded) in zones.iter() {
                 let mut zone = zone_guarded.lock().expect("Zone is poisoned [Mutex]");
-                if !zone.contains_point(last_point.x, last_point.y) {
+                if !zone.contains_point(last_x, last_y) {
                     continue;
                 }
                 zone.current_statistics.occupancy += 1; // Increment current load to match number of objects in zone
-                let projected_pt = zone.project_to_skeleton(last_point.x, last_point.y);
+                object_extra.register_zone(&zone.id);
+                zone.update_speed_trap(object_id.clone(), last_time, last_x, last_y);
+                let projected_pt = zone.project_to_skeleton(last_x, last_y);
                 let pixels_per_meters = zone.get_skeleton_ppm();
+                let classname = object_extra.get_classname();
                 match object_extra.spatial_info {
                     Some(ref mut spatial_info) => {
                         spatial_info.update_avg(
                             last_time,
-                            last_point.x,
-                            last_point.y,
+                            last_x,
+                            last_y,
                             projected_pt.0,
                             projected_pt.1,
                             pixels_per_meters,
@@ -516,41 +984,104 @@ fn run(
                         zone.register_or_update_object(
                             object_id.clone(),
                             spatial_info.speed,
-                            object_extra.get_classname(),
+                            classname.clone(),
+                        );
+                        zone.register_crossing(
+                            object_id.clone(),
+                            last_time,
+                            last_x,
+                            last_y,
+                            spatial_info.speed,
+                            &classname,
                         );
                     }
                     None => {
                         object_extra.spatial_info = Some(SpatialInfo::new(
                             last_time,
-                            last_point.x,
-                            last_point.y,
+                            last_x,
+                            last_y,
                             projected_pt.0,
                             projected_pt.1,
                         ));
                         zone.register_or_update_object(
                             object_id.clone(),
                             -1.0,
-                            object_extra.get_classname(),
+                            classname.clone(),
+                        );
+                        zone.register_crossing(
+                            object_id.clone(),
+                            last_time,
+                            last_x,
+                            last_y,
+                            -1.0,
+                            &classname,
                         );
                     }
                 }
-            }
-        }
-        if enable_mjpeg || settings.output.enable {
-            for (_, v) in zones.iter() {
-                let polygon = v.lock().expect("Mutex poisoned");
-                polygon.draw_geom(&mut frame);
-                polygon.draw_skeleton(&mut frame);
-                polygon.draw_current_intensity(&mut frame);
+                zone.register_occupancy(last_x, last_y, &classname);
+                if zone.register_violation(object_id.clone(), last_time, &classname) {
+                    // Snapshot is encoded once per frame after the locks are released
+                    violations_found.push((zone.get_id(), object_id.to_string(), classname.clone()));
+                }
+                if let Some((x_before, y_before, seconds_between)) = point_before {
+                    zone.register_queue_candidate(
+                        last_x,
+                        last_y,
+                        x_before,
+                        y_before,
+                        seconds_between,
+                    );
+                }
             }
         }
 
-        // We need drop here explicitly, since we need to release lock on zones for MJPEG / REST API / Redis publisher and statistics threads
-        drop(zones);
-        drop(ds_guard);
+        drop(ground_plane);
+        lane_discovery.finish_lost(|object_id| tracker.objects_extra.contains_key(object_id));
+        if let Some(job) = lane_discovery.update(Utc::now().timestamp()) {
+            // Clustering takes a while for long recordings, so it should not stall video processing
+            let discovery_evaluated = ds_guard.lane_discovery.clone();
+            thread::spawn(move || {
+                let proposals = job.evaluate();
+                let mut lane_discovery = discovery_evaluated.write().expect("Lane discovery is poisoned [RWLock]");
+                if !lane_discovery.finish(job, proposals) {
+                    return;
+                }
+                println!(
+                    "Lane discovery has finished: {} lanes are proposed for {} trajectories",
+                    lane_discovery.get_proposals().len(),
+                    lane_discovery.get_trajectories_num()
+                );
+                for proposal in lane_discovery.get_proposals().iter() {
+                    println!(
+                        "\tProposal {} (direction: {}, lane: {}) is based on {} trajectories",
+                        proposal.id, proposal.lane_direction, proposal.lane_number, proposal.trajectories_num
+                    );
+                }
+            });
+        }
+        drop(lane_discovery);
+        match ds_guard.set_ground_tracks(ground_tracks) {
+            Ok(_) => {}
+            Err(err) => {
+                println!("Can't update ground tracks due the error: {}", err);
+            }
+        };
 
-        /* Imshow + re-stream input video as MJPEG */
+        // Evaluate queues and accumulate occupied time for the current frame
+        for (_, zone_guarded) in zones.iter() {
+            let mut zone = zone_guarded.lock().expect("Zone is poisoned [Mutex]");
+            zone.update_queue();
+            zone.update_occupancy(received.timestamp);
+        }
+        // Keep the frame before anything is drawn on it
+        let violation_frame = if violations_found.is_empty() { None } else { frame.try_clone().ok() };
         if enable_mjpeg || settings.output.enable {
+            // Dewarped frame matches the space of road lanes. Tracks are drawn before dewarping, so they are dewarped together
+            // with the frame, while zones are drawn afterwards. Otherwise zones are drawn under the tracks
+            let dewarp_enabled = lens_correction.as_ref().map(|lens| lens.is_dewarp_enabled()).unwrap_or(false);
+            if !dewarp_enabled {
+                draw_zones(&mut frame, &ds_guard, &zones, &frame_mapping, &mut metric_grid_cache, grid_scalar);
+            }
             draw::draw_trajectories(
                 &mut frame,
                 &tracker,
@@ -560,8 +1091,56 @@ fn run(
             draw::draw_bboxes(&mut frame, &tracker, bbox_scalar, bbox_scalar_inverse);
             draw::draw_identifiers(&mut frame, &tracker, id_scalar, id_scalar_inverse);
             draw::draw_speeds(&mut frame, &tracker, id_scalar, id_scalar_inverse);
-            draw::draw_projections(&mut frame, &tracker, id_scalar, id_scalar_inverse);
+            if let Some(ref lens) = lens_correction {
+                if lens.is_dewarp_enabled() {
+                    match lens.dewarp(&frame) {
+                        Ok(dewarped) => {
+                            frame = dewarped;
+                        }
+                        Err(err) => {
+                            println!("Can't dewarp frame due the error: {}", err);
+                        }
+                    }
+                }
+            }
+            if dewarp_enabled {
+                draw_zones(&mut frame, &ds_guard, &zones, &frame_mapping, &mut metric_grid_cache, grid_scalar);
+            }
+            draw::draw_projections(&mut frame, &tracker, &frame_mapping, id_scalar, id_scalar_inverse);
+        }
+
+        // We need drop here explicitly, since we need to release lock on zones for MJPEG / REST API / Redis publisher and statistics threads
+        drop(zones);
+        drop(ds_guard);
 
+        if !violations_found.is_empty() {
+            let mut buffer = Vector::<u8>::new();
+            let params = Vector::<i32>::new();
+            let snapshot: Arc<[u8]> = match violation_frame.map(|snapshot_frame| imencode(".jpg", &snapshot_frame, &mut buffer, &params)) {
+                Some(Ok(true)) => buffer.to_vec().into(),
+                _ => {
+                    println!("Can't encode snapshot for {} violation(s)", violations_found.len());
+                    Arc::from(vec![])
+                }
+            };
+            let ds_guard = ds_tracker.read().expect("DataStorage is poisoned [RWLock]");
+            for (zone_id, object_id, classname) in violations_found.into_iter() {
+                match ds_guard.register_violation(zone_id.clone(), object_id, classname.clone(), snapshot.clone()) {
+                    Ok(event_id) => {
+                        if verbose {
+                            println!("Violation {}: '{}' in zone {}", event_id, classname, zone_id);
+                        }
+                    }
+                    Err(err) => {
+                        println!("Can't register violation due the error: {}", err);
+                    }
+                };
+            }
+            drop(ds_guard);
+        }
+
+        /* Imshow + re-stream input video as MJPEG */
+        if enable_mjpeg || settings.output.enable {
             if settings.output.enable {
                 match resize(
                     &mut frame,
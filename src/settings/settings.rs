@@ -1,6 +1,7 @@
 use std::fs;
 
 use chrono::Utc;
+use std::collections::HashMap;
 use serde::{Deserialize, Serialize};
 use std::error::Error;
 use toml;
@@ -14,6 +15,13 @@ pub struct AppSettings {
     pub tracking: TrackingSettings,
     pub equipment_info: EquipmentInfo,
     pub road_lanes: Vec<RoadLanesSettings>,
+    pub exclusion_masks: Option<Vec<ExclusionMaskSettings>>,
+    pub calibration: Option<CalibrationSettings>,
+    pub projection: Option<ProjectionSettings>,
+    pub lens: Option<LensSettings>,
+    pub stabilization: Option<StabilizationSettings>,
+    pub tamper: Option<TamperSettings>,
+    pub history: Option<HistorySettings>,
     pub worker: WorkerSettings,
     pub rest_api: RestAPISettings,
     pub redis_publisher: RedisPublisherSettings,
@@ -64,14 +72,101 @@ pub struct EquipmentInfo {
 pub struct RoadLanesSettings {
     pub lane_number: u16,
     pub lane_direction: u8,
+    pub group_name: Option<String>,
+    // Could be omitted when camera-wide calibration is set: it is projected from 'geometry_wgs84' then
+    #[serde(default)]
     pub geometry: Vec<[i32; 2]>,
-    pub geometry_wgs84: Vec<[f32; 2]>,
+    pub geometry_wgs84: Vec<[f64; 2]>,
     pub color_rgb: [i16; 3],
+    pub counted_classes: Option<Vec<String>>,
+    pub allowed_classes: Option<Vec<String>>,
+    pub level_of_service: Option<LevelOfServiceThresholds>,
+    pub queue: Option<QueueParameters>,
+    pub speed_trap: Option<SpeedTrapLines>,
+    pub control_points: Option<Vec<ControlPoint>>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ExclusionMaskSettings {
+    pub geometry: Vec<[i32; 2]>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct CalibrationSettings {
+    // Pairs of pixel and WGS84 points for the ground plane of the whole camera.
+    // They are shared by every road lane which has no own control points
+    pub control_points: Vec<ControlPoint>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ProjectionSettings {
+    // Metric plane for spatial conversions: "mercator" (Web Mercator, EPSG:3857) or "enu" (local tangent plane, true meters)
+    pub kind: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct LensSettings {
+    // "pinhole" (Brown-Conrady) or "fisheye" (Kannala-Brandt)
+    pub model: String,
+    // Focal lengths and principal point (pixels) for the source video width/height
+    pub fx: f64,
+    pub fy: f64,
+    pub cx: f64,
+    pub cy: f64,
+    // Pinhole: k1, k2, p1, p2[, k3[, k4, k5, k6]]. Fisheye: k1, k2, k3, k4
+    pub distortion: Vec<f64>,
+    pub dewarp_frames: Option<bool>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct StabilizationSettings {
+    // Number of ORB features detected on each frame. Optional. Default is 1000
+    pub features_num: Option<i32>,
+    // Displacement is estimated for every N-th frame. Optional. Default is 5
+    pub every_n_frames: Option<u32>,
+    // Estimates with larger displacement (pixels) are rejected. Optional. Default is 50
+    pub max_displacement: Option<f32>,
+    // Image which zones are defined for. Optional. The first frame is used if it is not set
+    pub reference_image: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct TamperSettings {
+    // Frames are checked every N-th frame. Optional. Default is 25
+    pub every_n_frames: Option<u32>,
+    // Number of ORB features used to compare frames with the reference scene. Optional. Default is 500
+    pub features_num: Option<i32>,
+    // Largest allowed shift (pixels) relative to the reference scene. Optional. Default is 30
+    pub max_displacement: Option<f32>,
+    // Minimum variance of Laplacian. Optional. Default is 50
+    pub min_sharpness: Option<f32>,
+    // Allowed range of mean intensity [0; 255]. Optional. Defaults are 20 and 235
+    pub min_brightness: Option<f32>,
+    pub max_brightness: Option<f32>,
+    // Frames with smaller mean absolute difference are considered as the same ones. Optional. Default is 0.5
+    pub frozen_difference: Option<f32>,
+    // Frames should stay the same this long (seconds) to raise event. Optional. Default is 10
+    pub frozen_seconds: Option<i64>,
+    // Other conditions should last this long (seconds) to raise event. Any condition should be absent this long to finish event. Optional. Default is 5
+    pub min_duration_seconds: Option<i64>,
+    // Image of the reference scene. Optional. The first frame is used if it is not set
+    pub reference_image: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct HistorySettings {
+    // Path to SQLite database file. It is created if it does not exist
+    pub path: String,
+    // Finished periods older than this are removed. Optional. Default is 30. Value "0" keeps periods forever
+    pub retention_days: Option<u32>,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct WorkerSettings {
     pub reset_data_milliseconds: i64,
+    pub pcu_factors: Option<HashMap<String, f32>>,
+    // Additional aggregation intervals. Statistics for them is computed from the same observations as for 'reset_data_milliseconds'
+    pub aggregation_intervals_milliseconds: Option<Vec<i64>>,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -98,14 +193,128 @@ pub struct MJPEGStreamingSettings {
     pub enable: bool,
 }
 
-use crate::lib::spatial::epsg::lonlat_to_meters;
 use crate::lib::zones::Zone;
+use crate::lib::masks::ExclusionMask;
+use crate::lib::zones::los::LevelOfServiceThresholds;
+use crate::lib::zones::queue::QueueParameters;
+use crate::lib::zones::speed_trap::SpeedTrapLines;
+use crate::lib::spatial::calibration::{calibrate, CalibrationReport, ControlPoint};
+use crate::lib::spatial::SpatialConverter;
+use crate::lib::spatial::projection::ProjectionKind;
+use crate::lib::spatial::lens::{LensCorrection, LensModel};
+use crate::lib::spatial::stabilization::Stabilizer;
+use crate::lib::tamper::{TamperMonitor, TamperThresholds};
+use crate::lib::data_storage::HistoryStorage;
+use crate::lib::zones::validation::{validate_zone_geometry, validate_los_thresholds, GeometryError};
+use opencv::core::Point2d;
 use opencv::core::Point2f;
 use opencv::core::Scalar;
 use std::convert::From;
 
-impl From<&RoadLanesSettings> for Zone {
-    fn from(setting: &RoadLanesSettings) -> Self {
+impl LensSettings {
+    // Intrinsics are scaled the same way as geometry of road lanes
+    pub fn to_lens_correction(&self, scale_x: f32, scale_y: f32) -> Result<LensCorrection, Box<dyn Error>> {
+        let model = self.model.parse::<LensModel>()?;
+        LensCorrection::new(
+            model,
+            self.fx * scale_x as f64,
+            self.fy * scale_y as f64,
+            self.cx * scale_x as f64,
+            self.cy * scale_y as f64,
+            &self.distortion,
+            self.dewarp_frames.unwrap_or(false),
+        )
+    }
+}
+
+impl StabilizationSettings {
+    pub fn to_stabilizer(&self) -> Result<Stabilizer, Box<dyn Error>> {
+        Stabilizer::new(self.features_num.unwrap_or(1000), self.max_displacement.unwrap_or(50.0))
+    }
+    pub fn get_every_n_frames(&self) -> u32 {
+        u32::max(self.every_n_frames.unwrap_or(5), 1)
+    }
+}
+
+impl TamperSettings {
+    pub fn to_monitor(&self) -> Result<TamperMonitor, Box<dyn Error>> {
+        let defaults = TamperThresholds::default();
+        let thresholds = TamperThresholds {
+            min_sharpness: self.min_sharpness.unwrap_or(defaults.min_sharpness),
+            min_brightness: self.min_brightness.unwrap_or(defaults.min_brightness),
+            max_brightness: self.max_brightness.unwrap_or(defaults.max_brightness),
+            frozen_difference: self.frozen_difference.unwrap_or(defaults.frozen_difference),
+            max_displacement: self.max_displacement.unwrap_or(defaults.max_displacement),
+            min_duration_seconds: self.min_duration_seconds.unwrap_or(defaults.min_duration_seconds),
+            frozen_seconds: self.frozen_seconds.unwrap_or(defaults.frozen_seconds),
+        };
+        if thresholds.min_brightness >= thresholds.max_brightness {
+            return Err(format!("Minimum brightness {} should be less than maximum {}", thresholds.min_brightness, thresholds.max_brightness).into());
+        }
+        TamperMonitor::new(self.features_num.unwrap_or(500), thresholds)
+    }
+    pub fn get_every_n_frames(&self) -> u32 {
+        u32::max(self.every_n_frames.unwrap_or(25), 1)
+    }
+}
+
+impl HistorySettings {
+    pub fn to_storage(&self) -> Result<HistoryStorage, Box<dyn Error>> {
+        Ok(HistoryStorage::open(&self.path, self.retention_days.unwrap_or(30))?)
+    }
+}
+
+impl CalibrationSettings {
+    // Estimates camera-wide ground plane. Control points are scaled the same way as geometry of road lanes
+    pub fn to_ground_plane(&self, projection: ProjectionKind, scale_x: f32, scale_y: f32) -> Result<(SpatialConverter, CalibrationReport), Box<dyn Error>> {
+        let points: Vec<ControlPoint> = self
+            .control_points
+            .iter()
+            .map(|point| ControlPoint {
+                pixel: [point.pixel[0] * scale_x, point.pixel[1] * scale_y],
+                wgs84: point.wgs84,
+            })
+            .collect();
+        calibrate(projection, &points)
+    }
+}
+
+impl ProjectionSettings {
+    pub fn to_kind(&self) -> Result<ProjectionKind, String> {
+        self.kind.parse::<ProjectionKind>()
+    }
+}
+
+impl RoadLanesSettings {
+    // Fills pixel geometry by projecting WGS84 geometry with the ground plane
+    pub fn project_geometry(&mut self, ground_plane: &SpatialConverter) -> Result<(), String> {
+        let geometry = ground_plane.project_wgs84_points(&self.geometry_wgs84)?;
+        self.geometry = geometry.iter().map(|pt| [pt.x.round() as i32, pt.y.round() as i32]).collect();
+        Ok(())
+    }
+    // Checks geometry before the zone is created, since invalid geometry could not be used for the spatial conversion
+    pub fn validate_geometry(&self) -> Result<(), Vec<GeometryError>> {
+        let geom: Vec<Point2f> = self.geometry.iter().map(|pt| Point2f::new(pt[0] as f32, pt[1] as f32)).collect();
+        let geom_epsg4326: Vec<Point2d> = self.geometry_wgs84.iter().map(|pt| Point2d::new(pt[0], pt[1])).collect();
+        validate_zone_geometry("geometry", &geom, "geometry_wgs84", &geom_epsg4326)
+    }
+    // Checks custom level of service thresholds, since unordered thresholds break the classification
+    pub fn validate_level_of_service(&self) -> Result<(), Vec<GeometryError>> {
+        let errors = match &self.level_of_service {
+            Some(thresholds) => validate_los_thresholds("level_of_service", thresholds),
+            None => vec![]
+        };
+        if errors.len() > 0 {
+            return Err(errors);
+        }
+        Ok(())
+    }
+}
+
+impl RoadLanesSettings {
+    // Zone's spatial converter maps pixels to the given metric plane
+    pub fn to_zone(&self, projection: ProjectionKind) -> Zone {
+        let setting = self;
         let geom = setting
             .geometry
             .iter()
@@ -115,26 +324,16 @@ impl From<&RoadLanesSettings> for Zone {
         let geom_epsg4326 = setting
             .geometry_wgs84
             .iter()
-            .map(|pt| Point2f::new(pt[0], pt[1]))
-            .collect();
-
-        let geom_epsg3857 = setting
-            .geometry_wgs84
-            .iter()
-            .map(|pt| {
-                let lonlat = lonlat_to_meters(pt[0], pt[1]);
-                Point2f::new(lonlat.0, lonlat.1)
-            })
+            .map(|pt| Point2d::new(pt[0], pt[1]))
             .collect();
 
-        Zone::new(
+        let mut zone = Zone::new(
             format!(
                 "dir_{}_lane_{}",
                 setting.lane_direction, setting.lane_number
             ),
             geom,
             geom_epsg4326,
-            geom_epsg3857,
             Scalar::from((
                 setting.color_rgb[2] as f64,
                 setting.color_rgb[1] as f64,
@@ -142,6 +341,56 @@ impl From<&RoadLanesSettings> for Zone {
             )),
             setting.lane_number,
             setting.lane_direction,
+            projection,
+        );
+        match &setting.group_name {
+            Some(name) => {
+                zone.set_group_name(name.clone());
+            }
+            None => {}
+        }
+        match &setting.counted_classes {
+            Some(classes) => {
+                zone.set_target_classes(classes.clone());
+            }
+            None => {}
+        }
+        match &setting.allowed_classes {
+            Some(classes) => {
+                zone.set_allowed_classes(classes.clone());
+            }
+            None => {}
+        }
+        match &setting.level_of_service {
+            Some(thresholds) => {
+                zone.set_los_thresholds(thresholds.clone());
+            }
+            None => {}
+        }
+        match &setting.queue {
+            Some(parameters) => {
+                zone.set_queue_parameters(parameters.clone());
+            }
+            None => {}
+        }
+        match &setting.speed_trap {
+            Some(lines) => {
+                zone.set_speed_trap(lines.clone());
+            }
+            None => {}
+        }
+        zone
+    }
+}
+
+impl From<&ExclusionMaskSettings> for ExclusionMask {
+    fn from(setting: &ExclusionMaskSettings) -> Self {
+        ExclusionMask::new(
+            setting
+                .geometry
+                .iter()
+                .map(|pt| Point2f::new(pt[0] as f32, pt[1] as f32))
+                .collect(),
         )
     }
 }
@@ -197,6 +446,13 @@ impl AppSettings {
             tracking: self.tracking.clone(),
             equipment_info: self.equipment_info.clone(),
             road_lanes: Vec::new(),
+            exclusion_masks: None,
+            calibration: self.calibration.clone(),
+            projection: self.projection.clone(),
+            lens: self.lens.clone(),
+            stabilization: self.stabilization.clone(),
+            tamper: self.tamper.clone(),
+            history: self.history.clone(),
             worker: self.worker.clone(),
             rest_api: self.rest_api.clone(),
             redis_publisher: self.redis_publisher.clone(),
@@ -217,3 +473,17 @@ impl fmt::Display for AppSettings {
         )
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn test_projection_settings() {
+        let settings: ProjectionSettings = toml::from_str("kind = \"mercator\"").unwrap();
+        assert_eq!(settings.to_kind(), Ok(ProjectionKind::Mercator));
+        let settings: ProjectionSettings = toml::from_str("kind = \"ENU\"").unwrap();
+        assert_eq!(settings.to_kind(), Ok(ProjectionKind::Enu));
+        let settings: ProjectionSettings = toml::from_str("kind = \"utm\"").unwrap();
+        assert!(settings.to_kind().is_err());
+    }
+}
@@ -2,5 +2,8 @@ use opencv::core::Mat;
 
 pub struct ThreadedFrame {
     pub frame: Mat,
-    pub current_second: f32,
+    // Seconds since the start of the video capture. Unlike the statistics timer it is never reset,
+    // so accumulators which live through several periods (e.g. additional aggregation intervals) see monotonic time.
+    // Double precision keeps sub-millisecond resolution for years of uptime
+    pub timestamp: f64,
 }